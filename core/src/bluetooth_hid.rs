@@ -0,0 +1,469 @@
+use anyhow::Result;
+use crate::hid_gamepad;
+use crate::network::{ControllerInputData, ControllerState};
+
+// Object path our org.bluez.Profile1 lives at, BlueZ only needs it to exist
+#[cfg(target_os = "linux")]
+const PROFILE_PATH: &str = "/org/steamdeck_controls/hid";
+#[cfg(target_os = "linux")]
+const HID_UUID: &str = "00001124-0000-1000-8000-00805f9b34fb";
+// L2CAP channels of the HID profile, requests and handshakes on one, input reports on the other
+#[cfg(target_os = "linux")]
+const PSM_CONTROL: u16 = 0x11;
+#[cfg(target_os = "linux")]
+const PSM_INTERRUPT: u16 = 0x13;
+
+// First byte of every HIDP message, the transaction type in the high nibble
+const HANDSHAKE_SUCCESSFUL: u8 = 0x00;
+const HANDSHAKE_UNSUPPORTED: u8 = 0x03;
+const HID_CONTROL_VIRTUAL_CABLE_UNPLUG: u8 = 0x15;
+const DATA_INPUT: u8 = 0xa1;
+const DATA_OTHER: u8 = 0xa0;
+// GET_PROTOCOL is answered with this, there is no boot protocol for gamepads
+const REPORT_PROTOCOL: u8 = 0x01;
+
+/// Advertises the Deck as a Bluetooth HID gamepad through BlueZ, so a PC can pair with it like
+/// any other controller and needs no server. Takes the HID PSMs for itself, which BlueZ's input
+/// plugin holds by default: bluetoothd has to run with --noplugin=input.
+pub struct BluetoothGamepad {
+    #[cfg(target_os = "linux")]
+    bluez: bluez::Registration,
+    #[cfg(target_os = "linux")]
+    control_listener: l2cap::Socket,
+    #[cfg(target_os = "linux")]
+    interrupt_listener: l2cap::Socket,
+    #[cfg(target_os = "linux")]
+    control: Option<l2cap::Socket>,
+    #[cfg(target_os = "linux")]
+    interrupt: Option<l2cap::Socket>,
+    state: ControllerState,
+    last_report: Option<[u8; hid_gamepad::REPORT_LENGTH]>,
+    // Set when the host hasn't got the latest report yet, written again by flush
+    pending: bool,
+    connected: bool,
+    connection_changed: bool,
+}
+
+impl BluetoothGamepad {
+    #[cfg(target_os = "linux")]
+    pub fn start() -> Result<Self> {
+        use anyhow::Context;
+
+        // Listening first, so a host that connects as soon as the record shows up finds us
+        let control_listener = l2cap::Socket::listen(PSM_CONTROL)
+            .context("Failed to listen on the HID control channel, is bluetoothd running with --noplugin=input?")?;
+        let interrupt_listener = l2cap::Socket::listen(PSM_INTERRUPT)
+            .context("Failed to listen on the HID interrupt channel, is bluetoothd running with --noplugin=input?")?;
+        let bluez = bluez::Registration::register(&service_record())?;
+        log::info!("Bluetooth gamepad discoverable as {}", bluez.adapter());
+
+        Ok(Self {
+            bluez,
+            control_listener,
+            interrupt_listener,
+            control: None,
+            interrupt: None,
+            state: ControllerState::default(),
+            last_report: None,
+            pending: false,
+            connected: false,
+            connection_changed: false,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn start() -> Result<Self> {
+        anyhow::bail!("Bluetooth gamepad mode needs BlueZ, which is only available on Linux")
+    }
+
+    // All controllers are merged into the one gamepad the PC sees
+    pub fn send_controller_data(&mut self, data: &ControllerInputData) -> Result<()> {
+        self.state.apply(data);
+        let report = hid_gamepad::encode_report(&self.state);
+        if self.last_report != Some(report) {
+            self.last_report = Some(report);
+            self.pending = true;
+        }
+        self.flush()
+    }
+
+    // What the host sees once input should stop reaching it
+    pub fn release_all(&mut self) -> Result<()> {
+        let data = self.state.release_events(0);
+        self.send_controller_data(&data)
+    }
+
+    // Picks up hosts connecting, answers their requests and writes the latest report if the host
+    // hasn't got it yet. Call it regularly, nothing happens on the sockets otherwise.
+    pub fn flush(&mut self) -> Result<()> {
+        self.poll_connections()?;
+        let Some(report) = self.last_report.filter(|_| self.pending && self.connected) else {
+            return Ok(());
+        };
+        let mut message = [0u8; 1 + hid_gamepad::REPORT_LENGTH];
+        message[0] = DATA_INPUT;
+        message[1..].copy_from_slice(&report);
+        match self.write_interrupt(&message) {
+            Ok(()) => self.pending = false,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            // Out of range or switched off, the report stays pending for when it's back
+            Err(e) => {
+                log::info!("Bluetooth host went away: {}", e);
+                self.disconnect();
+            }
+        }
+        Ok(())
+    }
+
+    // Some(true) once a host has connected both channels, Some(false) once it's gone
+    pub fn connection_update(&mut self) -> Option<bool> {
+        if self.connection_changed {
+            self.connection_changed = false;
+            return Some(self.connected);
+        }
+        None
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn set_connected(&mut self, connected: bool) {
+        if self.connected != connected {
+            self.connected = connected;
+            self.connection_changed = true;
+            // A new host hasn't seen anything yet
+            self.pending |= connected;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn poll_connections(&mut self) -> Result<()> {
+        if self.control.is_none() {
+            self.control = self.control_listener.accept()?;
+        }
+        if self.interrupt.is_none() {
+            self.interrupt = self.interrupt_listener.accept()?;
+        }
+        self.set_connected(self.control.is_some() && self.interrupt.is_some());
+
+        let mut buffer = [0u8; 64];
+        while let Some(control) = &self.control {
+            let length = match control.recv(&mut buffer) {
+                Ok(0) => {
+                    log::info!("Bluetooth host disconnected");
+                    self.disconnect();
+                    break;
+                }
+                Ok(length) => length,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::info!("Bluetooth host went away: {}", e);
+                    self.disconnect();
+                    break;
+                }
+            };
+            let Some(reply) = self.reply(&buffer[..length]) else {
+                continue;
+            };
+            if let Some(Err(e)) = self.control.as_ref().map(|control| control.send(&reply)) {
+                log::debug!("Failed to answer the Bluetooth host: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn poll_connections(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // What goes back on the control channel for a request from the host, None for messages
+    // that don't get an answer
+    fn reply(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        let header = *request.first()?;
+        match header >> 4 {
+            // HID_CONTROL, only unplugging means anything to a gamepad
+            0x1 => {
+                if header == HID_CONTROL_VIRTUAL_CABLE_UNPLUG {
+                    log::info!("Bluetooth host unplugged the gamepad");
+                    self.disconnect();
+                }
+                None
+            }
+            // GET_REPORT, the only report there is
+            0x4 => {
+                let report = self.last_report.unwrap_or_else(|| hid_gamepad::encode_report(&self.state));
+                let mut reply = vec![DATA_INPUT];
+                reply.extend_from_slice(&report);
+                Some(reply)
+            }
+            // GET_PROTOCOL
+            0x6 => Some(vec![DATA_OTHER, REPORT_PROTOCOL]),
+            // SET_REPORT, SET_PROTOCOL and SET_IDLE, accepted and ignored
+            0x5 | 0x7 | 0x9 => Some(vec![HANDSHAKE_SUCCESSFUL]),
+            _ => Some(vec![HANDSHAKE_UNSUPPORTED]),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_interrupt(&mut self, message: &[u8]) -> std::io::Result<()> {
+        match &self.interrupt {
+            Some(interrupt) => interrupt.send(message),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn write_interrupt(&mut self, _message: &[u8]) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        #[cfg(target_os = "linux")]
+        {
+            self.control = None;
+            self.interrupt = None;
+        }
+        self.set_connected(false);
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for BluetoothGamepad {
+    fn drop(&mut self) {
+        self.bluez.unregister();
+        log::info!("Bluetooth gamepad stopped");
+    }
+}
+
+// The SDP record BlueZ publishes for us, what makes a host treat the Deck as a gamepad and
+// where it gets the report descriptor from
+#[cfg(target_os = "linux")]
+fn service_record() -> String {
+    let descriptor: String = hid_gamepad::REPORT_DESCRIPTOR.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(r#"<?xml version="1.0" encoding="UTF-8" ?>
+<record>
+  <attribute id="0x0001"><sequence><uuid value="0x1124" /></sequence></attribute>
+  <attribute id="0x0004">
+    <sequence>
+      <sequence><uuid value="0x0100" /><uint16 value="0x{control:04x}" /></sequence>
+      <sequence><uuid value="0x0011" /></sequence>
+    </sequence>
+  </attribute>
+  <attribute id="0x0005"><sequence><uuid value="0x1002" /></sequence></attribute>
+  <attribute id="0x0006"><sequence><uint16 value="0x656e" /><uint16 value="0x006a" /><uint16 value="0x0100" /></sequence></attribute>
+  <attribute id="0x0009"><sequence><sequence><uuid value="0x1124" /><uint16 value="0x0101" /></sequence></sequence></attribute>
+  <attribute id="0x000d">
+    <sequence>
+      <sequence>
+        <sequence><uuid value="0x0100" /><uint16 value="0x{interrupt:04x}" /></sequence>
+        <sequence><uuid value="0x0011" /></sequence>
+      </sequence>
+    </sequence>
+  </attribute>
+  <attribute id="0x0100"><text value="Steam Deck Gamepad" /></attribute>
+  <attribute id="0x0101"><text value="Gamepad" /></attribute>
+  <attribute id="0x0102"><text value="SteamDeck Controls" /></attribute>
+  <attribute id="0x0201"><uint16 value="0x0111" /></attribute>
+  <attribute id="0x0202"><uint8 value="0x08" /></attribute>
+  <attribute id="0x0203"><uint8 value="0x00" /></attribute>
+  <attribute id="0x0204"><boolean value="true" /></attribute>
+  <attribute id="0x0205"><boolean value="true" /></attribute>
+  <attribute id="0x0206"><sequence><sequence><uint8 value="0x22" /><text encoding="hex" value="{descriptor}" /></sequence></sequence></attribute>
+  <attribute id="0x0207"><sequence><sequence><uint16 value="0x0409" /><uint16 value="0x0100" /></sequence></sequence></attribute>
+  <attribute id="0x020b"><uint16 value="0x0100" /></attribute>
+  <attribute id="0x020c"><uint16 value="0x0c80" /></attribute>
+  <attribute id="0x020d"><boolean value="true" /></attribute>
+  <attribute id="0x020e"><boolean value="false" /></attribute>
+</record>
+"#, control = PSM_CONTROL, interrupt = PSM_INTERRUPT, descriptor = descriptor)
+}
+
+// Talking to bluetoothd over the system bus: the profile with our SDP record, and the adapter
+// made discoverable so the PC can find us
+#[cfg(target_os = "linux")]
+mod bluez {
+    use anyhow::{Context, Result};
+    use std::collections::HashMap;
+    use zbus::blocking::Connection;
+    use zbus::zvariant::{ObjectPath, OwnedFd, OwnedObjectPath, OwnedValue, Value};
+
+    // Connections are accepted on our own sockets, BlueZ never hands any over
+    struct Profile;
+
+    #[zbus::dbus_interface(name = "org.bluez.Profile1")]
+    impl Profile {
+        fn release(&self) {}
+
+        fn new_connection(&self, _device: ObjectPath<'_>, _fd: OwnedFd, _properties: HashMap<String, OwnedValue>) {}
+
+        fn request_disconnection(&self, _device: ObjectPath<'_>) {}
+    }
+
+    // The profile stays published for as long as the connection is open
+    pub struct Registration {
+        connection: Connection,
+        adapter: OwnedObjectPath,
+    }
+
+    impl Registration {
+        pub fn register(service_record: &str) -> Result<Self> {
+            let connection = Connection::system().context("Failed to connect to the system bus")?;
+            let adapter = find_adapter(&connection)?;
+            connection.object_server().at(super::PROFILE_PATH, Profile)?;
+
+            let mut options: HashMap<&str, Value> = HashMap::new();
+            options.insert("ServiceRecord", Value::from(service_record));
+            options.insert("Role", Value::from("server"));
+            options.insert("RequireAuthentication", Value::from(true));
+            options.insert("RequireAuthorization", Value::from(false));
+            connection.call_method(
+                Some("org.bluez"),
+                "/org/bluez",
+                Some("org.bluez.ProfileManager1"),
+                "RegisterProfile",
+                &(ObjectPath::try_from(super::PROFILE_PATH)?, super::HID_UUID, options),
+            ).context("BlueZ refused the HID profile")?;
+
+            let registration = Self { connection, adapter };
+            for property in ["Powered", "Pairable", "Discoverable"] {
+                registration.set_adapter_property(property, true)
+                    .with_context(|| format!("Failed to make the Bluetooth adapter {}", property.to_lowercase()))?;
+            }
+            Ok(registration)
+        }
+
+        pub fn adapter(&self) -> &str {
+            self.adapter.as_str()
+        }
+
+        // Errors just mean bluetoothd went away first
+        pub fn unregister(&self) {
+            let _ = self.set_adapter_property("Discoverable", false);
+            let _ = self.connection.call_method(
+                Some("org.bluez"),
+                "/org/bluez",
+                Some("org.bluez.ProfileManager1"),
+                "UnregisterProfile",
+                &(ObjectPath::from_static_str_unchecked(super::PROFILE_PATH),),
+            );
+        }
+
+        fn set_adapter_property(&self, property: &str, value: bool) -> Result<()> {
+            self.connection.call_method(
+                Some("org.bluez"),
+                self.adapter.as_str(),
+                Some("org.freedesktop.DBus.Properties"),
+                "Set",
+                &("org.bluez.Adapter1", property, Value::from(value)),
+            )?;
+            Ok(())
+        }
+    }
+
+    // The first adapter BlueZ knows about, the Deck only has the one
+    fn find_adapter(connection: &Connection) -> Result<OwnedObjectPath> {
+        let reply = connection.call_method(
+            Some("org.bluez"),
+            "/",
+            Some("org.freedesktop.DBus.ObjectManager"),
+            "GetManagedObjects",
+            &(),
+        ).context("Failed to reach bluetoothd, is it running?")?;
+        let objects: HashMap<OwnedObjectPath, HashMap<String, HashMap<String, OwnedValue>>> = reply.body()?;
+        let mut adapters: Vec<OwnedObjectPath> = objects.into_iter()
+            .filter(|(_, interfaces)| interfaces.contains_key("org.bluez.Adapter1"))
+            .map(|(path, _)| path)
+            .collect();
+        adapters.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        adapters.into_iter().next().ok_or_else(|| anyhow::anyhow!("No Bluetooth adapter found"))
+    }
+}
+
+// Non-blocking L2CAP sequential packet sockets, which std has no type for
+#[cfg(target_os = "linux")]
+mod l2cap {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    const BTPROTO_L2CAP: i32 = 0;
+
+    #[repr(C)]
+    struct SockaddrL2 {
+        l2_family: libc::sa_family_t,
+        l2_psm: u16,
+        l2_bdaddr: [u8; 6],
+        l2_cid: u16,
+        l2_bdaddr_type: u8,
+    }
+
+    pub struct Socket(OwnedFd);
+
+    impl Socket {
+        // On every adapter, PSMs are little endian on the wire and in the address
+        pub fn listen(psm: u16) -> io::Result<Self> {
+            let fd = unsafe {
+                libc::socket(libc::AF_BLUETOOTH, libc::SOCK_SEQPACKET | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, BTPROTO_L2CAP)
+            };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let socket = Self(unsafe { OwnedFd::from_raw_fd(fd) });
+            let address = SockaddrL2 {
+                l2_family: libc::AF_BLUETOOTH as libc::sa_family_t,
+                l2_psm: psm.to_le(),
+                l2_bdaddr: [0; 6],
+                l2_cid: 0,
+                l2_bdaddr_type: 0,
+            };
+            let result = unsafe {
+                libc::bind(
+                    fd,
+                    &address as *const SockaddrL2 as *const libc::sockaddr,
+                    std::mem::size_of::<SockaddrL2>() as libc::socklen_t,
+                )
+            };
+            if result < 0 || unsafe { libc::listen(fd, 1) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(socket)
+        }
+
+        // None while nobody is connecting
+        pub fn accept(&self) -> io::Result<Option<Self>> {
+            let fd = unsafe {
+                libc::accept4(self.0.as_raw_fd(), std::ptr::null_mut(), std::ptr::null_mut(), libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC)
+            };
+            if fd < 0 {
+                let error = io::Error::last_os_error();
+                return match error.kind() {
+                    io::ErrorKind::WouldBlock => Ok(None),
+                    _ => Err(error),
+                };
+            }
+            Ok(Some(Self(unsafe { OwnedFd::from_raw_fd(fd) })))
+        }
+
+        // Whole packets only, a short write can't happen on a sequential packet socket
+        pub fn send(&self, message: &[u8]) -> io::Result<()> {
+            let result = unsafe {
+                libc::send(self.0.as_raw_fd(), message.as_ptr() as *const libc::c_void, message.len(), libc::MSG_NOSIGNAL)
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        pub fn recv(&self, buffer: &mut [u8]) -> io::Result<usize> {
+            let result = unsafe {
+                libc::recv(self.0.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0)
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(result as usize)
+        }
+    }
+}
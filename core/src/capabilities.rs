@@ -0,0 +1,149 @@
+use gilrs::{Axis, Button, Gamepad};
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "linux")]
+use crate::hid_passthrough::{hidraw_nodes, STEAM_DECK};
+use crate::input_id::{AxisId, ButtonId};
+use crate::network::{axis_to_id, button_to_id};
+
+// Bits in Capabilities::sensors
+pub const SENSOR_GYRO: u32 = 1 << 0;
+pub const SENSOR_ACCELEROMETER: u32 = 1 << 1;
+pub const SENSOR_TOUCHPAD: u32 = 1 << 2;
+
+// Motion sensors and touchpads gilrs doesn't report, by USB vendor and product ID
+#[cfg(target_os = "linux")]
+const KNOWN_SENSORS: [(u16, u16, u32); 7] = [
+    (0x28de, 0x1205, SENSOR_GYRO | SENSOR_ACCELEROMETER | SENSOR_TOUCHPAD), // Steam Deck
+    (0x28de, 0x1102, SENSOR_GYRO | SENSOR_ACCELEROMETER | SENSOR_TOUCHPAD), // Steam Controller
+    (0x28de, 0x1142, SENSOR_GYRO | SENSOR_ACCELEROMETER | SENSOR_TOUCHPAD), // Steam Controller dongle
+    (0x054c, 0x05c4, SENSOR_GYRO | SENSOR_ACCELEROMETER | SENSOR_TOUCHPAD), // DualShock 4
+    (0x054c, 0x09cc, SENSOR_GYRO | SENSOR_ACCELEROMETER | SENSOR_TOUCHPAD), // DualShock 4 v2
+    (0x054c, 0x0ce6, SENSOR_GYRO | SENSOR_ACCELEROMETER | SENSOR_TOUCHPAD), // DualSense
+    (0x057e, 0x2009, SENSOR_GYRO | SENSOR_ACCELEROMETER),                   // Switch Pro Controller
+];
+// The gamepad Steam Input presents in place of the real device
+#[cfg(target_os = "linux")]
+const STEAM_VIRTUAL_PAD: (u16, u16) = (0x28de, 0x11ff);
+
+/// What a controller can actually produce, one bit per ButtonId and AxisId discriminant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub buttons: u32,
+    pub axes: u32,
+    pub sensors: u32,
+}
+
+impl Capabilities {
+    // For devices that can't be probed, nothing gets greyed out
+    pub fn everything() -> Self {
+        let mut capabilities = Self::default();
+        ButtonId::ALL.iter().for_each(|&button| capabilities.add_button(button));
+        AxisId::ALL.iter().for_each(|&axis| capabilities.add_axis(axis));
+        capabilities
+    }
+
+    pub fn has_button(&self, button: ButtonId) -> bool {
+        self.buttons & (1 << button as u32) != 0
+    }
+
+    pub fn has_axis(&self, axis: AxisId) -> bool {
+        self.axes & (1 << axis as u32) != 0
+    }
+
+    pub fn has_sensor(&self, sensor: u32) -> bool {
+        self.sensors & sensor != 0
+    }
+
+    pub fn add_button(&mut self, button: ButtonId) {
+        self.buttons |= 1 << button as u32;
+    }
+
+    pub fn add_axis(&mut self, axis: AxisId) {
+        self.axes |= 1 << axis as u32;
+    }
+
+    // Buttons and axes from the gamepad's mapping, sensors from the hidraw device behind it
+    pub fn probe(gamepad: &Gamepad) -> Self {
+        let mut capabilities = Self::default();
+        for button in ALL_BUTTONS {
+            if gamepad.button_code(button).is_some() {
+                if let Some(id) = button_to_id(button) {
+                    capabilities.add_button(id);
+                }
+            }
+        }
+        for axis in ALL_AXES {
+            if gamepad.axis_code(axis).is_some() {
+                if let Some(id) = axis_to_id(axis) {
+                    capabilities.add_axis(id);
+                }
+            }
+        }
+        // Analog triggers often come in as button values, which are sent as the trigger axes
+        for (button, axis) in [(Button::LeftTrigger2, AxisId::LeftTrigger), (Button::RightTrigger2, AxisId::RightTrigger)] {
+            if gamepad.button_code(button).is_some() {
+                capabilities.add_axis(axis);
+            }
+        }
+        // Hat switches show up as D-Pad axes but are sent as D-Pad buttons
+        if gamepad.axis_code(Axis::DPadX).is_some() {
+            capabilities.add_button(ButtonId::DPadLeft);
+            capabilities.add_button(ButtonId::DPadRight);
+        }
+        if gamepad.axis_code(Axis::DPadY).is_some() {
+            capabilities.add_button(ButtonId::DPadUp);
+            capabilities.add_button(ButtonId::DPadDown);
+        }
+        if let (Some(vendor_id), Some(product_id)) = (gamepad.vendor_id(), gamepad.product_id()) {
+            capabilities.sensors = probe_sensors(vendor_id, product_id);
+        }
+        capabilities
+    }
+}
+
+const ALL_BUTTONS: [Button; 17] = [
+    Button::South, Button::East, Button::North, Button::West,
+    Button::LeftTrigger, Button::RightTrigger, Button::LeftTrigger2, Button::RightTrigger2,
+    Button::Select, Button::Start, Button::Mode, Button::LeftThumb, Button::RightThumb,
+    Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+];
+
+const ALL_AXES: [Axis; 8] = [
+    Axis::LeftStickX, Axis::LeftStickY, Axis::RightStickX, Axis::RightStickY,
+    Axis::LeftZ, Axis::RightZ, Axis::DPadX, Axis::DPadY,
+];
+
+#[cfg(target_os = "linux")]
+fn known_sensors(ids: (u16, u16)) -> u32 {
+    KNOWN_SENSORS.iter()
+        .find(|&&(vendor_id, product_id, _)| (vendor_id, product_id) == ids)
+        .map_or(0, |&(_, _, sensors)| sensors)
+}
+
+// Only trusted when the kernel has a hidraw node for the device, so the sensors can be read.
+// Behind Steam Input the pad is virtual, its sensors are the Deck's if the Deck's node is there.
+// Elsewhere there's no hidraw and no sensors are reported.
+#[cfg(target_os = "linux")]
+fn probe_sensors(vendor_id: u16, product_id: u16) -> u32 {
+    let devices = hidraw_devices();
+    let ids = if (vendor_id, product_id) == STEAM_VIRTUAL_PAD && devices.contains(&STEAM_DECK) {
+        STEAM_DECK
+    } else {
+        (vendor_id, product_id)
+    };
+    if devices.contains(&ids) {
+        known_sensors(ids)
+    } else {
+        0
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe_sensors(_vendor_id: u16, _product_id: u16) -> u32 {
+    0
+}
+
+#[cfg(target_os = "linux")]
+fn hidraw_devices() -> Vec<(u16, u16)> {
+    hidraw_nodes().into_iter().map(|node| (node.vendor_id, node.product_id)).collect()
+}
@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Messages the server hasn't acknowledged yet are remembered up to this many, a server that
+// never answers would otherwise grow the list forever
+const MAX_TRACKED: usize = 4096;
+
+/// How the server is keeping up with what we send, reported each time it acknowledges
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeliveryStats {
+    // Sent but not acknowledged yet
+    pub in_flight: usize,
+    // Messages this acknowledgement covered, up to and including acked_id
+    pub acked: usize,
+    pub acked_id: u64,
+    // From sending the acknowledged message to hearing back, minus the time the server held the ack
+    pub ack_latency: Duration,
+    // TCP segments the kernel had to send again on this connection, None where it can't be read
+    pub retransmissions: Option<u32>,
+}
+
+// Matches the server's acknowledgements, which carry the highest message ID it has applied,
+// with when each message went out
+#[derive(Default)]
+pub struct DeliveryTracker {
+    sent: VecDeque<(u64, Instant)>,
+}
+
+impl DeliveryTracker {
+    pub fn new() -> Self {
+        Self {
+            sent: VecDeque::new(),
+        }
+    }
+
+    pub fn sent(&mut self, message_id: u64, now: Instant) {
+        if self.sent.len() >= MAX_TRACKED {
+            self.sent.pop_front();
+        }
+        self.sent.push_back((message_id, now));
+    }
+
+    // Everything up to message_id counts as delivered, a reordered message included. held is how
+    // long the server waited before sending the ack. None for an ack of nothing we still track.
+    pub fn acked(&mut self, message_id: u64, held: Duration, now: Instant) -> Option<Duration> {
+        let mut sent_at = None;
+        while let Some(&(id, at)) = self.sent.front() {
+            if id > message_id {
+                break;
+            }
+            if id == message_id {
+                sent_at = Some(at);
+            }
+            self.sent.pop_front();
+        }
+        sent_at.map(|at| now.duration_since(at).saturating_sub(held))
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.sent.len()
+    }
+
+    // A new connection starts counting from scratch
+    pub fn clear(&mut self) {
+        self.sent.clear();
+    }
+}
+
+// Total retransmitted segments from the socket's TCP_INFO
+#[cfg(target_os = "linux")]
+pub fn tcp_retransmissions(fd: std::os::fd::RawFd) -> Option<u32> {
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut length = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(fd, libc::IPPROTO_TCP, libc::TCP_INFO, &mut info as *mut _ as *mut libc::c_void, &mut length)
+    };
+    (result == 0).then_some(info.tcpi_total_retrans)
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+// Force feedback is scheduled in steps this long, gilrs can't get any finer
+pub const STEP_MS: u32 = 50;
+
+/// Strength of the left and right motor, 0 to 1, at a point in the pattern
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HapticPoint {
+    pub at_ms: u32,
+    pub left: f32,
+    pub right: f32,
+}
+
+/// An envelope for both motors, the strength runs in straight lines from point to point and
+/// the pattern ends at the last one
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HapticPattern {
+    pub name: String,
+    pub points: Vec<HapticPoint>,
+}
+
+impl HapticPattern {
+    pub fn new(name: &str, points: &[(u32, f32, f32)]) -> Self {
+        Self {
+            name: name.to_string(),
+            points: points.iter().map(|&(at_ms, left, right)| HapticPoint { at_ms, left, right }).collect(),
+        }
+    }
+
+    pub fn duration_ms(&self) -> u32 {
+        self.points.iter().map(|point| point.at_ms).max().unwrap_or(0)
+    }
+
+    // Left and right strength at a time, nothing before the first point or after the last
+    pub fn at(&self, ms: u32) -> (f32, f32) {
+        let mut points = self.points.clone();
+        points.sort_by_key(|point| point.at_ms);
+        let Some(next) = points.iter().position(|point| point.at_ms >= ms) else {
+            return (0.0, 0.0);
+        };
+        let to = points[next];
+        if next == 0 {
+            return if to.at_ms == ms { (to.left, to.right) } else { (0.0, 0.0) };
+        }
+        let from = points[next - 1];
+        let t = (ms - from.at_ms) as f32 / (to.at_ms - from.at_ms).max(1) as f32;
+        (from.left + (to.left - from.left) * t, from.right + (to.right - from.right) * t)
+    }
+
+    // Start and left and right strength of each STEP_MS step, sampled halfway through it
+    pub fn steps(&self) -> Vec<(u32, f32, f32)> {
+        (0..self.duration_ms().div_ceil(STEP_MS))
+            .map(|step| {
+                let start = step * STEP_MS;
+                let (left, right) = self.at(start + STEP_MS / 2);
+                (start, left.clamp(0.0, 1.0), right.clamp(0.0, 1.0))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,103 @@
+use crate::input_id::{AxisId, ButtonId};
+use crate::network::ControllerState;
+
+// A plain HID gamepad, for transports where the PC sees the Deck as a device instead of talking
+// to the server: 13 buttons, a hat switch for the D-Pad, both sticks and both triggers
+pub const REPORT_DESCRIPTOR: [u8; 82] = [
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x05, // Usage (Game Pad)
+    0xa1, 0x01, // Collection (Application)
+    0x05, 0x09, //   Usage Page (Button)
+    0x19, 0x01, //   Usage Minimum (1)
+    0x29, 0x0d, //   Usage Maximum (13)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x0d, //   Report Count (13)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x95, 0x03, //   Report Count (3)
+    0x81, 0x03, //   Input (Constant), padding
+    0x05, 0x01, //   Usage Page (Generic Desktop)
+    0x09, 0x39, //   Usage (Hat Switch)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x07, //   Logical Maximum (7)
+    0x46, 0x3b, 0x01, // Physical Maximum (315)
+    0x65, 0x14, //   Unit (Degrees)
+    0x75, 0x04, //   Report Size (4)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x42, //   Input (Data, Variable, Absolute, Null State)
+    0x45, 0x00, //   Physical Maximum (0), back to the logical range
+    0x65, 0x00, //   Unit (None)
+    0x81, 0x03, //   Input (Constant), padding
+    0x09, 0x30, //   Usage (X)
+    0x09, 0x31, //   Usage (Y)
+    0x09, 0x33, //   Usage (Rx)
+    0x09, 0x34, //   Usage (Ry)
+    0x16, 0x01, 0x80, // Logical Minimum (-32767)
+    0x26, 0xff, 0x7f, // Logical Maximum (32767)
+    0x75, 0x10, //   Report Size (16)
+    0x95, 0x04, //   Report Count (4)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x09, 0x32, //   Usage (Z)
+    0x09, 0x35, //   Usage (Rz)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x95, 0x02, //   Report Count (2)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0xc0,       // End Collection
+];
+
+pub const REPORT_LENGTH: usize = 15;
+
+// In report order, the D-Pad goes into the hat switch instead
+const BUTTONS: [ButtonId; 13] = [
+    ButtonId::South, ButtonId::East, ButtonId::North, ButtonId::West,
+    ButtonId::LeftBumper, ButtonId::RightBumper, ButtonId::LeftTrigger, ButtonId::RightTrigger,
+    ButtonId::Select, ButtonId::Start, ButtonId::Guide, ButtonId::LeftStick, ButtonId::RightStick,
+];
+
+// The same state always encodes to the same bytes, so callers can skip unchanged reports
+pub fn encode_report(state: &ControllerState) -> [u8; REPORT_LENGTH] {
+    let mut report = [0u8; REPORT_LENGTH];
+    let buttons = BUTTONS.iter()
+        .enumerate()
+        .filter(|(_, &button)| state.button(button))
+        .fold(0u16, |bits, (index, _)| bits | 1 << index);
+    report[0..2].copy_from_slice(&buttons.to_le_bytes());
+    report[2] = hat(state);
+
+    // HID counts Y downwards, our sticks count it upwards
+    let stick = |axis, sign: f32| ((state.axis(axis) * sign).clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+    let trigger = |axis| (state.axis(axis).clamp(0.0, 1.0) * i16::MAX as f32) as i16;
+    let axes = [
+        stick(AxisId::LeftStickX, 1.0),
+        stick(AxisId::LeftStickY, -1.0),
+        stick(AxisId::RightStickX, 1.0),
+        stick(AxisId::RightStickY, -1.0),
+        trigger(AxisId::LeftTrigger),
+        trigger(AxisId::RightTrigger),
+    ];
+    for (index, value) in axes.iter().enumerate() {
+        report[3 + index * 2..5 + index * 2].copy_from_slice(&value.to_le_bytes());
+    }
+    report
+}
+
+// 0 is up, counting clockwise in steps of 45 degrees, 8 is outside the logical range and means centered.
+// Pads that report the D-Pad as axes count too.
+fn hat(state: &ControllerState) -> u8 {
+    let x = state.button(ButtonId::DPadRight) as i8 - state.button(ButtonId::DPadLeft) as i8
+        + state.axis(AxisId::DPadX).round() as i8;
+    let y = state.button(ButtonId::DPadUp) as i8 - state.button(ButtonId::DPadDown) as i8
+        + state.axis(AxisId::DPadY).round() as i8;
+    match (x.signum(), y.signum()) {
+        (0, 1) => 0,
+        (1, 1) => 1,
+        (1, 0) => 2,
+        (1, -1) => 3,
+        (0, -1) => 4,
+        (-1, -1) => 5,
+        (-1, 0) => 6,
+        (-1, 1) => 7,
+        _ => 8,
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// The Deck's built-in controller, its gamepad interface is the one with a vendor-defined usage page
+pub const STEAM_DECK: (u16, u16) = (0x28de, 0x1205);
+// The Deck sends 64 byte reports, anything longer is cut off
+pub const MAX_REPORT_SIZE: usize = 64;
+// Reports waiting to be sent, newer ones are dropped while the queue is full
+#[cfg(target_os = "linux")]
+const REPORT_QUEUE_CAPACITY: usize = 256;
+
+/// Sent once per connection before any report, so the server knows what the binary frames
+/// contain and can recreate the device with the same report descriptor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HidDevice {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub name: String,
+    pub report_descriptor: Vec<u8>,
+}
+
+// None tells the server passthrough was switched off
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HidDeviceAnnouncement {
+    pub hid_device: Option<HidDevice>,
+}
+
+// One hidraw node as the kernel describes it in sysfs
+#[derive(Debug, Clone)]
+pub struct HidrawNode {
+    pub path: PathBuf,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub name: String,
+}
+
+// Raw reports go out as binary frames: the client's timestamp in milliseconds, little endian,
+// then the report exactly as it was read
+pub fn encode_report(timestamp: u64, report: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(8 + report.len());
+    frame.extend_from_slice(&timestamp.to_le_bytes());
+    frame.extend_from_slice(&report[..report.len().min(MAX_REPORT_SIZE)]);
+    frame
+}
+
+// One of the Deck's trackpads in a raw report, coordinates grow right and up
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeckTouchpad {
+    pub touched: bool,
+    pub x: i16,
+    pub y: i16,
+}
+
+// The left and right trackpad, None for anything but the Deck's controller state report.
+// Offsets as in SDL's SteamDeckStatePacket_t after the 4 byte header.
+pub fn deck_touchpads(report: &[u8]) -> Option<[DeckTouchpad; 2]> {
+    if report.len() < 24 || report[0] != 0x01 || report[2] != 0x09 {
+        return None;
+    }
+    let i16_at = |offset: usize| i16::from_le_bytes([report[offset], report[offset + 1]]);
+    Some([
+        DeckTouchpad {
+            touched: report[10] & 0x08 != 0,
+            x: i16_at(16),
+            y: i16_at(18),
+        },
+        DeckTouchpad {
+            touched: report[10] & 0x10 != 0,
+            x: i16_at(20),
+            y: i16_at(22),
+        },
+    ])
+}
+
+// Every hidraw node with its vendor and product, from HID_ID=<bus>:<vendor>:<product> in its uevent
+#[cfg(target_os = "linux")]
+pub fn hidraw_nodes() -> Vec<HidrawNode> {
+    let Ok(entries) = std::fs::read_dir("/sys/class/hidraw") else {
+        return Vec::new();
+    };
+    entries.flatten()
+        .filter_map(|entry| {
+            let uevent = std::fs::read_to_string(entry.path().join("device/uevent")).ok()?;
+            let id = uevent.lines().find_map(|line| line.strip_prefix("HID_ID="))?;
+            let mut parts = id.split(':').skip(1).map(|part| u32::from_str_radix(part, 16).ok());
+            let (vendor_id, product_id) = (parts.next()?? as u16, parts.next()?? as u16);
+            let name = uevent.lines().find_map(|line| line.strip_prefix("HID_NAME=")).unwrap_or("").to_string();
+            Some(HidrawNode {
+                path: PathBuf::from("/dev").join(entry.file_name()),
+                vendor_id,
+                product_id,
+                name,
+            })
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn hidraw_nodes() -> Vec<HidrawNode> {
+    Vec::new()
+}
+
+/// Reads the Deck's raw input reports on a thread of its own, for forwarding what gilrs can't
+/// see such as touchpad pressure
+pub struct HidReader {
+    device: HidDevice,
+    reports: std::sync::mpsc::Receiver<(u64, Vec<u8>)>,
+}
+
+impl HidReader {
+    #[cfg(target_os = "linux")]
+    pub fn open() -> Result<Self> {
+        use anyhow::Context;
+        use std::io::Read;
+
+        let (node, report_descriptor) = hidraw_nodes().into_iter()
+            .filter(|node| (node.vendor_id, node.product_id) == STEAM_DECK)
+            .find_map(|node| {
+                let name = node.path.file_name()?.to_owned();
+                let descriptor = std::fs::read(PathBuf::from("/sys/class/hidraw").join(name).join("device/report_descriptor")).ok()?;
+                is_vendor_defined(&descriptor).then_some((node, descriptor))
+            })
+            .ok_or_else(|| anyhow::anyhow!("No Steam Deck controller found under /sys/class/hidraw"))?;
+        // Steam's udev rules make the Deck's nodes readable by the logged in user
+        let mut file = std::fs::File::open(&node.path)
+            .with_context(|| format!("Failed to open {}", node.path.display()))?;
+        log::info!("Reading raw HID reports from {} ({})", node.path.display(), node.name);
+
+        let (sender, reports) = std::sync::mpsc::sync_channel(REPORT_QUEUE_CAPACITY);
+        std::thread::Builder::new()
+            .name("hidraw".to_string())
+            .spawn(move || {
+                let mut buffer = [0u8; MAX_REPORT_SIZE];
+                loop {
+                    let length = match file.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(length) => length,
+                        Err(e) => {
+                            log::error!("Failed to read HID report: {}", e);
+                            break;
+                        }
+                    };
+                    let report = (crate::network::get_current_timestamp(), buffer[..length].to_vec());
+                    if let Err(std::sync::mpsc::TrySendError::Disconnected(_)) = sender.try_send(report) {
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            device: HidDevice {
+                vendor_id: node.vendor_id,
+                product_id: node.product_id,
+                name: node.name,
+                report_descriptor,
+            },
+            reports,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn open() -> Result<Self> {
+        anyhow::bail!("Raw HID passthrough needs hidraw, which is only available on Linux")
+    }
+
+    pub fn device(&self) -> &HidDevice {
+        &self.device
+    }
+
+    // The client timestamp and bytes of the oldest report not taken yet
+    pub fn next_report(&self) -> Option<(u64, Vec<u8>)> {
+        self.reports.try_recv().ok()
+    }
+}
+
+// The gamepad interface starts with Usage Page (Vendor Defined 0xFFxx), the Deck's keyboard
+// and mouse interfaces use the standard pages
+#[cfg(target_os = "linux")]
+fn is_vendor_defined(descriptor: &[u8]) -> bool {
+    matches!(descriptor, [0x06, _, 0xff, ..])
+}
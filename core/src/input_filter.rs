@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use crate::input_id::{AxisId, ButtonId};
+use crate::network::ControllerInputData;
+
+pub const DEFAULT_AXIS_EPSILON: f32 = 0.01;
+
+/// Remembers what was last sent so repeated or negligible input never hits the network
+pub struct InputFilter {
+    pub axis_epsilon: f32,
+    buttons: HashMap<(u32, ButtonId), bool>,
+    axes: HashMap<(u32, AxisId), f32>,
+}
+
+impl InputFilter {
+    pub fn new(axis_epsilon: f32) -> Self {
+        Self {
+            axis_epsilon,
+            buttons: HashMap::new(),
+            axes: HashMap::new(),
+        }
+    }
+
+    // Keeps only button transitions and axis moves larger than axis_epsilon. Values within
+    // axis_epsilon of center are sent as exactly 0.
+    pub fn filter(&mut self, data: &mut ControllerInputData) {
+        let controller_id = data.controller_id;
+
+        data.button_events.retain(|event| {
+            self.buttons.insert((controller_id, event.button), event.pressed) != Some(event.pressed)
+        });
+
+        let axis_epsilon = self.axis_epsilon;
+        data.axis_events.retain_mut(|event| {
+            // A stick rarely settles on exactly 0, and the last sample on the way back can be
+            // too close to the previous one to pass, so the whole band counts as center
+            if event.value.abs() <= axis_epsilon {
+                event.value = 0.0;
+            }
+            let last = self.axes.get(&(controller_id, event.axis)).copied();
+            let changed = match last {
+                None => true,
+                // Always let the rest position through, or a released stick could stay slightly off center
+                Some(last) => (event.value - last).abs() > axis_epsilon || (event.value == 0.0 && last != 0.0),
+            };
+            if changed {
+                self.axes.insert((controller_id, event.axis), event.value);
+            }
+            changed
+        });
+    }
+
+    // After a reconnect the server knows nothing, so everything has to be sent again
+    pub fn reset(&mut self) {
+        self.buttons.clear();
+        self.axes.clear();
+    }
+}
@@ -0,0 +1,140 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// The discriminants are the wire format shared by the Deck client and the server, never renumber them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum ButtonId {
+    South = 0,
+    East = 1,
+    North = 2,
+    West = 3,
+    LeftBumper = 4,
+    RightBumper = 5,
+    LeftTrigger = 6,
+    RightTrigger = 7,
+    Select = 8,
+    Start = 9,
+    Guide = 10,
+    LeftStick = 11,
+    RightStick = 12,
+    DPadUp = 13,
+    DPadDown = 14,
+    DPadLeft = 15,
+    DPadRight = 16,
+}
+
+impl ButtonId {
+    pub const ALL: [ButtonId; 17] = [
+        ButtonId::South, ButtonId::East, ButtonId::North, ButtonId::West,
+        ButtonId::LeftBumper, ButtonId::RightBumper, ButtonId::LeftTrigger, ButtonId::RightTrigger,
+        ButtonId::Select, ButtonId::Start, ButtonId::Guide,
+        ButtonId::LeftStick, ButtonId::RightStick,
+        ButtonId::DPadUp, ButtonId::DPadDown, ButtonId::DPadLeft, ButtonId::DPadRight,
+    ];
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ButtonId::South => "A (South)",
+            ButtonId::East => "B (East)",
+            ButtonId::North => "Y (North)",
+            ButtonId::West => "X (West)",
+            ButtonId::LeftBumper => "LB",
+            ButtonId::RightBumper => "RB",
+            ButtonId::LeftTrigger => "LT",
+            ButtonId::RightTrigger => "RT",
+            ButtonId::Select => "Select",
+            ButtonId::Start => "Start",
+            ButtonId::Guide => "Guide",
+            ButtonId::LeftStick => "LSB",
+            ButtonId::RightStick => "RSB",
+            ButtonId::DPadUp => "D-Pad Up",
+            ButtonId::DPadDown => "D-Pad Down",
+            ButtonId::DPadLeft => "D-Pad Left",
+            ButtonId::DPadRight => "D-Pad Right",
+        }
+    }
+}
+
+/// The discriminants are the wire format shared by the Deck client and the server, never renumber them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum AxisId {
+    LeftStickX = 0,
+    LeftStickY = 1,
+    RightStickX = 2,
+    RightStickY = 3,
+    LeftTrigger = 4,
+    RightTrigger = 5,
+    DPadX = 6,
+    DPadY = 7,
+}
+
+impl AxisId {
+    pub const ALL: [AxisId; 8] = [
+        AxisId::LeftStickX, AxisId::LeftStickY,
+        AxisId::RightStickX, AxisId::RightStickY,
+        AxisId::LeftTrigger, AxisId::RightTrigger,
+        AxisId::DPadX, AxisId::DPadY,
+    ];
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AxisId::LeftStickX => "Left Stick X",
+            AxisId::LeftStickY => "Left Stick Y",
+            AxisId::RightStickX => "Right Stick X",
+            AxisId::RightStickY => "Right Stick Y",
+            AxisId::LeftTrigger => "Left Trigger",
+            AxisId::RightTrigger => "Right Trigger",
+            AxisId::DPadX => "D-Pad X",
+            AxisId::DPadY => "D-Pad Y",
+        }
+    }
+}
+
+impl fmt::Display for ButtonId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Display for AxisId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for ButtonId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+impl<'de> Deserialize<'de> for ButtonId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u8::deserialize(deserializer)?;
+        ButtonId::from_u8(value).ok_or_else(|| D::Error::custom(format!("unknown button id {}", value)))
+    }
+}
+
+impl Serialize for AxisId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+impl<'de> Deserialize<'de> for AxisId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u8::deserialize(deserializer)?;
+        AxisId::from_u8(value).ok_or_else(|| D::Error::custom(format!("unknown axis id {}", value)))
+    }
+}
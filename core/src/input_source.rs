@@ -0,0 +1,244 @@
+use anyhow::Result;
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat, Replay, Ticks};
+use gilrs::{Axis, Button, EventType, Gamepad, Gilrs, PowerInfo};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime};
+use crate::capabilities::Capabilities;
+use crate::haptics::{HapticPattern, STEP_MS};
+
+/// The subset of gilrs events the app acts on, without the platform-specific codes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Connected,
+    Disconnected,
+    ButtonPressed(Button),
+    ButtonReleased(Button),
+    ButtonChanged(Button, f32),
+    AxisChanged(Axis, f32),
+}
+
+impl InputEvent {
+    pub fn from_gilrs(event: EventType) -> Option<Self> {
+        match event {
+            EventType::Connected => Some(Self::Connected),
+            EventType::Disconnected => Some(Self::Disconnected),
+            EventType::ButtonPressed(button, _) => Some(Self::ButtonPressed(button)),
+            EventType::ButtonReleased(button, _) => Some(Self::ButtonReleased(button)),
+            EventType::ButtonChanged(button, value, _) => Some(Self::ButtonChanged(button, value)),
+            EventType::AxisChanged(axis, value, _) => Some(Self::AxisChanged(axis, value)),
+            _ => None,
+        }
+    }
+}
+
+/// An [`InputEvent`] from one controller, as [`InputSource::next_event`] hands them out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourceEvent {
+    pub controller_id: u32,
+    pub event: InputEvent,
+    pub time: SystemTime,
+}
+
+/// A connected controller as its [`InputSource`] sees it, from [`InputSource::controllers`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControllerInfo {
+    pub controller_id: u32,
+    pub name: String,
+    pub uuid: [u8; 16],
+    // USB IDs as reported by the device, None where the platform doesn't expose them
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+    pub capabilities: Capabilities,
+    // Set for the Deck's own controls, None for every other controller
+    pub deck_model: Option<DeckModel>,
+}
+
+// The board's name as the firmware reports it, Valve names them after moons
+const DMI_PRODUCT_NAME: &str = "/sys/class/dmi/id/product_name";
+// The Deck's controls as a USB device, and as Steam Input re-creates them
+const DECK_CONTROLS: [(u16, u16); 2] = [(0x28DE, 0x1205), (0x28DE, 0x11FF)];
+
+/// Which Deck the controls belong to, see [`DeckModel::detect`] for the one the app runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeckModel {
+    // Jupiter
+    Lcd,
+    // Galileo, with finer haptics than the LCD model
+    Oled,
+}
+
+impl DeckModel {
+    pub fn from_product_name(product_name: &str) -> Option<Self> {
+        match product_name.trim() {
+            "Jupiter" => Some(DeckModel::Lcd),
+            "Galileo" => Some(DeckModel::Oled),
+            _ => None,
+        }
+    }
+
+    // The Deck this runs on, None on any other machine. Read once, the hardware won't change.
+    pub fn detect() -> Option<Self> {
+        static DETECTED: OnceLock<Option<DeckModel>> = OnceLock::new();
+        *DETECTED.get_or_init(|| {
+            std::fs::read_to_string(DMI_PRODUCT_NAME).ok().and_then(|name| Self::from_product_name(&name))
+        })
+    }
+
+    // On a Deck a Steam virtual gamepad is almost always its own controls, Steam Input wraps them
+    // in one while a game runs
+    pub fn of_controller(vendor_id: Option<u16>, product_id: Option<u16>) -> Option<Self> {
+        let usb_ids = vendor_id.zip(product_id)?;
+        DECK_CONTROLS.contains(&usb_ids).then(Self::detect).flatten()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DeckModel::Lcd => "Steam Deck LCD",
+            DeckModel::Oled => "Steam Deck OLED",
+        }
+    }
+}
+
+/// Charge of a battery powered controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Battery {
+    pub percent: u8,
+    pub charging: bool,
+}
+
+// The same threshold the server warns at
+pub const LOW_BATTERY_PERCENT: u8 = 20;
+
+impl Battery {
+    pub fn is_low(&self) -> bool {
+        !self.charging && self.percent <= LOW_BATTERY_PERCENT
+    }
+}
+
+/// Where controller input comes from: gilrs on the Deck, a script in headless tests
+pub trait InputSource {
+    fn next_event(&mut self) -> Option<SourceEvent>;
+    fn controllers(&self) -> Vec<ControllerInfo>;
+    fn is_pressed(&self, controller_id: u32, button: Button) -> bool;
+    fn value(&self, controller_id: u32, axis: Axis) -> f32;
+
+    // None for wired controllers and wherever the platform can't tell
+    fn battery(&self, _controller_id: u32) -> Option<Battery> {
+        None
+    }
+
+    fn controller_info(&self, controller_id: u32) -> Option<ControllerInfo> {
+        self.controllers().into_iter().find(|info| info.controller_id == controller_id)
+    }
+
+    // Plays the pattern on the controller's rumble motors and returns right away
+    fn play_haptics(&mut self, _controller_id: u32, _pattern: &HapticPattern) -> Result<()> {
+        anyhow::bail!("This input source has no force feedback")
+    }
+}
+
+/// Real controllers through gilrs, with rumble played as force feedback in 50 ms steps
+pub struct GilrsSource {
+    gilrs: Gilrs,
+    // gilrs stops an effect once its last handle is dropped, so they're kept until they ended
+    playing: Vec<(Effect, Instant)>,
+}
+
+impl GilrsSource {
+    pub fn new() -> Result<Self> {
+        let gilrs = Gilrs::new().map_err(|e| anyhow::anyhow!("Failed to initialize gilrs: {}", e))?;
+        Ok(Self {
+            gilrs,
+            playing: Vec::new(),
+        })
+    }
+
+    fn gamepad(&self, controller_id: u32) -> Option<Gamepad<'_>> {
+        self.gilrs.gamepads()
+            .find(|(id, _)| usize::from(*id) as u32 == controller_id)
+            .map(|(_, gamepad)| gamepad)
+    }
+}
+
+impl InputSource for GilrsSource {
+    fn next_event(&mut self) -> Option<SourceEvent> {
+        // Events the app has no use for are skipped rather than ending the poll early
+        while let Some(gilrs::Event { id, event, time }) = self.gilrs.next_event() {
+            if let Some(event) = InputEvent::from_gilrs(event) {
+                return Some(SourceEvent {
+                    controller_id: usize::from(id) as u32,
+                    event,
+                    time,
+                });
+            }
+        }
+        None
+    }
+
+    fn controllers(&self) -> Vec<ControllerInfo> {
+        self.gilrs.gamepads()
+            .map(|(id, gamepad)| ControllerInfo {
+                controller_id: usize::from(id) as u32,
+                name: gamepad.name().to_string(),
+                uuid: gamepad.uuid(),
+                vendor_id: gamepad.vendor_id(),
+                product_id: gamepad.product_id(),
+                capabilities: Capabilities::probe(&gamepad),
+                deck_model: DeckModel::of_controller(gamepad.vendor_id(), gamepad.product_id()),
+            })
+            .collect()
+    }
+
+    fn is_pressed(&self, controller_id: u32, button: Button) -> bool {
+        self.gamepad(controller_id).is_some_and(|gamepad| gamepad.is_pressed(button))
+    }
+
+    fn value(&self, controller_id: u32, axis: Axis) -> f32 {
+        self.gamepad(controller_id).map_or(0.0, |gamepad| gamepad.value(axis))
+    }
+
+    fn battery(&self, controller_id: u32) -> Option<Battery> {
+        match self.gamepad(controller_id)?.power_info() {
+            PowerInfo::Discharging(percent) => Some(Battery { percent, charging: false }),
+            PowerInfo::Charging(percent) => Some(Battery { percent, charging: true }),
+            PowerInfo::Unknown | PowerInfo::Wired | PowerInfo::Charged => None,
+        }
+    }
+
+    fn play_haptics(&mut self, controller_id: u32, pattern: &HapticPattern) -> Result<()> {
+        let now = Instant::now();
+        self.playing.retain(|(_, ends)| *ends > now);
+
+        let Some(id) = self.gamepad(controller_id).map(|gamepad| gamepad.id()) else {
+            anyhow::bail!("Controller {} isn't connected", controller_id);
+        };
+        let duration = pattern.duration_ms();
+        if duration == 0 {
+            return Ok(());
+        }
+
+        // One base effect per motor and step. The Deck's driver, like XInput, puts the strong
+        // motor on the left and the weak one on the right.
+        let mut builder = EffectBuilder::new();
+        for (start, left, right) in pattern.steps() {
+            let scheduling = Replay {
+                after: Ticks::from_ms(start),
+                play_for: Ticks::from_ms(STEP_MS),
+                // Only repeats after the whole pattern, which Repeat::For has ended by then
+                with_delay: Ticks::from_ms(duration),
+            };
+            let magnitude = |strength: f32| (strength * u16::MAX as f32) as u16;
+            for kind in [BaseEffectType::Strong { magnitude: magnitude(left) }, BaseEffectType::Weak { magnitude: magnitude(right) }] {
+                builder.add_effect(BaseEffect { kind, scheduling, ..Default::default() });
+            }
+        }
+        let effect = builder.gamepads(&[id])
+            .repeat(Repeat::For(Ticks::from_ms(duration)))
+            .finish(&mut self.gilrs)
+            .map_err(|e| anyhow::anyhow!("Failed to create haptic effect: {}", e))?;
+        effect.play().map_err(|e| anyhow::anyhow!("Failed to play haptic effect: {}", e))?;
+        self.playing.push((effect, now + Duration::from_millis(duration as u64)));
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+// Controller input and streaming for the Steam Deck client, without the imgui app around it.
+// Read events from an InputSource, turn them into ControllerInputData with push_network_event
+// and hand them to a NetworkStreamer, which keeps the connection to the server on its own task.
+// See README.md for a complete example.
+#![doc = include_str!("../README.md")]
+
+pub mod bluetooth_hid;
+pub mod capabilities;
+pub mod delivery;
+pub mod haptics;
+pub mod hid_gamepad;
+pub mod hid_passthrough;
+pub mod input_filter;
+pub mod input_id;
+pub mod input_source;
+pub mod link_quality;
+pub mod network;
+pub mod network_sim;
+pub mod uinput_pad;
+pub mod usb_gadget;
+
+pub use bluetooth_hid::BluetoothGamepad;
+pub use capabilities::Capabilities;
+pub use delivery::DeliveryStats;
+pub use haptics::{HapticPattern, HapticPoint};
+pub use hid_passthrough::{HidDevice, HidReader};
+pub use input_filter::InputFilter;
+pub use input_id::{AxisId, ButtonId};
+pub use input_source::{Battery, ControllerInfo, DeckModel, GilrsSource, InputEvent, InputSource, SourceEvent};
+pub use link_quality::{LinkMonitor, LinkQuality};
+pub use network::{
+    discover_servers, get_current_timestamp, probe_server, push_network_event, AxisEvent, ButtonEvent,
+    BatteryReport, CapabilityReport, ControllerInputData, DiscoveryEvent, NetworkStatus, NetworkStreamer, ProfileList, ProfileRequest,
+};
+pub use network_sim::NetworkConditions;
+pub use uinput_pad::UinputPad;
+pub use usb_gadget::UsbGadget;
@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// The score only looks this far back, so it recovers soon after a bad patch
+const WINDOW: Duration = Duration::from_secs(5);
+// More messages a second than most games need, each one costs airtime on Wi-Fi
+const BUSY_MESSAGE_RATE: f64 = 400.0;
+// Anything that costs less than this many points isn't worth a hint
+const HINT_PENALTY: f64 = 5.0;
+
+// What the link looked like over the last few seconds
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkSample {
+    pub rtt_ms: f64,
+    // Average change from one round trip to the next
+    pub jitter_ms: f64,
+    // Share of messages that went missing or had to be sent again, 0 to 1
+    pub loss: f64,
+    pub messages_per_sec: f64,
+}
+
+/// 0 to 100, with what to do about whatever drags it down, the biggest drag first
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkQuality {
+    pub score: u8,
+    pub sample: LinkSample,
+    pub hints: Vec<&'static str>,
+}
+
+impl LinkQuality {
+    pub fn assess(sample: LinkSample) -> Self {
+        let rtt_penalty = ((sample.rtt_ms - 10.0) * 0.5).clamp(0.0, 40.0);
+        let jitter_penalty = ((sample.jitter_ms - 2.0) * 2.0).clamp(0.0, 30.0);
+        let loss_penalty = (sample.loss * 500.0).clamp(0.0, 40.0);
+        let rate_penalty = ((sample.messages_per_sec - BUSY_MESSAGE_RATE) / 20.0).clamp(0.0, 15.0);
+        let score = 100.0 - rtt_penalty - jitter_penalty - loss_penalty - rate_penalty;
+
+        let mut hints = vec![
+            (rtt_penalty, "Switch the Deck and the PC to 5 GHz Wi-Fi, or move closer to the router"),
+            (jitter_penalty, "Pause downloads and streams on the same network, they make delays uneven"),
+            (loss_penalty, "Messages are getting lost, try a less crowded Wi-Fi channel or connect the PC by cable"),
+        ];
+        // A busy link makes the rest worse, sending less is the fix then. On its own it's only
+        // wasted airtime.
+        if rate_penalty > 0.0 && (jitter_penalty >= HINT_PENALTY || loss_penalty >= HINT_PENALTY) {
+            hints.push((rate_penalty.max(HINT_PENALTY), "Reduce the send rate, 125 Hz is plenty for most games"));
+        } else {
+            hints.push((rate_penalty, "Raise the Axis Deadband so tiny stick movements aren't sent"));
+        }
+        hints.retain(|(penalty, _)| *penalty >= HINT_PENALTY);
+        hints.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        Self {
+            score: score.clamp(0.0, 100.0).round() as u8,
+            sample,
+            hints: hints.into_iter().map(|(_, hint)| hint).collect(),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self.score {
+            85.. => "Excellent",
+            65.. => "Good",
+            40.. => "Fair",
+            _ => "Poor",
+        }
+    }
+}
+
+/// Collects round trips and message counts as they come in, for a LinkQuality over the last few
+/// seconds
+#[derive(Default)]
+pub struct LinkMonitor {
+    round_trips: VecDeque<(Instant, f64)>,
+    // Delivered and lost, per report
+    messages: VecDeque<(Instant, u64, u64)>,
+}
+
+impl LinkMonitor {
+    pub fn new() -> Self {
+        Self {
+            round_trips: VecDeque::new(),
+            messages: VecDeque::new(),
+        }
+    }
+
+    pub fn round_trip(&mut self, now: Instant, rtt_ms: f64) {
+        self.round_trips.push_back((now, rtt_ms));
+        self.prune(now);
+    }
+
+    // Counts since the last call
+    pub fn messages(&mut self, now: Instant, delivered: u64, lost: u64) {
+        self.messages.push_back((now, delivered, lost));
+        self.prune(now);
+    }
+
+    // A new connection starts from scratch
+    pub fn clear(&mut self) {
+        self.round_trips.clear();
+        self.messages.clear();
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while self.round_trips.front().is_some_and(|(at, _)| now.duration_since(*at) > WINDOW) {
+            self.round_trips.pop_front();
+        }
+        while self.messages.front().is_some_and(|(at, _, _)| now.duration_since(*at) > WINDOW) {
+            self.messages.pop_front();
+        }
+    }
+
+    // None without a round trip in the last few seconds, nothing was sent then
+    pub fn quality(&self, now: Instant) -> Option<LinkQuality> {
+        let recent = |at: &Instant| now.duration_since(*at) <= WINDOW;
+        let round_trips: Vec<f64> = self.round_trips.iter().filter(|(at, _)| recent(at)).map(|(_, ms)| *ms).collect();
+        if round_trips.is_empty() {
+            return None;
+        }
+        let rtt_ms = round_trips.iter().sum::<f64>() / round_trips.len() as f64;
+        let jitter_ms = if round_trips.len() > 1 {
+            round_trips.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum::<f64>() / (round_trips.len() - 1) as f64
+        } else {
+            0.0
+        };
+
+        let messages: Vec<&(Instant, u64, u64)> = self.messages.iter().filter(|(at, _, _)| recent(at)).collect();
+        let delivered: u64 = messages.iter().map(|(_, delivered, _)| delivered).sum();
+        let lost: u64 = messages.iter().map(|(_, _, lost)| lost).sum();
+        let loss = if delivered + lost > 0 { lost as f64 / (delivered + lost) as f64 } else { 0.0 };
+        // Right after connecting there's less than a full window to go by
+        let span = messages.first().map_or(Duration::ZERO, |(at, _, _)| now.duration_since(*at));
+        let messages_per_sec = delivered as f64 / span.max(Duration::from_secs(1)).as_secs_f64();
+
+        Some(LinkQuality::assess(LinkSample {
+            rtt_ms,
+            jitter_ms,
+            loss,
+            messages_per_sec,
+        }))
+    }
+}
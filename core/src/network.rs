@@ -0,0 +1,1053 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use gilrs::{Button, Axis};
+use std::time::{Duration, Instant};
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use futures_util::{SinkExt, StreamExt};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{WebSocketStream, MaybeTlsStream};
+use crate::capabilities::Capabilities;
+use crate::delivery::{DeliveryStats, DeliveryTracker};
+use crate::hid_passthrough::{encode_report, HidDevice, HidDeviceAnnouncement};
+use crate::input_id::{AxisId, ButtonId};
+use crate::input_source::{Battery, ControllerInfo, InputEvent};
+use crate::network_sim::{NetworkConditions, NetworkSimulator};
+
+/// One message of input for the server, serialized as JSON. Stamp it with
+/// [`get_current_timestamp`] and fill it with [`push_network_event`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControllerInputData {
+    // Assigned by the client when sending, lets client and server logs be matched up
+    #[serde(default)]
+    pub message_id: u64,
+    pub timestamp: u64,
+    // When the message actually went out, after any queueing or simulated delay. 0 from older clients.
+    #[serde(default)]
+    pub sent_at: u64,
+    pub controller_id: u32,
+    pub button_events: Vec<ButtonEvent>,
+    pub axis_events: Vec<AxisEvent>,
+}
+
+/// A button going down or up, as part of a [`ControllerInputData`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ButtonEvent {
+    pub button: ButtonId,
+    pub pressed: bool,
+    pub timestamp: u64,
+}
+
+/// A stick or trigger moving, as part of a [`ControllerInputData`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AxisEvent {
+    pub axis: AxisId,
+    pub value: f32,
+    pub timestamp: u64,
+}
+
+/// Advertised by the server when the client connects and again whenever its mapping profiles change
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProfileList {
+    pub profiles: Vec<String>,
+    pub active_profile: String,
+}
+
+/// Asks the server to switch to one of the profiles it advertised
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileRequest {
+    pub select_profile: String,
+}
+
+// The server's clock probe, echoed back with our own clock so it can work out the offset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockProbe {
+    pub clock_probe: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockReply {
+    pub clock_probe: u64,
+    pub client_time: u64,
+}
+
+// A controller attached to the server's PC, streamed to us while the server's local controller
+// is set to go to the Deck
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PcInput {
+    pub pc_input: ControllerInputData,
+}
+
+// Whether the server's virtual pad takes input
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PadHealth {
+    #[default]
+    Working,
+    // The PC lost the pad and the server is plugging it back in, with the error it hit
+    Failed(String),
+}
+
+// Sent by the server when its virtual pad fails and again once it's back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PadHealthReport {
+    pub pad_health: PadHealth,
+}
+
+// What this client's input ends up on at the PC
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PadStatus {
+    // No virtual pad plugged in, the input only shows up in the server's window
+    #[default]
+    Unplugged,
+    // player is 1-based, None for pads without an XInput slot
+    Active { pad: String, player: Option<u32> },
+    // The server has forwarding to its virtual controller switched off
+    Paused,
+    // Paired with no pad on purpose
+    Spectator,
+}
+
+// Sent by the server on connect and whenever this client's pad is plugged in or out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PadStatusReport {
+    pub pad_status: PadStatus,
+}
+
+// The highest message ID the server has applied, sent a few times a second while input arrives
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ack {
+    pub ack: u64,
+    // How long the server had applied it before sending this
+    pub held_us: u64,
+}
+
+/// What one of the client's controllers can produce, sent on connect so the server can grey out
+/// controls it will never see
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CapabilityReport {
+    pub controller_id: u32,
+    pub capabilities: Capabilities,
+    // USB IDs, so the server can tell when the same controller also shows up on the PC
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+}
+
+/// Charge of one of the client's battery powered controllers, sent when it changes so the server
+/// can warn before the controller dies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatteryReport {
+    pub controller_id: u32,
+    pub battery_percent: u8,
+    pub charging: bool,
+}
+
+// Messages queued beyond this aren't queued on their own, the tracked state goes out in their
+// place once the link catches up, see send_controller_data
+const SEND_QUEUE_CAPACITY: usize = 64;
+
+// Rates offered for fixed-rate transmission
+pub const SEND_RATES: [u32; 3] = [125, 250, 500];
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+// A connection attempt, polled by the connection manager's select so commands don't wait for it
+type PendingConnection = std::pin::Pin<Box<dyn std::future::Future<Output = Result<WsStream, tokio_tungstenite::tungstenite::Error>> + Send>>;
+
+// Latest known state of one controller, streamed as a whole in fixed-rate mode
+#[derive(Debug, Clone, Default)]
+pub struct ControllerState {
+    buttons: HashMap<ButtonId, bool>,
+    axes: HashMap<AxisId, f32>,
+}
+
+impl ControllerState {
+    pub fn apply(&mut self, data: &ControllerInputData) {
+        for button_event in &data.button_events {
+            self.buttons.insert(button_event.button, button_event.pressed);
+        }
+        for axis_event in &data.axis_events {
+            self.axes.insert(axis_event.axis, axis_event.value);
+        }
+    }
+
+    // Released until the first event for it arrives
+    pub fn button(&self, button: ButtonId) -> bool {
+        self.buttons.get(&button).copied().unwrap_or(false)
+    }
+
+    pub fn axis(&self, axis: AxisId) -> f32 {
+        self.axes.get(&axis).copied().unwrap_or(0.0)
+    }
+
+    // Releases whatever is held and centers whatever is deflected, leaving the rest out
+    pub fn release_events(&self, controller_id: u32) -> ControllerInputData {
+        let timestamp = get_current_timestamp();
+
+        ControllerInputData {
+            message_id: 0,
+            timestamp,
+            sent_at: 0,
+            controller_id,
+            button_events: self.buttons.iter()
+                .filter(|(_, &pressed)| pressed)
+                .map(|(&button, _)| ButtonEvent { button, pressed: false, timestamp })
+                .collect(),
+            axis_events: self.axes.iter()
+                .filter(|(_, &value)| value != 0.0)
+                .map(|(&axis, _)| AxisEvent { axis, value: 0.0, timestamp })
+                .collect(),
+        }
+    }
+
+    pub fn to_input_data(&self, controller_id: u32) -> ControllerInputData {
+        let timestamp = get_current_timestamp();
+
+        ControllerInputData {
+            message_id: 0,
+            timestamp,
+            sent_at: 0,
+            controller_id,
+            button_events: self.buttons.iter()
+                .map(|(&button, &pressed)| ButtonEvent { button, pressed, timestamp })
+                .collect(),
+            axis_events: self.axes.iter()
+                .map(|(&axis, &value)| AxisEvent { axis, value, timestamp })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct TrackedInput {
+    states: HashMap<u32, ControllerState>,
+    // Set when input didn't fit in the send queue, the task then sends the tracked state
+    resync: bool,
+}
+
+type SharedStates = Arc<Mutex<TrackedInput>>;
+
+#[derive(Debug, Clone)]
+enum NetworkCommand {
+    Connect(String, i32),
+    Disconnect,
+    SetSendRate(u32),
+    SetConditions(NetworkConditions),
+    SelectProfile(String),
+    ReportCapabilities(CapabilityReport),
+    ReportBattery(BatteryReport),
+    SetHidDevice(Option<HidDevice>),
+}
+
+/// Reported by the connection manager task so the UI reflects what actually happened
+#[derive(Debug, Clone)]
+pub enum NetworkStatus {
+    Connecting(String),
+    Connected(String),
+    ConnectionFailed(String, String),
+    ConnectionLost(String),
+    Disconnected,
+    Profiles(ProfileList),
+    // After each acknowledgement, servers that don't send them never produce this
+    Delivery(DeliveryStats),
+    // Input from the server's PC controller, see PcInput
+    PcInput(ControllerInputData),
+    // Repeated while the server keeps failing to plug its pad back in
+    PadHealth(PadHealth),
+    // Servers that don't send it leave the client guessing
+    PadStatus(PadStatus),
+}
+
+/// Handle to the connection manager task, which owns the WebSocket for the whole app lifetime.
+/// [`NetworkStreamer::new`] spawns that task, so it has to be created inside a tokio runtime.
+///
+/// [`connect`](Self::connect), [`disconnect`](Self::disconnect) and
+/// [`send_controller_data`](Self::send_controller_data) never block. Connection results come
+/// back as [`NetworkStatus`], either awaited with [`next_status`](Self::next_status) or polled
+/// each frame with [`poll_status`](Self::poll_status).
+///
+/// - The server advertises its mapping profiles as [`NetworkStatus::Profiles`];
+///   [`select_profile`](Self::select_profile) asks for one now and on every later connect.
+/// - [`report_capabilities`](Self::report_capabilities) tells the server which buttons, axes and
+///   sensors a controller has, from the [`Capabilities`] every [`ControllerInfo`] carries;
+///   [`report_controller`](Self::report_controller) adds the controller's USB IDs, which lets the
+///   server spot the same controller showing up on the PC as well.
+/// - [`report_battery`](Self::report_battery) forwards a wireless controller's charge from
+///   [`InputSource::battery`](crate::InputSource::battery), so the server can warn when it runs
+///   low; unchanged readings are not sent again.
+/// - [`send_queue_depth`](Self::send_queue_depth) and [`hid_queue_depth`](Self::hid_queue_depth)
+///   report how full the queues to the network task are. A queue that stays full means the app
+///   is producing faster than the connection drains it; input that doesn't fit goes out as the
+///   controller's whole state once it has drained.
+/// - Servers that acknowledge what they applied produce [`NetworkStatus::Delivery`] with
+///   [`DeliveryStats`]: messages still in flight, how many the acknowledgement covered and the
+///   last one's ID, acknowledgement latency and, on Linux, TCP retransmissions. Feed those into a
+///   [`LinkMonitor`](crate::LinkMonitor) for a [`LinkQuality`](crate::LinkQuality): a 0-100
+///   score from round trip, jitter, loss and message rate, with hints on what would improve it.
+/// - When the server's virtual pad stops taking input, e.g. after the PC slept,
+///   [`NetworkStatus::PadHealth`] reports [`PadHealth::Failed`] until the server has plugged it
+///   back in.
+/// - [`NetworkStatus::PadStatus`] says what the input ends up on: a [`PadStatus::Active`] pad
+///   with its player number, or why it reaches no games. It comes on connect and again whenever
+///   that changes.
+pub struct NetworkStreamer {
+    command_sender: mpsc::UnboundedSender<NetworkCommand>,
+    data_sender: mpsc::Sender<ControllerInputData>,
+    hid_sender: mpsc::Sender<Vec<u8>>,
+    status_receiver: mpsc::UnboundedReceiver<NetworkStatus>,
+    connected: Arc<AtomicBool>,
+    states: SharedStates,
+    send_rate_hz: u32,
+    next_message_id: Arc<AtomicU64>,
+}
+
+// Spawns the connection task like new(), so also only within the tokio runtime
+impl Default for NetworkStreamer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkStreamer {
+    // Must be called from within the tokio runtime
+    pub fn new() -> Self {
+        let (command_sender, command_receiver) = mpsc::unbounded_channel();
+        let (data_sender, data_receiver) = mpsc::channel(SEND_QUEUE_CAPACITY);
+        let (hid_sender, hid_receiver) = mpsc::channel(SEND_QUEUE_CAPACITY);
+        let (status_sender, status_receiver) = mpsc::unbounded_channel();
+        let connected = Arc::new(AtomicBool::new(false));
+        let states = SharedStates::default();
+        let next_message_id = Arc::new(AtomicU64::new(1));
+
+        tokio::spawn(connection_manager(
+            command_receiver,
+            data_receiver,
+            hid_receiver,
+            status_sender,
+            connected.clone(),
+            states.clone(),
+            next_message_id.clone(),
+        ));
+
+        Self {
+            command_sender,
+            data_sender,
+            hid_sender,
+            status_receiver,
+            connected,
+            states,
+            send_rate_hz: 0,
+            next_message_id,
+        }
+    }
+
+    // 0 goes back to sending on every change
+    pub fn set_send_rate(&mut self, send_rate_hz: u32) {
+        self.send_rate_hz = send_rate_hz;
+        let _ = self.command_sender.send(NetworkCommand::SetSendRate(send_rate_hz));
+    }
+
+    pub fn set_conditions(&self, conditions: NetworkConditions) {
+        let _ = self.command_sender.send(NetworkCommand::SetConditions(conditions));
+    }
+
+    pub fn connect(&self, server_ip: &str, port: i32) {
+        let _ = self.command_sender.send(NetworkCommand::Connect(server_ip.to_string(), port));
+    }
+
+    pub fn disconnect(&self) {
+        let _ = self.command_sender.send(NetworkCommand::Disconnect);
+    }
+
+    // Requested right away when connected and again on every later connect, empty leaves it to the server
+    pub fn select_profile(&self, name: &str) {
+        let _ = self.command_sender.send(NetworkCommand::SelectProfile(name.to_string()));
+    }
+
+    // Sent right away when connected and again on every later connect, replacing the last report
+    // for the same controller
+    pub fn report_capabilities(&self, controller_id: u32, capabilities: Capabilities) {
+        let report = CapabilityReport { controller_id, capabilities, vendor_id: None, product_id: None };
+        let _ = self.command_sender.send(NetworkCommand::ReportCapabilities(report));
+    }
+
+    // report_capabilities with the controller's USB IDs
+    pub fn report_controller(&self, info: &ControllerInfo) {
+        let report = CapabilityReport {
+            controller_id: info.controller_id,
+            capabilities: info.capabilities,
+            vendor_id: info.vendor_id,
+            product_id: info.product_id,
+        };
+        let _ = self.command_sender.send(NetworkCommand::ReportCapabilities(report));
+    }
+
+    // Same as the last report for this controller is not sent again. The latest one is re-sent on
+    // every later connect.
+    pub fn report_battery(&self, controller_id: u32, battery: Battery) {
+        let report = BatteryReport { controller_id, battery_percent: battery.percent, charging: battery.charging };
+        let _ = self.command_sender.send(NetworkCommand::ReportBattery(report));
+    }
+
+    // Raw reports from this device follow as binary frames, announced now and on every later
+    // connect. None switches passthrough off again.
+    pub fn set_hid_device(&self, device: Option<HidDevice>) {
+        let _ = self.command_sender.send(NetworkCommand::SetHidDevice(device));
+    }
+
+    // Dropped while disconnected, raw reports only make sense live. Simulated network
+    // conditions don't apply to them.
+    pub fn send_hid_report(&self, timestamp: u64, report: &[u8]) -> Result<()> {
+        if !self.is_connected() {
+            return Ok(());
+        }
+        match self.hid_sender.try_send(encode_report(timestamp, report)) {
+            Ok(_) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(_)) => Err(anyhow::anyhow!("Send queue full, dropping HID report")),
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(anyhow::anyhow!("Connection manager stopped")),
+        }
+    }
+
+    // Returns the message ID it went out under, the server shows the same one with each event.
+    // None when it didn't go out on its own, while disconnected or in fixed-rate mode.
+    // Nothing is lost when the queue is full: the state is tracked first, and once the queue
+    // has drained the task sends it for every controller, so a release still arrives late
+    // rather than never. Callers filtering out unchanged input can rely on that.
+    pub fn send_controller_data(&self, mut data: ControllerInputData) -> Result<Option<u64>> {
+        // Track state even while disconnected so the first tick after connecting is complete
+        self.states.lock().unwrap()
+            .states
+            .entry(data.controller_id)
+            .or_default()
+            .apply(&data);
+
+        // In fixed-rate mode the connection manager transmits the tracked state on its own tick
+        if !self.is_connected() || self.send_rate_hz > 0 {
+            return Ok(None);
+        }
+
+        data.message_id = self.next_message_id.fetch_add(1, Ordering::SeqCst);
+        tracing::debug!(
+            message_id = data.message_id,
+            buttons = data.button_events.len(),
+            axes = data.axis_events.len(),
+            "queued controller data"
+        );
+
+        let message_id = data.message_id;
+        match self.data_sender.try_send(data) {
+            Ok(_) => Ok(Some(message_id)),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                tracing::debug!(message_id, "send queue full, resending the tracked state once it drains");
+                self.states.lock().unwrap().resync = true;
+                Ok(None)
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(anyhow::anyhow!("Connection manager stopped")),
+        }
+    }
+
+    // Messages waiting for the network task and how many fit, new input is folded into the
+    // tracked state once it's full
+    pub fn send_queue_depth(&self) -> (usize, usize) {
+        queue_depth(&self.data_sender)
+    }
+
+    pub fn hid_queue_depth(&self) -> (usize, usize) {
+        queue_depth(&self.hid_sender)
+    }
+
+    // A neutral snapshot for every controller that has sent something
+    pub fn release_all(&self) -> Result<()> {
+        let releases: Vec<ControllerInputData> = self.states.lock().unwrap()
+            .states
+            .iter()
+            .map(|(&controller_id, state)| state.release_events(controller_id))
+            .filter(|data| !data.button_events.is_empty() || !data.axis_events.is_empty())
+            .collect();
+        for data in releases {
+            self.send_controller_data(data)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    pub fn poll_status(&mut self) -> Option<NetworkStatus> {
+        self.status_receiver.try_recv().ok()
+    }
+
+    // For async callers, waits for the next status instead of polling every frame
+    pub async fn next_status(&mut self) -> Option<NetworkStatus> {
+        self.status_receiver.recv().await
+    }
+}
+
+// Queued and total slots of a bounded channel
+fn queue_depth<T>(sender: &mpsc::Sender<T>) -> (usize, usize) {
+    (sender.max_capacity() - sender.capacity(), sender.max_capacity())
+}
+
+async fn connection_manager(
+    mut commands: mpsc::UnboundedReceiver<NetworkCommand>,
+    mut data: mpsc::Receiver<ControllerInputData>,
+    mut hid_reports: mpsc::Receiver<Vec<u8>>,
+    status: mpsc::UnboundedSender<NetworkStatus>,
+    connected: Arc<AtomicBool>,
+    states: SharedStates,
+    next_message_id: Arc<AtomicU64>,
+) {
+    let mut websocket: Option<WsStream> = None;
+    let mut server_address = String::new();
+    let mut ticker: Option<tokio::time::Interval> = None;
+    let mut simulator = NetworkSimulator::new();
+    let mut requested_profile: Option<String> = None;
+    let mut capability_reports: HashMap<u32, CapabilityReport> = HashMap::new();
+    let mut battery_reports: HashMap<u32, BatteryReport> = HashMap::new();
+    let mut hid_device: Option<HidDevice> = None;
+    let mut delivery = DeliveryTracker::new();
+    let mut connecting: Option<PendingConnection> = None;
+
+    loop {
+        tokio::select! {
+            command = commands.recv() => {
+                let Some(command) = command else {
+                    break;
+                };
+
+                // Changing the send rate, simulated conditions or profile, reporting a controller or its
+                // battery or switching HID passthrough keeps the current connection
+                if !matches!(command, NetworkCommand::SetSendRate(_) | NetworkCommand::SetConditions(_)
+                    | NetworkCommand::SelectProfile(_) | NetworkCommand::ReportCapabilities(_)
+                    | NetworkCommand::ReportBattery(_) | NetworkCommand::SetHidDevice(_)) {
+                    // Also gives up on an attempt that hasn't got through yet
+                    connecting = None;
+                    if let Some(mut ws_stream) = websocket.take() {
+                        connected.store(false, Ordering::SeqCst);
+                        let _ = ws_stream.close(None).await;
+                    }
+                    simulator.clear();
+                    delivery.clear();
+                }
+
+                match command {
+                    NetworkCommand::Connect(server_ip, port) => {
+                        server_address = format!("{}:{}", server_ip, port);
+                        let url = format!("ws://{}/controller", server_address);
+                        log::info!("Attempting to connect to {}", url);
+                        let _ = status.send(NetworkStatus::Connecting(server_address.clone()));
+                        connecting = Some(Box::pin(async move {
+                            connect_async(&url).await.map(|(ws_stream, _)| ws_stream)
+                        }));
+                    }
+                    NetworkCommand::Disconnect => {
+                        log::info!("Disconnected from server");
+                        let _ = status.send(NetworkStatus::Disconnected);
+                    }
+                    NetworkCommand::SetSendRate(send_rate_hz) => {
+                        ticker = (send_rate_hz > 0).then(|| {
+                            let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / send_rate_hz as f64));
+                            // A late tick should not be followed by a burst of catch-up sends
+                            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+                            interval
+                        });
+                        log::info!("Send rate set to {}", if send_rate_hz > 0 { format!("{} Hz", send_rate_hz) } else { "on change".to_string() });
+                    }
+                    NetworkCommand::SetConditions(conditions) => {
+                        if conditions.is_active() {
+                            log::warn!("Simulating network conditions: {:?}", conditions);
+                        } else {
+                            log::info!("Network simulation off");
+                        }
+                        simulator.set_conditions(conditions);
+                    }
+                    NetworkCommand::ReportCapabilities(report) => {
+                        capability_reports.insert(report.controller_id, report);
+                        if websocket.is_some() && !send_message(&mut websocket, &report).await {
+                            connected.store(false, Ordering::SeqCst);
+                            let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+                        }
+                    }
+                    NetworkCommand::ReportBattery(report) => {
+                        let changed = battery_reports.insert(report.controller_id, report) != Some(report);
+                        if changed && websocket.is_some() && !send_message(&mut websocket, &report).await {
+                            connected.store(false, Ordering::SeqCst);
+                            let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+                        }
+                    }
+                    NetworkCommand::SetHidDevice(device) => {
+                        hid_device = device;
+                        let announcement = HidDeviceAnnouncement { hid_device: hid_device.clone() };
+                        if websocket.is_some() && !send_message(&mut websocket, &announcement).await {
+                            connected.store(false, Ordering::SeqCst);
+                            let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+                        }
+                    }
+                    NetworkCommand::SelectProfile(name) => {
+                        requested_profile = (!name.is_empty()).then_some(name);
+                        if let Some(name) = requested_profile.as_ref().filter(|_| websocket.is_some()) {
+                            if !request_profile(&mut websocket, name).await {
+                                connected.store(false, Ordering::SeqCst);
+                                let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+                            }
+                        }
+                    }
+                }
+            }
+            result = next_connection(&mut connecting) => {
+                connecting = None;
+                match result {
+                    Ok(ws_stream) => {
+                        // Anything queued for the previous connection is stale by now
+                        while data.try_recv().is_ok() {}
+                        while hid_reports.try_recv().is_ok() {}
+                        websocket = Some(ws_stream);
+                        connected.store(true, Ordering::SeqCst);
+                        log::info!("Successfully connected to server");
+                        let _ = status.send(NetworkStatus::Connected(server_address.clone()));
+                        // Sent before any input so the first press already uses the chosen profile
+                        let mut sent = match &requested_profile {
+                            Some(name) => request_profile(&mut websocket, name).await,
+                            None => true,
+                        };
+                        for report in capability_reports.values() {
+                            sent = sent && send_message(&mut websocket, report).await;
+                        }
+                        for report in battery_reports.values() {
+                            sent = sent && send_message(&mut websocket, report).await;
+                        }
+                        if hid_device.is_some() {
+                            let announcement = HidDeviceAnnouncement { hid_device: hid_device.clone() };
+                            sent = sent && send_message(&mut websocket, &announcement).await;
+                        }
+                        if !sent {
+                            connected.store(false, Ordering::SeqCst);
+                            let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Failed to connect to server: {}", e);
+                        let _ = status.send(NetworkStatus::ConnectionFailed(server_address.clone(), e.to_string()));
+                    }
+                }
+            }
+            item = data.recv(), if websocket.is_some() => {
+                let Some(item) = item else {
+                    break;
+                };
+                let mut sent = match simulator.submit(item) {
+                    Some(item) => transmit_or_drop(&mut websocket, item, &mut delivery).await,
+                    None => true,
+                };
+                // Input that found the queue full is only in the tracked state, send all of it now
+                // that the backlog is gone
+                if sent && data.is_empty() && std::mem::take(&mut states.lock().unwrap().resync) {
+                    sent = send_snapshots(&mut websocket, &states, &next_message_id, &mut simulator, &mut delivery).await;
+                }
+                if !sent {
+                    connected.store(false, Ordering::SeqCst);
+                    let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+                }
+            }
+            frame = hid_reports.recv(), if websocket.is_some() => {
+                let Some(frame) = frame else {
+                    break;
+                };
+                if !send_binary(&mut websocket, frame).await {
+                    connected.store(false, Ordering::SeqCst);
+                    let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+                }
+            }
+            _ = next_tick(&mut ticker), if websocket.is_some() => {
+                if !send_snapshots(&mut websocket, &states, &next_message_id, &mut simulator, &mut delivery).await {
+                    connected.store(false, Ordering::SeqCst);
+                    let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+                }
+            }
+            _ = next_release(simulator.next_release()), if websocket.is_some() => {
+                for item in simulator.take_due(Instant::now()) {
+                    if !transmit_or_drop(&mut websocket, item, &mut delivery).await {
+                        connected.store(false, Ordering::SeqCst);
+                        let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+                        break;
+                    }
+                }
+            }
+            msg = next_message(&mut websocket) => {
+                match msg {
+                    Some(Ok(Message::Close(_))) | None => {
+                        log::info!("Server closed the connection");
+                    }
+                    Some(Err(e)) => {
+                        log::error!("WebSocket error: {}", e);
+                    }
+                    Some(Ok(Message::Text(text))) => {
+                        if let Ok(probe) = serde_json::from_str::<ClockProbe>(&text) {
+                            // Answered right away, the time it spends queued here counts as network delay
+                            let reply = ClockReply { clock_probe: probe.clock_probe, client_time: get_current_timestamp() };
+                            if !send_message(&mut websocket, &reply).await {
+                                connected.store(false, Ordering::SeqCst);
+                                let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+                            }
+                            continue;
+                        }
+                        if let Ok(ack) = serde_json::from_str::<Ack>(&text) {
+                            let held = Duration::from_micros(ack.held_us);
+                            let in_flight = delivery.in_flight();
+                            if let Some(ack_latency) = delivery.acked(ack.ack, held, Instant::now()) {
+                                let _ = status.send(NetworkStatus::Delivery(DeliveryStats {
+                                    in_flight: delivery.in_flight(),
+                                    acked: in_flight - delivery.in_flight(),
+                                    acked_id: ack.ack,
+                                    ack_latency,
+                                    retransmissions: websocket.as_ref().and_then(tcp_retransmissions),
+                                }));
+                            }
+                            continue;
+                        }
+                        if let Ok(input) = serde_json::from_str::<PcInput>(&text) {
+                            let _ = status.send(NetworkStatus::PcInput(input.pc_input));
+                            continue;
+                        }
+                        if let Ok(report) = serde_json::from_str::<PadHealthReport>(&text) {
+                            let _ = status.send(NetworkStatus::PadHealth(report.pad_health));
+                            continue;
+                        }
+                        if let Ok(report) = serde_json::from_str::<PadStatusReport>(&text) {
+                            let _ = status.send(NetworkStatus::PadStatus(report.pad_status));
+                            continue;
+                        }
+                        match serde_json::from_str::<ProfileList>(&text) {
+                            Ok(profiles) => {
+                                let _ = status.send(NetworkStatus::Profiles(profiles));
+                            }
+                            Err(e) => log::warn!("Ignoring unexpected message from server: {}", e),
+                        }
+                        continue;
+                    }
+                    Some(Ok(_)) => continue,
+                }
+                websocket = None;
+                simulator.clear();
+                delivery.clear();
+                connected.store(false, Ordering::SeqCst);
+                let _ = status.send(NetworkStatus::ConnectionLost(server_address.clone()));
+            }
+        }
+    }
+
+    connected.store(false, Ordering::SeqCst);
+    if let Some(mut ws_stream) = websocket {
+        let _ = ws_stream.close(None).await;
+    }
+}
+
+// The spans carry the message ID, which the server logs as well
+async fn transmit(ws_stream: &mut WsStream, data: &ControllerInputData) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+    let serialized = tracing::debug_span!("serialize", message_id = data.message_id)
+        .in_scope(|| serde_json::to_string(data));
+    let json_data = match serialized {
+        Ok(json_data) => json_data,
+        Err(e) => {
+            tracing::error!(message_id = data.message_id, "Failed to serialize controller data: {}", e);
+            return Ok(());
+        }
+    };
+
+    ws_stream.send(Message::Text(json_data))
+        .instrument(tracing::debug_span!("send", message_id = data.message_id))
+        .await
+}
+
+// Closes the socket when sending fails, returns false in that case
+async fn transmit_or_drop(websocket: &mut Option<WsStream>, mut data: ControllerInputData, delivery: &mut DeliveryTracker) -> bool {
+    let Some(ws_stream) = websocket else {
+        return false;
+    };
+    data.sent_at = get_current_timestamp();
+    if let Err(e) = transmit(ws_stream, &data).await {
+        log::error!("Failed to send WebSocket message: {}", e);
+        *websocket = None;
+        return false;
+    }
+    delivery.sent(data.message_id, Instant::now());
+    true
+}
+
+#[cfg(target_os = "linux")]
+fn tcp_retransmissions(ws_stream: &WsStream) -> Option<u32> {
+    use std::os::fd::AsRawFd;
+    match ws_stream.get_ref() {
+        MaybeTlsStream::Plain(tcp) => crate::delivery::tcp_retransmissions(tcp.as_raw_fd()),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn tcp_retransmissions(_ws_stream: &WsStream) -> Option<u32> {
+    None
+}
+
+async fn request_profile(websocket: &mut Option<WsStream>, name: &str) -> bool {
+    log::info!("Requesting mapping profile '{}'", name);
+    send_message(websocket, &ProfileRequest { select_profile: name.to_string() }).await
+}
+
+// Closes the socket when sending fails, returns false in that case
+async fn send_message(websocket: &mut Option<WsStream>, message: &impl Serialize) -> bool {
+    let Some(ws_stream) = websocket else {
+        return false;
+    };
+    let json = match serde_json::to_string(message) {
+        Ok(json) => json,
+        Err(e) => {
+            log::error!("Failed to serialize message: {}", e);
+            return true;
+        }
+    };
+    if let Err(e) = ws_stream.send(Message::Text(json)).await {
+        log::error!("Failed to send WebSocket message: {}", e);
+        *websocket = None;
+        return false;
+    }
+    true
+}
+
+// Closes the socket when sending fails, returns false in that case
+async fn send_binary(websocket: &mut Option<WsStream>, frame: Vec<u8>) -> bool {
+    let Some(ws_stream) = websocket else {
+        return false;
+    };
+    if let Err(e) = ws_stream.send(Message::Binary(frame)).await {
+        log::error!("Failed to send WebSocket message: {}", e);
+        *websocket = None;
+        return false;
+    }
+    true
+}
+
+// Never resolves while no simulated message is being held back
+async fn next_release(release: Option<Instant>) {
+    match release {
+        Some(release) => tokio::time::sleep_until(release.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+// Never resolves while no connection attempt is running
+async fn next_connection(connecting: &mut Option<PendingConnection>) -> Result<WsStream, tokio_tungstenite::tungstenite::Error> {
+    match connecting {
+        Some(attempt) => attempt.await,
+        None => std::future::pending().await,
+    }
+}
+
+// The full tracked state of every controller, false once the connection is gone
+async fn send_snapshots(
+    websocket: &mut Option<WsStream>,
+    states: &SharedStates,
+    next_message_id: &AtomicU64,
+    simulator: &mut NetworkSimulator,
+    delivery: &mut DeliveryTracker,
+) -> bool {
+    let snapshots: Vec<ControllerInputData> = states.lock().unwrap()
+        .states
+        .iter()
+        .map(|(&controller_id, state)| state.to_input_data(controller_id))
+        .collect();
+
+    for mut snapshot in snapshots {
+        snapshot.message_id = next_message_id.fetch_add(1, Ordering::SeqCst);
+        let Some(snapshot) = simulator.submit(snapshot) else {
+            continue;
+        };
+        if !transmit_or_drop(websocket, snapshot, delivery).await {
+            return false;
+        }
+    }
+    true
+}
+
+// Never resolves while in on-change mode
+async fn next_tick(ticker: &mut Option<tokio::time::Interval>) {
+    match ticker {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// Never resolves while disconnected, so the select above simply waits on commands
+async fn next_message(websocket: &mut Option<WsStream>) -> Option<Result<Message, tokio_tungstenite::tungstenite::Error>> {
+    match websocket {
+        Some(ws_stream) => ws_stream.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Inputs the virtual Xbox pad has no equivalent for are not sent
+pub fn button_to_id(button: Button) -> Option<ButtonId> {
+    match button {
+        Button::South => Some(ButtonId::South),
+        Button::East => Some(ButtonId::East),
+        Button::North => Some(ButtonId::North),
+        Button::West => Some(ButtonId::West),
+        Button::LeftTrigger => Some(ButtonId::LeftBumper),
+        Button::RightTrigger => Some(ButtonId::RightBumper),
+        Button::LeftTrigger2 => Some(ButtonId::LeftTrigger),
+        Button::RightTrigger2 => Some(ButtonId::RightTrigger),
+        Button::Select => Some(ButtonId::Select),
+        Button::Start => Some(ButtonId::Start),
+        Button::Mode => Some(ButtonId::Guide),
+        Button::LeftThumb => Some(ButtonId::LeftStick),
+        Button::RightThumb => Some(ButtonId::RightStick),
+        Button::DPadUp => Some(ButtonId::DPadUp),
+        Button::DPadDown => Some(ButtonId::DPadDown),
+        Button::DPadLeft => Some(ButtonId::DPadLeft),
+        Button::DPadRight => Some(ButtonId::DPadRight),
+        _ => None,
+    }
+}
+
+pub fn axis_to_id(axis: Axis) -> Option<AxisId> {
+    match axis {
+        Axis::LeftStickX => Some(AxisId::LeftStickX),
+        Axis::LeftStickY => Some(AxisId::LeftStickY),
+        Axis::LeftZ => Some(AxisId::LeftTrigger),
+        Axis::RightStickX => Some(AxisId::RightStickX),
+        Axis::RightStickY => Some(AxisId::RightStickY),
+        Axis::RightZ => Some(AxisId::RightTrigger),
+        Axis::DPadX => Some(AxisId::DPadX),
+        Axis::DPadY => Some(AxisId::DPadY),
+        _ => None,
+    }
+}
+
+/// Turns a controller event into the button or axis events sent to the server
+pub fn push_network_event(network_data: &mut ControllerInputData, event: InputEvent, timestamp: u64) {
+    let (button, pressed) = match event {
+        InputEvent::ButtonPressed(button) => (button, true),
+        InputEvent::ButtonReleased(button) => (button, false),
+        // Treat as digital input with threshold
+        InputEvent::ButtonChanged(button, value) => (button, value > 0.5),
+        InputEvent::AxisChanged(axis, value) => {
+            // Negligible changes are dropped by the input filter before sending
+            if let Some(axis_id) = axis_to_id(axis) {
+                network_data.axis_events.push(AxisEvent {
+                    axis: axis_id,
+                    value,
+                    timestamp,
+                });
+
+                // Debug log for network data
+                if matches!(axis, Axis::LeftZ | Axis::RightZ) {
+                    tracing::debug!(axis = %axis_id, value, "sending trigger");
+                }
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    // Don't send trigger buttons as digital events - they're handled as analog axes
+    if matches!(button, Button::LeftTrigger2 | Button::RightTrigger2) {
+        return;
+    }
+    if let Some(button_id) = button_to_id(button) {
+        network_data.button_events.push(ButtonEvent {
+            button: button_id,
+            pressed,
+            timestamp,
+        });
+    }
+}
+
+/// Progress of [`discover_servers`], one `Found` per server and a `Finished` at the end
+#[derive(Debug, Clone)]
+pub enum DiscoveryEvent {
+    Found(String),
+    Finished,
+}
+
+// Best guess at our LAN address: connecting a UDP socket only picks a route, no packet is sent
+pub fn local_ipv4() -> Option<std::net::Ipv4Addr> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    match socket.local_addr().ok()?.ip() {
+        std::net::IpAddr::V4(ip) if !ip.is_loopback() => Some(ip),
+        _ => None,
+    }
+}
+
+/// Probes every host of our /24 concurrently and reports the ones accepting a WebSocket handshake
+pub async fn discover_servers(port: i32, sender: tokio::sync::mpsc::UnboundedSender<DiscoveryEvent>) {
+    let Some(local_ip) = local_ipv4() else {
+        log::warn!("Could not determine local IP address for discovery");
+        let _ = sender.send(DiscoveryEvent::Finished);
+        return;
+    };
+
+    let [a, b, c, _] = local_ip.octets();
+    log::info!("Scanning {}.{}.{}.0/24 on port {}", a, b, c, port);
+
+    let mut probes = tokio::task::JoinSet::new();
+    for host in 1..=254u8 {
+        let ip = format!("{}.{}.{}.{}", a, b, c, host);
+        probes.spawn(async move {
+            let address = format!("{}:{}", ip, port);
+            let reachable = matches!(
+                tokio::time::timeout(Duration::from_millis(300), TcpStream::connect(&address)).await,
+                Ok(Ok(_))
+            );
+            if reachable && probe_server(&ip, port).await.is_ok() {
+                Some(ip)
+            } else {
+                None
+            }
+        });
+    }
+
+    while let Some(result) = probes.join_next().await {
+        if let Ok(Some(ip)) = result {
+            log::info!("Discovered server at {}:{}", ip, port);
+            let _ = sender.send(DiscoveryEvent::Found(ip));
+        }
+    }
+
+    let _ = sender.send(DiscoveryEvent::Finished);
+}
+
+/// Opens a throwaway WebSocket connection and measures the ping/pong round trip
+pub async fn probe_server(server_ip: &str, port: i32) -> Result<Duration> {
+    let url = format!("ws://{}:{}/controller", server_ip, port);
+    let timeout = Duration::from_secs(2);
+
+    let (mut ws_stream, _) = tokio::time::timeout(timeout, connect_async(&url)).await
+        .map_err(|_| anyhow::anyhow!("Timed out connecting to {}", url))??;
+
+    let start = Instant::now();
+    ws_stream.send(Message::Ping(b"probe".to_vec())).await?;
+
+    let round_trip = tokio::time::timeout(timeout, async {
+        while let Some(msg) = ws_stream.next().await {
+            if let Message::Pong(_) = msg? {
+                return Ok(start.elapsed());
+            }
+        }
+        Err(anyhow::anyhow!("Connection closed before pong"))
+    }).await.map_err(|_| anyhow::anyhow!("No pong received from {}", url))??;
+
+    let _ = ws_stream.close(None).await;
+    Ok(round_trip)
+}
+
+/// Milliseconds since the client started on a monotonic clock, so NTP adjustments can't make
+/// events jump. The server lines it up with its own clock through the probes it sends.
+pub fn get_current_timestamp() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
@@ -0,0 +1,123 @@
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::{Duration, Instant};
+use crate::network::ControllerInputData;
+
+// How much longer than its neighbours a reordered message is held back
+const REORDER_HOLD_MS: u64 = 30;
+
+/// Degrades the link on purpose so jitter and loss handling can be tested on a good network
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetworkConditions {
+    pub latency_ms: u32,
+    pub jitter_ms: u32,
+    pub loss_percent: f32,
+    pub reorder_percent: f32,
+}
+
+impl NetworkConditions {
+    pub fn is_active(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+struct Delayed {
+    release: Instant,
+    // Keeps messages released at the same instant in submission order
+    sequence: u64,
+    data: ControllerInputData,
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        (self.release, self.sequence) == (other.release, other.sequence)
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.release, self.sequence).cmp(&(other.release, other.sequence))
+    }
+}
+
+pub struct NetworkSimulator {
+    conditions: NetworkConditions,
+    queue: BinaryHeap<Reverse<Delayed>>,
+    next_sequence: u64,
+}
+
+impl Default for NetworkSimulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkSimulator {
+    pub fn new() -> Self {
+        Self {
+            conditions: NetworkConditions::default(),
+            queue: BinaryHeap::new(),
+            next_sequence: 0,
+        }
+    }
+
+    pub fn set_conditions(&mut self, conditions: NetworkConditions) {
+        self.conditions = conditions;
+    }
+
+    // Returns the message when it should go out right away, otherwise it is dropped or held back
+    pub fn submit(&mut self, data: ControllerInputData) -> Option<ControllerInputData> {
+        if !self.conditions.is_active() {
+            return Some(data);
+        }
+
+        let mut rng = rand::thread_rng();
+        if rng.gen_bool((self.conditions.loss_percent as f64 / 100.0).clamp(0.0, 1.0)) {
+            tracing::debug!(message_id = data.message_id, "simulated loss");
+            return None;
+        }
+
+        let jitter = self.conditions.jitter_ms as i64;
+        let mut delay_ms = (self.conditions.latency_ms as i64 + rng.gen_range(-jitter..=jitter)).max(0) as u64;
+        if rng.gen_bool((self.conditions.reorder_percent as f64 / 100.0).clamp(0.0, 1.0)) {
+            tracing::debug!(message_id = data.message_id, "simulated reorder");
+            delay_ms += REORDER_HOLD_MS;
+        }
+
+        self.queue.push(Reverse(Delayed {
+            release: Instant::now() + Duration::from_millis(delay_ms),
+            sequence: self.next_sequence,
+            data,
+        }));
+        self.next_sequence += 1;
+        None
+    }
+
+    pub fn next_release(&self) -> Option<Instant> {
+        self.queue.peek().map(|Reverse(delayed)| delayed.release)
+    }
+
+    pub fn take_due(&mut self, now: Instant) -> Vec<ControllerInputData> {
+        let mut due = Vec::new();
+        while self.queue.peek().is_some_and(|Reverse(delayed)| delayed.release <= now) {
+            if let Some(Reverse(delayed)) = self.queue.pop() {
+                due.push(delayed.data);
+            }
+        }
+        due
+    }
+
+    // Held messages belong to the connection they were sent on
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use crate::input_id::{AxisId, ButtonId};
+use crate::network::{ControllerInputData, ControllerState};
+
+// From linux/input-event-codes.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0x00;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+#[cfg(target_os = "linux")]
+const BUS_USB: u16 = 0x03;
+
+// The codes and IDs the kernel's xpad driver uses for a wired Xbox 360 pad, so games and SDL
+// treat this one the same. xpad reports the west button as BTN_X and the north one as BTN_Y.
+const BUTTONS: [(ButtonId, u16); 11] = [
+    (ButtonId::South, 0x130),
+    (ButtonId::East, 0x131),
+    (ButtonId::West, 0x133),
+    (ButtonId::North, 0x134),
+    (ButtonId::LeftBumper, 0x136),
+    (ButtonId::RightBumper, 0x137),
+    (ButtonId::Select, 0x13a),
+    (ButtonId::Start, 0x13b),
+    (ButtonId::Guide, 0x13c),
+    (ButtonId::LeftStick, 0x13d),
+    (ButtonId::RightStick, 0x13e),
+];
+#[cfg(target_os = "linux")]
+const VENDOR_ID: u16 = 0x045e;
+#[cfg(target_os = "linux")]
+const PRODUCT_ID: u16 = 0x028e;
+
+/// A virtual gamepad on this machine through /dev/uinput, for input that should reach games on
+/// the Deck itself. Needs write access to /dev/uinput, which SteamOS gives the logged in user.
+pub struct UinputPad {
+    #[cfg(target_os = "linux")]
+    device: std::fs::File,
+    state: ControllerState,
+    // Last value written for each (type, code), only changes are sent
+    written: HashMap<(u16, u16), i32>,
+}
+
+impl UinputPad {
+    #[cfg(target_os = "linux")]
+    pub fn create(name: &str) -> Result<Self> {
+        use anyhow::Context;
+        use std::os::fd::AsRawFd;
+
+        let device = std::fs::OpenOptions::new()
+            .write(true)
+            .open("/dev/uinput")
+            .context("Failed to open /dev/uinput")?;
+        setup_device(device.as_raw_fd(), name).context("Failed to create the uinput device")?;
+        log::info!("Created uinput gamepad '{}'", name);
+
+        Ok(Self {
+            device,
+            state: ControllerState::default(),
+            written: HashMap::new(),
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn create(_name: &str) -> Result<Self> {
+        anyhow::bail!("Virtual gamepads need uinput, which is only available on Linux")
+    }
+
+    // All controllers are merged into the one pad
+    pub fn send_controller_data(&mut self, data: &ControllerInputData) -> Result<()> {
+        self.state.apply(data);
+        let changes: Vec<(u16, u16, i32)> = events(&self.state)
+            .into_iter()
+            .filter(|&(kind, code, value)| self.written.get(&(kind, code)) != Some(&value))
+            .collect();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        for &(kind, code, value) in &changes {
+            self.write(kind, code, value)?;
+            self.written.insert((kind, code), value);
+        }
+        self.write(EV_SYN, SYN_REPORT, 0)?;
+        Ok(())
+    }
+
+    // Back to a centered gamepad with nothing held
+    pub fn release_all(&mut self) -> Result<()> {
+        let data = self.state.release_events(0);
+        self.send_controller_data(&data)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write(&mut self, kind: u16, code: u16, value: i32) -> std::io::Result<()> {
+        use std::io::Write;
+
+        // The timestamp is filled in by the kernel
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        event.type_ = kind;
+        event.code = code;
+        event.value = value;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(&event as *const _ as *const u8, std::mem::size_of::<libc::input_event>())
+        };
+        self.device.write_all(bytes)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn write(&mut self, _kind: u16, _code: u16, _value: i32) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for UinputPad {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+        unsafe { libc::ioctl(self.device.as_raw_fd(), UI_DEV_DESTROY) };
+        log::info!("Removed uinput gamepad");
+    }
+}
+
+// Every key and axis with its value for the state, the D-Pad goes into the hat like on xpad
+fn events(state: &ControllerState) -> Vec<(u16, u16, i32)> {
+    let mut events: Vec<(u16, u16, i32)> = BUTTONS.iter()
+        .map(|&(button, code)| (EV_KEY, code, state.button(button) as i32))
+        .collect();
+
+    // evdev counts Y downwards, our sticks count it upwards
+    let stick = |axis, sign: f32| ((state.axis(axis) * sign).clamp(-1.0, 1.0) * i16::MAX as f32) as i32;
+    // Pads that only report the trigger as a button still pull it all the way
+    let trigger = |axis, button| (state.axis(axis).max(state.button(button) as u8 as f32).clamp(0.0, 1.0) * 255.0) as i32;
+    let dpad = |positive, negative, axis| {
+        (state.button(positive) as i32 - state.button(negative) as i32 + state.axis(axis).round() as i32).signum()
+    };
+    events.extend([
+        (EV_ABS, ABS_X, stick(AxisId::LeftStickX, 1.0)),
+        (EV_ABS, ABS_Y, stick(AxisId::LeftStickY, -1.0)),
+        (EV_ABS, ABS_RX, stick(AxisId::RightStickX, 1.0)),
+        (EV_ABS, ABS_RY, stick(AxisId::RightStickY, -1.0)),
+        (EV_ABS, ABS_Z, trigger(AxisId::LeftTrigger, ButtonId::LeftTrigger)),
+        (EV_ABS, ABS_RZ, trigger(AxisId::RightTrigger, ButtonId::RightTrigger)),
+        (EV_ABS, ABS_HAT0X, dpad(ButtonId::DPadRight, ButtonId::DPadLeft, AxisId::DPadX)),
+        // Up is negative on the hat
+        (EV_ABS, ABS_HAT0Y, -dpad(ButtonId::DPadUp, ButtonId::DPadDown, AxisId::DPadY)),
+    ]);
+    events
+}
+
+// ioctl numbers from linux/uinput.h, encoded as on x86 and ARM
+#[cfg(target_os = "linux")]
+const fn uinput_ioctl(write_size: usize, number: u32) -> libc::Ioctl {
+    let direction = if write_size > 0 { 1u32 << 30 } else { 0 };
+    (direction | (write_size as u32) << 16 | (b'U' as u32) << 8 | number) as libc::Ioctl
+}
+
+#[cfg(target_os = "linux")]
+const UI_DEV_CREATE: libc::Ioctl = uinput_ioctl(0, 1);
+#[cfg(target_os = "linux")]
+const UI_DEV_DESTROY: libc::Ioctl = uinput_ioctl(0, 2);
+#[cfg(target_os = "linux")]
+const UI_DEV_SETUP: libc::Ioctl = uinput_ioctl(std::mem::size_of::<libc::uinput_setup>(), 3);
+#[cfg(target_os = "linux")]
+const UI_ABS_SETUP: libc::Ioctl = uinput_ioctl(std::mem::size_of::<libc::uinput_abs_setup>(), 4);
+#[cfg(target_os = "linux")]
+const UI_SET_EVBIT: libc::Ioctl = uinput_ioctl(std::mem::size_of::<libc::c_int>(), 100);
+#[cfg(target_os = "linux")]
+const UI_SET_KEYBIT: libc::Ioctl = uinput_ioctl(std::mem::size_of::<libc::c_int>(), 101);
+#[cfg(target_os = "linux")]
+const UI_SET_ABSBIT: libc::Ioctl = uinput_ioctl(std::mem::size_of::<libc::c_int>(), 103);
+
+#[cfg(target_os = "linux")]
+fn setup_device(fd: std::os::fd::RawFd, name: &str) -> std::io::Result<()> {
+    let check = |result: libc::c_int| if result < 0 { Err(std::io::Error::last_os_error()) } else { Ok(()) };
+
+    unsafe {
+        check(libc::ioctl(fd, UI_SET_EVBIT, EV_KEY as libc::c_int))?;
+        for &(_, code) in &BUTTONS {
+            check(libc::ioctl(fd, UI_SET_KEYBIT, code as libc::c_int))?;
+        }
+
+        check(libc::ioctl(fd, UI_SET_EVBIT, EV_ABS as libc::c_int))?;
+        let sticks = (i16::MIN as i32, i16::MAX as i32, 16, 128);
+        let axes = [
+            (ABS_X, sticks),
+            (ABS_Y, sticks),
+            (ABS_RX, sticks),
+            (ABS_RY, sticks),
+            (ABS_Z, (0, 255, 0, 0)),
+            (ABS_RZ, (0, 255, 0, 0)),
+            (ABS_HAT0X, (-1, 1, 0, 0)),
+            (ABS_HAT0Y, (-1, 1, 0, 0)),
+        ];
+        for (code, (minimum, maximum, fuzz, flat)) in axes {
+            check(libc::ioctl(fd, UI_SET_ABSBIT, code as libc::c_int))?;
+            let mut setup: libc::uinput_abs_setup = std::mem::zeroed();
+            setup.code = code;
+            setup.absinfo.minimum = minimum;
+            setup.absinfo.maximum = maximum;
+            setup.absinfo.fuzz = fuzz;
+            setup.absinfo.flat = flat;
+            check(libc::ioctl(fd, UI_ABS_SETUP, &setup))?;
+        }
+
+        let mut setup: libc::uinput_setup = std::mem::zeroed();
+        setup.id.bustype = BUS_USB;
+        setup.id.vendor = VENDOR_ID;
+        setup.id.product = PRODUCT_ID;
+        setup.id.version = 1;
+        for (target, &byte) in setup.name.iter_mut().zip(name.as_bytes().iter().take(libc::UINPUT_MAX_NAME_SIZE - 1)) {
+            *target = byte as libc::c_char;
+        }
+        check(libc::ioctl(fd, UI_DEV_SETUP, &setup))?;
+        check(libc::ioctl(fd, UI_DEV_CREATE))?;
+    }
+    Ok(())
+}
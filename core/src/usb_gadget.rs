@@ -0,0 +1,179 @@
+use anyhow::Result;
+use crate::hid_gamepad;
+use crate::network::{ControllerInputData, ControllerState};
+
+#[cfg(target_os = "linux")]
+const GADGET_DIR: &str = "/sys/kernel/config/usb_gadget/steamdeck_controls";
+// Linux Foundation's multifunction composite gadget IDs, meant for exactly this kind of device
+#[cfg(target_os = "linux")]
+const VENDOR_ID: &str = "0x1d6b";
+#[cfg(target_os = "linux")]
+const PRODUCT_ID: &str = "0x0104";
+
+/// Presents the Deck to the PC on the other end of the USB-C cable as a wired HID gamepad through
+/// ConfigFS, so input skips the network and the server entirely. Needs root, the libcomposite
+/// module and the port switched to device mode (USB Dual-Role Device in the Deck's BIOS).
+pub struct UsbGadget {
+    #[cfg(target_os = "linux")]
+    device: std::fs::File,
+    state: ControllerState,
+    last_report: Option<[u8; hid_gamepad::REPORT_LENGTH]>,
+    // Set when the host hasn't picked up the previous report yet, written again by flush
+    pending: bool,
+}
+
+impl UsbGadget {
+    #[cfg(target_os = "linux")]
+    pub fn start() -> Result<Self> {
+        use anyhow::Context;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        // A gadget left behind by a crash would keep the UDC busy
+        remove_gadget();
+        let minor = create_gadget().inspect_err(|_| remove_gadget())?;
+        let path = format!("/dev/hidg{}", minor);
+        // Writes would block until the host reads, which it never does while the cable is out
+        let device = std::fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path))
+            .inspect_err(|_| remove_gadget())?;
+        log::info!("USB gadget started, writing reports to {}", path);
+
+        Ok(Self {
+            device,
+            state: ControllerState::default(),
+            last_report: None,
+            pending: false,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn start() -> Result<Self> {
+        anyhow::bail!("USB gadget mode needs ConfigFS, which is only available on Linux")
+    }
+
+    // All controllers are merged into the one gamepad the PC sees
+    pub fn send_controller_data(&mut self, data: &ControllerInputData) -> Result<()> {
+        self.state.apply(data);
+        let report = hid_gamepad::encode_report(&self.state);
+        if self.last_report != Some(report) {
+            self.last_report = Some(report);
+            self.pending = true;
+        }
+        self.flush()
+    }
+
+    // Back to a centered gamepad with nothing held
+    pub fn release_all(&mut self) -> Result<()> {
+        let data = self.state.release_events(0);
+        self.send_controller_data(&data)
+    }
+
+    // Writes the latest report if the host hasn't got it yet, call it regularly so a report
+    // that didn't fit earlier still goes out
+    pub fn flush(&mut self) -> Result<()> {
+        let Some(report) = self.last_report.filter(|_| self.pending) else {
+            return Ok(());
+        };
+        match self.write(&report) {
+            Ok(()) => {
+                self.pending = false;
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+            // Unplugged, or the host hasn't set the gadget up yet. Kept pending for when it has.
+            #[cfg(target_os = "linux")]
+            Err(e) if e.raw_os_error() == Some(libc::ESHUTDOWN) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write(&mut self, report: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+        self.device.write_all(report)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn write(&mut self, _report: &[u8]) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for UsbGadget {
+    fn drop(&mut self) {
+        remove_gadget();
+        log::info!("USB gadget stopped");
+    }
+}
+
+// Builds the gadget and binds it to the first UDC, returns the hidg minor number
+#[cfg(target_os = "linux")]
+fn create_gadget() -> Result<u32> {
+    use anyhow::Context;
+    use std::fs;
+    use std::path::Path;
+
+    let gadget = Path::new(GADGET_DIR);
+    fs::create_dir_all(gadget)
+        .context("Failed to create the gadget in ConfigFS, is libcomposite loaded and are we root?")?;
+    fs::write(gadget.join("idVendor"), VENDOR_ID)?;
+    fs::write(gadget.join("idProduct"), PRODUCT_ID)?;
+    fs::write(gadget.join("bcdDevice"), "0x0100")?;
+    fs::write(gadget.join("bcdUSB"), "0x0200")?;
+
+    let strings = gadget.join("strings/0x409");
+    fs::create_dir_all(&strings)?;
+    fs::write(strings.join("manufacturer"), "SteamDeck Controls")?;
+    fs::write(strings.join("product"), "Steam Deck Gamepad")?;
+    fs::write(strings.join("serialnumber"), "0001")?;
+
+    let function = gadget.join("functions/hid.usb0");
+    fs::create_dir_all(&function)?;
+    fs::write(function.join("protocol"), "0")?;
+    fs::write(function.join("subclass"), "0")?;
+    fs::write(function.join("report_length"), hid_gamepad::REPORT_LENGTH.to_string())?;
+    fs::write(function.join("report_desc"), hid_gamepad::REPORT_DESCRIPTOR)?;
+
+    let config = gadget.join("configs/c.1");
+    fs::create_dir_all(config.join("strings/0x409"))?;
+    fs::write(config.join("strings/0x409/configuration"), "Gamepad")?;
+    fs::write(config.join("MaxPower"), "100")?;
+    std::os::unix::fs::symlink(&function, config.join("hid.usb0"))?;
+
+    let udc = fs::read_dir("/sys/class/udc")
+        .ok()
+        .and_then(|mut entries| entries.next())
+        .and_then(|entry| entry.ok())
+        .ok_or_else(|| anyhow::anyhow!("No USB device controller found, is the port in device mode?"))?;
+    fs::write(gadget.join("UDC"), udc.file_name().as_encoded_bytes())
+        .context("Failed to bind the gadget to the USB device controller")?;
+
+    // "major:minor" of the /dev/hidg node the function created
+    let dev = fs::read_to_string(function.join("dev"))?;
+    dev.trim().rsplit(':').next()
+        .and_then(|minor| minor.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("Unexpected hidg device number '{}'", dev.trim()))
+}
+
+// ConfigFS wants everything taken apart in reverse, errors just mean that part wasn't there
+#[cfg(target_os = "linux")]
+fn remove_gadget() {
+    use std::fs;
+    use std::path::Path;
+
+    let gadget = Path::new(GADGET_DIR);
+    if !gadget.exists() {
+        return;
+    }
+    let _ = fs::write(gadget.join("UDC"), "");
+    let _ = fs::remove_file(gadget.join("configs/c.1/hid.usb0"));
+    let _ = fs::remove_dir(gadget.join("configs/c.1/strings/0x409"));
+    let _ = fs::remove_dir(gadget.join("configs/c.1"));
+    let _ = fs::remove_dir(gadget.join("functions/hid.usb0"));
+    let _ = fs::remove_dir(gadget.join("strings/0x409"));
+    let _ = fs::remove_dir(gadget);
+}
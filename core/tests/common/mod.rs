@@ -0,0 +1,127 @@
+use gilrs::{Axis, Button};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime};
+use steamdeck_controls_core::capabilities::Capabilities;
+use steamdeck_controls_core::input_source::{ControllerInfo, InputEvent, InputSource, SourceEvent};
+
+#[derive(Debug, Clone, Default)]
+struct MockController {
+    name: String,
+    buttons: HashMap<Button, bool>,
+    axes: HashMap<Axis, f32>,
+}
+
+// Plays back a scripted sequence of events, each released once its offset has passed
+pub struct MockInputSource {
+    script: VecDeque<(Duration, u32, InputEvent)>,
+    script_end: Duration,
+    started: Instant,
+    controllers: HashMap<u32, MockController>,
+}
+
+impl MockInputSource {
+    pub fn new() -> Self {
+        Self {
+            script: VecDeque::new(),
+            script_end: Duration::ZERO,
+            started: Instant::now(),
+            controllers: HashMap::new(),
+        }
+    }
+
+    // Queues an event `delay` after the previously queued one
+    pub fn push(&mut self, delay: Duration, controller_id: u32, event: InputEvent) -> &mut Self {
+        self.script_end += delay;
+        self.script.push_back((self.script_end, controller_id, event));
+        self
+    }
+
+    pub fn connect(&mut self, controller_id: u32, name: &str) -> &mut Self {
+        self.controllers.entry(controller_id).or_default().name = name.to_string();
+        self.push(Duration::ZERO, controller_id, InputEvent::Connected)
+    }
+
+    pub fn press(&mut self, delay: Duration, controller_id: u32, button: Button) -> &mut Self {
+        self.push(delay, controller_id, InputEvent::ButtonPressed(button))
+    }
+
+    pub fn release(&mut self, delay: Duration, controller_id: u32, button: Button) -> &mut Self {
+        self.push(delay, controller_id, InputEvent::ButtonReleased(button))
+    }
+
+    pub fn move_axis(&mut self, delay: Duration, controller_id: u32, axis: Axis, value: f32) -> &mut Self {
+        self.push(delay, controller_id, InputEvent::AxisChanged(axis, value))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.script.is_empty()
+    }
+
+    // Restarts the clock so offsets count from now, e.g. once a test has finished its setup
+    pub fn start(&mut self) {
+        self.started = Instant::now();
+    }
+}
+
+impl InputSource for MockInputSource {
+    fn next_event(&mut self) -> Option<SourceEvent> {
+        let (offset, _, _) = *self.script.front()?;
+        if offset > self.started.elapsed() {
+            return None;
+        }
+
+        let (_, controller_id, event) = self.script.pop_front()?;
+        let controller = self.controllers.entry(controller_id).or_default();
+        match event {
+            InputEvent::ButtonPressed(button) => {
+                controller.buttons.insert(button, true);
+            }
+            InputEvent::ButtonReleased(button) => {
+                controller.buttons.insert(button, false);
+            }
+            InputEvent::ButtonChanged(button, value) => {
+                controller.buttons.insert(button, value > 0.5);
+            }
+            InputEvent::AxisChanged(axis, value) => {
+                controller.axes.insert(axis, value);
+            }
+            InputEvent::Connected | InputEvent::Disconnected => {}
+        }
+
+        Some(SourceEvent {
+            controller_id,
+            event,
+            time: SystemTime::now(),
+        })
+    }
+
+    fn controllers(&self) -> Vec<ControllerInfo> {
+        let mut controllers: Vec<ControllerInfo> = self.controllers.iter()
+            .map(|(&controller_id, controller)| ControllerInfo {
+                controller_id,
+                name: controller.name.clone(),
+                uuid: [0; 16],
+                vendor_id: None,
+                product_id: None,
+                capabilities: Capabilities::everything(),
+                deck_model: None,
+            })
+            .collect();
+        controllers.sort_by_key(|info| info.controller_id);
+        controllers
+    }
+
+    fn is_pressed(&self, controller_id: u32, button: Button) -> bool {
+        self.controllers.get(&controller_id)
+            .and_then(|controller| controller.buttons.get(&button))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn value(&self, controller_id: u32, axis: Axis) -> f32 {
+        self.controllers.get(&controller_id)
+            .and_then(|controller| controller.axes.get(&axis))
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
@@ -0,0 +1,275 @@
+use gilrs::{Axis, Button};
+use std::time::{Duration, Instant};
+
+#[allow(dead_code)]
+mod common;
+
+use common::MockInputSource;
+use steamdeck_controls_core::delivery::DeliveryTracker;
+use steamdeck_controls_core::input_filter::{InputFilter, DEFAULT_AXIS_EPSILON};
+use steamdeck_controls_core::input_id::{AxisId, ButtonId};
+use steamdeck_controls_core::input_source::{DeckModel, InputSource};
+use steamdeck_controls_core::haptics::{self, HapticPattern};
+use steamdeck_controls_core::link_quality::{LinkMonitor, LinkQuality, LinkSample};
+use steamdeck_controls_core::{hid_gamepad, hid_passthrough};
+use steamdeck_controls_core::network::{push_network_event, ControllerInputData, ControllerState};
+
+// What App::update does with each batch before handing it to the network streamer
+fn poll(source: &mut dyn InputSource, filter: &mut InputFilter) -> ControllerInputData {
+    let mut data = ControllerInputData {
+        message_id: 0,
+        timestamp: 0,
+        sent_at: 0,
+        controller_id: 0,
+        button_events: Vec::new(),
+        axis_events: Vec::new(),
+    };
+    while let Some(event) = source.next_event() {
+        data.controller_id = event.controller_id;
+        push_network_event(&mut data, event.event, 0);
+    }
+    filter.filter(&mut data);
+    data
+}
+
+fn buttons(data: &ControllerInputData) -> Vec<(ButtonId, bool)> {
+    data.button_events.iter().map(|e| (e.button, e.pressed)).collect()
+}
+
+fn axes(data: &ControllerInputData) -> Vec<(AxisId, f32)> {
+    data.axis_events.iter().map(|e| (e.axis, e.value)).collect()
+}
+
+#[test]
+fn repeated_button_states_are_sent_once() {
+    let mut source = MockInputSource::new();
+    source.connect(0, "Mock Pad")
+        .press(Duration::ZERO, 0, Button::South)
+        .press(Duration::ZERO, 0, Button::South)
+        .release(Duration::ZERO, 0, Button::South);
+    let mut filter = InputFilter::new(DEFAULT_AXIS_EPSILON);
+
+    let data = poll(&mut source, &mut filter);
+
+    assert_eq!(buttons(&data), [(ButtonId::South, true), (ButtonId::South, false)]);
+    assert!(source.is_finished());
+}
+
+#[test]
+fn analog_triggers_are_sent_as_axes_only() {
+    let mut source = MockInputSource::new();
+    source.connect(1, "Mock Pad")
+        .press(Duration::ZERO, 1, Button::LeftTrigger2)
+        .move_axis(Duration::ZERO, 1, Axis::LeftZ, 0.8);
+    let mut filter = InputFilter::new(DEFAULT_AXIS_EPSILON);
+
+    let data = poll(&mut source, &mut filter);
+
+    assert_eq!(data.controller_id, 1);
+    assert!(data.button_events.is_empty());
+    assert_eq!(axes(&data), [(AxisId::LeftTrigger, 0.8)]);
+}
+
+#[test]
+fn negligible_axis_moves_are_dropped_but_center_is_not() {
+    let mut source = MockInputSource::new();
+    source.connect(0, "Mock Pad")
+        .move_axis(Duration::ZERO, 0, Axis::LeftStickX, 0.5)
+        .move_axis(Duration::ZERO, 0, Axis::LeftStickX, 0.505)
+        .move_axis(Duration::ZERO, 0, Axis::LeftStickX, 0.005)
+        .move_axis(Duration::ZERO, 0, Axis::LeftStickX, 0.0);
+    let mut filter = InputFilter::new(DEFAULT_AXIS_EPSILON);
+
+    let data = poll(&mut source, &mut filter);
+
+    // 0.005 is within the center band, so the later 0.0 has nothing left to change
+    assert_eq!(axes(&data), [(AxisId::LeftStickX, 0.5), (AxisId::LeftStickX, 0.0)]);
+}
+
+#[test]
+fn stick_returning_to_center_always_ends_at_zero() {
+    let mut source = MockInputSource::new();
+    source.connect(0, "Mock Pad")
+        .move_axis(Duration::ZERO, 0, Axis::RightStickY, 0.3)
+        .move_axis(Duration::ZERO, 0, Axis::RightStickY, 0.015)
+        // Too close to 0.015 to count as a move, but it's where the stick came to rest
+        .move_axis(Duration::ZERO, 0, Axis::RightStickY, 0.008)
+        .move_axis(Duration::ZERO, 0, Axis::RightStickY, -0.004);
+    let mut filter = InputFilter::new(DEFAULT_AXIS_EPSILON);
+
+    let data = poll(&mut source, &mut filter);
+
+    assert_eq!(axes(&data), [(AxisId::RightStickY, 0.3), (AxisId::RightStickY, 0.015), (AxisId::RightStickY, 0.0)]);
+}
+
+#[test]
+fn scripted_events_wait_for_their_offset() {
+    let mut source = MockInputSource::new();
+    source.connect(0, "Mock Pad")
+        .press(Duration::from_millis(50), 0, Button::East);
+    let mut filter = InputFilter::new(DEFAULT_AXIS_EPSILON);
+    source.start();
+
+    assert!(poll(&mut source, &mut filter).button_events.is_empty());
+    assert!(!source.is_finished());
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert_eq!(buttons(&poll(&mut source, &mut filter)), [(ButtonId::East, true)]);
+}
+
+#[test]
+fn mock_reports_state_for_sync() {
+    let mut source = MockInputSource::new();
+    source.connect(2, "Mock Pad")
+        .press(Duration::ZERO, 2, Button::North)
+        .move_axis(Duration::ZERO, 2, Axis::RightStickY, -0.25);
+    while source.next_event().is_some() {}
+
+    assert!(source.is_pressed(2, Button::North));
+    assert!(!source.is_pressed(2, Button::South));
+    assert_eq!(source.value(2, Axis::RightStickY), -0.25);
+    assert_eq!(source.controller_info(2).map(|info| info.name), Some("Mock Pad".to_string()));
+}
+
+#[test]
+fn gamepad_report_follows_the_controller_state() {
+    let mut source = MockInputSource::new();
+    let mut filter = InputFilter::new(DEFAULT_AXIS_EPSILON);
+    source.connect(0, "Mock Pad")
+        .press(Duration::ZERO, 0, Button::South)
+        .press(Duration::ZERO, 0, Button::DPadUp)
+        .press(Duration::ZERO, 0, Button::DPadRight)
+        .move_axis(Duration::ZERO, 0, Axis::LeftStickY, 1.0)
+        .move_axis(Duration::ZERO, 0, Axis::RightZ, 0.5);
+    let mut state = ControllerState::default();
+    state.apply(&poll(&mut source, &mut filter));
+
+    let report = hid_gamepad::encode_report(&state);
+    assert_eq!(report.len(), hid_gamepad::REPORT_LENGTH);
+    assert_eq!(u16::from_le_bytes([report[0], report[1]]), 1 << 0);
+    // Up and right make north-east
+    assert_eq!(report[2], 1);
+    // Up on the stick is negative Y in HID
+    assert_eq!(i16::from_le_bytes([report[5], report[6]]), -i16::MAX);
+    assert_eq!(i16::from_le_bytes([report[13], report[14]]), i16::MAX / 2);
+}
+
+#[test]
+fn release_events_only_touch_what_is_held() {
+    let mut source = MockInputSource::new();
+    let mut filter = InputFilter::new(DEFAULT_AXIS_EPSILON);
+    source.connect(1, "Mock Pad")
+        .press(Duration::ZERO, 1, Button::South)
+        .press(Duration::ZERO, 1, Button::East)
+        .release(Duration::ZERO, 1, Button::East)
+        .move_axis(Duration::ZERO, 1, Axis::LeftStickX, 0.75);
+    let mut state = ControllerState::default();
+    state.apply(&poll(&mut source, &mut filter));
+
+    let release = state.release_events(1);
+    assert_eq!(release.controller_id, 1);
+    assert_eq!(buttons(&release), vec![(ButtonId::South, false)]);
+    assert_eq!(axes(&release), vec![(AxisId::LeftStickX, 0.0)]);
+
+    state.apply(&release);
+    assert!(!state.button(ButtonId::South));
+    assert_eq!(state.axis(AxisId::LeftStickX), 0.0);
+    assert!(state.release_events(1).button_events.is_empty());
+}
+
+#[test]
+fn an_ack_covers_everything_sent_before_it() {
+    let mut tracker = DeliveryTracker::new();
+    let start = Instant::now();
+    for message_id in 1..=4 {
+        tracker.sent(message_id, start);
+    }
+
+    let latency = tracker.acked(3, Duration::from_millis(5), start + Duration::from_millis(20));
+    assert_eq!(latency, Some(Duration::from_millis(15)));
+    assert_eq!(tracker.in_flight(), 1);
+
+    // Nothing left that it could be about
+    assert_eq!(tracker.acked(3, Duration::ZERO, start + Duration::from_millis(30)), None);
+}
+
+#[test]
+fn link_quality_blames_whatever_costs_the_most() {
+    let mut monitor = LinkMonitor::new();
+    let start = Instant::now();
+    assert!(monitor.quality(start).is_none());
+
+    // Steady round trips, nothing lost, 50 messages a second
+    for tick in 0..10 {
+        let at = start + Duration::from_millis(200 * tick);
+        monitor.round_trip(at, 4.0);
+        monitor.messages(at, 10, 0);
+    }
+    let good = monitor.quality(start + Duration::from_secs(2)).unwrap();
+    assert_eq!(good.score, 100);
+    assert_eq!(good.label(), "Excellent");
+    assert!(good.hints.is_empty());
+    assert_eq!(good.sample.messages_per_sec, 50.0);
+    // Only the last few seconds count
+    assert!(monitor.quality(start + Duration::from_secs(10)).is_none());
+
+    // Lost messages cost more than the uneven delays, and with a busy link sending less helps
+    let bad = LinkQuality::assess(LinkSample {
+        rtt_ms: 20.0,
+        jitter_ms: 8.0,
+        loss: 0.05,
+        messages_per_sec: 600.0,
+    });
+    assert_eq!(bad.score, 100 - 5 - 12 - 25 - 10);
+    assert_eq!(bad.label(), "Fair");
+    assert!(bad.hints[0].starts_with("Messages are getting lost"));
+    assert!(bad.hints[1].starts_with("Pause downloads"));
+    assert!(bad.hints[2].starts_with("Reduce the send rate"));
+    assert!(bad.hints[3].starts_with("Switch the Deck"));
+}
+
+#[test]
+fn deck_models_are_told_apart_by_their_board() {
+    // As read from sysfs, with the newline
+    assert_eq!(DeckModel::from_product_name("Jupiter\n"), Some(DeckModel::Lcd));
+    assert_eq!(DeckModel::from_product_name("Galileo\n"), Some(DeckModel::Oled));
+    assert_eq!(DeckModel::from_product_name("ROG Ally RC71L\n"), None);
+    // Other controllers never get a model, whatever machine this runs on
+    assert_eq!(DeckModel::of_controller(Some(0x045E), Some(0x028E)), None);
+    assert_eq!(DeckModel::of_controller(None, None), None);
+}
+
+#[test]
+fn deck_touchpads_are_read_from_the_state_report() {
+    let mut report = vec![0u8; hid_passthrough::MAX_REPORT_SIZE];
+    report[0] = 0x01;
+    report[2] = 0x09;
+    // Only the left pad is touched
+    report[10] = 0x08;
+    report[16..18].copy_from_slice(&(-1200i16).to_le_bytes());
+    report[18..20].copy_from_slice(&3400i16.to_le_bytes());
+
+    let [left, right] = hid_passthrough::deck_touchpads(&report).unwrap();
+    assert!(left.touched);
+    assert_eq!((left.x, left.y), (-1200, 3400));
+    assert!(!right.touched);
+
+    // Other report types carry no pads
+    report[2] = 0x04;
+    assert_eq!(hid_passthrough::deck_touchpads(&report), None);
+}
+
+#[test]
+fn haptic_steps_follow_the_envelope() {
+    let pattern = HapticPattern::new("Ramp", &[(100, 1.0, 0.0), (0, 0.0, 1.0), (200, 0.0, 0.0)]);
+    assert_eq!(pattern.duration_ms(), 200);
+    // Points needn't be in order
+    assert_eq!(pattern.at(50), (0.5, 0.5));
+    assert_eq!(pattern.at(250), (0.0, 0.0));
+
+    let steps = pattern.steps();
+    assert_eq!(steps.len(), 200 / haptics::STEP_MS as usize);
+    // Each step is sampled halfway through
+    assert_eq!(steps[0], (0, 0.25, 0.75));
+    assert_eq!(steps[3], (150, 0.25, 0.0));
+}
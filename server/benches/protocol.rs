@@ -0,0 +1,140 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// The server is a binary crate, so the modules under test are pulled in by path. Without a test
+// harness their unit tests compile down to just the imports, hence unused_imports on those.
+#[allow(dead_code)]
+#[path = "../../core/src/input_id.rs"]
+mod input_id;
+#[allow(dead_code)]
+#[path = "../src/clock.rs"]
+mod clock;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/protocol.rs"]
+mod protocol;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/deadzone.rs"]
+mod deadzone;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/smoothing.rs"]
+mod smoothing;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/input_filter.rs"]
+mod input_filter;
+#[allow(dead_code)]
+#[path = "../src/launcher.rs"]
+mod launcher;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/mapping.rs"]
+mod mapping;
+#[allow(dead_code)]
+#[path = "../src/i18n.rs"]
+mod i18n;
+#[allow(dead_code)]
+#[path = "../src/palette.rs"]
+mod palette;
+#[allow(dead_code)]
+#[path = "../src/scripting.rs"]
+mod scripting;
+#[allow(dead_code)]
+#[path = "../src/virtual_controller.rs"]
+mod virtual_controller;
+
+use input_id::{AxisId, ButtonId};
+use protocol::{AxisEvent, ButtonEvent, ControllerInputData};
+
+// A single stick move, the most common message during play
+fn single_axis_message() -> ControllerInputData {
+    ControllerInputData {
+        message_id: 42,
+        timestamp: 1_700_000_000_000,
+        sent_at: 0,
+        controller_id: 0,
+        button_events: Vec::new(),
+        axis_events: vec![AxisEvent {
+            axis: AxisId::LeftStickX,
+            value: 0.5,
+            timestamp: 1_700_000_000_000,
+        }],
+    }
+}
+
+// Every button and axis at once, like the fixed rate mode sends
+fn full_state_message() -> ControllerInputData {
+    ControllerInputData {
+        message_id: 42,
+        timestamp: 1_700_000_000_000,
+        sent_at: 0,
+        controller_id: 0,
+        button_events: ButtonId::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, &button)| ButtonEvent {
+                button,
+                pressed: i % 2 == 0,
+                timestamp: 1_700_000_000_000,
+            })
+            .collect(),
+        axis_events: AxisId::ALL
+            .iter()
+            .map(|&axis| AxisEvent {
+                axis,
+                value: -0.75,
+                timestamp: 1_700_000_000_000,
+            })
+            .collect(),
+    }
+}
+
+fn samples() -> [(&'static str, ControllerInputData); 2] {
+    [("single_axis", single_axis_message()), ("full_state", full_state_message())]
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for (name, data) in samples() {
+        group.bench_with_input(BenchmarkId::new("json", name), &data, |b, data| {
+            b.iter(|| serde_json::to_string(black_box(data)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("bincode", name), &data, |b, data| {
+            b.iter(|| bincode::serialize(black_box(data)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, data) in samples() {
+        let json = serde_json::to_string(&data).unwrap();
+        let binary = bincode::serialize(&data).unwrap();
+        group.bench_with_input(BenchmarkId::new("json", name), &json, |b, json| {
+            b.iter(|| serde_json::from_str::<ControllerInputData>(black_box(json)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("bincode", name), &binary, |b, binary| {
+            b.iter(|| bincode::deserialize::<ControllerInputData>(black_box(binary)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn mapping(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mapping");
+    for (name, data) in samples() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &data, |b, data| {
+            let mut gamepad = vigem_client::XGamepad::default();
+            b.iter(|| {
+                for event in &data.button_events {
+                    virtual_controller::apply_button(&mut gamepad, event.button, event.pressed);
+                }
+                for event in &data.axis_events {
+                    virtual_controller::apply_axis(&mut gamepad, event.axis, event.value);
+                }
+                black_box(&gamepad);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode, mapping);
+criterion_main!(benches);
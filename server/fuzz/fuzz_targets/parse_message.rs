@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The server is a binary crate, so the modules under test are pulled in by path
+#[allow(dead_code)]
+#[path = "../../../core/src/input_id.rs"]
+mod input_id;
+#[allow(dead_code)]
+#[path = "../../src/protocol.rs"]
+mod protocol;
+
+// Run with `cargo fuzz run parse_message` from the server directory
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(message) = protocol::parse_message(text) {
+        // Anything accepted must be safe to hand to the virtual controller
+        assert!(message.button_events.len() + message.axis_events.len() <= protocol::MAX_EVENTS_PER_MESSAGE);
+        assert!(message.axis_events.iter().all(|e| e.value.is_finite() && e.value.abs() <= 1.0));
+    }
+});
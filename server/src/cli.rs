@@ -0,0 +1,108 @@
+use clap::{value_parser, Arg, ArgAction, Command};
+use std::path::PathBuf;
+use tracing_subscriber::filter::LevelFilter;
+use crate::settings::NetworkSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControllerType {
+    Xbox360,
+    // Input is received and shown but never reaches a game
+    None,
+}
+
+// How the terminal output is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    // One JSON object per line, see json_log
+    Json,
+}
+
+// --bind and --port only apply to this run, they are never written to the settings
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub bind_address: Option<String>,
+    pub port: Option<i32>,
+    pub headless: bool,
+    pub controller: ControllerType,
+    pub profile: Option<PathBuf>,
+    pub log_level: Option<LevelFilter>,
+    pub log_format: LogFormat,
+}
+
+impl Args {
+    pub fn parse() -> Self {
+        let matches = command().get_matches();
+
+        let controller = match matches.get_one::<String>("controller").map(String::as_str) {
+            Some("none") => ControllerType::None,
+            _ => ControllerType::Xbox360,
+        };
+        let log_level = matches.get_one::<String>("log-level")
+            .and_then(|level| level.parse::<LevelFilter>().ok());
+        let log_format = match matches.get_one::<String>("log-format").map(String::as_str) {
+            Some("json") => LogFormat::Json,
+            _ => LogFormat::Text,
+        };
+
+        Self {
+            bind_address: matches.get_one::<String>("bind").cloned(),
+            port: matches.get_one::<i32>("port").copied(),
+            headless: matches.get_flag("headless"),
+            controller,
+            profile: matches.get_one::<PathBuf>("profile").cloned(),
+            log_level,
+            log_format,
+        }
+    }
+
+    pub fn network(&self, saved: &NetworkSettings) -> NetworkSettings {
+        NetworkSettings {
+            bind_address: self.bind_address.clone().unwrap_or_else(|| saved.bind_address.clone()),
+            port: self.port.unwrap_or(saved.port),
+        }
+    }
+}
+
+fn command() -> Command {
+    Command::new("server")
+        .about("Receives Steam Deck controller input over WebSocket and feeds it to a virtual controller")
+        .version(env!("CARGO_PKG_VERSION"))
+        .arg(Arg::new("bind")
+            .long("bind")
+            .value_name("ADDRESS")
+            .help("Address to listen on, use the PC's LAN address to refuse connections from other networks [default: the saved one, or 0.0.0.0]"))
+        .arg(Arg::new("port")
+            .long("port")
+            .short('p')
+            .value_name("PORT")
+            .value_parser(value_parser!(i32).range(1..=65535))
+            .help("Port to listen on, must match the one entered on the Deck [default: the saved one, or 8080]"))
+        .arg(Arg::new("headless")
+            .long("headless")
+            .action(ArgAction::SetTrue)
+            .help("Run without a window, events are only logged. Stop with Ctrl+C"))
+        .arg(Arg::new("controller")
+            .long("controller")
+            .value_name("TYPE")
+            .value_parser(["xbox360", "none"])
+            .default_value("xbox360")
+            .help("Virtual controller to plug in, none only monitors input"))
+        .arg(Arg::new("profile")
+            .long("profile")
+            .value_name("FILE")
+            .value_parser(value_parser!(PathBuf))
+            .help("Settings file to load and save instead of settings.toml in the config directory"))
+        .arg(Arg::new("log-level")
+            .long("log-level")
+            .value_name("LEVEL")
+            .value_parser(["off", "error", "warn", "info", "debug", "trace"])
+            .help("Log verbosity, takes precedence over RUST_LOG [default: info]"))
+        .arg(Arg::new("log-format")
+            .long("log-format")
+            .value_name("FORMAT")
+            .value_parser(["text", "json"])
+            .default_value("text")
+            .help("Terminal log format, json writes one object per line with the event's fields"))
+}
+
@@ -0,0 +1,68 @@
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+// Recent probes kept per client, the one with the shortest round trip is trusted most
+const MAX_SAMPLES: usize = 8;
+
+// Milliseconds since the server started, on a clock NTP can't step backwards or forwards
+pub fn now_ms() -> u64 {
+    now_us() / 1000
+}
+
+// The same clock in microseconds, for the pipeline trace
+pub fn now_us() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockEstimate {
+    // Client clock minus server clock
+    pub offset_ms: i64,
+    pub round_trip_ms: u64,
+}
+
+impl ClockEstimate {
+    // Where a time the client stamped falls on our clock, in microseconds
+    pub fn to_server_us(self, client_ms: u64) -> u64 {
+        ((client_ms as i64 - self.offset_ms).max(0) as u64) * 1000
+    }
+}
+
+// Works out how a client's monotonic clock lines up with ours from probe round trips, assuming
+// the reply took as long to come back as the probe took to get there
+pub struct ClockSync {
+    samples: VecDeque<ClockEstimate>,
+}
+
+impl ClockSync {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn add_sample(&mut self, sent: u64, client_time: u64, received: u64) {
+        let round_trip_ms = received.saturating_sub(sent);
+        let midpoint = sent + round_trip_ms / 2;
+        self.samples.push_back(ClockEstimate {
+            offset_ms: client_time as i64 - midpoint as i64,
+            round_trip_ms,
+        });
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        self.samples.iter().min_by_key(|sample| sample.round_trip_ms).copied()
+    }
+
+    // How long ago on our clock the client stamped an event, None until a probe came back
+    pub fn delay_ms(&self, client_timestamp: u64, now: u64) -> Option<u64> {
+        let estimate = self.estimate()?;
+        let local_time = client_timestamp as i64 - estimate.offset_ms;
+        Some((now as i64 - local_time).max(0) as u64)
+    }
+}
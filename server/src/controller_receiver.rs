@@ -1,11 +1,26 @@
+use anyhow::Result;
 use imgui::*;
+use serde::Serialize;
 use std::collections::VecDeque;
-use std::time::{SystemTime, UNIX_EPOCH};
-use crate::{ControllerInputData, AxisEvent};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use crate::i18n::{self, tr};
+use crate::{AxisId, ButtonId, InputDigest};
+use crate::clock::ClockEstimate;
+use crate::link_quality::{LinkMonitor, LinkQuality};
+use crate::palette::{self, Status};
+use crate::pipeline_trace::{self, LatencyBudget};
+use crate::protocol::{BatteryReport, Capabilities, CapabilityReport, SENSOR_ACCELEROMETER, SENSOR_GYRO, SENSOR_TOUCHPAD};
+use crate::usage_stats::UsageStats;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ReceivedInputEvent {
     pub timestamp: u64,
+    // The client's ID of the message that carried it, the client's Input History shows the same one
+    pub message_id: u64,
+    // As stamped by the client, on its own monotonic clock
+    pub client_timestamp: u64,
     pub controller_id: u32,
     pub event_type: String,
     pub details: String,
@@ -14,47 +29,154 @@ pub struct ReceivedInputEvent {
 
 pub struct ControllerReceiver {
     connected_clients: u32,
+    clients: Vec<std::net::SocketAddr>,
     total_events_received: u64,
+    total_messages_received: u64,
+    dropped_axis_events: u64,
     recent_events: VecDeque<ReceivedInputEvent>,
     max_events: usize,
+    // Events older than this are dropped, 0 keeps them regardless of age
+    max_event_age_secs: u64,
     server_status: String,
     last_received_timestamp: u64,
+    last_client_timestamp: u64,
+    clock: Option<ClockEstimate>,
+    link: LinkMonitor,
     // Callback to send trigger events to virtual controller
-    trigger_callback: Option<Box<dyn Fn(&str, f32) + Send + Sync>>,
+    trigger_callback: Option<Box<dyn Fn(AxisId, f32) + Send + Sync>>,
+    // Result of the last export, shown in the Controller Events window
+    export_status: Option<String>,
+    usage_stats: UsageStats,
+    // Reported by each connected client for each of its controllers
+    devices: Vec<(std::net::SocketAddr, CapabilityReport)>,
+    // Only from wireless controllers, wired ones never report
+    batteries: Vec<(std::net::SocketAddr, BatteryReport)>,
 }
 
 impl ControllerReceiver {
     pub fn new() -> Self {
         Self {
             connected_clients: 0,
+            clients: Vec::new(),
             total_events_received: 0,
+            total_messages_received: 0,
+            dropped_axis_events: 0,
             recent_events: VecDeque::new(),
             max_events: 100,
+            max_event_age_secs: 0,
             server_status: "Starting...".to_string(),
             last_received_timestamp: 0,
+            last_client_timestamp: 0,
+            clock: None,
+            link: LinkMonitor::new(),
             trigger_callback: None,
+            export_status: None,
+            usage_stats: UsageStats::new(),
+            devices: Vec::new(),
+            batteries: Vec::new(),
         }
     }
 
+    pub fn set_server_status(&mut self, status: impl Into<String>) {
+        self.server_status = status.into();
+    }
+
     pub fn update(&mut self) {
-        self.server_status = "Listening on 192.168.1.185:8080".to_string();
+        self.prune_events();
+    }
+
+    fn prune_events(&mut self) {
+        while self.recent_events.len() > self.max_events {
+            self.recent_events.pop_front();
+        }
+
+        if self.max_event_age_secs > 0 {
+            let current_time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64;
+            let cutoff = current_time.saturating_sub(self.max_event_age_secs * 1000);
+
+            while self.recent_events.front().is_some_and(|e| e.timestamp < cutoff) {
+                self.recent_events.pop_front();
+            }
+        }
+    }
+
+    pub fn client_connected(&mut self, addr: std::net::SocketAddr) {
+        self.connected_clients += 1;
+        self.clients.push(addr);
+    }
+
+    pub fn client_disconnected(&mut self, addr: std::net::SocketAddr) {
+        self.connected_clients = self.connected_clients.saturating_sub(1);
+        self.clients.retain(|client| *client != addr);
+        if self.clients.is_empty() {
+            self.link.clear();
+        }
+        self.devices.retain(|(device_addr, _)| *device_addr != addr);
+        self.batteries.retain(|(device_addr, _)| *device_addr != addr);
+    }
+
+    // Addresses of the connected clients, a client connected twice counts once
+    pub fn client_ips(&self) -> Vec<std::net::IpAddr> {
+        let mut ips: Vec<std::net::IpAddr> = self.clients.iter().map(|client| client.ip()).collect();
+        ips.sort();
+        ips.dedup();
+        ips
+    }
+
+    pub fn set_capabilities(&mut self, addr: std::net::SocketAddr, report: CapabilityReport) {
+        self.devices.retain(|(device_addr, device)| (*device_addr, device.controller_id) != (addr, report.controller_id));
+        self.devices.push((addr, report));
+    }
+
+    // Returns true when the controller just ran low, so the warning is given once and not on
+    // every further drop
+    pub fn set_battery(&mut self, addr: std::net::SocketAddr, report: BatteryReport) -> bool {
+        let key = (addr, report.controller_id);
+        let was_low = self.batteries.iter().any(|(device_addr, device)| (*device_addr, device.controller_id) == key && device.is_low());
+        self.batteries.retain(|(device_addr, device)| (*device_addr, device.controller_id) != key);
+        self.batteries.push((addr, report));
+        report.is_low() && !was_low
+    }
+
+    // Every connected client's controllers
+    pub fn devices(&self) -> &[(std::net::SocketAddr, CapabilityReport)] {
+        &self.devices
     }
 
-    pub fn add_controller_event(&mut self, data: ControllerInputData) {
+    // Everything the connected controllers can produce together, None until one has reported
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.devices.iter().map(|(_, report)| report.capabilities).reduce(Capabilities::union)
+    }
+
+    pub fn add_controller_event(&mut self, digest: InputDigest) {
         let current_time = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_millis() as u64;
         
-        let delay = if data.timestamp < current_time {
-            current_time - data.timestamp
-        } else {
-            0
-        };
+        let delay = digest.delay_ms;
+        let data = digest.data;
+        self.last_client_timestamp = data.timestamp;
+        self.clock = digest.clock;
+        self.total_messages_received += digest.message_count as u64;
+        self.dropped_axis_events += digest.dropped_axis_events as u64;
+        let now = Instant::now();
+        // The delays are one way, the score goes by round trips
+        for &delay in &digest.delays_ms {
+            self.link.round_trip(now, delay as f64 * 2.0);
+        }
+        self.link.messages(now, digest.message_count as u64, digest.missing_messages);
+
+        self.usage_stats.record(&data);
 
-        for button_event in &data.button_events {
+        for (button_event, &message_id) in data.button_events.iter().zip(&digest.button_message_ids) {
             let event = ReceivedInputEvent {
                 timestamp: current_time,
+                message_id,
+                client_timestamp: button_event.timestamp,
                 controller_id: data.controller_id,
                 event_type: "Button".to_string(),
                 details: format!("{} - {}", 
@@ -67,22 +189,24 @@ impl ControllerReceiver {
             self.total_events_received += 1;
             
             // Todo fix RT/LT triggers
-            if button_event.button.contains("RT [ID: 7]") || button_event.button.contains("Right Trigger") {
+            if button_event.button == ButtonId::RightTrigger {
                 log::info!("RT digital button event: {} -> {}", button_event.button, button_event.pressed);
                 if let Some(ref callback) = self.trigger_callback {
-                    callback("RT Axis", if button_event.pressed { 1.0 } else { 0.0 });
+                    callback(AxisId::RightTrigger, if button_event.pressed { 1.0 } else { 0.0 });
                 }
-            } else if button_event.button.contains("LT [ID: 6]") || button_event.button.contains("Left Trigger") {
+            } else if button_event.button == ButtonId::LeftTrigger {
                 log::info!("LT digital button event: {} -> {}", button_event.button, button_event.pressed);
                 if let Some(ref callback) = self.trigger_callback {
-                    callback("LT Axis", if button_event.pressed { 1.0 } else { 0.0 });
+                    callback(AxisId::LeftTrigger, if button_event.pressed { 1.0 } else { 0.0 });
                 }
             }
         }
 
-        for axis_event in &data.axis_events {
+        for (axis_event, &message_id) in data.axis_events.iter().zip(&digest.axis_message_ids) {
             let event = ReceivedInputEvent {
                 timestamp: current_time,
+                message_id,
+                client_timestamp: axis_event.timestamp,
                 controller_id: data.controller_id,
                 event_type: "Axis".to_string(),
                 details: format!("{} - {:.3}", axis_event.axis, axis_event.value),
@@ -92,70 +216,119 @@ impl ControllerReceiver {
             self.recent_events.push_back(event);
             self.total_events_received += 1;
             
-            if axis_event.axis.contains("RightZ") || axis_event.axis.contains("Right Trigger") {
+            if axis_event.axis == AxisId::RightTrigger {
                 if axis_event.value > 0.1 {
                     log::info!("RT pressed - setting Xbox 360 RT to 100%");
                     if let Some(ref callback) = self.trigger_callback {
-                        callback("RT Axis", 1.0); // Set to 100%
+                        callback(AxisId::RightTrigger, 1.0); // Set to 100%
                     }
                 } else {
                     log::info!("RT released - setting Xbox 360 RT to 0%");
                     if let Some(ref callback) = self.trigger_callback {
-                        callback("RT Axis", 0.0); // Set to 0%
+                        callback(AxisId::RightTrigger, 0.0); // Set to 0%
                     }
                 }
-            } else if axis_event.axis.contains("LeftZ") || axis_event.axis.contains("Left Trigger") {
+            } else if axis_event.axis == AxisId::LeftTrigger {
                 if axis_event.value > 0.1 {
                     log::info!("LT pressed - setting Xbox 360 LT to 100%");
                     if let Some(ref callback) = self.trigger_callback {
-                        callback("LT Axis", 1.0); // Set to 100%
+                        callback(AxisId::LeftTrigger, 1.0); // Set to 100%
                     }
                 } else {
                     log::info!("LT released - setting Xbox 360 LT to 0%");
                     if let Some(ref callback) = self.trigger_callback {
-                        callback("LT Axis", 0.0); // Set to 0%
+                        callback(AxisId::LeftTrigger, 0.0); // Set to 0%
                     }
                 }
             }
         }
 
-        while self.recent_events.len() > self.max_events {
-            self.recent_events.pop_front();
-        }
+        self.prune_events();
 
         self.last_received_timestamp = current_time;
     }
 
+    pub fn clear_events(&mut self) {
+        self.recent_events.clear();
+    }
+
     pub fn set_trigger_callback<F>(&mut self, callback: F) 
     where
-        F: Fn(&str, f32) + Send + Sync + 'static,
+        F: Fn(AxisId, f32) + Send + Sync + 'static,
     {
         self.trigger_callback = Some(Box::new(callback));
     }
 
-    pub fn render(&mut self, ui: &Ui) {
+    pub fn export_events_csv(&self, path: &Path) -> Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(file, "timestamp,message_id,client_timestamp,controller_id,event_type,details,delay_ms")?;
+        for event in &self.recent_events {
+            writeln!(file, "{},{},{},{},{},\"{}\",{}",
+                event.timestamp,
+                event.message_id,
+                event.client_timestamp,
+                event.controller_id,
+                event.event_type,
+                event.details.replace('"', "\"\""),
+                event.delay_ms)?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn export_events_json(&self, path: &Path) -> Result<()> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer_pretty(file, &self.recent_events)?;
+        Ok(())
+    }
+
+    fn export_events(&mut self, extension: &str) {
+        let path = PathBuf::from(format!("controller_events_{}.{}",
+            chrono::Local::now().format("%Y%m%d_%H%M%S"),
+            extension));
+
+        let result = if extension == "csv" {
+            self.export_events_csv(&path)
+        } else {
+            self.export_events_json(&path)
+        };
+
+        self.export_status = Some(match result {
+            Ok(_) => {
+                log::info!("Exported {} events to {}", self.recent_events.len(), path.display());
+                format!("Exported {} events to {}", self.recent_events.len(), path.display())
+            }
+            Err(e) => {
+                log::error!("Failed to export events to {}: {}", path.display(), e);
+                format!("Export failed: {}", e)
+            }
+        });
+    }
+
+    pub fn render(&mut self, ui: &Ui, latency_budget: Option<LatencyBudget>) {
         ui.main_menu_bar(|| {
-            ui.menu("View", || {
-                ui.menu_item("Controller Events");
-                ui.menu_item("Server Status");
+            ui.menu(tr("View"), || {
+                ui.menu_item(tr("Controller Events"));
+                ui.menu_item(tr("Server Status"));
             });
         });
 
-        ui.window("Server Status")
+        ui.window(i18n::window_title("Server Status"))
             .size([400.0, 200.0], Condition::FirstUseEver)
             .build(|| {
-                ui.text("Steam Deck Controller Server");
+                ui.text(tr("Steam Deck Controller Server"));
                 ui.separator();
                 
-                let status_color = if self.server_status.contains("Listening") {
-                    [0.0, 1.0, 0.0, 1.0] // Green
-                } else {
-                    [1.0, 1.0, 0.0, 1.0] // Yellow
-                };
-                
-                ui.text_colored(status_color, &format!("Status: {}", self.server_status));
-                ui.text(&format!("Connected Clients: {}", self.connected_clients));
-                ui.text(&format!("Total Events Received: {}", self.total_events_received));
+                let status = if self.server_status.contains("Listening") { Status::Good } else { Status::Warning };
+                palette::status_text(ui, status, format!("{} {}", tr("Status:"), self.server_status));
+                ui.text(format!("{} {}", tr("Connected Clients:"), self.connected_clients));
+                if let Some(quality) = self.link.quality(Instant::now()) {
+                    palette::status_text(ui, link_quality_status(&quality), format!("{} {} ({}/100)", tr("Connection Quality:"), tr(quality.label()), quality.score));
+                }
+                ui.text(format!("{} {}", tr("Total Messages Received:"), self.total_messages_received));
+                ui.text(format!("{} {}", tr("Total Events Received:"), self.total_events_received));
+                // Only the display is affected, the virtual controller sees every update
+                ui.text(&format!("Coalesced Axis Updates: {}", self.dropped_axis_events));
                 
                 if self.last_received_timestamp > 0 {
                     let current_time = SystemTime::now()
@@ -166,27 +339,62 @@ impl ControllerReceiver {
                     let seconds_since_last = (current_time - self.last_received_timestamp) / 1000;
                     ui.text(&format!("Last Event: {}s ago", seconds_since_last));
                 }
+
+                for (addr, report) in &self.devices {
+                    let label = format!("Controller {} on {}", report.controller_id, addr.ip());
+                    if ui.collapsing_header(&label, TreeNodeFlags::empty()) {
+                        render_capabilities(ui, &report.capabilities);
+                    }
+                }
+
+                for (addr, report) in &self.batteries {
+                    let text = format!("{} {} ({}): {}%{}", tr("Battery"), report.controller_id, addr.ip(), report.battery_percent,
+                        if report.charging { format!(", {}", tr("charging")) } else { String::new() });
+                    let status = if report.is_low() { Status::Warning } else { Status::Good };
+                    palette::status_text(ui, status, text);
+                }
             });
 
-        ui.window("Controller Events")
+        ui.window(i18n::window_title("Controller Events"))
             .size([800.0, 600.0], Condition::FirstUseEver)
             .build(|| {
-                ui.text("Real-time Controller Input from Steam Deck");
+                ui.text(tr("Real-time Controller Input from Steam Deck"));
                 ui.separator();
                 
-                if ui.button("Clear Events") {
-                    self.recent_events.clear();
+                if ui.button(tr("Clear Events")) {
+                    self.clear_events();
+                }
+                
+                ui.same_line();
+                if ui.button("Export CSV") {
+                    self.export_events("csv");
+                }
+                ui.same_line();
+                if ui.button("Export JSON") {
+                    self.export_events("json");
                 }
                 
                 ui.same_line();
                 ui.text(&format!("({} events)", self.recent_events.len()));
                 
+                if let Some(ref status) = self.export_status {
+                    ui.text_disabled(status);
+                }
+                
+                ui.set_next_item_width(150.0);
+                ui.slider("Max events", 10, 10000, &mut self.max_events);
+                ui.same_line();
+                ui.set_next_item_width(150.0);
+                ui.slider("Max age (s, 0 = unlimited)", 0, 3600, &mut self.max_event_age_secs);
+                
                 ui.separator();
                 
                 // Table headers
-                ui.columns(5, "events_table", true);
+                ui.columns(6, "events_table", true);
                 ui.text("Timestamp");
                 ui.next_column();
+                ui.text("Message");
+                ui.next_column();
                 ui.text("Controller");
                 ui.next_column();
                 ui.text("Type");
@@ -201,16 +409,22 @@ impl ControllerReceiver {
                 for event in self.recent_events.iter().rev() {
                     // Color code by delay
                     let delay_color = if event.delay_ms < 10 {
-                        [0.0, 1.0, 0.0, 1.0] // Green - excellent
+                        palette::color(Status::Good)
                     } else if event.delay_ms < 50 {
-                        [1.0, 1.0, 0.0, 1.0] // Yellow - good
+                        palette::color(Status::Warning)
                     } else {
-                        [1.0, 0.0, 0.0, 1.0] // Red - poor
+                        palette::color(Status::Bad)
                     };
                     
                     let timestamp_str = format!("{:.3}", (event.timestamp % 100000) as f64 / 1000.0);
                     ui.text(&timestamp_str);
                     ui.next_column();
+
+                    // Clients that don't number their messages send 0
+                    if event.message_id > 0 {
+                        ui.text(format!("#{}", event.message_id));
+                    }
+                    ui.next_column();
                     
                     ui.text(&format!("{}", event.controller_id));
                     ui.next_column();
@@ -235,10 +449,12 @@ impl ControllerReceiver {
                 ui.columns(1, "", false);
             });
 
-        ui.window("Performance Statistics")
+        self.usage_stats.render(ui);
+
+        ui.window(i18n::window_title("Performance Statistics"))
             .size([400.0, 300.0], Condition::FirstUseEver)
             .build(|| {
-                ui.text("Network Performance");
+                ui.text(tr("Network Performance"));
                 ui.separator();
                 
                 if !self.recent_events.is_empty() {
@@ -261,23 +477,90 @@ impl ControllerReceiver {
                     
                     ui.separator();
                     
-                    // Simple delay quality indicator
-                    let quality = if avg_delay < 10.0 {
-                        ("Excellent", [0.0, 1.0, 0.0, 1.0])
-                    } else if avg_delay < 30.0 {
-                        ("Good", [1.0, 1.0, 0.0, 1.0])
-                    } else if avg_delay < 100.0 {
-                        ("Fair", [1.0, 0.5, 0.0, 1.0])
-                    } else {
-                        ("Poor", [1.0, 0.0, 0.0, 1.0])
-                    };
-                    
-                    ui.text("Connection Quality:");
-                    ui.text_colored(quality.1, quality.0);
+                    ui.text(tr("Connection Quality:"));
+                    match self.link.quality(Instant::now()) {
+                        Some(quality) => render_link_quality(ui, &quality),
+                        None => ui.text_disabled(tr("Not enough traffic to rate the connection yet")),
+                    }
+
+                    ui.separator();
+                    self.render_clock_sync(ui);
+
+                    ui.separator();
+                    ui.text(tr("Latency Budget:"));
+                    match &latency_budget {
+                        Some(budget) => pipeline_trace::render_latency_budget(ui, budget),
+                        None => ui.text_disabled(tr("Waiting for sampled messages, needs a synced clock and forwarding on")),
+                    }
                 } else {
                     ui.text("No events received yet...");
                     ui.text("Make sure the Steam Deck client is connected.");
                 }
             });
     }
+
+    // Delays are worked out on the server's clock, these show what went into that
+    fn render_clock_sync(&self, ui: &Ui) {
+        ui.text("Clock Sync:");
+        match self.clock {
+            Some(clock) => {
+                let corrected = self.last_client_timestamp as i64 - clock.offset_ms;
+                ui.text(format!("Client Offset: {:+}ms (round trip {}ms)", clock.offset_ms, clock.round_trip_ms));
+                ui.text(format!("Last Timestamp: {}ms raw, {}ms on the server clock", self.last_client_timestamp, corrected));
+            }
+            None => {
+                palette::status_text(ui, Status::Warning, "Not synced yet, delays read as 0");
+                ui.text(format!("Last Timestamp: {}ms raw", self.last_client_timestamp));
+            }
+        }
+    }
+}
+
+// Lists every control, greyed out where the device can't produce it
+fn render_capabilities(ui: &Ui, capabilities: &Capabilities) {
+    let _id = ui.push_id_ptr(capabilities);
+    let grid = |names: Vec<(&str, bool)>| {
+        for (index, (name, supported)) in names.into_iter().enumerate() {
+            if index % 4 != 0 {
+                ui.same_line_with_pos(110.0 * (index % 4) as f32 + 8.0);
+            }
+            if supported {
+                ui.text(name);
+            } else {
+                ui.text_disabled(name);
+            }
+        }
+    };
+    grid(ButtonId::ALL.iter().map(|&button| (button.name(), capabilities.has_button(button))).collect());
+    ui.separator();
+    grid(AxisId::ALL.iter().map(|&axis| (axis.name(), capabilities.has_axis(axis))).collect());
+    ui.separator();
+    grid(vec![
+        ("Gyro", capabilities.has_sensor(SENSOR_GYRO)),
+        ("Accelerometer", capabilities.has_sensor(SENSOR_ACCELEROMETER)),
+        ("Touchpad", capabilities.has_sensor(SENSOR_TOUCHPAD)),
+    ]);
+}
+
+// The score with the numbers behind it and what would help, the other side shows the same
+fn render_link_quality(ui: &Ui, quality: &LinkQuality) {
+    palette::status_text(ui, link_quality_status(quality), format!("{} ({}/100)", tr(quality.label()), quality.score));
+    let sample = &quality.sample;
+    ui.text_disabled(format!(
+        "RTT {:.1} ms, jitter {:.1} ms, loss {:.1}%, {:.0} msg/s",
+        sample.rtt_ms, sample.jitter_ms, sample.loss * 100.0, sample.messages_per_sec
+    ));
+    for hint in &quality.hints {
+        ui.bullet();
+        ui.same_line();
+        ui.text_wrapped(tr(hint));
+    }
+}
+
+fn link_quality_status(quality: &LinkQuality) -> Status {
+    match quality.score {
+        65.. => Status::Good,
+        40.. => Status::Warning,
+        _ => Status::Bad,
+    }
 }
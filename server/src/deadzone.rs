@@ -0,0 +1,248 @@
+use imgui::*;
+use serde::{Deserialize, Serialize};
+
+const PREVIEW_SIZE: f32 = 120.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DeadzoneShape {
+    // Measured on the distance from center, the stick has to leave a circle
+    Circular,
+    // Measured on each axis on its own, the stick has to leave a square
+    Square,
+    // Circular, and directions close to an axis snap onto it so a push forward stays straight
+    Axial,
+}
+
+impl DeadzoneShape {
+    pub const ALL: [DeadzoneShape; 3] = [DeadzoneShape::Circular, DeadzoneShape::Square, DeadzoneShape::Axial];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DeadzoneShape::Circular => "Circular",
+            DeadzoneShape::Square => "Square",
+            DeadzoneShape::Axial => "Axial Snap",
+        }
+    }
+}
+
+// How one stick's raw position turns into what the virtual stick reports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StickShaping {
+    pub shape: DeadzoneShape,
+    // Anything closer to center than this reads as centered
+    pub inner: f32,
+    // Anything further out than this reads as fully pushed
+    pub outer: f32,
+    // With Axial Snap, how many degrees either side of an axis snap onto it
+    pub snap_angle_deg: f32,
+    // Rescales so every direction reaches exactly full deflection at the outer deadzone, the
+    // square deadzone otherwise overshoots on the diagonals
+    pub circularity_correction: bool,
+}
+
+impl Default for StickShaping {
+    // No deadzone, only a corner push past full deflection is pulled back onto the circle
+    fn default() -> Self {
+        Self {
+            shape: DeadzoneShape::Circular,
+            inner: 0.0,
+            outer: 1.0,
+            snap_angle_deg: 15.0,
+            circularity_correction: false,
+        }
+    }
+}
+
+impl StickShaping {
+    fn rescale(&self, value: f32) -> f32 {
+        let range = (self.outer - self.inner).max(0.01);
+        ((value - self.inner) / range).clamp(0.0, 1.0)
+    }
+
+    pub fn apply(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let magnitude = (x * x + y * y).sqrt();
+        let radial = if magnitude > 0.0 {
+            let scale = self.rescale(magnitude) / magnitude;
+            [x * scale, y * scale]
+        } else {
+            [0.0, 0.0]
+        };
+
+        let [x, y] = match self.shape {
+            DeadzoneShape::Circular => radial,
+            DeadzoneShape::Square => [
+                self.rescale(x.abs()).copysign(x),
+                self.rescale(y.abs()).copysign(y),
+            ],
+            DeadzoneShape::Axial => {
+                let [x, y] = radial;
+                let angle = y.abs().atan2(x.abs()).to_degrees();
+                if angle < self.snap_angle_deg {
+                    [(x * x + y * y).sqrt().copysign(x), 0.0]
+                } else if angle > 90.0 - self.snap_angle_deg {
+                    [0.0, (x * x + y * y).sqrt().copysign(y)]
+                } else {
+                    [x, y]
+                }
+            }
+        };
+
+        let output_magnitude = (x * x + y * y).sqrt();
+        if self.circularity_correction && output_magnitude > 0.0 {
+            let scale = self.rescale(magnitude) / output_magnitude;
+            [x * scale, y * scale]
+        } else {
+            [x, y]
+        }
+    }
+
+    // Returns true when a setting changed
+    pub fn render(&mut self, ui: &Ui, raw: [f32; 2]) -> bool {
+        let mut changed = false;
+
+        ui.group(|| {
+            let mut index = DeadzoneShape::ALL.iter().position(|shape| *shape == self.shape).unwrap_or(0);
+            let names: Vec<&str> = DeadzoneShape::ALL.iter().map(|shape| shape.name()).collect();
+            ui.set_next_item_width(150.0);
+            if ui.combo_simple_string("Shape", &mut index, &names) {
+                self.shape = DeadzoneShape::ALL[index];
+                changed = true;
+            }
+            ui.set_next_item_width(150.0);
+            changed |= ui.slider_config("Inner", 0.0, 0.5).display_format("%.2f").build(&mut self.inner);
+            ui.set_next_item_width(150.0);
+            changed |= ui.slider_config("Outer", 0.5, 1.0).display_format("%.2f").build(&mut self.outer);
+            if self.shape == DeadzoneShape::Axial {
+                ui.set_next_item_width(150.0);
+                changed |= ui.slider_config("Snap Angle", 0.0, 40.0).display_format("%.0f deg").build(&mut self.snap_angle_deg);
+            }
+            changed |= ui.checkbox("Circularity correction", &mut self.circularity_correction);
+        });
+        ui.same_line();
+        self.render_preview(ui, raw);
+
+        changed
+    }
+
+    // Gray is where the stick is, green is what the virtual stick reports
+    fn render_preview(&self, ui: &Ui, raw: [f32; 2]) {
+        let origin = ui.cursor_screen_pos();
+        ui.dummy([PREVIEW_SIZE, PREVIEW_SIZE]);
+
+        let radius = PREVIEW_SIZE / 2.0;
+        let center = [origin[0] + radius, origin[1] + radius];
+        let point = |[x, y]: [f32; 2]| [center[0] + x * radius, center[1] - y * radius];
+        let draw_list = ui.get_window_draw_list();
+        draw_list.add_circle(center, radius, [0.7, 0.7, 0.7, 1.0]).build();
+        draw_list.add_circle(center, self.outer * radius, [0.4, 0.4, 0.4, 1.0]).build();
+
+        let deadzone_color = [1.0, 0.3, 0.3, 0.4];
+        match self.shape {
+            DeadzoneShape::Square => {
+                draw_list.add_rect(point([-self.inner, self.inner]), point([self.inner, -self.inner]), deadzone_color)
+                    .filled(true)
+                    .build();
+            }
+            DeadzoneShape::Circular | DeadzoneShape::Axial => {
+                draw_list.add_circle(center, self.inner * radius, deadzone_color).filled(true).build();
+            }
+        }
+        if self.shape == DeadzoneShape::Axial {
+            // The wedges around each axis that snap onto it
+            for axis_angle in [0.0f32, 90.0, 180.0, 270.0] {
+                for side in [-1.0, 1.0] {
+                    let angle = (axis_angle + side * self.snap_angle_deg).to_radians();
+                    draw_list.add_line(center, point([angle.cos(), angle.sin()]), [1.0, 1.0, 0.0, 0.3]).build();
+                }
+            }
+        }
+
+        draw_list.add_circle(point(raw), 5.0, [0.6, 0.6, 0.6, 1.0]).filled(true).build();
+        draw_list.add_circle(point(self.apply(raw)), 5.0, [0.0, 1.0, 0.0, 1.0]).filled(true).build();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Also used by the mapping and filter tests
+    pub(crate) fn assert_stick(actual: [f32; 2], expected: [f32; 2]) {
+        assert!(
+            (actual[0] - expected[0]).abs() < 1e-5 && (actual[1] - expected[1]).abs() < 1e-5,
+            "stick at {:?}, expected {:?}", actual, expected,
+        );
+    }
+
+    fn shaping(shape: DeadzoneShape) -> StickShaping {
+        StickShaping { shape, inner: 0.2, outer: 0.9, ..Default::default() }
+    }
+
+    // Between the deadzones, how far a stick pushed `raw` out reads
+    fn rescaled(raw: f32) -> f32 {
+        (raw - 0.2) / 0.7
+    }
+
+    #[test]
+    fn circular_deadzone_measures_the_distance_from_center() {
+        let stick = shaping(DeadzoneShape::Circular);
+
+        assert_stick(stick.apply([0.2, 0.0]), [0.0, 0.0]);
+        assert_stick(stick.apply([0.1, -0.1]), [0.0, 0.0]);
+        assert_stick(stick.apply([0.55, 0.0]), [0.5, 0.0]);
+        assert_stick(stick.apply([0.0, 0.9]), [0.0, 1.0]);
+        assert_stick(stick.apply([-0.95, 0.0]), [-1.0, 0.0]);
+
+        let diagonal = rescaled(0.5f32.hypot(0.5)) / 2f32.sqrt();
+        assert_stick(stick.apply([0.5, 0.5]), [diagonal, diagonal]);
+        // A corner push is pulled back onto the circle
+        let corner = 1.0 / 2f32.sqrt();
+        assert_stick(stick.apply([1.0, -1.0]), [corner, -corner]);
+    }
+
+    #[test]
+    fn square_deadzone_measures_each_axis_on_its_own() {
+        let mut stick = shaping(DeadzoneShape::Square);
+
+        assert_stick(stick.apply([0.15, 0.15]), [0.0, 0.0]);
+        // Inside the inner deadzone on one axis only, that axis alone reads as centered
+        assert_stick(stick.apply([0.19, 0.5]), [0.0, rescaled(0.5)]);
+        assert_stick(stick.apply([0.9, 0.0]), [1.0, 0.0]);
+        assert_stick(stick.apply([0.0, -0.95]), [0.0, -1.0]);
+
+        // Overshoots on the diagonals unless corrected
+        assert_stick(stick.apply([1.0, 1.0]), [1.0, 1.0]);
+        stick.circularity_correction = true;
+        let corner = 1.0 / 2f32.sqrt();
+        assert_stick(stick.apply([1.0, 1.0]), [corner, corner]);
+        assert_stick(stick.apply([0.9, 0.0]), [1.0, 0.0]);
+    }
+
+    #[test]
+    fn axial_deadzone_snaps_near_axis_directions_onto_the_axis() {
+        let stick = shaping(DeadzoneShape::Axial);
+
+        assert_stick(stick.apply([0.15, 0.05]), [0.0, 0.0]);
+        assert_stick(stick.apply([0.9, 0.0]), [1.0, 0.0]);
+        assert_stick(stick.apply([-0.95, 0.0]), [-1.0, 0.0]);
+
+        // Within 15 degrees of an axis
+        let near_axis = rescaled(0.5f32.hypot(0.1));
+        assert_stick(stick.apply([0.5, 0.1]), [near_axis, 0.0]);
+        assert_stick(stick.apply([0.1, -0.5]), [0.0, -near_axis]);
+        // Diagonals are left alone
+        let diagonal = rescaled(0.5f32.hypot(0.5)) / 2f32.sqrt();
+        assert_stick(stick.apply([-0.5, 0.5]), [-diagonal, diagonal]);
+    }
+
+    #[test]
+    fn default_stick_shaping_only_pulls_corners_back() {
+        let stick = StickShaping::default();
+
+        assert_stick(stick.apply([0.05, -0.3]), [0.05, -0.3]);
+        assert_stick(stick.apply([0.6, 0.6]), [0.6, 0.6]);
+        let corner = 1.0 / 2f32.sqrt();
+        assert_stick(stick.apply([1.0, 1.0]), [corner, corner]);
+    }
+}
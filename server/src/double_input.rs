@@ -0,0 +1,133 @@
+use anyhow::Result;
+use gilrs::Gilrs;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::protocol::CapabilityReport;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// ViGEm's Xbox 360 and DualShock 4 pads, those are this server's own output
+const VIRTUAL_PAD_IDS: [(u16, u16); 2] = [(0x045E, 0x028E), (0x054C, 0x05C4)];
+// What Steam creates on the PC for a controller it streams in, as with Remote Play
+const STEAM_VIRTUAL_GAMEPAD: (u16, u16) = (0x28DE, 0x11FF);
+const STEAM_DECK: (u16, u16) = (0x28DE, 0x1205);
+
+// A gamepad plugged into or emulated on this PC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalPad {
+    pub name: String,
+    // None where the platform doesn't expose them
+    pub usb_ids: Option<(u16, u16)>,
+}
+
+// A pad on this PC that is probably the same controller a client forwards, so games get its
+// input twice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DoubleInput {
+    pub local_name: String,
+    pub client: SocketAddr,
+    // What to do about it
+    pub hint: &'static str,
+}
+
+// Keeps the list of this PC's gamepads current on a thread of its own, since gilrs has to be
+// polled to notice pads coming and going
+pub struct LocalPads {
+    stop: Arc<AtomicBool>,
+    pads: Arc<Mutex<Vec<LocalPad>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LocalPads {
+    pub fn start() -> Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let pads = Arc::new(Mutex::new(Vec::new()));
+        let (ready_sender, ready_receiver) = std::sync::mpsc::channel();
+
+        let thread = {
+            let stop = stop.clone();
+            let pads = pads.clone();
+            std::thread::Builder::new()
+                .name("local-pads".to_string())
+                .spawn(move || {
+                    let mut gilrs = match Gilrs::new() {
+                        Ok(gilrs) => {
+                            let _ = ready_sender.send(Ok(()));
+                            gilrs
+                        }
+                        Err(e) => {
+                            let _ = ready_sender.send(Err(anyhow::anyhow!("Failed to list local controllers: {}", e)));
+                            return;
+                        }
+                    };
+                    while !stop.load(Ordering::SeqCst) {
+                        while gilrs.next_event().is_some() {}
+                        *pads.lock().unwrap() = gilrs.gamepads()
+                            .filter(|(_, gamepad)| gamepad.is_connected())
+                            .map(|(_, gamepad)| LocalPad {
+                                name: gamepad.name().to_string(),
+                                usb_ids: gamepad.vendor_id().zip(gamepad.product_id()),
+                            })
+                            .collect();
+                        std::thread::sleep(POLL_INTERVAL);
+                    }
+                })?
+        };
+        ready_receiver.recv()??;
+
+        Ok(Self {
+            stop,
+            pads,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn pads(&self) -> Vec<LocalPad> {
+        self.pads.lock().unwrap().clone()
+    }
+}
+
+impl Drop for LocalPads {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Matches by USB IDs, so two controllers of the same model count as well. Only the user can
+// tell those apart, which is why this warns instead of acting.
+pub fn detect(pads: &[LocalPad], devices: &[(SocketAddr, CapabilityReport)]) -> Vec<DoubleInput> {
+    let mut found: Vec<DoubleInput> = Vec::new();
+    for pad in pads {
+        let Some(pad_ids) = pad.usb_ids else {
+            continue;
+        };
+        if VIRTUAL_PAD_IDS.contains(&pad_ids) {
+            continue;
+        }
+        for (client, device) in devices {
+            let Some(device_ids) = device.usb_ids() else {
+                continue;
+            };
+            let hint = if pad_ids == device_ids {
+                "The same model is connected to this PC. If it's the same controller, disconnect it here or stop forwarding it."
+            } else if pad_ids == STEAM_VIRTUAL_GAMEPAD && device_ids == STEAM_DECK {
+                "Steam streams the Deck's controls to this PC too, as with Remote Play. Turn off controller input for the stream, or stop forwarding here."
+            } else {
+                continue;
+            };
+            if !found.iter().any(|double| double.local_name == pad.name && double.client == *client) {
+                found.push(DoubleInput {
+                    local_name: pad.name.clone(),
+                    client: *client,
+                    hint,
+                });
+            }
+        }
+    }
+    found
+}
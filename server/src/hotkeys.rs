@@ -0,0 +1,182 @@
+use global_hotkey::hotkey::HotKey;
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use crate::i18n;
+use crate::palette::{self, Status};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyAction {
+    ToggleForwarding,
+    ReconnectController,
+    ClearEvents,
+    // Starts a pipeline trace, or stops and saves the running one
+    ToggleTrace,
+    // Zero the virtual controller and ignore input until resumed
+    ReleaseAll,
+}
+
+impl HotkeyAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            HotkeyAction::ToggleForwarding => "Toggle Forwarding",
+            HotkeyAction::ReconnectController => "Reconnect Virtual Controller",
+            HotkeyAction::ClearEvents => "Clear Events",
+            HotkeyAction::ToggleTrace => "Start/Stop Trace",
+            HotkeyAction::ReleaseAll => "Release Everything",
+        }
+    }
+}
+
+// Hotkeys are written like "ctrl+alt+F12", modifiers first. Empty leaves the action without one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotkeySettings {
+    pub enabled: bool,
+    pub toggle_forwarding: String,
+    pub reconnect_controller: String,
+    pub clear_events: String,
+    pub toggle_trace: String,
+    pub release_all: String,
+}
+
+impl Default for HotkeySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            toggle_forwarding: "ctrl+alt+F8".to_string(),
+            reconnect_controller: "ctrl+alt+F9".to_string(),
+            clear_events: "ctrl+alt+F10".to_string(),
+            toggle_trace: "ctrl+alt+F11".to_string(),
+            release_all: "ctrl+alt+F12".to_string(),
+        }
+    }
+}
+
+impl HotkeySettings {
+    fn bindings(&self) -> Vec<(HotkeyAction, &str)> {
+        vec![
+            (HotkeyAction::ToggleForwarding, self.toggle_forwarding.as_str()),
+            (HotkeyAction::ReconnectController, self.reconnect_controller.as_str()),
+            (HotkeyAction::ClearEvents, self.clear_events.as_str()),
+            (HotkeyAction::ToggleTrace, self.toggle_trace.as_str()),
+            (HotkeyAction::ReleaseAll, self.release_all.as_str()),
+        ]
+    }
+
+    fn binding_mut(&mut self, action: HotkeyAction) -> &mut String {
+        match action {
+            HotkeyAction::ToggleForwarding => &mut self.toggle_forwarding,
+            HotkeyAction::ReconnectController => &mut self.reconnect_controller,
+            HotkeyAction::ClearEvents => &mut self.clear_events,
+            HotkeyAction::ToggleTrace => &mut self.toggle_trace,
+            HotkeyAction::ReleaseAll => &mut self.release_all,
+        }
+    }
+
+    // Returns true when a hotkey changed, registration errors come from the last apply
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool, hotkeys: &Hotkeys) -> bool {
+        let mut changed = false;
+        ui.window(i18n::window_title("Hotkeys"))
+            .size([420.0, 300.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                ui.text_wrapped("Work system-wide, also while a game has the keyboard and mouse.");
+                changed |= ui.checkbox("Enabled", &mut self.enabled);
+                ui.separator();
+
+                let actions: Vec<HotkeyAction> = self.bindings().into_iter().map(|(action, _)| action).collect();
+                for action in actions {
+                    let binding = self.binding_mut(action);
+                    ui.input_text(action.name(), binding).build();
+                    // Registered once editing is done, not on every keystroke
+                    changed |= ui.is_item_deactivated_after_edit();
+                    if !binding.is_empty() && binding.parse::<HotKey>().is_err() {
+                        palette::status_text(ui, Status::Bad, "Not a valid hotkey, e.g. ctrl+alt+F12");
+                    }
+                }
+
+                if let Some(error) = hotkeys.error() {
+                    ui.separator();
+                    palette::status_text(ui, Status::Bad, error);
+                }
+            });
+        changed
+    }
+}
+
+// The registered system-wide hotkeys. On Windows they are delivered through the window's
+// message loop, so this lives on the thread that runs the event loop.
+pub struct Hotkeys {
+    manager: Option<GlobalHotKeyManager>,
+    registered: Vec<(HotKey, HotkeyAction)>,
+    // Why the last apply didn't register everything
+    error: Option<String>,
+}
+
+impl Hotkeys {
+    pub fn new() -> Self {
+        let (manager, error) = match GlobalHotKeyManager::new() {
+            Ok(manager) => (Some(manager), None),
+            Err(e) => {
+                log::warn!("Global hotkeys unavailable: {}", e);
+                (None, Some(format!("Global hotkeys unavailable: {}", e)))
+            }
+        };
+        Self {
+            manager,
+            registered: Vec::new(),
+            error,
+        }
+    }
+
+    // Replaces whatever was registered before
+    pub fn apply(&mut self, settings: &HotkeySettings) {
+        let Some(manager) = &self.manager else {
+            return;
+        };
+        for (hotkey, _) in self.registered.drain(..) {
+            let _ = manager.unregister(hotkey);
+        }
+        self.error = None;
+        if !settings.enabled {
+            return;
+        }
+
+        let mut errors = Vec::new();
+        for (action, binding) in settings.bindings() {
+            if binding.is_empty() {
+                continue;
+            }
+            let result = binding.parse::<HotKey>()
+                .map_err(|e| e.to_string())
+                .and_then(|hotkey| manager.register(hotkey).map(|_| hotkey).map_err(|e| e.to_string()));
+            match result {
+                Ok(hotkey) => self.registered.push((hotkey, action)),
+                Err(e) => errors.push(format!("{} ({}): {}", action.name(), binding, e)),
+            }
+        }
+        if !errors.is_empty() {
+            log::warn!("Failed to register hotkeys: {}", errors.join(", "));
+            self.error = Some(errors.join("\n"));
+        }
+    }
+
+    // Actions whose hotkey was pressed since the last call
+    pub fn poll(&self) -> Vec<HotkeyAction> {
+        let mut actions = Vec::new();
+        while let Ok(event) = GlobalHotKeyEvent::receiver().try_recv() {
+            if event.state != HotKeyState::Pressed {
+                continue;
+            }
+            if let Some((_, action)) = self.registered.iter().find(|(hotkey, _)| hotkey.id() == event.id) {
+                actions.push(*action);
+            }
+        }
+        actions
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
@@ -0,0 +1,189 @@
+use imgui::Ui;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// UI languages. The English strings double as the keys, text without a translation stays English.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+}
+
+// Like the palette, windows look the language up at draw time
+static ACTIVE: AtomicU8 = AtomicU8::new(0);
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::German];
+
+    // Written in the language itself, so it can be found without reading the current one
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::German => "Deutsch",
+        }
+    }
+}
+
+pub fn set_language(language: Language) {
+    ACTIVE.store(language as u8, Ordering::Relaxed);
+}
+
+pub fn language() -> Language {
+    Language::ALL[ACTIVE.load(Ordering::Relaxed) as usize % Language::ALL.len()]
+}
+
+pub fn tr(text: &'static str) -> &'static str {
+    match language() {
+        Language::English => text,
+        Language::German => german(text).unwrap_or(text),
+    }
+}
+
+// imgui keys windows by their title, the English one stays the ID so saved positions
+// survive switching languages
+pub fn window_title(title: &'static str) -> String {
+    format!("{}###{}", tr(title), title)
+}
+
+// Picks the language in a settings window, returns true when it changed
+pub fn render_combo(ui: &Ui, language: &mut Language) -> bool {
+    let names: Vec<&str> = Language::ALL.iter().map(|l| l.name()).collect();
+    let mut index = Language::ALL.iter().position(|l| l == language).unwrap_or(0);
+    if ui.combo_simple_string(tr("Language"), &mut index, &names) {
+        *language = Language::ALL[index];
+        return true;
+    }
+    false
+}
+
+fn german(text: &str) -> Option<&'static str> {
+    Some(match text {
+        // Menus
+        "Settings" => "Einstellungen",
+        "UI Settings" => "Oberfläche",
+        "Mapping" => "Belegung",
+        "Stream Overlay" => "Stream-Overlay",
+        "Scripting" => "Skripte",
+        "Hotkeys" => "Tastenkürzel",
+        "Import / Export..." => "Import / Export...",
+        "Import Steam Config..." => "Steam-Konfiguration importieren...",
+        "Setup Wizard..." => "Einrichtungsassistent...",
+        "Performance Overlay" => "Leistungsanzeige",
+        "View" => "Ansicht",
+        "Log" => "Protokoll",
+        "Pipeline Trace" => "Pipeline-Trace",
+        "HID Passthrough" => "HID-Weiterleitung",
+        "Controller Events" => "Controller-Ereignisse",
+        "Server Status" => "Serverstatus",
+
+        // Windows
+        "Virtual Xbox Controller" => "Virtueller Xbox-Controller",
+        "Performance Statistics" => "Leistungsstatistik",
+        "Input Injection" => "Eingaben einspeisen",
+        "Import / Export Settings" => "Einstellungen importieren / exportieren",
+        "Import Steam Config" => "Steam-Konfiguration importieren",
+        "Setup" => "Einrichtung",
+        "Session Summary" => "Sitzungsübersicht",
+        "Usage Statistics" => "Nutzungsstatistik",
+        "Session History" => "Sitzungsverlauf",
+
+        // Settings
+        "Appearance" => "Darstellung",
+        "Theme" => "Design",
+        "Dark" => "Dunkel",
+        "Light" => "Hell",
+        "Classic" => "Klassisch",
+        "Status Colors" => "Statusfarben",
+        "Standard" => "Standard",
+        "Colorblind Friendly" => "Farbenblind-freundlich",
+        "High Contrast" => "Hoher Kontrast",
+        "Language" => "Sprache",
+        "Font Size" => "Schriftgröße",
+        "UI Scale" => "Skalierung",
+        "Performance" => "Leistung",
+        "Update Rate (Hz)" => "Abfragerate (Hz)",
+        "Max FPS" => "Max. FPS",
+        "Reset to Defaults" => "Zurücksetzen",
+        "Windows Notifications" => "Windows-Benachrichtigungen",
+        "Low controller batteries are also announced by Windows, which shows over fullscreen games" =>
+            "Fast leere Controller-Akkus meldet auch Windows, sichtbar über Spielen im Vollbild",
+
+        // Performance overlay
+        "Pipeline" => "Verarbeitung",
+        "Queues that stay full point at a backed-up pipeline rather than the network" =>
+            "Dauerhaft volle Warteschlangen deuten auf eine überlastete Verarbeitung statt auf das Netzwerk hin",
+        "peak" => "Spitze",
+        "Event Queue" => "Ereignis-Warteschlange",
+
+        // Server status
+        "Steam Deck Controller Server" => "Steam Deck Controller-Server",
+        "Status:" => "Status:",
+        "Connected Clients:" => "Verbundene Clients:",
+        "Total Messages Received:" => "Empfangene Nachrichten:",
+        "Total Events Received:" => "Empfangene Ereignisse:",
+        "Battery" => "Akku",
+        "charging" => "lädt",
+        "Real-time Controller Input from Steam Deck" => "Controller-Eingaben des Steam Deck in Echtzeit",
+        "Clear Events" => "Ereignisse leeren",
+        "Network Performance" => "Netzwerkleistung",
+        "Connection Quality:" => "Verbindungsqualität:",
+        "Latency Budget:" => "Latenzaufteilung:",
+        "Waiting for sampled messages, needs a synced clock and forwarding on" =>
+            "Warte auf Stichproben, braucht eine abgeglichene Uhr und aktive Weiterleitung",
+        "Excellent" => "Ausgezeichnet",
+        "Good" => "Gut",
+        "Fair" => "Mittel",
+        "Poor" => "Schlecht",
+        "Not enough traffic to rate the connection yet" => "Noch zu wenig Verkehr, um die Verbindung zu bewerten",
+        "Switch the Deck and the PC to 5 GHz Wi-Fi, or move closer to the router" =>
+            "Deck und PC auf 5-GHz-WLAN umstellen oder näher an den Router gehen",
+        "Pause downloads and streams on the same network, they make delays uneven" =>
+            "Downloads und Streams im selben Netzwerk pausieren, sie machen die Verzögerung ungleichmäßig",
+        "Messages are getting lost, try a less crowded Wi-Fi channel or connect the PC by cable" =>
+            "Nachrichten gehen verloren, einen weniger belegten WLAN-Kanal versuchen oder den PC per Kabel anschließen",
+        "Reduce the send rate, 125 Hz is plenty for most games" =>
+            "Die Senderate senken, 125 Hz reichen für die meisten Spiele",
+        "Raise the Axis Deadband so tiny stick movements aren't sent" =>
+            "Das Achsen-Totband erhöhen, damit winzige Stickbewegungen nicht gesendet werden",
+
+        // Virtual controller
+        "Virtual Controller: Connected" => "Virtueller Controller: Verbunden",
+        "Virtual Controller: Disconnected" => "Virtueller Controller: Getrennt",
+        "Forward to virtual controller" => "An virtuellen Controller weiterleiten",
+        "Spectating - incoming input is not forwarded" => "Zuschauen - eingehende Eingaben werden nicht weitergeleitet",
+        "Input blocked - everything was released" => "Eingaben gesperrt - alles wurde losgelassen",
+        "Resume Input" => "Eingaben fortsetzen",
+        "Release Everything" => "Alles loslassen",
+        "Active Buttons:" => "Gedrückte Tasten:",
+        "Axis Values:" => "Achsenwerte:",
+        "Reconnect Virtual Controller" => "Virtuellen Controller neu verbinden",
+        "Not responding:" => "Reagiert nicht:",
+        "Plugging it back in, next attempt in" => "Wird neu angeschlossen, nächster Versuch in",
+        "Clients" => "Clients",
+
+        // Session history
+        "Refresh" => "Aktualisieren",
+        "Group by Game" => "Nach Spiel gruppieren",
+        "No sessions saved yet" => "Noch keine Sitzungen gespeichert",
+        "(none)" => "(keins)",
+        "Profile" => "Profil",
+        "Game" => "Spiel",
+        "Weekly:" => "Wöchentlich:",
+        "Weekly: all sessions" => "Wöchentlich: alle Sitzungen",
+        "Average Latency (ms)" => "Durchschnittliche Latenz (ms)",
+        "Week" => "Woche",
+        "Sessions" => "Sitzungen",
+        "Hours" => "Stunden",
+        "Events" => "Ereignisse",
+        "Avg Latency" => "Mittlere Latenz",
+        "Double input:" => "Doppelte Eingabe:",
+        "The same model is connected to this PC. If it's the same controller, disconnect it here or stop forwarding it." =>
+            "Dasselbe Modell ist an diesem PC angeschlossen. Ist es derselbe Controller, hier trennen oder nicht mehr weiterleiten.",
+        "Steam streams the Deck's controls to this PC too, as with Remote Play. Turn off controller input for the stream, or stop forwarding here." =>
+            "Steam überträgt die Steuerung des Decks ebenfalls an diesen PC, wie bei Remote Play. Controller-Eingaben im Stream abschalten oder hier nicht mehr weiterleiten.",
+        "Stop Forwarding" => "Nicht mehr weiterleiten",
+        "Not the Same Controller" => "Nicht derselbe Controller",
+        _ => return None,
+    })
+}
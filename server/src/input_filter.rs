@@ -0,0 +1,378 @@
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+use crate::deadzone::StickShaping;
+use crate::input_id::{AxisId, ButtonId};
+use crate::mapping::{button_combo, MappingProfile};
+
+const LEFT_STICK: [AxisId; 2] = [AxisId::LeftStickX, AxisId::LeftStickY];
+const RIGHT_STICK: [AxisId; 2] = [AxisId::RightStickX, AxisId::RightStickY];
+const TRIGGERS: [AxisId; 2] = [AxisId::LeftTrigger, AxisId::RightTrigger];
+
+// Everything held and pushed on the virtual pad, after the mapping profile
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PadState {
+    pub buttons: HashMap<ButtonId, bool>,
+    pub axes: HashMap<AxisId, f32>,
+}
+
+impl PadState {
+    // None until the stick has moved, so an untouched stick stays out of the state
+    fn stick(&self, axes: [AxisId; 2]) -> Option<[f32; 2]> {
+        if !axes.iter().any(|axis| self.axes.contains_key(axis)) {
+            return None;
+        }
+        Some(axes.map(|axis| self.axes.get(&axis).copied().unwrap_or(0.0)))
+    }
+
+    fn set_stick(&mut self, axes: [AxisId; 2], value: [f32; 2]) {
+        for (axis, value) in axes.into_iter().zip(value) {
+            self.axes.insert(axis, value);
+        }
+    }
+}
+
+// One step between the mapping profile and the virtual pad. The whole chain runs on every update
+// of the pad, filters that change the output on their own, like turbo, ask for ticks meanwhile.
+pub trait InputFilter: Send {
+    fn process(&mut self, state: PadState, now: Instant) -> PadState;
+
+    fn needs_tick(&self) -> bool {
+        false
+    }
+}
+
+pub struct StickDeadzone {
+    axes: [AxisId; 2],
+    shaping: StickShaping,
+}
+
+impl StickDeadzone {
+    pub fn new(axes: [AxisId; 2], shaping: StickShaping) -> Self {
+        Self { axes, shaping }
+    }
+}
+
+impl InputFilter for StickDeadzone {
+    fn process(&mut self, mut state: PadState, _now: Instant) -> PadState {
+        if let Some(stick) = state.stick(self.axes) {
+            state.set_stick(self.axes, self.shaping.apply(stick));
+        }
+        state
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CurveTarget {
+    LeftStick,
+    RightStick,
+    Triggers,
+}
+
+impl CurveTarget {
+    const ALL: [CurveTarget; 3] = [CurveTarget::LeftStick, CurveTarget::RightStick, CurveTarget::Triggers];
+
+    fn name(self) -> &'static str {
+        match self {
+            CurveTarget::LeftStick => "Left Stick",
+            CurveTarget::RightStick => "Right Stick",
+            CurveTarget::Triggers => "Triggers",
+        }
+    }
+}
+
+pub struct ResponseCurve {
+    target: CurveTarget,
+    exponent: f32,
+}
+
+impl ResponseCurve {
+    pub fn new(target: CurveTarget, exponent: f32) -> Self {
+        Self { target, exponent }
+    }
+
+    fn curve(&self, value: f32) -> f32 {
+        value.clamp(0.0, 1.0).powf(self.exponent.max(0.1))
+    }
+}
+
+impl InputFilter for ResponseCurve {
+    fn process(&mut self, mut state: PadState, _now: Instant) -> PadState {
+        let axes = match self.target {
+            CurveTarget::LeftStick => LEFT_STICK,
+            CurveTarget::RightStick => RIGHT_STICK,
+            CurveTarget::Triggers => {
+                for axis in TRIGGERS {
+                    if let Some(value) = state.axes.get_mut(&axis) {
+                        *value = self.curve(*value);
+                    }
+                }
+                return state;
+            }
+        };
+        // Curved by distance from the center, so the stick keeps pointing the same way
+        if let Some([x, y]) = state.stick(axes) {
+            let magnitude = (x * x + y * y).sqrt();
+            if magnitude > 0.0 {
+                let scale = self.curve(magnitude) / magnitude;
+                state.set_stick(axes, [x * scale, y * scale]);
+            }
+        }
+        state
+    }
+}
+
+pub struct Turbo {
+    button: ButtonId,
+    rate_hz: f32,
+    held_since: Option<Instant>,
+}
+
+impl Turbo {
+    pub fn new(button: ButtonId, rate_hz: f32) -> Self {
+        Self { button, rate_hz, held_since: None }
+    }
+}
+
+impl InputFilter for Turbo {
+    fn process(&mut self, mut state: PadState, now: Instant) -> PadState {
+        if state.buttons.get(&self.button) != Some(&true) {
+            self.held_since = None;
+            return state;
+        }
+        // Pressed for the first half of every cycle, starting with the press itself
+        let held_since = *self.held_since.get_or_insert(now);
+        let half_cycles = (now.duration_since(held_since).as_secs_f32() * self.rate_hz.max(1.0) * 2.0) as u64;
+        if half_cycles % 2 == 1 {
+            state.buttons.insert(self.button, false);
+        }
+        state
+    }
+
+    fn needs_tick(&self) -> bool {
+        self.held_since.is_some()
+    }
+}
+
+// The filters a profile adds after its deadzones, in the order they run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FilterConfig {
+    // Above 1 gives finer control near the center, below 1 makes small movements count more
+    Curve { target: CurveTarget, exponent: f32 },
+    // Presses the button over and over while it's held
+    Turbo { button: ButtonId, rate_hz: f32 },
+}
+
+impl FilterConfig {
+    fn create(&self) -> Box<dyn InputFilter> {
+        match *self {
+            FilterConfig::Curve { target, exponent } => Box::new(ResponseCurve::new(target, exponent)),
+            FilterConfig::Turbo { button, rate_hz } => Box::new(Turbo::new(button, rate_hz)),
+        }
+    }
+
+    // Returns true when a setting changed
+    fn render(&mut self, ui: &Ui) -> bool {
+        let mut changed = false;
+        match self {
+            FilterConfig::Curve { target, exponent } => {
+                ui.text("Curve");
+                ui.same_line();
+                let mut index = CurveTarget::ALL.iter().position(|t| t == target).unwrap_or(0);
+                let names: Vec<&str> = CurveTarget::ALL.iter().map(|t| t.name()).collect();
+                ui.set_next_item_width(110.0);
+                if ui.combo_simple_string("##target", &mut index, &names) {
+                    *target = CurveTarget::ALL[index];
+                    changed = true;
+                }
+                ui.same_line();
+                ui.set_next_item_width(100.0);
+                changed |= ui.slider_config("Exponent", 0.3, 3.0)
+                    .display_format("%.2f")
+                    .build(exponent);
+            }
+            FilterConfig::Turbo { button, rate_hz } => {
+                ui.text("Turbo");
+                ui.same_line();
+                ui.set_next_item_width(110.0);
+                changed |= button_combo(ui, "##button", button);
+                ui.same_line();
+                ui.set_next_item_width(100.0);
+                changed |= ui.slider_config("Rate", 2.0, 30.0)
+                    .display_format("%.0f Hz")
+                    .build(rate_hz);
+            }
+        }
+        changed
+    }
+}
+
+pub struct FilterChain {
+    filters: Vec<Box<dyn InputFilter>>,
+}
+
+impl FilterChain {
+    pub fn new() -> Self {
+        Self {
+            filters: Vec::new(),
+        }
+    }
+
+    // Deadzones run first, so curves and everything after them see the shaped sticks
+    pub fn from_profile(profile: &MappingProfile) -> Self {
+        let mut chain = Self::new();
+        chain.push(Box::new(StickDeadzone::new(LEFT_STICK, profile.left_stick.clone())));
+        chain.push(Box::new(StickDeadzone::new(RIGHT_STICK, profile.right_stick.clone())));
+        for filter in &profile.filters {
+            chain.push(filter.create());
+        }
+        chain
+    }
+
+    pub fn push(&mut self, filter: Box<dyn InputFilter>) {
+        self.filters.push(filter);
+    }
+
+    pub fn process(&mut self, state: PadState, now: Instant) -> PadState {
+        self.filters.iter_mut().fold(state, |state, filter| filter.process(state, now))
+    }
+
+    pub fn needs_tick(&self) -> bool {
+        self.filters.iter().any(|filter| filter.needs_tick())
+    }
+}
+
+// Returns true when the list changed
+pub fn render_filters(ui: &Ui, filters: &mut Vec<FilterConfig>) -> bool {
+    let mut changed = false;
+    let mut remove = None;
+    let mut move_up = None;
+    for (index, filter) in filters.iter_mut().enumerate() {
+        let _id = ui.push_id_usize(index);
+        changed |= filter.render(ui);
+        ui.same_line();
+        if index > 0 && ui.small_button("Up") {
+            move_up = Some(index);
+        }
+        ui.same_line();
+        if ui.small_button("Remove") {
+            remove = Some(index);
+        }
+    }
+    if let Some(index) = move_up {
+        filters.swap(index - 1, index);
+        changed = true;
+    }
+    if let Some(index) = remove {
+        filters.remove(index);
+        changed = true;
+    }
+
+    if ui.button("Add Curve") {
+        filters.push(FilterConfig::Curve { target: CurveTarget::LeftStick, exponent: 1.5 });
+        changed = true;
+    }
+    ui.same_line();
+    if ui.button("Add Turbo") {
+        filters.push(FilterConfig::Turbo { button: ButtonId::South, rate_hz: 10.0 });
+        changed = true;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::deadzone::tests::assert_stick;
+
+    fn pad_state(buttons: &[(ButtonId, bool)], axes: &[(AxisId, f32)]) -> PadState {
+        PadState {
+            buttons: buttons.iter().copied().collect(),
+            axes: axes.iter().copied().collect(),
+        }
+    }
+
+    fn left_stick(state: &PadState) -> [f32; 2] {
+        [state.axes[&AxisId::LeftStickX], state.axes[&AxisId::LeftStickY]]
+    }
+
+    #[test]
+    fn filter_chain_runs_filters_in_order() {
+        let deadzone = || Box::new(StickDeadzone::new(
+            LEFT_STICK,
+            StickShaping { inner: 0.2, ..Default::default() },
+        ));
+        let curve = || Box::new(ResponseCurve::new(CurveTarget::LeftStick, 2.0));
+        let now = Instant::now();
+        let pushed = || pad_state(&[], &[(AxisId::LeftStickX, 0.6), (AxisId::LeftStickY, 0.0)]);
+
+        let mut chain = FilterChain::new();
+        chain.push(deadzone());
+        chain.push(curve());
+        // 0.6 is halfway out of the deadzone, then squared
+        assert_stick(left_stick(&chain.process(pushed(), now)), [0.25, 0.0]);
+
+        let mut chain = FilterChain::new();
+        chain.push(curve());
+        chain.push(deadzone());
+        assert_stick(left_stick(&chain.process(pushed(), now)), [0.2, 0.0]);
+
+        // A profile's deadzones go ahead of its own filters
+        let mut chain = FilterChain::from_profile(&MappingProfile {
+            left_stick: StickShaping { inner: 0.2, ..Default::default() },
+            filters: vec![FilterConfig::Curve { target: CurveTarget::LeftStick, exponent: 2.0 }],
+            ..Default::default()
+        });
+        assert_stick(left_stick(&chain.process(pushed(), now)), [0.25, 0.0]);
+    }
+
+    #[test]
+    fn turbo_alternates_each_half_cycle_while_held() {
+        let ms = Duration::from_millis;
+        let mut turbo = Turbo::new(ButtonId::South, 10.0);
+        let start = Instant::now();
+        let mut south_at = |pressed, at| turbo.process(pad_state(&[(ButtonId::South, pressed)], &[]), start + ms(at)).buttons[&ButtonId::South];
+
+        // Pressed for the first 50 ms of every 100, starting with the press
+        assert!(south_at(true, 0));
+        assert!(south_at(true, 40));
+        assert!(!south_at(true, 60));
+        assert!(!south_at(true, 90));
+        assert!(south_at(true, 110));
+        assert!(!south_at(true, 160));
+        assert!(!south_at(false, 170));
+        // A new press starts a new cycle
+        assert!(south_at(true, 180));
+        assert!(south_at(true, 220));
+        assert!(!south_at(true, 240));
+
+        assert!(turbo.needs_tick());
+        turbo.process(pad_state(&[(ButtonId::South, false)], &[]), start + ms(250));
+        assert!(!turbo.needs_tick());
+        // Other buttons are left alone
+        let state = turbo.process(pad_state(&[(ButtonId::East, true)], &[]), start + ms(300));
+        assert!(state.buttons[&ButtonId::East]);
+    }
+
+    #[test]
+    fn response_curves_keep_the_endpoints_and_the_direction() {
+        let now = Instant::now();
+        let mut curve = ResponseCurve::new(CurveTarget::LeftStick, 2.0);
+        let mut curved = |x, y| left_stick(&curve.process(pad_state(&[], &[(AxisId::LeftStickX, x), (AxisId::LeftStickY, y)]), now));
+
+        assert_stick(curved(0.0, 0.0), [0.0, 0.0]);
+        assert_stick(curved(1.0, 0.0), [1.0, 0.0]);
+        assert_stick(curved(-1.0, 0.0), [-1.0, 0.0]);
+        assert_stick(curved(0.0, -0.5), [0.0, -0.25]);
+        // Curved by the distance from center, so a diagonal keeps pointing the same way
+        assert_stick(curved(-0.3, 0.4), [-0.15, 0.2]);
+
+        let mut curve = ResponseCurve::new(CurveTarget::Triggers, 0.5);
+        let mut trigger = |value| curve.process(pad_state(&[], &[(AxisId::LeftTrigger, value)]), now).axes[&AxisId::LeftTrigger];
+        assert_eq!(trigger(0.0), 0.0);
+        assert_eq!(trigger(1.0), 1.0);
+        assert!((trigger(0.25) - 0.5).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,146 @@
+use imgui::*;
+use std::collections::HashMap;
+use crate::i18n;
+use crate::{AxisEvent, AxisId, ButtonEvent, ButtonId, ControllerInputData};
+use crate::clock;
+
+const STICK_SIZE: f32 = 100.0;
+
+// Laid out roughly like a pad
+const BUTTON_ROWS: [&[ButtonId]; 5] = [
+    &[ButtonId::LeftBumper, ButtonId::RightBumper, ButtonId::Select, ButtonId::Start, ButtonId::Guide],
+    &[ButtonId::DPadUp, ButtonId::North],
+    &[ButtonId::DPadLeft, ButtonId::DPadRight, ButtonId::West, ButtonId::East],
+    &[ButtonId::DPadDown, ButtonId::South],
+    &[ButtonId::LeftStick, ButtonId::RightStick],
+];
+
+pub struct InputInjector {
+    held_buttons: HashMap<ButtonId, bool>,
+    left_stick: [f32; 2],
+    right_stick: [f32; 2],
+    left_trigger: f32,
+    right_trigger: f32,
+}
+
+impl InputInjector {
+    pub fn new() -> Self {
+        Self {
+            held_buttons: HashMap::new(),
+            left_stick: [0.0, 0.0],
+            right_stick: [0.0, 0.0],
+            left_trigger: 0.0,
+            right_trigger: 0.0,
+        }
+    }
+
+    // Returns the input to feed to the virtual controller when anything changed this frame
+    pub fn render(&mut self, ui: &Ui) -> Option<ControllerInputData> {
+        let timestamp = clock::now_ms();
+
+        let mut data = ControllerInputData {
+            message_id: 0,
+            timestamp,
+            sent_at: 0,
+            controller_id: 0,
+            button_events: Vec::new(),
+            axis_events: Vec::new(),
+        };
+
+        ui.window(i18n::window_title("Input Injection"))
+            .size([420.0, 480.0], Condition::FirstUseEver)
+            .build(|| {
+                ui.text_wrapped("Hold buttons or drag the sticks to drive the virtual controller without a Deck connected.");
+                ui.separator();
+
+                for row in BUTTON_ROWS {
+                    for (index, &button) in row.iter().enumerate() {
+                        if index > 0 {
+                            ui.same_line();
+                        }
+                        ui.button_with_size(button.name(), [75.0, 0.0]);
+                        let held = ui.is_item_active();
+                        let was_held = self.held_buttons.insert(button, held).unwrap_or(false);
+                        if held != was_held {
+                            data.button_events.push(ButtonEvent {
+                                button,
+                                pressed: held,
+                                timestamp,
+                            });
+                        }
+                    }
+                }
+
+                ui.separator();
+
+                if ui.slider("LT", 0.0, 1.0, &mut self.left_trigger) {
+                    data.axis_events.push(AxisEvent { axis: AxisId::LeftTrigger, value: self.left_trigger, timestamp });
+                }
+                if ui.slider("RT", 0.0, 1.0, &mut self.right_trigger) {
+                    data.axis_events.push(AxisEvent { axis: AxisId::RightTrigger, value: self.right_trigger, timestamp });
+                }
+
+                ui.separator();
+
+                ui.text("Left Stick");
+                ui.same_line_with_pos(STICK_SIZE + 30.0);
+                ui.text("Right Stick");
+
+                if stick_widget(ui, "##left_stick", &mut self.left_stick) {
+                    data.axis_events.push(AxisEvent { axis: AxisId::LeftStickX, value: self.left_stick[0], timestamp });
+                    data.axis_events.push(AxisEvent { axis: AxisId::LeftStickY, value: self.left_stick[1], timestamp });
+                }
+                ui.same_line_with_pos(STICK_SIZE + 30.0);
+                if stick_widget(ui, "##right_stick", &mut self.right_stick) {
+                    data.axis_events.push(AxisEvent { axis: AxisId::RightStickX, value: self.right_stick[0], timestamp });
+                    data.axis_events.push(AxisEvent { axis: AxisId::RightStickY, value: self.right_stick[1], timestamp });
+                }
+
+                ui.text(&format!("({:.2}, {:.2})", self.left_stick[0], self.left_stick[1]));
+                ui.same_line_with_pos(STICK_SIZE + 30.0);
+                ui.text(&format!("({:.2}, {:.2})", self.right_stick[0], self.right_stick[1]));
+            });
+
+        if data.button_events.is_empty() && data.axis_events.is_empty() {
+            None
+        } else {
+            Some(data)
+        }
+    }
+}
+
+// Drag inside the circle to deflect the stick, releasing springs it back to center
+fn stick_widget(ui: &Ui, id: &str, value: &mut [f32; 2]) -> bool {
+    let origin = ui.cursor_screen_pos();
+    ui.invisible_button(id, [STICK_SIZE, STICK_SIZE]);
+
+    let new_value = if ui.is_item_active() {
+        let mouse = ui.io().mouse_pos;
+        let mut x = (mouse[0] - origin[0]) / STICK_SIZE * 2.0 - 1.0;
+        // Screen Y grows downwards, stick Y grows upwards
+        let mut y = 1.0 - (mouse[1] - origin[1]) / STICK_SIZE * 2.0;
+        let magnitude = (x * x + y * y).sqrt();
+        if magnitude > 1.0 {
+            x /= magnitude;
+            y /= magnitude;
+        }
+        [x, y]
+    } else {
+        [0.0, 0.0]
+    };
+
+    let changed = new_value != *value;
+    *value = new_value;
+
+    let radius = STICK_SIZE / 2.0;
+    let center = [origin[0] + radius, origin[1] + radius];
+    let draw_list = ui.get_window_draw_list();
+    draw_list.add_circle(center, radius, [0.7, 0.7, 0.7, 1.0]).build();
+    draw_list.add_circle(
+        [center[0] + value[0] * radius, center[1] - value[1] * radius],
+        8.0,
+        [0.0, 0.8, 1.0, 1.0],
+    ).filled(true).build();
+
+    changed
+}
@@ -0,0 +1,146 @@
+use serde_json::{json, Map, Value};
+use std::io::Write;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+use tracing_subscriber::Layer;
+
+// One JSON object per line on stdout for jq or a log shipper, laid out like tracing-subscriber's
+// own JSON format. Events carry the fields of the spans they happened in, so everything inside a
+// connection has the client's address.
+pub struct JsonLayer;
+
+struct SpanFields {
+    fields: Map<String, Value>,
+    opened: Instant,
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for JsonLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = JsonVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(SpanFields {
+            fields: visitor.fields,
+            opened: Instant::now(),
+        });
+    }
+
+    // Fields declared Empty and filled in later, like a message's delay
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(span_fields) = extensions.get_mut::<SpanFields>() {
+            let mut visitor = JsonVisitor {
+                fields: std::mem::take(&mut span_fields.fields),
+                target: None,
+            };
+            values.record(&mut visitor);
+            span_fields.fields = visitor.fields;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+        let spans: Vec<Value> = ctx.event_scope(event)
+            .map(|scope| scope.from_root().map(|span| span_json(&span)).collect())
+            .unwrap_or_default();
+        let metadata = event.metadata();
+        write_line(json!({
+            "timestamp": timestamp(),
+            "level": metadata.level().as_str(),
+            "target": visitor.target.unwrap_or_else(|| metadata.target().to_string()),
+            "fields": visitor.fields,
+            "spans": spans,
+        }));
+    }
+
+    // Stands in for the text output's close lines, with how long the span was open
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let elapsed_us = span.extensions().get::<SpanFields>()
+            .map(|span_fields| span_fields.opened.elapsed().as_micros() as u64);
+        let spans: Vec<Value> = span.scope().from_root().map(|span| span_json(&span)).collect();
+        write_line(json!({
+            "timestamp": timestamp(),
+            "level": span.metadata().level().as_str(),
+            "target": span.metadata().target(),
+            "fields": { "message": "close", "elapsed_us": elapsed_us },
+            "spans": spans,
+        }));
+    }
+}
+
+fn span_json<S: for<'a> LookupSpan<'a>>(span: &SpanRef<'_, S>) -> Value {
+    let mut object = span.extensions().get::<SpanFields>()
+        .map(|span_fields| span_fields.fields.clone())
+        .unwrap_or_default();
+    object.insert("name".to_string(), Value::from(span.name()));
+    Value::Object(object)
+}
+
+fn timestamp() -> String {
+    chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, false)
+}
+
+// Whole lines only, so lines from different threads never interleave
+fn write_line(line: Value) {
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", line);
+}
+
+#[derive(Default)]
+struct JsonVisitor {
+    fields: Map<String, Value>,
+    // Where a log crate macro was called from, events forwarded from the log crate all have the
+    // same target otherwise
+    target: Option<String>,
+}
+
+impl JsonVisitor {
+    // Where a log crate macro was called from is left out, apart from the target
+    fn insert(&mut self, field: &Field, value: Value) {
+        if !field.name().starts_with("log.") {
+            self.fields.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+impl Visit for JsonVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "log.target" => self.target = Some(value.to_string()),
+            _ => self.insert(field, Value::from(value)),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.insert(field, Value::from(format!("{:?}", value)));
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::process::{Child, Command};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaunchSettings {
+    // A program and its arguments, or a URL such as steam://rungameid/<id>
+    pub command: String,
+    pub close_on_disconnect: bool,
+}
+
+impl LaunchSettings {
+    fn is_url(&self) -> bool {
+        self.command.trim().contains("://")
+    }
+
+    // What session history lists the game as: the URL, or the program's file name without its
+    // extension. None without a command.
+    pub fn game_name(&self) -> Option<String> {
+        let command = self.command.trim();
+        if command.is_empty() {
+            return None;
+        }
+        if self.is_url() {
+            return Some(command.to_string());
+        }
+        let program = split_command(command).into_iter().next()?;
+        // Split by hand, the path may be a Windows one while this runs elsewhere
+        let file_name = program.rsplit(['/', '\\']).next().unwrap_or(&program);
+        let stem = file_name.rsplit_once('.').map_or(file_name, |(stem, _)| stem);
+        Some(stem.to_string())
+    }
+
+    // Returns true when a setting changed
+    pub fn render(&mut self, ui: &Ui) -> bool {
+        let mut changed = ui.input_text("Launch command", &mut self.command)
+            .hint("steam://rungameid/1234 or \"C:\\Games\\game.exe\" -arg")
+            .build();
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Started on this PC when the Deck connects. Quote paths with spaces.");
+        }
+        if self.command.trim().is_empty() {
+            return changed;
+        }
+        if self.is_url() {
+            ui.text_disabled("Games started through a URL can't be closed again from here");
+        } else {
+            changed |= ui.checkbox("Close it when the Deck disconnects", &mut self.close_on_disconnect);
+            if ui.is_item_hovered() {
+                ui.tooltip_text("The game is ended like from the task manager, unsaved progress is lost");
+            }
+        }
+        changed
+    }
+}
+
+// Starts the active profile's game when the first client connects and, if asked to, ends it
+// again when the last one leaves
+pub struct GameLauncher {
+    clients: usize,
+    game: Option<Child>,
+}
+
+impl GameLauncher {
+    pub fn new() -> Self {
+        Self {
+            clients: 0,
+            game: None,
+        }
+    }
+
+    pub fn client_connected(&mut self, settings: &LaunchSettings) -> Result<()> {
+        self.clients += 1;
+        let command = settings.command.trim();
+        if self.clients > 1 || command.is_empty() || self.is_running() {
+            return Ok(());
+        }
+
+        if settings.is_url() {
+            open_url(command)?;
+        } else {
+            let parts = split_command(command);
+            let Some((program, args)) = parts.split_first() else {
+                return Ok(());
+            };
+            self.game = Some(Command::new(program).args(args).spawn()
+                .with_context(|| format!("Failed to launch {}", program))?);
+        }
+        log::info!("Launched {}", command);
+        Ok(())
+    }
+
+    pub fn client_disconnected(&mut self, settings: &LaunchSettings) -> Result<()> {
+        self.clients = self.clients.saturating_sub(1);
+        if self.clients > 0 || !settings.close_on_disconnect || !self.is_running() {
+            return Ok(());
+        }
+        if let Some(mut game) = self.game.take() {
+            game.kill().context("Failed to close the launched game")?;
+            let _ = game.wait();
+            log::info!("Closed the launched game");
+        }
+        Ok(())
+    }
+
+    // Reconnecting while the game is still up doesn't start a second copy
+    fn is_running(&mut self) -> bool {
+        match &mut self.game {
+            Some(game) => matches!(game.try_wait(), Ok(None)),
+            None => false,
+        }
+    }
+}
+
+// Splits on whitespace outside double quotes, like a shell would for a plain command line
+fn split_command(command: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut in_part = false;
+    for c in command.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_part = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_part {
+                    parts.push(std::mem::take(&mut current));
+                    in_part = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_part = true;
+            }
+        }
+    }
+    if in_part {
+        parts.push(current);
+    }
+    parts
+}
+
+// Hands the URL to whatever the system opens it with, e.g. Steam for steam:// links
+fn open_url(url: &str) -> Result<()> {
+    #[cfg(windows)]
+    let mut opener = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut opener = Command::new("open");
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let mut opener = Command::new("xdg-open");
+
+    opener.arg(url).spawn().with_context(|| format!("Failed to open {}", url))?;
+    Ok(())
+}
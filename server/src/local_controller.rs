@@ -0,0 +1,355 @@
+use anyhow::Result;
+use gilrs::{Axis, Button, EventType, Gilrs};
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use crate::clock;
+use crate::palette::{self, Status};
+use crate::input_id::{AxisId, ButtonId};
+use crate::protocol::{AxisEvent, ButtonEvent, ControllerInputData};
+use crate::virtual_controller::VirtualController;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+// What ViGEm's virtual Xbox 360 pad reports, merging it into itself would loop
+const VIRTUAL_PAD_IDS: (u16, u16) = (0x045E, 0x028E);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalControllerSettings {
+    pub enabled: bool,
+    // Picked by name since gilrs ids change between runs
+    pub controller_name: String,
+    // Sent to the connected Decks instead, which play it through a virtual pad of their own
+    pub stream_to_deck: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalControllerInfo {
+    pub name: String,
+    pub maybe_virtual: bool,
+}
+
+// Where the local controller's input goes
+struct Outputs {
+    virtual_controller: Arc<Mutex<VirtualController>>,
+    // Every client connection passes this on, see websocket_server::SharedController
+    pc_input: Arc<broadcast::Sender<ControllerInputData>>,
+}
+
+// Reads a controller plugged into this PC and merges it into the same virtual pad as the Deck,
+// so someone at the PC can take over or help out, or streams it to the Deck when that's the
+// machine running the game. Runs on its own thread since gilrs has to be polled and isn't Send
+// on every platform.
+pub struct LocalController {
+    stop: Arc<AtomicBool>,
+    controllers: Arc<Mutex<Vec<LocalControllerInfo>>>,
+    controller_name: Arc<Mutex<String>>,
+    stream_to_deck: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LocalController {
+    pub fn start(
+        virtual_controller: Arc<Mutex<VirtualController>>,
+        pc_input: Arc<broadcast::Sender<ControllerInputData>>,
+        settings: &LocalControllerSettings,
+    ) -> Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let controllers = Arc::new(Mutex::new(Vec::new()));
+        let controller_name = Arc::new(Mutex::new(settings.controller_name.clone()));
+        let stream_to_deck = Arc::new(AtomicBool::new(settings.stream_to_deck));
+        let (ready_sender, ready_receiver) = std::sync::mpsc::channel();
+
+        let thread = {
+            let stop = stop.clone();
+            let controllers = controllers.clone();
+            let controller_name = controller_name.clone();
+            let stream_to_deck = stream_to_deck.clone();
+            let outputs = Outputs { virtual_controller, pc_input };
+            std::thread::Builder::new()
+                .name("local-controller".to_string())
+                .spawn(move || {
+                    let gilrs = match Gilrs::new() {
+                        Ok(gilrs) => {
+                            let _ = ready_sender.send(Ok(()));
+                            gilrs
+                        }
+                        Err(e) => {
+                            let _ = ready_sender.send(Err(anyhow::anyhow!("Failed to read local controllers: {}", e)));
+                            return;
+                        }
+                    };
+                    poll(gilrs, &stop, &controllers, &controller_name, &stream_to_deck, &outputs);
+                })?
+        };
+        ready_receiver.recv()??;
+        log::info!("Reading local controllers");
+
+        Ok(Self {
+            stop,
+            controllers,
+            controller_name,
+            stream_to_deck,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn apply(&self, settings: &LocalControllerSettings) {
+        *self.controller_name.lock().unwrap() = settings.controller_name.clone();
+        self.stream_to_deck.store(settings.stream_to_deck, Ordering::SeqCst);
+    }
+
+    pub fn controllers(&self) -> Vec<LocalControllerInfo> {
+        self.controllers.lock().unwrap().clone()
+    }
+}
+
+impl Drop for LocalController {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// What the controller holds on the Deck, so it can be let go of there as well
+#[derive(Default)]
+struct Held {
+    buttons: HashMap<ButtonId, bool>,
+    axes: HashMap<AxisId, f32>,
+}
+
+impl Held {
+    fn apply(&mut self, data: &ControllerInputData) {
+        for event in &data.button_events {
+            self.buttons.insert(event.button, event.pressed);
+        }
+        for event in &data.axis_events {
+            self.axes.insert(event.axis, event.value);
+        }
+    }
+
+    fn release(&mut self) -> ControllerInputData {
+        let timestamp = clock::now_ms();
+        ControllerInputData {
+            message_id: 0,
+            timestamp,
+            sent_at: 0,
+            controller_id: 0,
+            button_events: self.buttons.drain()
+                .filter(|&(_, pressed)| pressed)
+                .map(|(button, _)| ButtonEvent { button, pressed: false, timestamp })
+                .collect(),
+            axis_events: self.axes.drain()
+                .filter(|&(_, value)| value != 0.0)
+                .map(|(axis, _)| AxisEvent { axis, value: 0.0, timestamp })
+                .collect(),
+        }
+    }
+}
+
+impl Outputs {
+    fn send(&self, data: ControllerInputData, streaming: bool, held: &mut Held) {
+        if streaming {
+            held.apply(&data);
+            // Nobody listening just means no Deck is connected
+            let _ = self.pc_input.send(data);
+        } else if let Err(e) = self.virtual_controller.lock().unwrap().process_local_input(data) {
+            log::error!("Failed to merge local controller input: {}", e);
+        }
+    }
+
+    fn release(&self, streaming: bool, held: &mut Held) {
+        if streaming {
+            let _ = self.pc_input.send(held.release());
+        } else {
+            self.virtual_controller.lock().unwrap().clear_local_input().ok();
+        }
+    }
+}
+
+fn poll(
+    mut gilrs: Gilrs,
+    stop: &AtomicBool,
+    controllers: &Mutex<Vec<LocalControllerInfo>>,
+    controller_name: &Mutex<String>,
+    stream_to_deck: &AtomicBool,
+    outputs: &Outputs,
+) {
+    let mut active = None;
+    let mut streaming = stream_to_deck.load(Ordering::SeqCst);
+    let mut held = Held::default();
+    *controllers.lock().unwrap() = list_controllers(&gilrs);
+    while !stop.load(Ordering::SeqCst) {
+        // Whatever is held stays behind where it was going until now
+        if stream_to_deck.load(Ordering::SeqCst) != streaming {
+            outputs.release(streaming, &mut held);
+            streaming = !streaming;
+            log::info!("Local controller {}", if streaming { "streams to the Deck" } else { "merges into the virtual controller" });
+        }
+
+
+        let mut data = ControllerInputData {
+            message_id: 0,
+            timestamp: clock::now_ms(),
+            sent_at: 0,
+            controller_id: 0,
+            button_events: Vec::new(),
+            axis_events: Vec::new(),
+        };
+
+        let mut connections_changed = false;
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::Connected | EventType::Disconnected => connections_changed = true,
+                _ if Some(event.id) == active => push_event(&mut data, event.event),
+                _ => {}
+            }
+        }
+
+        // Follows the chosen name, so the controller is picked up again after being replugged
+        let wanted = controller_name.lock().unwrap().clone();
+        let found = gilrs.gamepads()
+            .find(|(_, gamepad)| gamepad.is_connected() && !wanted.is_empty() && gamepad.name() == wanted)
+            .map(|(id, _)| id);
+        if found != active {
+            log::info!("Local controller {}", if found.is_some() { "connected" } else { "disconnected" });
+            active = found;
+            // Let go of whatever the previous controller was holding
+            outputs.release(streaming, &mut held);
+            connections_changed = true;
+        } else if !data.button_events.is_empty() || !data.axis_events.is_empty() {
+            outputs.send(data, streaming, &mut held);
+        }
+
+        if connections_changed {
+            *controllers.lock().unwrap() = list_controllers(&gilrs);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    outputs.release(streaming, &mut held);
+}
+
+fn list_controllers(gilrs: &Gilrs) -> Vec<LocalControllerInfo> {
+    gilrs.gamepads()
+        .filter(|(_, gamepad)| gamepad.is_connected())
+        .map(|(_, gamepad)| LocalControllerInfo {
+            name: gamepad.name().to_string(),
+            maybe_virtual: (gamepad.vendor_id(), gamepad.product_id()) == (Some(VIRTUAL_PAD_IDS.0), Some(VIRTUAL_PAD_IDS.1)),
+        })
+        .collect()
+}
+
+fn push_event(data: &mut ControllerInputData, event: EventType) {
+    let timestamp = data.timestamp;
+    match event {
+        EventType::ButtonPressed(button, _) | EventType::ButtonReleased(button, _) => {
+            // Triggers arrive as ButtonChanged with their analog value
+            if matches!(button, Button::LeftTrigger2 | Button::RightTrigger2) {
+                return;
+            }
+            if let Some(button_id) = button_to_id(button) {
+                let pressed = matches!(event, EventType::ButtonPressed(..));
+                data.button_events.push(ButtonEvent { button: button_id, pressed, timestamp });
+            }
+        }
+        EventType::ButtonChanged(Button::LeftTrigger2, value, _) => {
+            data.axis_events.push(AxisEvent { axis: AxisId::LeftTrigger, value, timestamp });
+        }
+        EventType::ButtonChanged(Button::RightTrigger2, value, _) => {
+            data.axis_events.push(AxisEvent { axis: AxisId::RightTrigger, value, timestamp });
+        }
+        EventType::AxisChanged(axis, value, _) => {
+            if let Some(axis_id) = axis_to_id(axis) {
+                data.axis_events.push(AxisEvent { axis: axis_id, value, timestamp });
+            }
+        }
+        _ => {}
+    }
+}
+
+fn button_to_id(button: Button) -> Option<ButtonId> {
+    match button {
+        Button::South => Some(ButtonId::South),
+        Button::East => Some(ButtonId::East),
+        Button::North => Some(ButtonId::North),
+        Button::West => Some(ButtonId::West),
+        Button::LeftTrigger => Some(ButtonId::LeftBumper),
+        Button::RightTrigger => Some(ButtonId::RightBumper),
+        Button::Select => Some(ButtonId::Select),
+        Button::Start => Some(ButtonId::Start),
+        Button::Mode => Some(ButtonId::Guide),
+        Button::LeftThumb => Some(ButtonId::LeftStick),
+        Button::RightThumb => Some(ButtonId::RightStick),
+        Button::DPadUp => Some(ButtonId::DPadUp),
+        Button::DPadDown => Some(ButtonId::DPadDown),
+        Button::DPadLeft => Some(ButtonId::DPadLeft),
+        Button::DPadRight => Some(ButtonId::DPadRight),
+        _ => None,
+    }
+}
+
+fn axis_to_id(axis: Axis) -> Option<AxisId> {
+    match axis {
+        Axis::LeftStickX => Some(AxisId::LeftStickX),
+        Axis::LeftStickY => Some(AxisId::LeftStickY),
+        Axis::LeftZ => Some(AxisId::LeftTrigger),
+        Axis::RightStickX => Some(AxisId::RightStickX),
+        Axis::RightStickY => Some(AxisId::RightStickY),
+        Axis::RightZ => Some(AxisId::RightTrigger),
+        _ => None,
+    }
+}
+
+impl LocalControllerSettings {
+    // Returns true when a setting changed
+    pub fn render(&mut self, ui: &Ui, controllers: &[LocalControllerInfo]) -> bool {
+        let mut changed = ui.checkbox("Merge a controller attached to this PC", &mut self.enabled);
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Its input goes to the same virtual controller as the Deck's, so someone at the PC can take over or help out");
+        }
+        if !self.enabled {
+            return changed;
+        }
+        changed |= ui.checkbox("Stream It to the Deck Instead", &mut self.stream_to_deck);
+        if ui.is_item_hovered() {
+            ui.tooltip_text("For when the Deck runs the game, it shows up there as a gamepad once the Deck has \"Play the PC's Controller\" on");
+        }
+
+        let label = |info: &LocalControllerInfo| {
+            if info.maybe_virtual {
+                format!("{} (may be the virtual controller)", info.name)
+            } else {
+                info.name.clone()
+            }
+        };
+        let preview = if self.controller_name.is_empty() { "None".to_string() } else { self.controller_name.clone() };
+        if let Some(_combo) = ui.begin_combo("Local Controller", preview) {
+            if ui.selectable_config("None").selected(self.controller_name.is_empty()).build() {
+                self.controller_name.clear();
+                changed = true;
+            }
+            for info in controllers {
+                if ui.selectable_config(label(info)).selected(info.name == self.controller_name).build() {
+                    self.controller_name = info.name.clone();
+                    changed = true;
+                }
+            }
+        }
+
+        if !self.controller_name.is_empty() {
+            if controllers.iter().any(|info| info.name == self.controller_name) {
+                palette::status_text(ui, Status::Good, if self.stream_to_deck { "Streaming to the Deck" } else { "Merging" });
+            } else {
+                palette::status_text(ui, Status::Warning, "Waiting for the controller to be plugged in");
+            }
+        }
+        changed
+    }
+}
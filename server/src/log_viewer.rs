@@ -0,0 +1,154 @@
+use imgui::*;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::mpsc;
+use tracing::field::{Field, Visit};
+use crate::i18n;
+use crate::palette::{self, Status};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+const MAX_ENTRIES: usize = 2000;
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: chrono::DateTime<chrono::Local>,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+// Hands every event to the log window, which drains the channel once per frame
+pub struct ChannelLayer {
+    sender: mpsc::Sender<LogEntry>,
+}
+
+pub fn channel_layer() -> (ChannelLayer, mpsc::Receiver<LogEntry>) {
+    let (sender, receiver) = mpsc::channel();
+    (ChannelLayer { sender }, receiver)
+}
+
+impl<S: Subscriber> Layer<S> for ChannelLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        // Fails once the window is gone, e.g. when running headless
+        let _ = self.sender.send(LogEntry {
+            time: chrono::Local::now(),
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            message: visitor.message,
+        });
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => {
+                let fields = std::mem::take(&mut self.message);
+                let _ = write!(self.message, "{:?}{}", value, fields);
+            }
+            // Where the log crate macros were called from, the target already covers it
+            name if name.starts_with("log.") => {}
+            name => {
+                let _ = write!(self.message, " {}={:?}", name, value);
+            }
+        }
+    }
+}
+
+pub struct LogViewer {
+    pub show: bool,
+    receiver: mpsc::Receiver<LogEntry>,
+    entries: VecDeque<LogEntry>,
+    // Index into LEVELS, entries less severe than this are hidden
+    min_level: usize,
+    module_filter: String,
+    auto_scroll: bool,
+}
+
+const LEVELS: [Level; 5] = [Level::ERROR, Level::WARN, Level::INFO, Level::DEBUG, Level::TRACE];
+
+impl LogViewer {
+    pub fn new(receiver: mpsc::Receiver<LogEntry>) -> Self {
+        Self {
+            show: false,
+            receiver,
+            entries: VecDeque::new(),
+            min_level: 2,
+            module_filter: String::new(),
+            auto_scroll: true,
+        }
+    }
+
+    fn receive(&mut self) {
+        while let Ok(entry) = self.receiver.try_recv() {
+            if self.entries.len() >= MAX_ENTRIES {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(entry);
+        }
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        // Keeps draining while hidden so the channel doesn't grow without bound
+        self.receive();
+        if !self.show {
+            return;
+        }
+
+        let mut show = self.show;
+        ui.window(i18n::window_title("Log"))
+            .size([700.0, 350.0], Condition::FirstUseEver)
+            .opened(&mut show)
+            .build(|| {
+                ui.set_next_item_width(100.0);
+                ui.combo_simple_string("Level", &mut self.min_level, &["Error", "Warn", "Info", "Debug", "Trace"]);
+                ui.same_line();
+                ui.set_next_item_width(200.0);
+                ui.input_text("Module", &mut self.module_filter)
+                    .hint("e.g. network")
+                    .build();
+                ui.same_line();
+                ui.checkbox("Auto-scroll", &mut self.auto_scroll);
+                ui.same_line();
+                if ui.button("Clear") {
+                    self.entries.clear();
+                }
+                ui.separator();
+
+                let min_level = LEVELS[self.min_level];
+                let module_filter = self.module_filter.trim();
+                ui.child_window("log_entries").build(|| {
+                    for entry in &self.entries {
+                        if entry.level > min_level || !entry.target.contains(module_filter) {
+                            continue;
+                        }
+                        let color = match entry.level {
+                            Level::ERROR => palette::color(Status::Bad),
+                            Level::WARN => palette::color(Status::Warning),
+                            Level::INFO => [1.0, 1.0, 1.0, 1.0],
+                            _ => [0.6, 0.6, 0.6, 1.0],
+                        };
+                        ui.text_colored(color, format!("{} {:5} {}: {}",
+                            entry.time.format("%H:%M:%S%.3f"),
+                            entry.level,
+                            entry.target,
+                            entry.message));
+                    }
+                    if self.auto_scroll && ui.scroll_y() >= ui.scroll_max_y() {
+                        ui.set_scroll_here_y_with_ratio(1.0);
+                    }
+                });
+            });
+        self.show = show;
+    }
+}
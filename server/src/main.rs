@@ -1,46 +1,88 @@
 use anyhow::Result;
 use imgui_wgpu::{Renderer, RendererConfig};
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
 use wgpu::{Device, Queue, Surface, SurfaceConfiguration};
 use winit::{
     event::{Event as WinitEvent, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::{Window, WindowBuilder},
 };
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use tokio::net::TcpListener;
-use tokio_tungstenite::{accept_async, tungstenite::Message};
-use futures_util::StreamExt;
-use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
+mod cli;
+mod clock;
 mod controller_receiver;
+mod deadzone;
+mod double_input;
 mod virtual_controller;
+mod frame_stats;
+mod hotkeys;
+mod i18n;
+mod input_filter;
+// The wire IDs come from the client's copy so the two can't drift apart
+#[path = "../../core/src/input_id.rs"]
+mod input_id;
+mod input_injector;
+mod json_log;
+mod launcher;
+// The client scores its side with the same code
+#[path = "../../core/src/link_quality.rs"]
+mod link_quality;
+mod local_controller;
+mod log_viewer;
+mod mapping;
+mod notifications;
+mod overlay;
+mod pad_watchdog;
+mod palette;
+mod pipeline_trace;
+mod protocol;
+mod raw_hid;
+mod scripting;
+mod session_history;
+mod session_report;
+mod settings;
+mod settings_bundle;
+mod setup_wizard;
+mod smoothing;
+mod steam_vdf;
+mod toasts;
+mod usage_stats;
+mod websocket_server;
+use cli::{Args, ControllerType, LogFormat};
 use controller_receiver::ControllerReceiver;
-use virtual_controller::VirtualController;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ControllerInputData {
-    pub timestamp: u64,
-    pub controller_id: u32,
-    pub button_events: Vec<ButtonEvent>,
-    pub axis_events: Vec<AxisEvent>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ButtonEvent {
-    pub button: String,
-    pub pressed: bool,
-    pub timestamp: u64,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AxisEvent {
-    pub axis: String,
-    pub value: f32,
-    pub timestamp: u64,
-}
+use double_input::{DoubleInput, LocalPads};
+use frame_stats::FrameStats;
+use hotkeys::{HotkeyAction, Hotkeys};
+use i18n::tr;
+use palette::Status;
+use raw_hid::HidPassthrough;
+use input_id::{AxisId, ButtonId};
+use input_injector::InputInjector;
+use launcher::GameLauncher;
+use local_controller::LocalController;
+use log_viewer::{LogEntry, LogViewer};
+use pad_watchdog::{PadWatchdog, WatchdogEvent};
+use pipeline_trace::{PipelineTrace, TraceWindow};
+use protocol::{AxisEvent, ButtonEvent, ControllerInputData, PadHealth, ProfileList};
+use scripting::ScriptWatcher;
+use session_history::SessionHistoryWindow;
+use session_report::{SessionReportWindow, SessionSummary, SessionTracker};
+use settings::{NetworkSettings, Settings};
+use settings_bundle::BundleWindow;
+use setup_wizard::SetupWizard;
+use steam_vdf::SteamImportWindow;
+use toasts::{ToastKind, Toasts};
+use virtual_controller::{NullBackend, PadFactory, PadType, VirtualController};
+use websocket_server::{InputDigest, ServerEvent, SharedController};
 
 pub struct App {
     surface: Surface,
@@ -52,60 +94,82 @@ pub struct App {
     platform: WinitPlatform,
     renderer: Renderer,
     controller_receiver: ControllerReceiver,
-    virtual_controller: VirtualController,
+    virtual_controller: Arc<Mutex<VirtualController>>,
     last_cursor: Option<imgui::MouseCursor>,
-    event_receiver: tokio::sync::mpsc::Receiver<ControllerInputData>,
+    event_receiver: tokio::sync::mpsc::Receiver<ServerEvent>,
+    event_sender: tokio::sync::mpsc::Sender<ServerEvent>,
+    server_task: Option<tokio::task::JoinHandle<Result<()>>>,
+    // Where the server is listening right now, --bind and --port included
+    network: NetworkSettings,
+    args: Args,
+    settings: Settings,
+    settings_path: PathBuf,
+    base_style: imgui::Style,
+    show_settings: bool,
+    show_mapping: bool,
+    show_overlay: bool,
+    show_scripting: bool,
+    script_watcher: ScriptWatcher,
+    settings_dirty: bool,
+    bundle_window: BundleWindow,
+    steam_import_window: SteamImportWindow,
+    setup_wizard: SetupWizard,
+    toasts: Toasts,
+    frame_stats: FrameStats,
+    gpu_lost: Arc<AtomicBool>,
+    forwarding_enabled: Arc<AtomicBool>,
+    // What connected clients are told they can pick from, republished when it changes
+    profiles: Arc<tokio::sync::watch::Sender<ProfileList>>,
+    pad_types: Arc<tokio::sync::watch::Sender<HashMap<IpAddr, PadType>>>,
+    pad_health: Arc<tokio::sync::watch::Sender<PadHealth>>,
+    pad_watchdog: PadWatchdog,
+    pc_input: Arc<tokio::sync::broadcast::Sender<ControllerInputData>>,
+    sessions: SessionTracker,
+    session_report: SessionReportWindow,
+    session_history: SessionHistoryWindow,
+    trace: Arc<PipelineTrace>,
+    trace_window: TraceWindow,
+    show_trace: bool,
+    hid_passthrough: Arc<HidPassthrough>,
+    show_hid_passthrough: bool,
+    hotkeys: Hotkeys,
+    show_hotkeys: bool,
+    input_injector: InputInjector,
+    local_controller: Option<LocalController>,
+    // Started, stopped or pointed at another controller in update(), outside the frame
+    local_controller_changed: bool,
+    // None where gilrs can't list this PC's controllers
+    local_pads: Option<LocalPads>,
+    double_inputs: Vec<DoubleInput>,
+    // Local pads the user said aren't doubled, until the server restarts
+    ignored_double_inputs: Vec<String>,
+    overlay_task: Option<tokio::task::JoinHandle<Result<()>>>,
+    overlay_error: Option<String>,
+    overlay_changed: bool,
+    game_launcher: GameLauncher,
+    log_viewer: LogViewer,
 }
 
 impl App {
-    async fn new(window: &Window, event_receiver: tokio::sync::mpsc::Receiver<ControllerInputData>) -> Result<Self> {
+    async fn new(window: &Window, event_receiver: tokio::sync::mpsc::Receiver<ServerEvent>, event_sender: tokio::sync::mpsc::Sender<ServerEvent>, args: Args, log_viewer: LogViewer) -> Result<Self> {
         let size = window.inner_size();
         
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(),
-        });
-
-        let surface = unsafe { instance.create_surface(window) }?;
-        
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            },
-        ).await.unwrap();
-
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-            },
-            None,
-        ).await.unwrap();
-
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps.formats.iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
-
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-        };
-        surface.configure(&device, &config);
+        let gpu_lost = Arc::new(AtomicBool::new(false));
+        let (surface, device, queue, config) = create_gpu(window, gpu_lost.clone()).await?;
+        let surface_format = config.format;
 
         let mut imgui = imgui::Context::create();
+        imgui.set_ini_filename(settings::imgui_ini_path());
         let mut platform = WinitPlatform::init(&mut imgui);
         platform.attach_window(imgui.io_mut(), window, HiDpiMode::Default);
 
+        let settings_path = args.profile.clone().unwrap_or_else(settings::default_settings_path);
+        let first_run = Settings::is_first_run(&settings_path);
+        let settings = Settings::load(&settings_path);
+        let network = args.network(&settings.network);
+        let base_style = *imgui.style();
+        settings.ui.apply(&mut imgui, &base_style);
+
         let renderer_config = RendererConfig {
             texture_format: surface_format,
             ..Default::default()
@@ -114,14 +178,23 @@ impl App {
         let renderer = Renderer::new(&mut imgui, &device, &queue, renderer_config);
 
         let controller_receiver = ControllerReceiver::new();
-        
-        let mut virtual_controller = VirtualController::new()?;
-        if let Err(e) = virtual_controller.create_controller() {
-            log::error!("Failed to create virtual controller: {}", e);
-            log::info!("Make sure ViGEm Bus Driver is installed");
+        let mut toasts = Toasts::new();
+        let mut setup_wizard = SetupWizard::new();
+        if first_run {
+            setup_wizard.open(&network);
         }
+        
+        let (mut virtual_controller, toast_kind, message) = create_virtual_controller(args.controller, settings.virtual_controller.player_slots());
+        virtual_controller.set_profile(settings.mapping.active());
+        virtual_controller.set_script(settings.scripting.load()).ok();
+        toasts.push(toast_kind, message);
+        let profiles = Arc::new(tokio::sync::watch::channel(settings.mapping.profile_list()).0);
+        let pad_types = Arc::new(tokio::sync::watch::channel(settings.virtual_controller.pad_types()).0);
+        let hid_passthrough = Arc::new(HidPassthrough::new(settings.hid_passthrough.recreate_device));
+        let mut hotkeys = Hotkeys::new();
+        hotkeys.apply(&settings.hotkeys);
 
-        Ok(Self {
+        let mut app = Self {
             surface,
             device,
             queue,
@@ -131,10 +204,248 @@ impl App {
             platform,
             renderer,
             controller_receiver,
-            virtual_controller,
+            virtual_controller: Arc::new(Mutex::new(virtual_controller)),
             last_cursor: None,
             event_receiver,
-        })
+            event_sender,
+            server_task: None,
+            network,
+            args,
+            settings,
+            settings_path,
+            base_style,
+            show_settings: false,
+            show_mapping: false,
+            show_overlay: false,
+            show_scripting: false,
+            script_watcher: ScriptWatcher::new(),
+            settings_dirty: false,
+            bundle_window: BundleWindow::new(),
+            steam_import_window: SteamImportWindow::new(),
+            setup_wizard,
+            toasts,
+            frame_stats: FrameStats::new(),
+            gpu_lost,
+            forwarding_enabled: Arc::new(AtomicBool::new(true)),
+            profiles,
+            pad_types,
+            pad_health: Arc::new(tokio::sync::watch::channel(PadHealth::Working).0),
+            pad_watchdog: PadWatchdog::new(),
+            pc_input: Arc::new(tokio::sync::broadcast::channel(PC_INPUT_CAPACITY).0),
+            sessions: SessionTracker::new(),
+            session_report: SessionReportWindow::new(),
+            session_history: SessionHistoryWindow::new(),
+            trace: Arc::new(PipelineTrace::new()),
+            trace_window: TraceWindow::new(),
+            show_trace: false,
+            hid_passthrough,
+            show_hid_passthrough: false,
+            hotkeys,
+            show_hotkeys: false,
+            input_injector: InputInjector::new(),
+            local_controller: None,
+            local_controller_changed: false,
+            local_pads: LocalPads::start()
+                .inspect_err(|e| log::warn!("Double input detection unavailable: {:#}", e))
+                .ok(),
+            double_inputs: Vec::new(),
+            ignored_double_inputs: Vec::new(),
+            overlay_task: None,
+            overlay_error: None,
+            overlay_changed: false,
+            game_launcher: GameLauncher::new(),
+            log_viewer,
+        };
+        app.apply_local_controller_settings();
+        app.apply_overlay_settings();
+        Ok(app)
+    }
+
+    fn shared_controller(&self) -> SharedController {
+        SharedController {
+            virtual_controller: self.virtual_controller.clone(),
+            forwarding_enabled: self.forwarding_enabled.clone(),
+            profiles: self.profiles.clone(),
+            trace: self.trace.clone(),
+            hid_passthrough: self.hid_passthrough.clone(),
+            pad_types: self.pad_types.clone(),
+            pad_health: self.pad_health.clone(),
+            create_pad: pad_factory(self.args.controller),
+            pc_input: self.pc_input.clone(),
+        }
+    }
+
+    fn finish_session(&mut self, summary: SessionSummary) {
+        let saved_to = match summary.save(&session_report::reports_dir()) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                log::error!("Failed to save session summary: {:#}", e);
+                self.toasts.push(ToastKind::Error, format!("Failed to save session summary: {:#}", e));
+                None
+            }
+        };
+        self.session_history.invalidate();
+        self.session_report.show(summary, saved_to);
+    }
+
+    // Replaces the listener, clients that are already connected stay connected
+    fn start_server(&mut self, listener: tokio::net::TcpListener) {
+        if let Some(server_task) = self.server_task.take() {
+            server_task.abort();
+        }
+        self.controller_receiver.set_server_status(format!("Listening on {}", self.network.listen_address()));
+        self.server_task = Some(tokio::spawn(websocket_server::serve(listener, self.event_sender.clone(), self.shared_controller())));
+    }
+
+    fn apply_network_settings(&mut self, network: NetworkSettings) {
+        self.settings.network = network;
+        self.settings_dirty = true;
+
+        let network = self.args.network(&self.settings.network);
+        if network == self.network && self.server_task.is_some() {
+            return;
+        }
+        match websocket_server::listen(&network.listen_address()) {
+            Ok(listener) => {
+                self.network = network;
+                self.start_server(listener);
+                self.toasts.push(ToastKind::Success, format!("Listening on {}", self.network.listen_address()));
+            }
+            Err(e) => {
+                log::error!("{:#}", e);
+                self.toasts.push(ToastKind::Error, format!("{:#}", e));
+            }
+        }
+    }
+
+    fn apply_local_controller_settings(&mut self) {
+        let settings = &self.settings.local_controller;
+        if !settings.enabled {
+            self.local_controller = None;
+            return;
+        }
+        match &self.local_controller {
+            Some(local_controller) => local_controller.apply(settings),
+            None => match LocalController::start(self.virtual_controller.clone(), self.pc_input.clone(), settings) {
+                Ok(local_controller) => self.local_controller = Some(local_controller),
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    self.toasts.push(ToastKind::Error, format!("{:#}", e));
+                }
+            },
+        }
+    }
+
+    fn apply_overlay_settings(&mut self) {
+        if let Some(overlay_task) = self.overlay_task.take() {
+            overlay_task.abort();
+        }
+        self.overlay_error = None;
+        if !self.settings.overlay.enabled {
+            return;
+        }
+        match overlay::listen(&self.settings.overlay.listen_address()) {
+            Ok(listener) => {
+                log::info!("Serving the stream overlay at {}", self.settings.overlay.url());
+                self.overlay_task = Some(tokio::spawn(overlay::serve(listener, self.virtual_controller.clone())));
+            }
+            Err(e) => {
+                log::error!("{:#}", e);
+                self.overlay_error = Some(format!("{:#}", e));
+            }
+        }
+    }
+
+    fn check_pad_watchdog(&mut self) {
+        let event = self.pad_watchdog.check(&mut self.virtual_controller.lock().unwrap(), Instant::now());
+        let Some(event) = event else {
+            return;
+        };
+        event.log();
+        // Every failed retry would be one toast too many, the window shows the countdown
+        match event {
+            WatchdogEvent::Failed(_) => self.toasts.push(ToastKind::Warning, event.message()),
+            WatchdogEvent::Recovered { .. } => self.toasts.push(ToastKind::Success, event.message()),
+            WatchdogEvent::RetryFailed { .. } => {}
+        }
+        publish_pad_health(&self.pad_health, event.health());
+    }
+
+    // Warns once for each pad that starts to look like a controller a client already forwards
+    fn check_double_input(&mut self) {
+        let Some(local_pads) = &self.local_pads else {
+            return;
+        };
+        let found: Vec<DoubleInput> = double_input::detect(&local_pads.pads(), self.controller_receiver.devices())
+            .into_iter()
+            .filter(|double| !self.ignored_double_inputs.contains(&double.local_name))
+            .collect();
+        for double in &found {
+            if !self.double_inputs.contains(double) {
+                let message = format!("{} on this PC looks like the controller {} forwards, games may get its input twice", double.local_name, double.client.ip());
+                log::warn!("{}", message);
+                self.toasts.push(ToastKind::Warning, message);
+            }
+        }
+        self.double_inputs = found;
+    }
+
+    fn run_hotkey(&mut self, action: HotkeyAction) {
+        match action {
+            HotkeyAction::ToggleForwarding => {
+                let enabled = !self.forwarding_enabled.load(Ordering::SeqCst);
+                self.forwarding_enabled.store(enabled, Ordering::SeqCst);
+                if enabled {
+                    self.toasts.push(ToastKind::Info, "Forwarding to the virtual controller");
+                } else {
+                    if let Err(e) = self.virtual_controller.lock().unwrap().reset_state() {
+                        log::error!("Failed to reset virtual controller: {}", e);
+                    }
+                    self.toasts.push(ToastKind::Warning, "Spectating - incoming input is not forwarded");
+                }
+            }
+            HotkeyAction::ReconnectController => {
+                reconnect_virtual_controller(&mut self.virtual_controller.lock().unwrap(), &mut self.toasts);
+            }
+            HotkeyAction::ClearEvents => {
+                self.controller_receiver.clear_events();
+                self.toasts.push(ToastKind::Info, "Events cleared");
+            }
+            HotkeyAction::ToggleTrace => {
+                let status = self.trace_window.toggle(&self.trace);
+                self.toasts.push(ToastKind::Info, status);
+            }
+            HotkeyAction::ReleaseAll => self.release_all(),
+        }
+    }
+
+    fn release_all(&mut self) {
+        if let Err(e) = self.virtual_controller.lock().unwrap().release_all() {
+            log::error!("Failed to reset virtual controller: {}", e);
+        }
+        log::warn!("Released everything, input blocked until resumed");
+        self.toasts.push(ToastKind::Warning, "Released everything, input is blocked until you resume it");
+    }
+
+    // The bus driver may have been installed since startup, so a missing one is looked for again
+    fn check_virtual_controller(&mut self) -> Result<(), String> {
+        if self.args.controller == ControllerType::None {
+            return Err("Disabled with --controller none".to_string());
+        }
+
+        let mut virtual_controller = self.virtual_controller.lock().unwrap();
+        if virtual_controller.is_connected() {
+            return Ok(());
+        }
+        let (mut new_controller, _, message) = create_virtual_controller(self.args.controller, self.settings.virtual_controller.player_slots());
+        new_controller.set_profile(self.settings.mapping.active());
+        new_controller.set_script(self.settings.scripting.load()).ok();
+        if new_controller.is_connected() {
+            *virtual_controller = new_controller;
+            Ok(())
+        } else {
+            Err(message.to_string())
+        }
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -146,6 +457,32 @@ impl App {
         }
     }
 
+    // The window moved to a display with a different scale, e.g. the Deck docking to an external screen
+    fn scale_factor_changed(&mut self, window: &Window, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.platform.attach_window(self.imgui.io_mut(), window, HiDpiMode::Default);
+        self.resize(new_size);
+    }
+
+    fn recreate_gpu(&mut self, window: &Window) {
+        log::warn!("Recreating GPU device and surface");
+        match pollster::block_on(create_gpu(window, self.gpu_lost.clone())) {
+            Ok((surface, device, queue, config)) => {
+                let renderer_config = RendererConfig {
+                    texture_format: config.format,
+                    ..Default::default()
+                };
+                self.renderer = Renderer::new(&mut self.imgui, &device, &queue, renderer_config);
+                self.surface = surface;
+                self.device = device;
+                self.queue = queue;
+                self.config = config;
+                self.size = window.inner_size();
+                self.gpu_lost.store(false, Ordering::SeqCst);
+            }
+            Err(e) => log::error!("Failed to recreate GPU device: {}", e),
+        }
+    }
+
     fn input(&mut self, event: &WindowEvent, window: &Window) -> bool {
         let owned_event = match event {
             WindowEvent::CloseRequested => WindowEvent::CloseRequested,
@@ -181,14 +518,109 @@ impl App {
     }
 
     fn update(&mut self) {
+        self.frame_stats.set_queue_depth("Event Queue", self.event_receiver.len(), self.event_receiver.max_capacity());
+
         // Check for new controller events from WebSocket
-        while let Ok(controller_data) = self.event_receiver.try_recv() {
-            if let Err(e) = self.virtual_controller.process_controller_input(controller_data.clone()) {
-                log::error!("Failed to process controller input: {}", e);
+        while let Ok(event) = self.event_receiver.try_recv() {
+            match event {
+                ServerEvent::ClientConnected(addr) => {
+                    self.controller_receiver.client_connected(addr);
+                    let profile = self.settings.mapping.active();
+                    self.sessions.client_connected(&profile.name, profile.launch.game_name());
+                    self.toasts.push(ToastKind::Success, format!("Client connected: {}", addr));
+                    if let Err(e) = self.game_launcher.client_connected(&self.settings.mapping.active().launch) {
+                        log::error!("{:#}", e);
+                        self.toasts.push(ToastKind::Error, format!("{:#}", e));
+                    }
+                }
+                ServerEvent::ClientDisconnected(addr) => {
+                    self.controller_receiver.client_disconnected(addr);
+                    self.sessions.client_disconnected();
+                    self.toasts.push(ToastKind::Info, format!("Client disconnected: {}", addr));
+                    if let Err(e) = self.game_launcher.client_disconnected(&self.settings.mapping.active().launch) {
+                        log::error!("{:#}", e);
+                        self.toasts.push(ToastKind::Error, format!("{:#}", e));
+                    }
+                }
+                ServerEvent::Input(digest) => {
+                    if !digest.data.button_events.is_empty() {
+                        self.setup_wizard.input_received();
+                    }
+                    // Already forwarded to the virtual controller by the network task
+                    self.sessions.record(&digest);
+                    self.controller_receiver.add_controller_event(digest);
+                }
+                ServerEvent::CapabilitiesReported(addr, report) => {
+                    self.controller_receiver.set_capabilities(addr, report);
+                }
+                ServerEvent::BatteryReported(addr, report) => {
+                    if self.controller_receiver.set_battery(addr, report) {
+                        let message = format!("Controller {} on {} is low on battery ({}%)", report.controller_id, addr.ip(), report.battery_percent);
+                        log::warn!("{}", message);
+                        self.toasts.push(ToastKind::Warning, message.clone());
+                        if self.settings.ui.system_notifications {
+                            notifications::notify("Controller Battery Low", &message);
+                        }
+                    }
+                }
+                ServerEvent::ProfileRequested(name) => match self.settings.mapping.select(&name) {
+                    Ok(true) => {
+                        self.virtual_controller.lock().unwrap().set_profile(self.settings.mapping.active());
+                        self.settings_dirty = true;
+                        self.toasts.push(ToastKind::Info, format!("Client switched to profile {}", name));
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        log::warn!("{:#}", e);
+                        self.toasts.push(ToastKind::Warning, format!("{:#}", e));
+                    }
+                },
+            }
+        }
+
+        if let Some(summary) = self.sessions.update() {
+            self.finish_session(summary);
+        }
+        self.check_double_input();
+        self.check_pad_watchdog();
+        if let Some(script) = self.script_watcher.poll(&self.settings.scripting) {
+            if let Err(e) = self.virtual_controller.lock().unwrap().set_script(Some(script)) {
+                log::error!("Failed to update virtual controller: {}", e);
+            }
+        }
+
+        for action in self.hotkeys.poll() {
+            self.run_hotkey(action);
+        }
+
+        // Covers renames, new profiles and switches made in the mapping window as well
+        let profile_list = self.settings.mapping.profile_list();
+        self.profiles.send_if_modified(|current| {
+            if *current == profile_list {
+                return false;
             }
-            
-            // Also add to UI for display
-            self.controller_receiver.add_controller_event(controller_data);
+            *current = profile_list;
+            true
+        });
+
+        if let Some(network) = self.setup_wizard.network_update() {
+            self.apply_network_settings(network);
+        }
+        if self.local_controller_changed {
+            self.local_controller_changed = false;
+            self.apply_local_controller_settings();
+        }
+        if self.overlay_changed {
+            self.overlay_changed = false;
+            self.apply_overlay_settings();
+        }
+        if self.setup_wizard.should_check_vigem() {
+            let status = self.check_virtual_controller();
+            self.setup_wizard.set_vigem_status(status);
+        }
+        if self.setup_wizard.should_finish() {
+            // Written even if nothing changed, so setup doesn't come back on the next start
+            self.settings_dirty = true;
         }
         
         self.controller_receiver.update();
@@ -205,30 +637,159 @@ impl App {
         self.platform.prepare_frame(self.imgui.io_mut(), window).expect("Failed to prepare frame");
         let ui = self.imgui.frame();
 
-        self.controller_receiver.render(&ui);
+        self.controller_receiver.render(&ui, self.trace.latency_budget());
         
-        ui.window("Virtual Xbox Controller")
+        ui.window(i18n::window_title("Virtual Xbox Controller"))
             .size([400.0, 300.0], imgui::Condition::FirstUseEver)
             .build(|| {
-                if self.virtual_controller.is_connected() {
-                    ui.text_colored([0.0, 1.0, 0.0, 1.0], "Virtual Controller: Connected");
+                let mut virtual_controller = self.virtual_controller.lock().unwrap();
+                if virtual_controller.is_connected() {
+                    palette::status_text(ui, Status::Good, tr("Virtual Controller: Connected"));
                 } else {
-                    ui.text_colored([1.0, 0.0, 0.0, 1.0], "Virtual Controller: Disconnected");
+                    palette::status_text(ui, Status::Bad, tr("Virtual Controller: Disconnected"));
+                }
+                if let Some(fault) = virtual_controller.fault() {
+                    let retry_in = self.pad_watchdog.retry_in(Instant::now()).unwrap_or_default();
+                    palette::status_text(ui, Status::Bad, format!("{} {}", tr("Not responding:"), fault));
+                    ui.text_disabled(format!("{} {}s", tr("Plugging it back in, next attempt in"), retry_in.as_secs() + 1));
+                }
+                if virtual_controller.is_split() {
+                    ui.text("Split mode: player 2 is on a second virtual controller");
+                }
+
+                // Some games only read player 1
+                let slot_names = ["Automatic", "Player 1", "Player 2", "Player 3", "Player 4"];
+                let assigned_name = |slot: Option<u32>| slot.map_or("-".to_string(), |slot| format!("Player {}", slot + 1));
+                let [assigned, second_assigned] = virtual_controller.assigned_slots();
+                let mut slots_changed = false;
+                let mut player_slot = self.settings.virtual_controller.player_slot as usize;
+                ui.set_next_item_width(120.0);
+                if ui.combo_simple_string("XInput Slot", &mut player_slot, &slot_names) {
+                    self.settings.virtual_controller.player_slot = player_slot as u32;
+                    slots_changed = true;
+                }
+                ui.same_line();
+                ui.text(format!("Assigned: {}", assigned_name(assigned)));
+                if virtual_controller.is_split() {
+                    let mut second_player_slot = self.settings.virtual_controller.second_player_slot as usize;
+                    ui.set_next_item_width(120.0);
+                    if ui.combo_simple_string("Player 2 Slot", &mut second_player_slot, &slot_names) {
+                        self.settings.virtual_controller.second_player_slot = second_player_slot as u32;
+                        slots_changed = true;
+                    }
+                    ui.same_line();
+                    ui.text(format!("Assigned: {}", assigned_name(second_assigned)));
+                }
+                if slots_changed {
+                    self.settings_dirty = true;
+                    virtual_controller.set_player_slots(self.settings.virtual_controller.player_slots());
+                    // The slot is only picked while plugging in
+                    if virtual_controller.is_connected() {
+                        let replugged = virtual_controller.disconnect_controller().and_then(|_| virtual_controller.create_controller());
+                        if let Err(e) = replugged {
+                            log::error!("Failed to replug virtual controller: {}", e);
+                            self.toasts.push(ToastKind::Error, format!("Failed to replug virtual controller: {}", e));
+                        }
+                    }
+                }
+                
+                let mut forwarding_enabled = self.forwarding_enabled.load(Ordering::SeqCst);
+                if ui.checkbox(tr("Forward to virtual controller"), &mut forwarding_enabled) {
+                    self.forwarding_enabled.store(forwarding_enabled, Ordering::SeqCst);
+                    // Don't leave whatever was held when forwarding stopped stuck on the pad
+                    if !forwarding_enabled {
+                        if let Err(e) = virtual_controller.reset_state() {
+                            log::error!("Failed to reset virtual controller: {}", e);
+                        }
+                    }
+                }
+                if ui.is_item_hovered() && !self.settings.hotkeys.toggle_forwarding.is_empty() {
+                    ui.tooltip_text(format!("Hotkey: {}", self.settings.hotkeys.toggle_forwarding));
+                }
+                if !forwarding_enabled {
+                    palette::status_text(ui, Status::Warning, tr("Spectating - incoming input is not forwarded"));
+                }
+
+                if !self.double_inputs.is_empty() {
+                    ui.separator();
+                    for double in &self.double_inputs {
+                        palette::status_text(ui, Status::Warning, format!("{} {} / {}", tr("Double input:"), double.local_name, double.client.ip()));
+                        ui.text_wrapped(tr(double.hint));
+                    }
+                    if forwarding_enabled && ui.button(tr("Stop Forwarding")) {
+                        self.forwarding_enabled.store(false, Ordering::SeqCst);
+                        if let Err(e) = virtual_controller.reset_state() {
+                            log::error!("Failed to reset virtual controller: {}", e);
+                        }
+                    }
+                    if forwarding_enabled {
+                        ui.same_line();
+                    }
+                    if ui.button(tr("Not the Same Controller")) {
+                        self.ignored_double_inputs.extend(self.double_inputs.drain(..).map(|double| double.local_name));
+                    }
+                }
+
+                if virtual_controller.is_blocked() {
+                    palette::status_text(ui, Status::Bad, tr("Input blocked - everything was released"));
+                    if ui.button(tr("Resume Input")) {
+                        virtual_controller.unblock();
+                        log::info!("Input resumed");
+                        self.toasts.push(ToastKind::Info, "Input resumed");
+                    }
+                } else if ui.button(tr("Release Everything")) {
+                    if let Err(e) = virtual_controller.release_all() {
+                        log::error!("Failed to reset virtual controller: {}", e);
+                    }
+                    log::warn!("Released everything, input blocked until resumed");
+                    self.toasts.push(ToastKind::Warning, "Released everything, input is blocked until you resume it");
+                }
+                if ui.is_item_hovered() && !self.settings.hotkeys.release_all.is_empty() {
+                    ui.tooltip_text(format!("Zeroes the virtual controller and ignores input until resumed. Hotkey: {}", self.settings.hotkeys.release_all));
+                }
+
+                let local_controllers = self.local_controller.as_ref().map(|local| local.controllers()).unwrap_or_default();
+                if self.settings.local_controller.render(&ui, &local_controllers) {
+                    self.local_controller_changed = true;
+                    self.settings_dirty = true;
+                }
+
+                let client_ips = self.controller_receiver.client_ips();
+                if !client_ips.is_empty() && ui.collapsing_header(tr("Clients"), imgui::TreeNodeFlags::empty()) {
+                    let names: Vec<&str> = PadType::ALL.iter().map(|pad_type| pad_type.name()).collect();
+                    let mut paired = false;
+                    for ip in client_ips {
+                        let ip = ip.to_string();
+                        let pad_type = self.settings.virtual_controller.client_pads.get(&ip).copied().unwrap_or_default();
+                        let mut index = PadType::ALL.iter().position(|&t| t == pad_type).unwrap_or(0);
+                        ui.set_next_item_width(160.0);
+                        if ui.combo_simple_string(&ip, &mut index, &names) {
+                            self.settings.virtual_controller.client_pads.insert(ip, PadType::ALL[index]);
+                            paired = true;
+                        }
+                        if ui.is_item_hovered() {
+                            ui.tooltip_text("Remembered for this address. A DualShock 4 is a pad of the client's own, the other clients share the Xbox 360 pad.");
+                        }
+                    }
+                    if paired {
+                        self.pad_types.send_replace(self.settings.virtual_controller.pad_types());
+                        self.settings_dirty = true;
+                    }
                 }
                 
                 ui.separator();
                 
-                ui.text("Active Buttons:");
-                for (button, &pressed) in self.virtual_controller.get_button_states() {
+                ui.text(tr("Active Buttons:"));
+                for (button, &pressed) in virtual_controller.get_button_states() {
                     if pressed {
-                        ui.text_colored([0.0, 1.0, 0.0, 1.0], &format!("• {}", button));
+                        ui.text_colored(palette::color(Status::Good), format!("- {}", button));
                     }
                 }
                 
                 ui.separator();
                 
-                ui.text("Axis Values:");
-                for (axis, &value) in self.virtual_controller.get_axis_states() {
+                ui.text(tr("Axis Values:"));
+                for (axis, &value) in virtual_controller.get_axis_states() {
                     if value.abs() > 0.01 {
                         ui.text(&format!("{}: {:.3}", axis, value));
                     }
@@ -236,12 +797,138 @@ impl App {
                 
                 ui.separator();
                 
-                if ui.button("Reconnect Virtual Controller") {
-                    if let Err(e) = self.virtual_controller.create_controller() {
-                        log::error!("Failed to reconnect virtual controller: {}", e);
-                    }
+                if ui.button(tr("Reconnect Virtual Controller")) {
+                    reconnect_virtual_controller(&mut virtual_controller, &mut self.toasts);
+                }
+                if ui.is_item_hovered() && !self.settings.hotkeys.reconnect_controller.is_empty() {
+                    ui.tooltip_text(format!("Hotkey: {}", self.settings.hotkeys.reconnect_controller));
+                }
+            });
+
+        if let Some(injected) = self.input_injector.render(&ui) {
+            if let Err(e) = self.virtual_controller.lock().unwrap().process_controller_input(injected) {
+                log::error!("Failed to inject controller input: {}", e);
+            }
+        }
+
+        ui.main_menu_bar(|| {
+            ui.menu(tr("Settings"), || {
+                if ui.menu_item(tr("UI Settings")) {
+                    self.show_settings = true;
+                }
+                if ui.menu_item(tr("Mapping")) {
+                    self.show_mapping = true;
+                }
+                if ui.menu_item(tr("Stream Overlay")) {
+                    self.show_overlay = true;
+                }
+                if ui.menu_item(tr("Scripting")) {
+                    self.show_scripting = true;
+                }
+                if ui.menu_item(tr("Hotkeys")) {
+                    self.show_hotkeys = true;
+                }
+                if ui.menu_item(tr("Import / Export...")) {
+                    self.bundle_window.open = true;
+                }
+                if ui.menu_item(tr("Import Steam Config...")) {
+                    self.steam_import_window.open = true;
+                }
+                if ui.menu_item(tr("Setup Wizard...")) {
+                    self.setup_wizard.open(&self.settings.network);
+                }
+                if ui.menu_item_config(tr("Performance Overlay")).selected(self.frame_stats.show_overlay).build() {
+                    self.frame_stats.show_overlay = !self.frame_stats.show_overlay;
                 }
             });
+            ui.menu(tr("View"), || {
+                if ui.menu_item_config(tr("Log")).selected(self.log_viewer.show).build() {
+                    self.log_viewer.show = !self.log_viewer.show;
+                }
+                if ui.menu_item(tr("Pipeline Trace")) {
+                    self.show_trace = true;
+                }
+                if ui.menu_item(tr("HID Passthrough")) {
+                    self.show_hid_passthrough = true;
+                }
+                if ui.menu_item(tr("Session History")) {
+                    self.session_history.show = true;
+                }
+            });
+        });
+
+        if self.show_settings && self.settings.ui.render(&ui, &mut self.show_settings) {
+            self.settings_dirty = true;
+        }
+
+        let raw_sticks = self.virtual_controller.lock().unwrap().raw_sticks();
+        let capabilities = self.controller_receiver.capabilities();
+        if self.show_mapping && self.settings.mapping.render(&ui, &mut self.show_mapping, raw_sticks, capabilities) {
+            self.virtual_controller.lock().unwrap().set_profile(self.settings.mapping.active());
+            self.settings_dirty = true;
+        }
+
+        if self.show_overlay && self.settings.overlay.render(&ui, &mut self.show_overlay, self.overlay_error.as_deref()) {
+            self.overlay_changed = true;
+            self.settings_dirty = true;
+        }
+
+        if self.show_scripting {
+            let script_error = self.virtual_controller.lock().unwrap().script_error();
+            if self.settings.scripting.render(&ui, &mut self.show_scripting, script_error.as_deref()) {
+                if let Err(e) = self.virtual_controller.lock().unwrap().set_script(self.settings.scripting.load()) {
+                    log::error!("Failed to update virtual controller: {}", e);
+                }
+                self.settings_dirty = true;
+            }
+        }
+
+        self.session_report.render(&ui);
+        self.session_history.render(ui);
+        if self.show_trace {
+            self.trace_window.render(&ui, &mut self.show_trace, &self.trace);
+        }
+        if self.show_hid_passthrough && self.settings.hid_passthrough.render(&ui, &mut self.show_hid_passthrough, &self.hid_passthrough) {
+            self.hid_passthrough.set_recreate_device(self.settings.hid_passthrough.recreate_device);
+            self.settings_dirty = true;
+        }
+
+        if self.show_hotkeys && self.settings.hotkeys.render(&ui, &mut self.show_hotkeys, &self.hotkeys) {
+            self.hotkeys.apply(&self.settings.hotkeys);
+            self.settings_dirty = true;
+        }
+
+        if let Some(settings) = self.bundle_window.render(&ui, &self.settings) {
+            self.settings = settings;
+            let mut virtual_controller = self.virtual_controller.lock().unwrap();
+            virtual_controller.set_player_slots(self.settings.virtual_controller.player_slots());
+            virtual_controller.set_profile(self.settings.mapping.active());
+            virtual_controller.set_script(self.settings.scripting.load()).ok();
+            drop(virtual_controller);
+            self.pad_types.send_replace(self.settings.virtual_controller.pad_types());
+            self.hid_passthrough.set_recreate_device(self.settings.hid_passthrough.recreate_device);
+            self.hotkeys.apply(&self.settings.hotkeys);
+            self.local_controller_changed = true;
+            self.overlay_changed = true;
+            self.toasts.push(ToastKind::Success, "Settings imported");
+            // Applied and saved below like any other change
+            self.settings_dirty = true;
+        }
+
+        if let Some(profile) = self.steam_import_window.render(&ui) {
+            self.settings.mapping.add_profile(profile);
+            self.virtual_controller.lock().unwrap().set_profile(self.settings.mapping.active());
+            self.toasts.push(ToastKind::Success, format!("Mapping profile '{}' imported", self.settings.mapping.active().name));
+            self.settings_dirty = true;
+        }
+
+        self.setup_wizard.render(&ui);
+        self.log_viewer.render(&ui);
+        self.toasts.render(&ui);
+        self.frame_stats.render(&ui);
+
+        // Only persist once the user has let go of the slider being dragged
+        let save_settings = self.settings_dirty && !ui.is_any_item_active();
 
         let cursor = ui.mouse_cursor();
         if self.last_cursor != cursor {
@@ -276,19 +963,188 @@ impl App {
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
+        if self.settings_dirty {
+            self.settings.ui.apply(&mut self.imgui, &self.base_style);
+            if save_settings {
+                self.settings_dirty = false;
+                if let Err(e) = self.settings.save(&self.settings_path) {
+                    log::error!("Failed to save settings: {}", e);
+                }
+            }
+        }
+
         Ok(())
     }
 }
 
-async fn run() -> Result<()> {
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .filter_module("wgpu_hal", log::LevelFilter::Off)
-        .filter_module("wgpu_core", log::LevelFilter::Off)
-        .filter_module("wgpu", log::LevelFilter::Off)
+// Also used headless, the message is what the UI shows as a toast
+fn create_virtual_controller(controller_type: ControllerType, player_slots: [Option<u32>; 2]) -> (VirtualController, ToastKind, &'static str) {
+    if controller_type == ControllerType::None {
+        log::info!("Virtual controller disabled, input is only monitored");
+        return (VirtualController::with_backend(Box::new(NullBackend)), ToastKind::Info, "Virtual controller disabled - input will not be forwarded");
+    }
+
+    match VirtualController::new(PadType::Xbox360) {
+        Ok(mut virtual_controller) => {
+            virtual_controller.set_player_slots(player_slots);
+            if let Err(e) = virtual_controller.create_controller() {
+                log::error!("Failed to create virtual controller: {}", e);
+                log::info!("Make sure ViGEm Bus Driver is installed");
+                (virtual_controller, ToastKind::Error, "Failed to create virtual controller - is ViGEm Bus installed?")
+            } else {
+                (virtual_controller, ToastKind::Success, "Virtual controller plugged in")
+            }
+        }
+        Err(e) => {
+            // Input can still be monitored, it just doesn't reach any game
+            log::error!("Failed to connect to ViGEm Bus: {}", e);
+            (VirtualController::with_backend(Box::new(NullBackend)), ToastKind::Error, "ViGEm Bus not available - input will not be forwarded")
+        }
+    }
+}
+
+// Pads for clients paired with one of their own, none at all while the virtual controller is disabled
+fn pad_factory(controller_type: ControllerType) -> PadFactory {
+    if controller_type == ControllerType::None {
+        |_| Ok(VirtualController::with_backend(Box::new(NullBackend)))
+    } else {
+        VirtualController::new
+    }
+}
+
+// Clients only hear about changes
+fn publish_pad_health(pad_health: &tokio::sync::watch::Sender<PadHealth>, health: PadHealth) {
+    pad_health.send_if_modified(|current| {
+        if *current == health {
+            return false;
+        }
+        *current = health;
+        true
+    });
+}
+
+// Unplugs and plugs the pad back in, from the button or its hotkey
+fn reconnect_virtual_controller(virtual_controller: &mut VirtualController, toasts: &mut Toasts) {
+    if virtual_controller.is_connected() && virtual_controller.disconnect_controller().is_ok() {
+        toasts.push(ToastKind::Warning, "Virtual controller unplugged");
+    }
+    if let Err(e) = virtual_controller.create_controller() {
+        log::error!("Failed to reconnect virtual controller: {}", e);
+        toasts.push(ToastKind::Error, format!("Failed to reconnect virtual controller: {}", e));
+    } else {
+        toasts.push(ToastKind::Success, "Virtual controller plugged in");
+    }
+}
+
+// PC controller messages a connection may fall behind by before it skips ahead, a couple of
+// seconds of a busy stick
+const PC_INPUT_CAPACITY: usize = 256;
+
+// Creates the surface and device, also used to start over after the device is lost
+async fn create_gpu(window: &Window, gpu_lost: Arc<AtomicBool>) -> Result<(Surface, Device, Queue, SurfaceConfiguration)> {
+    let size = window.inner_size();
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        dx12_shader_compiler: Default::default(),
+    });
+
+    let surface = unsafe { instance.create_surface(window) }?;
+    
+    let adapter = instance.request_adapter(
+        &wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: Some(&surface),
+            force_fallback_adapter: false,
+        },
+    ).await.ok_or_else(|| anyhow::anyhow!("Failed to find suitable adapter"))?;
+
+    let (device, queue) = adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::default(),
+        },
+        None,
+    ).await?;
+
+    let surface_caps = surface.get_capabilities(&adapter);
+    let surface_format = surface_caps.formats.iter()
+        .copied()
+        .find(|f| f.is_srgb())
+        .unwrap_or(surface_caps.formats[0]);
+
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: surface_format,
+        width: size.width.max(1),
+        height: size.height.max(1),
+        // The frame limiter paces rendering, vsync would also hold back input processing
+        present_mode: wgpu::PresentMode::AutoNoVsync,
+        alpha_mode: surface_caps.alpha_modes[0],
+        view_formats: vec![],
+    };
+    surface.configure(&device, &config);
+
+    // wgpu panics on uncaptured errors by default. A lost device is flagged so the main loop can
+    // start over with a new one, anything else is only logged.
+    device.on_uncaptured_error(Box::new(move |e| {
+        log::error!("GPU error: {}", e);
+        if is_device_lost(&e) {
+            gpu_lost.store(true, Ordering::SeqCst);
+        }
+    }));
+
+    Ok((surface, device, queue, config))
+}
+
+// wgpu 0.16 reports a lost device as a validation error, the loss is one of its sources
+fn is_device_lost(error: &wgpu::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(error) = source {
+        if error.to_string() == "Parent device is lost" {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+// Code still using the log macros is forwarded into tracing, RUST_LOG takes the usual target=level list
+// unless --log-level is given. The Log window sees the same events as the terminal.
+fn init_tracing(log_level: Option<LevelFilter>, log_format: LogFormat) -> std::sync::mpsc::Receiver<LogEntry> {
+    let from_env = match log_level {
+        Some(_) => None,
+        None => std::env::var("RUST_LOG").ok().and_then(|value| value.parse::<Targets>().ok()),
+    };
+    let filter = from_env
+        .unwrap_or_else(|| Targets::new()
+            .with_default(log_level.unwrap_or(LevelFilter::INFO))
+            .with_target("wgpu_hal", LevelFilter::OFF)
+            .with_target("wgpu_core", LevelFilter::OFF)
+            .with_target("wgpu", LevelFilter::OFF));
+
+    let (log_layer, log_receiver) = log_viewer::channel_layer();
+    let json = log_format == LogFormat::Json;
+    tracing_subscriber::registry()
+        // Closing spans report how long each stage took
+        .with((!json).then(|| tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE).with_filter(filter.clone())))
+        .with(json.then(|| json_log::JsonLayer.with_filter(filter.clone())))
+        .with(log_layer.with_filter(filter))
         .init();
+    log_receiver
+}
+
+async fn run(args: Args) -> Result<()> {
+    let log_receiver = init_tracing(args.log_level, args.log_format);
     
-    let (tx, rx) = tokio::sync::mpsc::channel::<ControllerInputData>(100);
+    let (tx, rx) = tokio::sync::mpsc::channel::<ServerEvent>(100);
+
+    if args.headless {
+        // Nothing would drain it, the terminal has the same output
+        drop(log_receiver);
+        return run_headless(&args, tx, rx).await;
+    }
     
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
@@ -296,12 +1152,20 @@ async fn run() -> Result<()> {
         .with_inner_size(winit::dpi::LogicalSize::new(1200.0, 800.0))
         .build(&event_loop)?;
 
-    let mut app = App::new(&window, rx).await?;
+    let mut app = App::new(&window, rx, tx, args, LogViewer::new(log_receiver)).await?;
+    match websocket_server::listen(&app.network.listen_address()) {
+        Ok(listener) => app.start_server(listener),
+        // Keep the window up so the address can be fixed in the setup wizard
+        Err(e) => {
+            log::error!("{:#}", e);
+            app.controller_receiver.set_server_status(format!("Not listening: {:#}", e));
+            app.toasts.push(ToastKind::Error, format!("{:#} - change the address in Settings > Setup Wizard", e));
+        }
+    }
 
-    // Start the WebSocket server with the sender
-    let _server_handle = tokio::spawn(async move {
-        start_websocket_server(tx).await
-    });
+    // Updates and redraws are scheduled independently so input isn't tied to the frame rate
+    let mut next_update = Instant::now();
+    let mut next_render = Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
         match event {
@@ -316,100 +1180,184 @@ async fn run() -> Result<()> {
                             app.resize(*physical_size);
                         }
                         WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            app.resize(**new_inner_size);
+                            app.scale_factor_changed(&window, **new_inner_size);
                         }
                         _ => {}
                     }
                 }
             }
             WinitEvent::RedrawRequested(window_id) if window_id == window.id() => {
-                app.update();
+                // Minimized, there is nothing to draw into and the surface can't be configured
+                let size = window.inner_size();
+                if size.width == 0 || size.height == 0 {
+                    return;
+                }
+                if app.gpu_lost.load(Ordering::SeqCst) {
+                    app.recreate_gpu(&window);
+                }
+                // Resize events can go missing while switching displays
+                if size != app.size {
+                    app.resize(size);
+                }
+
+                app.frame_stats.begin_frame();
+                let render_start = Instant::now();
                 match app.render(&window) {
                     Ok(_) => {}
-                    Err(wgpu::SurfaceError::Lost) => app.resize(app.size),
+                    // Only the surface is stale, the device itself is rebuilt once it reports being lost
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        log::warn!("Surface lost or outdated, reconfiguring");
+                        app.resize(size);
+                    }
+                    Err(wgpu::SurfaceError::Timeout) => log::warn!("Timed out acquiring surface texture, skipping frame"),
                     Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    Err(e) => eprintln!("{:?}", e),
                 }
+                app.frame_stats.record_render(render_start.elapsed());
             }
             WinitEvent::MainEventsCleared => {
-                window.request_redraw();
+                let now = Instant::now();
+                if now >= next_update {
+                    let update_start = Instant::now();
+                    app.update();
+                    app.frame_stats.record_update(update_start.elapsed());
+                    next_update = next_deadline(next_update, app.settings.ui.update_interval(), now);
+                }
+                if now >= next_render {
+                    window.request_redraw();
+                    next_render = next_deadline(next_render, app.settings.ui.render_interval(), now);
+                }
+                if *control_flow != ControlFlow::Exit {
+                    *control_flow = ControlFlow::WaitUntil(next_update.min(next_render));
+                }
             }
             _ => {}
         }
     });
 }
 
-async fn start_websocket_server(event_sender: tokio::sync::mpsc::Sender<ControllerInputData>) -> Result<()> {
-    let listener = TcpListener::bind("192.168.1.185:8080").await?;
-    log::info!("WebSocket server listening on 192.168.1.185:8080");
-
-    while let Ok((stream, addr)) = listener.accept().await {
-        log::info!("New connection from {}", addr);
-        
-        let sender = event_sender.clone();
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream, sender).await {
-                log::error!("Error handling connection: {}", e);
-            }
-        });
+// Same server and virtual controller as the windowed app, with client activity going to the log
+async fn run_headless(
+    args: &Args,
+    event_sender: tokio::sync::mpsc::Sender<ServerEvent>,
+    mut event_receiver: tokio::sync::mpsc::Receiver<ServerEvent>,
+) -> Result<()> {
+    let settings_path = args.profile.clone().unwrap_or_else(settings::default_settings_path);
+    let mut settings = Settings::load(&settings_path);
+    let listener = websocket_server::listen(&args.network(&settings.network).listen_address())?;
+    let (mut virtual_controller, _, _) = create_virtual_controller(args.controller, settings.virtual_controller.player_slots());
+    virtual_controller.set_profile(settings.mapping.active());
+    virtual_controller.set_script(settings.scripting.load()).ok();
+    let shared_controller = SharedController {
+        virtual_controller: Arc::new(Mutex::new(virtual_controller)),
+        forwarding_enabled: Arc::new(AtomicBool::new(true)),
+        profiles: Arc::new(tokio::sync::watch::channel(settings.mapping.profile_list()).0),
+        trace: Arc::new(PipelineTrace::new()),
+        hid_passthrough: Arc::new(HidPassthrough::new(settings.hid_passthrough.recreate_device)),
+        pad_types: Arc::new(tokio::sync::watch::channel(settings.virtual_controller.pad_types()).0),
+        pad_health: Arc::new(tokio::sync::watch::channel(PadHealth::Working).0),
+        create_pad: pad_factory(args.controller),
+        pc_input: Arc::new(tokio::sync::broadcast::channel(PC_INPUT_CAPACITY).0),
+    };
+    let local_controller = if settings.local_controller.enabled {
+        Some(LocalController::start(shared_controller.virtual_controller.clone(), shared_controller.pc_input.clone(), &settings.local_controller)?)
+    } else {
+        None
+    };
+    tokio::spawn(websocket_server::serve(listener, event_sender, shared_controller.clone()));
+    if settings.overlay.enabled {
+        let overlay_listener = overlay::listen(&settings.overlay.listen_address())?;
+        tokio::spawn(overlay::serve(overlay_listener, shared_controller.virtual_controller.clone()));
+        log::info!("Serving the stream overlay at {}", settings.overlay.url());
     }
-    
-    Ok(())
-}
+    // Hotkeys come in through the window's event loop, there is none to register them with here
+    let mut game_launcher = GameLauncher::new();
+    let mut sessions = SessionTracker::new();
+    let mut pad_watchdog = PadWatchdog::new();
+    let mut script_watcher = ScriptWatcher::new();
+    // Nothing else wakes the loop up once the last client is gone, the watchdog runs on it too
+    let mut session_check = tokio::time::interval(Duration::from_secs(1));
+    log::info!("Running headless, press Ctrl+C to stop");
 
-async fn handle_connection(stream: tokio::net::TcpStream, event_sender: tokio::sync::mpsc::Sender<ControllerInputData>) -> Result<()> {
-    let ws_stream = accept_async(stream).await?;
-    let (_tx, mut rx) = ws_stream.split();
-    
-    log::info!("WebSocket connection established");
-    
-    while let Some(msg) = rx.next().await {
-        match msg? {
-            Message::Text(text) => {
-                if let Ok(controller_data) = serde_json::from_str::<ControllerInputData>(&text) {
-                    let current_time = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64;
-                    
-                    let delay = if controller_data.timestamp < current_time {
-                        current_time - controller_data.timestamp
-                    } else {
-                        0
-                    };
-                    
-                    for button_event in &controller_data.button_events {
-                        println!("Button: {} - {} ({}ms delay)", 
-                            button_event.button, 
-                            if button_event.pressed { "Pressed" } else { "Released" },
-                            delay);
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = session_check.tick() => {
+                let event = pad_watchdog.check(&mut shared_controller.virtual_controller.lock().unwrap(), Instant::now());
+                if let Some(event) = event {
+                    event.log();
+                    publish_pad_health(&shared_controller.pad_health, event.health());
+                }
+                if let Some(script) = script_watcher.poll(&settings.scripting) {
+                    shared_controller.virtual_controller.lock().unwrap().set_script(Some(script)).ok();
+                }
+                if let Some(summary) = sessions.update() {
+                    log::info!("Session ended: {}", serde_json::to_string(&summary)?);
+                    match summary.save(&session_report::reports_dir()) {
+                        Ok(path) => log::info!("Saved session summary to {}", path.display()),
+                        Err(e) => log::error!("Failed to save session summary: {:#}", e),
                     }
-                    
-                    for axis_event in &controller_data.axis_events {
-                        println!("Axis: {} - {:.3} ({}ms delay)", 
-                            axis_event.axis, 
-                            axis_event.value,
-                            delay);
+                }
+            }
+            event = event_receiver.recv() => match event {
+                Some(ServerEvent::ClientConnected(addr)) => {
+                    log::info!("Client connected: {}", addr);
+                    let profile = settings.mapping.active();
+                    sessions.client_connected(&profile.name, profile.launch.game_name());
+                    if let Err(e) = game_launcher.client_connected(&settings.mapping.active().launch) {
+                        log::error!("{:#}", e);
                     }
-                    
-                    if let Err(e) = event_sender.send(controller_data).await {
-                        log::error!("Failed to send controller data to UI: {}", e);
-                        break;
+                }
+                Some(ServerEvent::ClientDisconnected(addr)) => {
+                    log::info!("Client disconnected: {}", addr);
+                    sessions.client_disconnected();
+                    if let Err(e) = game_launcher.client_disconnected(&settings.mapping.active().launch) {
+                        log::error!("{:#}", e);
                     }
                 }
-            }
-            Message::Close(_) => {
-                log::info!("WebSocket connection closed");
-                break;
-            }
-            _ => {}
+                // Already forwarded to the virtual controller by the network task
+                Some(ServerEvent::Input(digest)) => sessions.record(&digest),
+                // Only the window greys out missing controls
+                Some(ServerEvent::CapabilitiesReported(..)) => {}
+                Some(ServerEvent::BatteryReported(addr, report)) => if report.is_low() {
+                    log::warn!("Controller {} on {} is low on battery ({}%)", report.controller_id, addr.ip(), report.battery_percent);
+                },
+                Some(ServerEvent::ProfileRequested(name)) => match settings.mapping.select(&name) {
+                    Ok(true) => {
+                        log::info!("Client switched to profile {}", name);
+                        shared_controller.virtual_controller.lock().unwrap().set_profile(settings.mapping.active());
+                        shared_controller.profiles.send_replace(settings.mapping.profile_list());
+                        if let Err(e) = settings.save(&settings_path) {
+                            log::error!("Failed to save settings: {}", e);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => log::warn!("{:#}", e),
+                },
+                None => break,
+            },
         }
     }
-    
+
+    drop(local_controller);
+    let mut virtual_controller = shared_controller.virtual_controller.lock().unwrap();
+    if virtual_controller.is_connected() {
+        virtual_controller.disconnect_controller()?;
+    }
     Ok(())
 }
 
+// Keeps a steady cadence, but skips ahead instead of bursting after a stall
+fn next_deadline(deadline: Instant, interval: Duration, now: Instant) -> Instant {
+    let next = deadline + interval;
+    if next < now {
+        now + interval
+    } else {
+        next
+    }
+}
+
 fn main() -> Result<()> {
+    let args = Args::parse();
     let rt = tokio::runtime::Runtime::new()?;
-    rt.block_on(run())
+    rt.block_on(run(args))
 }
@@ -0,0 +1,1184 @@
+use anyhow::Result;
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use crate::deadzone::StickShaping;
+use crate::i18n;
+use crate::input_filter::{render_filters, FilterConfig};
+use crate::input_id::{AxisId, ButtonId};
+use crate::launcher::LaunchSettings;
+use crate::protocol::{AxisEvent, ButtonEvent, Capabilities, ControllerInputData, ProfileList};
+use crate::smoothing::{Smoother, SmoothingSettings};
+
+// How far the stick has to be pushed before stick-to-D-Pad presses anything, and how far back it
+// has to come before everything is let go again
+const STICK_DPAD_PRESS: f32 = 0.5;
+const STICK_DPAD_RELEASE: f32 = 0.4;
+// How far past the edge of its 45 degree sector the stick can go before the direction changes,
+// so a stick resting on a boundary doesn't flicker between two directions
+const STICK_DPAD_HYSTERESIS_DEG: f32 = 10.0;
+
+const DPAD_BUTTONS: [ButtonId; 4] = [ButtonId::DPadUp, ButtonId::DPadDown, ButtonId::DPadLeft, ButtonId::DPadRight];
+
+// While the layer's modifier is held, pressing `from` presses `to` on the virtual pad instead
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ButtonRemap {
+    pub from: ButtonId,
+    pub to: ButtonId,
+}
+
+// A second set of bindings reached by holding a button, e.g. Select + D-Pad
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShiftLayer {
+    pub modifier: ButtonId,
+    // Keeps the modifier itself away from the game, so holding it only switches layers
+    pub consume_modifier: bool,
+    pub remaps: Vec<ButtonRemap>,
+}
+
+impl Default for ShiftLayer {
+    fn default() -> Self {
+        Self {
+            modifier: ButtonId::Select,
+            consume_modifier: false,
+            remaps: Vec::new(),
+        }
+    }
+}
+
+// Holding the button keeps pressing it again, like a held key in a text field. Unlike turbo the
+// first repeat waits, so a tap on the D-Pad still moves one menu entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepeatBinding {
+    pub button: ButtonId,
+    pub initial_delay_ms: u32,
+    pub rate_hz: f32,
+}
+
+impl Default for RepeatBinding {
+    fn default() -> Self {
+        Self {
+            button: ButtonId::DPadDown,
+            initial_delay_ms: 400,
+            rate_hz: 10.0,
+        }
+    }
+}
+
+// Sends some of the Deck's controls to a second virtual pad, e.g. one half of the Deck per player
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplitSettings {
+    pub enabled: bool,
+    // Controls that go to player 2, after the rest of the profile is applied
+    pub buttons: Vec<ButtonId>,
+    pub axes: Vec<AxisId>,
+    // Player 2 gets the right half on the left side of their pad, where games expect movement
+    pub swap_sides: bool,
+}
+
+const RIGHT_HALF_BUTTONS: [ButtonId; 8] = [
+    ButtonId::South, ButtonId::East, ButtonId::North, ButtonId::West,
+    ButtonId::RightBumper, ButtonId::RightTrigger, ButtonId::RightStick, ButtonId::Start,
+];
+const RIGHT_HALF_AXES: [AxisId; 3] = [AxisId::RightStickX, AxisId::RightStickY, AxisId::RightTrigger];
+
+impl Default for SplitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            buttons: RIGHT_HALF_BUTTONS.to_vec(),
+            axes: RIGHT_HALF_AXES.to_vec(),
+            swap_sides: true,
+        }
+    }
+}
+
+impl SplitSettings {
+    // What to press on player 2's pad, None if the button stays with player 1
+    pub fn player_two_button(&self, button: ButtonId) -> Option<ButtonId> {
+        if !self.enabled || !self.buttons.contains(&button) {
+            return None;
+        }
+        if !self.swap_sides {
+            return Some(button);
+        }
+        Some(match button {
+            ButtonId::South => ButtonId::DPadDown,
+            ButtonId::East => ButtonId::DPadRight,
+            ButtonId::West => ButtonId::DPadLeft,
+            ButtonId::North => ButtonId::DPadUp,
+            ButtonId::DPadDown => ButtonId::South,
+            ButtonId::DPadRight => ButtonId::East,
+            ButtonId::DPadLeft => ButtonId::West,
+            ButtonId::DPadUp => ButtonId::North,
+            ButtonId::LeftBumper => ButtonId::RightBumper,
+            ButtonId::RightBumper => ButtonId::LeftBumper,
+            ButtonId::LeftTrigger => ButtonId::RightTrigger,
+            ButtonId::RightTrigger => ButtonId::LeftTrigger,
+            ButtonId::LeftStick => ButtonId::RightStick,
+            ButtonId::RightStick => ButtonId::LeftStick,
+            ButtonId::Select => ButtonId::Start,
+            ButtonId::Start => ButtonId::Select,
+            ButtonId::Guide => ButtonId::Guide,
+        })
+    }
+
+    pub fn player_two_axis(&self, axis: AxisId) -> Option<AxisId> {
+        if !self.enabled || !self.axes.contains(&axis) {
+            return None;
+        }
+        if !self.swap_sides {
+            return Some(axis);
+        }
+        Some(match axis {
+            AxisId::LeftStickX => AxisId::RightStickX,
+            AxisId::LeftStickY => AxisId::RightStickY,
+            AxisId::RightStickX => AxisId::LeftStickX,
+            AxisId::RightStickY => AxisId::LeftStickY,
+            AxisId::LeftTrigger => AxisId::RightTrigger,
+            AxisId::RightTrigger => AxisId::LeftTrigger,
+            AxisId::DPadX => AxisId::DPadX,
+            AxisId::DPadY => AxisId::DPadY,
+        })
+    }
+
+    // Returns true when a setting changed
+    fn render(&mut self, ui: &Ui, capabilities: Option<Capabilities>) -> bool {
+        let mut changed = ui.checkbox("Split between two virtual controllers", &mut self.enabled);
+        if ui.is_item_hovered() {
+            ui.tooltip_text("The controls ticked below go to a second virtual controller, for two players on one Deck");
+        }
+        if !self.enabled {
+            return changed;
+        }
+
+        changed |= ui.checkbox("Move player 2's controls to the left side", &mut self.swap_sides);
+        if ui.button("Right Half to Player 2") {
+            self.buttons = RIGHT_HALF_BUTTONS.to_vec();
+            self.axes = RIGHT_HALF_AXES.to_vec();
+            changed = true;
+        }
+
+        ui.text("Player 2 gets:");
+        for (index, button) in ButtonId::ALL.iter().enumerate() {
+            let mut player_two = self.buttons.contains(button);
+            if index % 4 != 0 {
+                ui.same_line_with_pos(110.0 * (index % 4) as f32 + 8.0);
+            }
+            let _disabled = ui.begin_disabled(capabilities.is_some_and(|c| !c.has_button(*button)));
+            if ui.checkbox(format!("{}##split", button.name()), &mut player_two) {
+                if player_two {
+                    self.buttons.push(*button);
+                } else {
+                    self.buttons.retain(|b| b != button);
+                }
+                changed = true;
+            }
+        }
+        for (index, axis) in AxisId::ALL.iter().enumerate() {
+            let mut player_two = self.axes.contains(axis);
+            if index % 2 != 0 {
+                ui.same_line_with_pos(220.0 + 8.0);
+            }
+            let _disabled = ui.begin_disabled(capabilities.is_some_and(|c| !c.has_axis(*axis)));
+            if ui.checkbox(format!("{}##split", axis.name()), &mut player_two) {
+                if player_two {
+                    self.axes.push(*axis);
+                } else {
+                    self.axes.retain(|a| a != axis);
+                }
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MappingProfile {
+    pub name: String,
+    // Always on, a layer's remaps win while its modifier is held
+    pub remaps: Vec<ButtonRemap>,
+    pub layers: Vec<ShiftLayer>,
+    // A press latches these on until the next press, for games that want long holds
+    pub toggle_buttons: Vec<ButtonId>,
+    // Tapping a layer modifier keeps its layer active for the next button press
+    pub sticky_modifiers: bool,
+    pub repeats: Vec<RepeatBinding>,
+    // The left stick presses the D-Pad in eight directions instead of moving the virtual stick
+    pub stick_to_dpad: bool,
+    // The D-Pad pushes the virtual left stick instead, for games that ignore the D-Pad
+    pub dpad_to_stick: bool,
+    // How far the D-Pad pushes the stick, 1.0 is all the way
+    pub dpad_stick_magnitude: f32,
+    // Applied by the virtual controller's filter chain, ahead of the filters below
+    pub left_stick: StickShaping,
+    pub right_stick: StickShaping,
+    pub filters: Vec<FilterConfig>,
+    pub smoothing: SmoothingSettings,
+    pub split: SplitSettings,
+    pub launch: LaunchSettings,
+}
+
+impl Default for MappingProfile {
+    fn default() -> Self {
+        Self {
+            name: "Default".to_string(),
+            remaps: Vec::new(),
+            layers: Vec::new(),
+            toggle_buttons: Vec::new(),
+            sticky_modifiers: false,
+            repeats: Vec::new(),
+            stick_to_dpad: false,
+            dpad_to_stick: false,
+            dpad_stick_magnitude: 1.0,
+            left_stick: StickShaping::default(),
+            right_stick: StickShaping::default(),
+            filters: Vec::new(),
+            smoothing: SmoothingSettings::default(),
+            split: SplitSettings::default(),
+            launch: LaunchSettings::default(),
+        }
+    }
+}
+
+// Turns the buttons held on the Deck into the buttons pressed on the virtual pad
+pub struct MappingEngine {
+    profile: MappingProfile,
+    held: HashSet<ButtonId>,
+    // Modifiers tapped with sticky modifiers on, waiting for the button they apply to
+    sticky: HashSet<ButtonId>,
+    // Modifiers held down while another button was pressed, releasing them doesn't make them sticky
+    chorded: HashSet<ButtonId>,
+    // What each held or latched button pressed when it went down, None if it was consumed.
+    // Releases go to the same place even if the modifier was let go first.
+    pressed_as: HashMap<ButtonId, Option<ButtonId>>,
+    // How many held buttons keep each virtual button down
+    output_holds: HashMap<ButtonId, u32>,
+    // Held buttons with a repeat binding, keyed by the button on the Deck
+    repeats: HashMap<ButtonId, RepeatState>,
+    smoother: Smoother,
+    // Positions of the left and right stick after smoothing, before any deadzone
+    sticks: [[f32; 2]; 2],
+    // 0 is right, counting counter-clockwise in 45 degree steps
+    stick_sector: Option<u8>,
+    // The D-Pad is holding the virtual left stick, the real stick is ignored until it's let go
+    dpad_stick_active: bool,
+}
+
+struct RepeatState {
+    target: ButtonId,
+    next: Instant,
+    // Between the release and the press of a repeat
+    released: bool,
+}
+
+impl MappingEngine {
+    pub fn new() -> Self {
+        Self {
+            profile: MappingProfile::default(),
+            held: HashSet::new(),
+            sticky: HashSet::new(),
+            chorded: HashSet::new(),
+            pressed_as: HashMap::new(),
+            output_holds: HashMap::new(),
+            repeats: HashMap::new(),
+            smoother: Smoother::new(),
+            sticks: [[0.0, 0.0]; 2],
+            stick_sector: None,
+            dpad_stick_active: false,
+        }
+    }
+
+    // Buttons already held keep what they pressed, the new profile applies from the next press
+    pub fn set_profile(&mut self, profile: MappingProfile) {
+        self.profile = profile;
+        self.sticky.clear();
+    }
+
+    pub fn profile(&self) -> &MappingProfile {
+        &self.profile
+    }
+
+    // Also lets go of latched toggle buttons
+    pub fn reset(&mut self) {
+        self.held.clear();
+        self.sticky.clear();
+        self.chorded.clear();
+        self.pressed_as.clear();
+        self.output_holds.clear();
+        self.repeats.clear();
+        self.smoother.reset();
+        self.sticks = [[0.0, 0.0]; 2];
+        self.stick_sector = None;
+        self.dpad_stick_active = false;
+    }
+
+    pub fn map(&mut self, input: ControllerInputData) -> ControllerInputData {
+        let mut button_events = Vec::with_capacity(input.button_events.len());
+        for event in input.button_events {
+            self.map_button(event, &mut button_events);
+        }
+
+        let now = Instant::now();
+        let smoothed: Vec<AxisEvent> = input.axis_events.into_iter()
+            .map(|event| AxisEvent {
+                value: self.smoother.filter(&self.profile.smoothing, event.axis, event.value, now),
+                ..event
+            })
+            .collect();
+        let mut axis_events = Vec::with_capacity(smoothed.len());
+        self.apply_axes(smoothed, input.timestamp, &mut button_events, &mut axis_events);
+        self.convert_dpad(input.timestamp, &mut button_events, &mut axis_events);
+
+        ControllerInputData { button_events, axis_events, ..input }
+    }
+
+    fn apply_axes(&mut self, events: Vec<AxisEvent>, timestamp: u64, button_events: &mut Vec<ButtonEvent>, axis_events: &mut Vec<AxisEvent>) {
+        let mut left_stick_moved = None;
+        for event in events {
+            let (stick, index) = match event.axis {
+                AxisId::LeftStickX => (0, 0),
+                AxisId::LeftStickY => (0, 1),
+                AxisId::RightStickX => (1, 0),
+                AxisId::RightStickY => (1, 1),
+                _ => {
+                    axis_events.push(event);
+                    continue;
+                }
+            };
+            self.sticks[stick][index] = event.value;
+            if stick == 0 {
+                left_stick_moved = Some(event.timestamp);
+                // The D-Pad has the virtual left stick, one way or the other
+                if self.profile.stick_to_dpad || self.dpad_stick_active {
+                    continue;
+                }
+            }
+            axis_events.push(event);
+        }
+
+        // Also runs when switched off while the stick was pushed, to let go of the D-Pad
+        if left_stick_moved.is_some() || !self.profile.stick_to_dpad {
+            self.update_stick_dpad(left_stick_moved.unwrap_or(timestamp), button_events);
+        }
+    }
+
+    pub fn raw_sticks(&self) -> [[f32; 2]; 2] {
+        self.sticks
+    }
+
+    // Presses and releases due for held repeat buttons, and axes still being smoothed. Called on a
+    // timer since nothing arrives from the Deck while a button is just held.
+    pub fn tick(&mut self, now: Instant) -> (Vec<ButtonEvent>, Vec<AxisEvent>) {
+        let timestamp = crate::clock::now_ms();
+        let mut output = Vec::new();
+        for (button, repeat) in &mut self.repeats {
+            if now < repeat.next {
+                continue;
+            }
+            let Some(binding) = self.profile.repeats.iter().find(|binding| binding.button == *button) else {
+                continue;
+            };
+            let interval = Duration::from_secs_f32(1.0 / binding.rate_hz.max(1.0));
+
+            // A release wouldn't show while another held button keeps the same virtual button down,
+            // so the repeat waits until that one is let go
+            if self.output_holds.get(&repeat.target) != Some(&1) {
+                repeat.next = now + interval;
+                continue;
+            }
+            // Each repeat is a release then a press, half an interval apart, so the game sees both
+            repeat.released = !repeat.released;
+            repeat.next = now + interval / 2;
+            output.push(ButtonEvent {
+                button: repeat.target,
+                pressed: !repeat.released,
+                timestamp,
+            });
+        }
+
+        let mut axis_events = Vec::new();
+        let smoothed = self.smoother.tick(&self.profile.smoothing, now, timestamp);
+        if !smoothed.is_empty() {
+            self.apply_axes(smoothed, timestamp, &mut output, &mut axis_events);
+        }
+        self.convert_dpad(timestamp, &mut output, &mut axis_events);
+        (output, axis_events)
+    }
+
+    fn target(&self, button: ButtonId) -> Option<ButtonId> {
+        // With several modifiers held the layer listed last wins
+        for layer in self.profile.layers.iter().rev() {
+            let active = self.held.contains(&layer.modifier) || self.sticky.contains(&layer.modifier);
+            if layer.modifier == button || !active {
+                continue;
+            }
+            if let Some(remap) = layer.remaps.iter().find(|remap| remap.from == button) {
+                return Some(remap.to);
+            }
+        }
+
+        let consumed = self.profile.layers.iter().any(|layer| layer.consume_modifier && layer.modifier == button);
+        if consumed {
+            return None;
+        }
+        Some(self.profile.remaps.iter().find(|remap| remap.from == button).map_or(button, |remap| remap.to))
+    }
+
+    fn is_modifier(&self, button: ButtonId) -> bool {
+        self.profile.layers.iter().any(|layer| layer.modifier == button)
+    }
+
+    fn map_button(&mut self, event: ButtonEvent, output: &mut Vec<ButtonEvent>) {
+        if event.pressed {
+            // Fixed rate and sync messages repeat the state of held buttons
+            if !self.held.insert(event.button) {
+                return;
+            }
+
+            if self.is_modifier(event.button) {
+                // Tapping a sticky modifier again cancels it
+                if self.sticky.remove(&event.button) {
+                    self.chorded.insert(event.button);
+                }
+            } else {
+                self.chorded.extend(self.held.iter().copied().filter(|&button| button != event.button));
+            }
+
+            // The second press of a toggle button lets go of the latched one
+            if self.pressed_as.contains_key(&event.button) {
+                self.release(event, output);
+                return;
+            }
+
+            let target = self.target(event.button);
+            if !self.is_modifier(event.button) {
+                self.sticky.clear();
+            }
+            self.pressed_as.insert(event.button, target);
+
+            if let Some(target) = target {
+                if let Some(binding) = self.profile.repeats.iter().find(|binding| binding.button == event.button) {
+                    self.repeats.insert(event.button, RepeatState {
+                        target,
+                        next: Instant::now() + Duration::from_millis(binding.initial_delay_ms as u64),
+                        released: false,
+                    });
+                }
+                self.hold_output(target, event.timestamp, output);
+            }
+        } else {
+            if !self.held.remove(&event.button) {
+                return;
+            }
+            if !self.chorded.remove(&event.button) && self.profile.sticky_modifiers && self.is_modifier(event.button) {
+                self.sticky.insert(event.button);
+            }
+            if self.profile.toggle_buttons.contains(&event.button) {
+                return;
+            }
+            self.release(event, output);
+        }
+    }
+
+    fn release(&mut self, event: ButtonEvent, output: &mut Vec<ButtonEvent>) {
+        self.repeats.remove(&event.button);
+        if let Some(Some(target)) = self.pressed_as.remove(&event.button) {
+            self.release_output(target, event.timestamp, output);
+        }
+    }
+
+    fn hold_output(&mut self, target: ButtonId, timestamp: u64, output: &mut Vec<ButtonEvent>) {
+        let holds = self.output_holds.entry(target).or_insert(0);
+        *holds += 1;
+        let mut press = *holds == 1;
+        // A repeat between its release and its press has the button up although it's held, the
+        // new holder presses it straight away instead of waiting on the repeat
+        for repeat in self.repeats.values_mut().filter(|repeat| repeat.target == target && repeat.released) {
+            repeat.released = false;
+            press = true;
+        }
+        if press {
+            output.push(ButtonEvent { button: target, pressed: true, timestamp });
+        }
+    }
+
+    fn release_output(&mut self, target: ButtonId, timestamp: u64, output: &mut Vec<ButtonEvent>) {
+        if let Some(holds) = self.output_holds.get_mut(&target) {
+            *holds -= 1;
+            if *holds == 0 {
+                self.output_holds.remove(&target);
+                output.push(ButtonEvent { button: target, pressed: false, timestamp });
+            }
+        }
+    }
+
+    fn update_stick_dpad(&mut self, timestamp: u64, output: &mut Vec<ButtonEvent>) {
+        let [x, y] = self.sticks[0];
+        let threshold = if self.stick_sector.is_some() { STICK_DPAD_RELEASE } else { STICK_DPAD_PRESS };
+        let sector = if !self.profile.stick_to_dpad || (x * x + y * y).sqrt() < threshold {
+            None
+        } else {
+            let angle = y.atan2(x).to_degrees().rem_euclid(360.0);
+            match self.stick_sector {
+                Some(current) if angle_between(angle, current as f32 * 45.0) <= 22.5 + STICK_DPAD_HYSTERESIS_DEG => Some(current),
+                _ => Some(((angle + 22.5) / 45.0) as u8 % 8),
+            }
+        };
+        if sector == self.stick_sector {
+            return;
+        }
+
+        let old_buttons = sector_buttons(self.stick_sector);
+        let new_buttons = sector_buttons(sector);
+        for button in old_buttons.iter().filter(|button| !new_buttons.contains(button)) {
+            self.release_output(*button, timestamp, output);
+        }
+        for button in new_buttons.iter().filter(|button| !old_buttons.contains(button)) {
+            self.hold_output(*button, timestamp, output);
+        }
+        self.stick_sector = sector;
+    }
+
+    // Where the virtual left stick goes when the D-Pad lets go of it
+    fn left_stick_output(&self) -> [f32; 2] {
+        if self.profile.stick_to_dpad {
+            [0.0, 0.0]
+        } else {
+            self.sticks[0]
+        }
+    }
+
+    // Turns D-Pad output into left stick output. Runs last, so D-Pad presses from layers,
+    // repeats and stick-to-D-Pad all end up on the stick too.
+    fn convert_dpad(&mut self, timestamp: u64, button_events: &mut Vec<ButtonEvent>, axis_events: &mut Vec<AxisEvent>) {
+        let [x, y] = if self.profile.dpad_to_stick {
+            let count = button_events.len();
+            button_events.retain(|event| !DPAD_BUTTONS.contains(&event.button));
+            if button_events.len() == count {
+                return;
+            }
+
+            let held = |button| if self.output_holds.contains_key(&button) { 1.0 } else { 0.0 };
+            let x: f32 = held(ButtonId::DPadRight) - held(ButtonId::DPadLeft);
+            let y: f32 = held(ButtonId::DPadUp) - held(ButtonId::DPadDown);
+            self.dpad_stick_active = x != 0.0 || y != 0.0;
+            if self.dpad_stick_active {
+                // Diagonals get the same deflection as straight directions
+                let scale = self.profile.dpad_stick_magnitude.clamp(0.0, 1.0) / (x * x + y * y).sqrt();
+                [x * scale, y * scale]
+            } else {
+                self.left_stick_output()
+            }
+        } else if self.dpad_stick_active {
+            // Switched off while the D-Pad was held, hand the held directions back as buttons
+            self.dpad_stick_active = false;
+            for button in DPAD_BUTTONS {
+                if self.output_holds.contains_key(&button) && !button_events.iter().any(|event| event.button == button) {
+                    button_events.push(ButtonEvent { button, pressed: true, timestamp });
+                }
+            }
+            self.left_stick_output()
+        } else {
+            return;
+        };
+
+        axis_events.retain(|event| !matches!(event.axis, AxisId::LeftStickX | AxisId::LeftStickY));
+        axis_events.push(AxisEvent { axis: AxisId::LeftStickX, value: x, timestamp });
+        axis_events.push(AxisEvent { axis: AxisId::LeftStickY, value: y, timestamp });
+    }
+}
+
+fn angle_between(a: f32, b: f32) -> f32 {
+    let difference = (a - b).rem_euclid(360.0);
+    difference.min(360.0 - difference)
+}
+
+fn sector_buttons(sector: Option<u8>) -> Vec<ButtonId> {
+    match sector {
+        None => vec![],
+        Some(0) => vec![ButtonId::DPadRight],
+        Some(1) => vec![ButtonId::DPadRight, ButtonId::DPadUp],
+        Some(2) => vec![ButtonId::DPadUp],
+        Some(3) => vec![ButtonId::DPadUp, ButtonId::DPadLeft],
+        Some(4) => vec![ButtonId::DPadLeft],
+        Some(5) => vec![ButtonId::DPadLeft, ButtonId::DPadDown],
+        Some(6) => vec![ButtonId::DPadDown],
+        Some(_) => vec![ButtonId::DPadDown, ButtonId::DPadRight],
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MappingSettings {
+    pub profiles: Vec<MappingProfile>,
+    pub active_profile: usize,
+}
+
+impl Default for MappingSettings {
+    fn default() -> Self {
+        Self {
+            profiles: vec![MappingProfile::default()],
+            active_profile: 0,
+        }
+    }
+}
+
+impl MappingSettings {
+    pub fn active(&self) -> MappingProfile {
+        self.profiles.get(self.active_profile).cloned().unwrap_or_default()
+    }
+
+    pub fn profile_list(&self) -> ProfileList {
+        ProfileList {
+            profiles: self.profiles.iter().map(|profile| profile.name.clone()).collect(),
+            active_profile: self.active().name,
+        }
+    }
+
+    // Adds the profile and makes it the active one, renamed if another profile has its name
+    pub fn add_profile(&mut self, mut profile: MappingProfile) {
+        let base = profile.name.clone();
+        let mut copy = 1;
+        while self.profiles.iter().any(|existing| existing.name == profile.name) {
+            copy += 1;
+            profile.name = format!("{} ({})", base, copy);
+        }
+        self.profiles.push(profile);
+        self.active_profile = self.profiles.len() - 1;
+    }
+
+    // Returns true when the active profile changed, false when it already was the one asked for
+    pub fn select(&mut self, name: &str) -> Result<bool> {
+        let index = self.profiles.iter().position(|profile| profile.name == name)
+            .ok_or_else(|| anyhow::anyhow!("No mapping profile named '{}'", name))?;
+        let changed = index != self.active_profile;
+        self.active_profile = index;
+        Ok(changed)
+    }
+
+    // Returns true when the active profile changed and has to be handed to the virtual controller
+    // raw_sticks drives the live deadzone preview, controls the connected controllers can't
+    // produce are greyed out once they've reported what they have
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool, raw_sticks: [[f32; 2]; 2], capabilities: Option<Capabilities>) -> bool {
+        let mut changed = false;
+        if self.profiles.is_empty() {
+            self.profiles.push(MappingProfile::default());
+        }
+        self.active_profile = self.active_profile.min(self.profiles.len() - 1);
+
+        ui.window(i18n::window_title("Mapping"))
+            .size([450.0, 400.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                let names: Vec<&str> = self.profiles.iter().map(|profile| profile.name.as_str()).collect();
+                changed |= ui.combo_simple_string("Profile", &mut self.active_profile, &names);
+                if ui.button("New Profile") {
+                    self.profiles.push(MappingProfile {
+                        name: format!("Profile {}", self.profiles.len() + 1),
+                        ..Default::default()
+                    });
+                    self.active_profile = self.profiles.len() - 1;
+                    changed = true;
+                }
+                if self.profiles.len() > 1 {
+                    ui.same_line();
+                    if ui.button("Delete Profile") {
+                        self.profiles.remove(self.active_profile);
+                        self.active_profile = self.active_profile.saturating_sub(1);
+                        changed = true;
+                    }
+                }
+
+                let profile = &mut self.profiles[self.active_profile];
+                changed |= ui.input_text("Name", &mut profile.name).build();
+                changed |= profile.launch.render(ui);
+                ui.separator();
+
+                ui.text("Remaps");
+                ui.text_wrapped("Pressing the button on the left presses the one on the right on the virtual controller.");
+                {
+                    let _id = ui.push_id("remaps");
+                    changed |= render_remaps(ui, &mut profile.remaps);
+                }
+                ui.separator();
+
+                ui.text("Mode Shift Layers");
+                ui.text_wrapped("While the modifier is held, the buttons below press a different button on the virtual controller.");
+                let mut remove_layer = None;
+                for (layer_index, layer) in profile.layers.iter_mut().enumerate() {
+                    let _layer_id = ui.push_id_usize(layer_index);
+                    ui.separator();
+                    changed |= button_combo(ui, "Hold", &mut layer.modifier);
+                    changed |= ui.checkbox("Hide modifier from game", &mut layer.consume_modifier);
+
+                    changed |= render_remaps(ui, &mut layer.remaps);
+                    ui.same_line();
+                    if ui.button("Remove Layer") {
+                        remove_layer = Some(layer_index);
+                    }
+                }
+                if let Some(index) = remove_layer {
+                    profile.layers.remove(index);
+                    changed = true;
+                }
+
+                ui.separator();
+                if ui.button("Add Layer") {
+                    profile.layers.push(ShiftLayer::default());
+                    changed = true;
+                }
+                changed |= ui.checkbox("Sticky modifiers", &mut profile.sticky_modifiers);
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Tap a modifier, then press the button to use with it");
+                }
+                ui.separator();
+
+                changed |= profile.split.render(ui, capabilities);
+                ui.separator();
+
+                ui.text("Smoothing");
+                ui.text_wrapped("Filters out sensor noise. Higher strength is steadier but lags more.");
+                {
+                    let _id = ui.push_id("smoothing");
+                    changed |= profile.smoothing.render(ui);
+                }
+                ui.separator();
+
+                ui.text("Deadzones");
+                if let Some(_node) = ui.tree_node("Left Stick") {
+                    let _id = ui.push_id("left_stick");
+                    changed |= profile.left_stick.render(ui, raw_sticks[0]);
+                }
+                if let Some(_node) = ui.tree_node("Right Stick") {
+                    let _id = ui.push_id("right_stick");
+                    changed |= profile.right_stick.render(ui, raw_sticks[1]);
+                }
+                ui.separator();
+
+                ui.text("Filters");
+                ui.text_wrapped("Run after the deadzones, top to bottom, on what the virtual controller is about to report.");
+                {
+                    let _id = ui.push_id("filters");
+                    changed |= render_filters(ui, &mut profile.filters);
+                }
+                ui.separator();
+
+                changed |= ui.checkbox("Left stick to D-Pad", &mut profile.stick_to_dpad);
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("The left stick presses the D-Pad in eight directions, for fighting games and emulators");
+                }
+                changed |= ui.checkbox("D-Pad to left stick", &mut profile.dpad_to_stick);
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("The D-Pad pushes the left stick, for games that ignore the D-Pad");
+                }
+                if profile.dpad_to_stick {
+                    ui.indent();
+                    changed |= ui.slider_config("Deflection", 0.1, 1.0)
+                        .display_format("%.2f")
+                        .build(&mut profile.dpad_stick_magnitude);
+                    ui.unindent();
+                }
+                ui.separator();
+
+                ui.text("Hold to Repeat");
+                ui.text_wrapped("Holding these presses them again and again, e.g. to scroll through menus with the D-Pad.");
+                let mut remove_repeat = None;
+                for (index, repeat) in profile.repeats.iter_mut().enumerate() {
+                    let _repeat_id = ui.push_id_usize(index);
+                    ui.set_next_item_width(130.0);
+                    changed |= button_combo(ui, "##button", &mut repeat.button);
+                    ui.same_line();
+                    ui.set_next_item_width(100.0);
+                    changed |= ui.slider("Delay (ms)", 100, 1000, &mut repeat.initial_delay_ms);
+                    ui.same_line();
+                    ui.set_next_item_width(80.0);
+                    changed |= ui.slider_config("Rate", 1.0, 30.0)
+                        .display_format("%.0f Hz")
+                        .build(&mut repeat.rate_hz);
+                    ui.same_line();
+                    if ui.small_button("Remove") {
+                        remove_repeat = Some(index);
+                    }
+                }
+                if let Some(index) = remove_repeat {
+                    profile.repeats.remove(index);
+                    changed = true;
+                }
+                if ui.button("Add Repeat") {
+                    profile.repeats.push(RepeatBinding::default());
+                    changed = true;
+                }
+                ui.separator();
+
+                ui.text("Toggle Buttons");
+                ui.text_wrapped("A press keeps these held on the virtual controller until they are pressed again.");
+                for (index, button) in ButtonId::ALL.iter().enumerate() {
+                    let mut toggle = profile.toggle_buttons.contains(button);
+                    if index % 4 != 0 {
+                        ui.same_line_with_pos(110.0 * (index % 4) as f32 + 8.0);
+                    }
+                    let _disabled = ui.begin_disabled(capabilities.is_some_and(|c| !c.has_button(*button)));
+                    if ui.checkbox(button.name(), &mut toggle) {
+                        if toggle {
+                            profile.toggle_buttons.push(*button);
+                        } else {
+                            profile.toggle_buttons.retain(|b| b != button);
+                        }
+                        changed = true;
+                    }
+                }
+            });
+
+        changed
+    }
+}
+
+// Returns true when the list changed
+fn render_remaps(ui: &Ui, remaps: &mut Vec<ButtonRemap>) -> bool {
+    let mut changed = false;
+    let mut remove = None;
+    for (index, remap) in remaps.iter_mut().enumerate() {
+        let _id = ui.push_id_usize(index);
+        ui.set_next_item_width(130.0);
+        changed |= button_combo(ui, "##from", &mut remap.from);
+        ui.same_line();
+        ui.text("->");
+        ui.same_line();
+        ui.set_next_item_width(130.0);
+        changed |= button_combo(ui, "##to", &mut remap.to);
+        ui.same_line();
+        if ui.small_button("Remove") {
+            remove = Some(index);
+        }
+    }
+    if let Some(index) = remove {
+        remaps.remove(index);
+        changed = true;
+    }
+
+    if ui.button("Add Remap") {
+        remaps.push(ButtonRemap { from: ButtonId::DPadUp, to: ButtonId::DPadUp });
+        changed = true;
+    }
+    changed
+}
+
+pub fn button_combo(ui: &Ui, label: &str, button: &mut ButtonId) -> bool {
+    let names: Vec<&str> = ButtonId::ALL.iter().map(|button| button.name()).collect();
+    let mut index = ButtonId::ALL.iter().position(|b| b == button).unwrap_or(0);
+    if ui.combo_simple_string(label, &mut index, &names) {
+        *button = ButtonId::ALL[index];
+        return true;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deadzone::tests::assert_stick;
+
+    fn server_message(buttons: &[(ButtonId, bool)], axes: &[(AxisId, f32)]) -> ControllerInputData {
+        ControllerInputData {
+            message_id: 0,
+            timestamp: 0,
+            sent_at: 0,
+            controller_id: 0,
+            button_events: buttons.iter()
+                .map(|&(button, pressed)| ButtonEvent { button, pressed, timestamp: 0 })
+                .collect(),
+            axis_events: axes.iter()
+                .map(|&(axis, value)| AxisEvent { axis, value, timestamp: 0 })
+                .collect(),
+        }
+    }
+
+    // What the virtual pad is told after the Deck presses or releases one button
+    fn map_button(engine: &mut MappingEngine, button: ButtonId, pressed: bool) -> Vec<(ButtonId, bool)> {
+        engine.map(server_message(&[(button, pressed)], &[]))
+            .button_events.iter()
+            .map(|event| (event.button, event.pressed))
+            .collect()
+    }
+
+    fn select_layer(remaps: &[(ButtonId, ButtonId)], consume_modifier: bool) -> ShiftLayer {
+        ShiftLayer {
+            modifier: ButtonId::Select,
+            consume_modifier,
+            remaps: remaps.iter().map(|&(from, to)| ButtonRemap { from, to }).collect(),
+        }
+    }
+
+    #[test]
+    fn shift_layer_releases_what_was_pressed_when_the_modifier_goes_first() {
+        let mut engine = MappingEngine::new();
+        engine.set_profile(MappingProfile {
+            layers: vec![select_layer(&[(ButtonId::DPadUp, ButtonId::North)], true)],
+            ..Default::default()
+        });
+
+        assert_eq!(map_button(&mut engine, ButtonId::Select, true), vec![]);
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, true), vec![(ButtonId::North, true)]);
+        assert_eq!(map_button(&mut engine, ButtonId::Select, false), vec![]);
+        // Not a release of DPadUp, which the game never saw pressed
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, false), vec![(ButtonId::North, false)]);
+
+        // Without the modifier the button is itself again
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, true), vec![(ButtonId::DPadUp, true)]);
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, false), vec![(ButtonId::DPadUp, false)]);
+    }
+
+    #[test]
+    fn shift_layer_applies_only_to_buttons_pressed_while_the_modifier_is_held() {
+        let mut engine = MappingEngine::new();
+        engine.set_profile(MappingProfile {
+            layers: vec![select_layer(&[(ButtonId::DPadUp, ButtonId::North)], false)],
+            ..Default::default()
+        });
+
+        // Pressed before the modifier, it stays what it was
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, true), vec![(ButtonId::DPadUp, true)]);
+        // Not consumed, so the game sees the modifier too
+        assert_eq!(map_button(&mut engine, ButtonId::Select, true), vec![(ButtonId::Select, true)]);
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, false), vec![(ButtonId::DPadUp, false)]);
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, true), vec![(ButtonId::North, true)]);
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, false), vec![(ButtonId::North, false)]);
+        assert_eq!(map_button(&mut engine, ButtonId::Select, false), vec![(ButtonId::Select, false)]);
+    }
+
+    #[test]
+    fn chords_use_the_layer_listed_last() {
+        let mut engine = MappingEngine::new();
+        engine.set_profile(MappingProfile {
+            remaps: vec![ButtonRemap { from: ButtonId::South, to: ButtonId::East }],
+            layers: vec![
+                select_layer(&[(ButtonId::South, ButtonId::North)], true),
+                ShiftLayer {
+                    modifier: ButtonId::LeftBumper,
+                    consume_modifier: true,
+                    remaps: vec![ButtonRemap { from: ButtonId::South, to: ButtonId::West }],
+                },
+            ],
+            ..Default::default()
+        });
+
+        map_button(&mut engine, ButtonId::Select, true);
+        assert_eq!(map_button(&mut engine, ButtonId::South, true), vec![(ButtonId::North, true)]);
+        assert_eq!(map_button(&mut engine, ButtonId::South, false), vec![(ButtonId::North, false)]);
+
+        map_button(&mut engine, ButtonId::LeftBumper, true);
+        assert_eq!(map_button(&mut engine, ButtonId::South, true), vec![(ButtonId::West, true)]);
+        assert_eq!(map_button(&mut engine, ButtonId::South, false), vec![(ButtonId::West, false)]);
+
+        map_button(&mut engine, ButtonId::LeftBumper, false);
+        map_button(&mut engine, ButtonId::Select, false);
+        // The always-on remap is back once no modifier is held
+        assert_eq!(map_button(&mut engine, ButtonId::South, true), vec![(ButtonId::East, true)]);
+    }
+
+    #[test]
+    fn toggle_buttons_latch_until_pressed_again() {
+        let mut engine = MappingEngine::new();
+        engine.set_profile(MappingProfile {
+            toggle_buttons: vec![ButtonId::RightTrigger],
+            ..Default::default()
+        });
+
+        assert_eq!(map_button(&mut engine, ButtonId::RightTrigger, true), vec![(ButtonId::RightTrigger, true)]);
+        assert_eq!(map_button(&mut engine, ButtonId::RightTrigger, false), vec![]);
+        // Buttons that aren't toggles are unaffected
+        assert_eq!(map_button(&mut engine, ButtonId::South, true), vec![(ButtonId::South, true)]);
+        assert_eq!(map_button(&mut engine, ButtonId::South, false), vec![(ButtonId::South, false)]);
+
+        assert_eq!(map_button(&mut engine, ButtonId::RightTrigger, true), vec![(ButtonId::RightTrigger, false)]);
+        assert_eq!(map_button(&mut engine, ButtonId::RightTrigger, false), vec![]);
+        // And the next press latches it again
+        assert_eq!(map_button(&mut engine, ButtonId::RightTrigger, true), vec![(ButtonId::RightTrigger, true)]);
+    }
+
+    #[test]
+    fn sticky_modifiers_apply_to_the_next_press_only() {
+        let mut engine = MappingEngine::new();
+        engine.set_profile(MappingProfile {
+            layers: vec![select_layer(&[(ButtonId::DPadUp, ButtonId::North)], true)],
+            sticky_modifiers: true,
+            ..Default::default()
+        });
+
+        map_button(&mut engine, ButtonId::Select, true);
+        map_button(&mut engine, ButtonId::Select, false);
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, true), vec![(ButtonId::North, true)]);
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, false), vec![(ButtonId::North, false)]);
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, true), vec![(ButtonId::DPadUp, true)]);
+        map_button(&mut engine, ButtonId::DPadUp, false);
+
+        // Tapping the modifier a second time cancels it
+        for _ in 0..2 {
+            map_button(&mut engine, ButtonId::Select, true);
+            map_button(&mut engine, ButtonId::Select, false);
+        }
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, true), vec![(ButtonId::DPadUp, true)]);
+        map_button(&mut engine, ButtonId::DPadUp, false);
+
+        // Held through a press it was used as a normal modifier and doesn't stick afterwards
+        map_button(&mut engine, ButtonId::Select, true);
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, true), vec![(ButtonId::North, true)]);
+        map_button(&mut engine, ButtonId::DPadUp, false);
+        map_button(&mut engine, ButtonId::Select, false);
+        assert_eq!(map_button(&mut engine, ButtonId::DPadUp, true), vec![(ButtonId::DPadUp, true)]);
+    }
+
+    fn tick_buttons(engine: &mut MappingEngine, at: Instant) -> Vec<(ButtonId, bool)> {
+        engine.tick(at).0.iter().map(|event| (event.button, event.pressed)).collect()
+    }
+
+    fn repeat_profile(remaps: &[(ButtonId, ButtonId)]) -> MappingProfile {
+        MappingProfile {
+            remaps: remaps.iter().map(|&(from, to)| ButtonRemap { from, to }).collect(),
+            repeats: vec![RepeatBinding { button: ButtonId::DPadDown, initial_delay_ms: 400, rate_hz: 10.0 }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn held_repeat_buttons_press_again_after_the_delay() {
+        let ms = Duration::from_millis;
+        let mut engine = MappingEngine::new();
+        engine.set_profile(repeat_profile(&[]));
+
+        let start = Instant::now();
+        assert_eq!(map_button(&mut engine, ButtonId::DPadDown, true), vec![(ButtonId::DPadDown, true)]);
+        assert_eq!(tick_buttons(&mut engine, start + ms(300)), vec![]);
+
+        // Each repeat is a release, then a press half an interval later
+        assert_eq!(tick_buttons(&mut engine, start + ms(500)), vec![(ButtonId::DPadDown, false)]);
+        assert_eq!(tick_buttons(&mut engine, start + ms(520)), vec![]);
+        assert_eq!(tick_buttons(&mut engine, start + ms(550)), vec![(ButtonId::DPadDown, true)]);
+        assert_eq!(tick_buttons(&mut engine, start + ms(600)), vec![(ButtonId::DPadDown, false)]);
+        assert_eq!(tick_buttons(&mut engine, start + ms(650)), vec![(ButtonId::DPadDown, true)]);
+
+        assert_eq!(map_button(&mut engine, ButtonId::DPadDown, false), vec![(ButtonId::DPadDown, false)]);
+        assert_eq!(tick_buttons(&mut engine, start + ms(2000)), vec![]);
+    }
+
+    #[test]
+    fn repeats_wait_while_another_button_holds_the_same_target() {
+        let ms = Duration::from_millis;
+        let mut engine = MappingEngine::new();
+        engine.set_profile(repeat_profile(&[(ButtonId::South, ButtonId::DPadDown)]));
+
+        let start = Instant::now();
+        map_button(&mut engine, ButtonId::DPadDown, true);
+        assert_eq!(tick_buttons(&mut engine, start + ms(500)), vec![(ButtonId::DPadDown, false)]);
+        // Pressed between the repeat's release and press, the game has to see it down right away
+        assert_eq!(map_button(&mut engine, ButtonId::South, true), vec![(ButtonId::DPadDown, true)]);
+
+        // A release now would let go of a button South still holds
+        assert_eq!(tick_buttons(&mut engine, start + ms(550)), vec![]);
+        assert_eq!(tick_buttons(&mut engine, start + ms(700)), vec![]);
+        assert_eq!(map_button(&mut engine, ButtonId::South, false), vec![]);
+
+        // Back to repeating once the repeat button is the only one holding it
+        assert_eq!(tick_buttons(&mut engine, start + ms(1000)), vec![(ButtonId::DPadDown, false)]);
+        assert_eq!(tick_buttons(&mut engine, start + ms(1050)), vec![(ButtonId::DPadDown, true)]);
+    }
+
+    fn move_left_stick(engine: &mut MappingEngine, x: f32, y: f32) -> ControllerInputData {
+        engine.map(server_message(&[], &[(AxisId::LeftStickX, x), (AxisId::LeftStickY, y)]))
+    }
+
+    fn left_stick_buttons(engine: &mut MappingEngine, x: f32, y: f32) -> Vec<(ButtonId, bool)> {
+        let output = move_left_stick(engine, x, y);
+        // The D-Pad has the left stick, the game doesn't see it move
+        assert!(output.axis_events.is_empty());
+        output.button_events.iter().map(|event| (event.button, event.pressed)).collect()
+    }
+
+    #[test]
+    fn stick_to_dpad_presses_past_the_threshold_and_releases_when_centred() {
+        let mut engine = MappingEngine::new();
+        engine.set_profile(MappingProfile { stick_to_dpad: true, ..Default::default() });
+
+        assert_eq!(left_stick_buttons(&mut engine, 0.45, 0.0), vec![]);
+        assert_eq!(left_stick_buttons(&mut engine, 0.6, 0.0), vec![(ButtonId::DPadRight, true)]);
+        // Letting go takes coming back further than pressing took
+        assert_eq!(left_stick_buttons(&mut engine, 0.45, 0.0), vec![]);
+        assert_eq!(left_stick_buttons(&mut engine, 0.3, 0.0), vec![(ButtonId::DPadRight, false)]);
+
+        assert_eq!(left_stick_buttons(&mut engine, 0.0, -0.8), vec![(ButtonId::DPadDown, true)]);
+        assert_eq!(left_stick_buttons(&mut engine, 0.0, 0.0), vec![(ButtonId::DPadDown, false)]);
+    }
+
+    #[test]
+    fn stick_to_dpad_diagonals_press_two_directions() {
+        let mut engine = MappingEngine::new();
+        engine.set_profile(MappingProfile { stick_to_dpad: true, ..Default::default() });
+        let at = |degrees: f32| (0.8 * degrees.to_radians().cos(), 0.8 * degrees.to_radians().sin());
+
+        let (x, y) = at(45.0);
+        assert_eq!(left_stick_buttons(&mut engine, x, y), vec![(ButtonId::DPadRight, true), (ButtonId::DPadUp, true)]);
+        // Past the edge of the up-right sector, but not far enough past it to change direction
+        let (x, y) = at(75.0);
+        assert_eq!(left_stick_buttons(&mut engine, x, y), vec![]);
+        let (x, y) = at(85.0);
+        assert_eq!(left_stick_buttons(&mut engine, x, y), vec![(ButtonId::DPadRight, false)]);
+
+        let (x, y) = at(225.0);
+        assert_eq!(
+            left_stick_buttons(&mut engine, x, y),
+            vec![(ButtonId::DPadUp, false), (ButtonId::DPadLeft, true), (ButtonId::DPadDown, true)],
+        );
+        let (x, y) = at(315.0);
+        assert_eq!(
+            left_stick_buttons(&mut engine, x, y),
+            vec![(ButtonId::DPadLeft, false), (ButtonId::DPadRight, true)],
+        );
+        assert_eq!(
+            left_stick_buttons(&mut engine, 0.0, 0.0),
+            vec![(ButtonId::DPadDown, false), (ButtonId::DPadRight, false)],
+        );
+    }
+
+    // Where the virtual left stick was sent, asserting the D-Pad itself was kept from the game
+    fn dpad_stick(engine: &mut MappingEngine, button: ButtonId, pressed: bool) -> [f32; 2] {
+        let output = engine.map(server_message(&[(button, pressed)], &[]));
+        assert!(output.button_events.is_empty());
+        let axis = |axis| output.axis_events.iter().find(|event| event.axis == axis).unwrap().value;
+        [axis(AxisId::LeftStickX), axis(AxisId::LeftStickY)]
+    }
+
+    #[test]
+    fn dpad_to_stick_pushes_the_left_stick_and_lets_go_on_release() {
+        let mut engine = MappingEngine::new();
+        engine.set_profile(MappingProfile { dpad_to_stick: true, ..Default::default() });
+
+        assert_stick(dpad_stick(&mut engine, ButtonId::DPadUp, true), [0.0, 1.0]);
+        assert_stick(dpad_stick(&mut engine, ButtonId::DPadUp, false), [0.0, 0.0]);
+        assert_stick(dpad_stick(&mut engine, ButtonId::DPadLeft, true), [-1.0, 0.0]);
+        assert_stick(dpad_stick(&mut engine, ButtonId::DPadLeft, false), [0.0, 0.0]);
+        assert_stick(dpad_stick(&mut engine, ButtonId::DPadDown, true), [0.0, -1.0]);
+        assert_stick(dpad_stick(&mut engine, ButtonId::DPadDown, false), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn dpad_to_stick_diagonals_keep_the_configured_deflection() {
+        let mut engine = MappingEngine::new();
+        engine.set_profile(MappingProfile { dpad_to_stick: true, dpad_stick_magnitude: 0.5, ..Default::default() });
+        let diagonal = 0.5 / 2f32.sqrt();
+
+        assert_stick(dpad_stick(&mut engine, ButtonId::DPadRight, true), [0.5, 0.0]);
+        assert_stick(dpad_stick(&mut engine, ButtonId::DPadUp, true), [diagonal, diagonal]);
+        assert_stick(dpad_stick(&mut engine, ButtonId::DPadRight, false), [0.0, 0.5]);
+
+        // The real stick is ignored while the D-Pad has it and comes back once that's let go
+        assert!(move_left_stick(&mut engine, 0.3, 0.0).axis_events.is_empty());
+        assert_stick(dpad_stick(&mut engine, ButtonId::DPadUp, false), [0.3, 0.0]);
+    }
+}
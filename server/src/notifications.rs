@@ -0,0 +1,14 @@
+// Shown by the system, so a warning reaches the player while a game covers the server window
+
+#[cfg(windows)]
+pub fn notify(title: &str, text: &str) {
+    use tauri_winrt_notification::Toast;
+
+    // Borrows PowerShell's app ID, an unpackaged app has no registered one of its own
+    if let Err(e) = Toast::new(Toast::POWERSHELL_APP_ID).title(title).text1(text).show() {
+        log::warn!("Failed to show a Windows notification: {}", e);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn notify(_title: &str, _text: &str) {}
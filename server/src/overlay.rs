@@ -0,0 +1,130 @@
+use anyhow::{Context, Result};
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use crate::i18n;
+use crate::input_id::{AxisId, ButtonId};
+use crate::palette::{self, Status};
+use crate::virtual_controller::VirtualController;
+
+const OVERLAY_PAGE: &str = include_str!("overlay.html");
+// Only the request line is looked at
+const MAX_REQUEST_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlaySettings {
+    pub enabled: bool,
+    pub port: i32,
+}
+
+impl Default for OverlaySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8090,
+        }
+    }
+}
+
+impl OverlaySettings {
+    // OBS runs on the same PC, nothing else needs to see the page
+    pub fn listen_address(&self) -> String {
+        format!("127.0.0.1:{}", self.port)
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://localhost:{}/", self.port)
+    }
+
+    // Returns true when a setting changed. The port is only applied on Enter so the overlay
+    // isn't rebound for every digit typed.
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool, error: Option<&str>) -> bool {
+        let mut changed = false;
+        ui.window(i18n::window_title("Stream Overlay"))
+            .size([420.0, 200.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                ui.text_wrapped("Serves a page showing the live controller state. Add it to OBS as a browser source to show your inputs on stream.");
+                ui.separator();
+
+                changed |= ui.checkbox("Serve overlay", &mut self.enabled);
+                let mut port = self.port;
+                if ui.input_int("Port", &mut port).enter_returns_true(true).build() && (1..=65535).contains(&port) {
+                    self.port = port;
+                    changed = true;
+                }
+
+                if let Some(error) = error {
+                    palette::status_text(ui, Status::Bad, error);
+                } else if self.enabled {
+                    ui.text("Browser source URL (600 x 300):");
+                    let mut url = self.url();
+                    ui.input_text("##url", &mut url).read_only(true).build();
+                    ui.same_line();
+                    if ui.button("Copy") {
+                        ui.set_clipboard_text(&url);
+                    }
+                    ui.text_wrapped(format!("A JSON feed of the same state is at {}state.json", self.url()));
+                }
+            });
+        changed
+    }
+}
+
+pub fn listen(address: &str) -> Result<TcpListener> {
+    let listener = std::net::TcpListener::bind(address)
+        .with_context(|| format!("Failed to serve the overlay on {}", address))?;
+    listener.set_nonblocking(true)?;
+    Ok(TcpListener::from_std(listener)?)
+}
+
+pub async fn serve(listener: TcpListener, virtual_controller: Arc<Mutex<VirtualController>>) -> Result<()> {
+    while let Ok((stream, _)) = listener.accept().await {
+        let virtual_controller = virtual_controller.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_request(stream, &virtual_controller).await {
+                log::debug!("Overlay request failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn handle_request(mut stream: TcpStream, virtual_controller: &Mutex<VirtualController>) -> Result<()> {
+    let mut buffer = vec![0u8; MAX_REQUEST_SIZE];
+    let read = stream.read(&mut buffer).await?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+    let (status, content_type, body) = match path {
+        "/" | "/index.html" => ("200 OK", "text/html; charset=utf-8", OVERLAY_PAGE.to_string()),
+        "/state.json" => ("200 OK", "application/json", state_json(&virtual_controller.lock().unwrap())),
+        _ => ("404 Not Found", "text/plain", "Not found".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+// What the virtual pad is doing, every button and axis by name like a NohBoard feed
+fn state_json(virtual_controller: &VirtualController) -> String {
+    let buttons: serde_json::Map<String, serde_json::Value> = ButtonId::ALL.iter()
+        .map(|button| {
+            let pressed = virtual_controller.get_button_states().get(button).copied().unwrap_or(false);
+            (format!("{:?}", button), pressed.into())
+        })
+        .collect();
+    let axes: serde_json::Map<String, serde_json::Value> = AxisId::ALL.iter()
+        .map(|axis| {
+            let value = virtual_controller.get_axis_states().get(axis).copied().unwrap_or(0.0);
+            (format!("{:?}", axis), value.into())
+        })
+        .collect();
+    serde_json::json!({ "buttons": buttons, "axes": axes }).to_string()
+}
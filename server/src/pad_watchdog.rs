@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+use crate::protocol::PadHealth;
+use crate::virtual_controller::VirtualController;
+
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+// A bus that stays gone, like an uninstalled driver, is still retried but rarely
+const MAX_RETRY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchdogEvent {
+    Failed(String),
+    RetryFailed { error: String, retry_in: Duration },
+    Recovered { attempts: u32 },
+}
+
+impl WatchdogEvent {
+    pub fn message(&self) -> String {
+        match self {
+            WatchdogEvent::Failed(error) => format!("Virtual controller stopped working ({}), plugging it back in", error),
+            WatchdogEvent::RetryFailed { error, retry_in } => {
+                format!("Failed to plug the virtual controller back in ({}), retrying in {}s", error, retry_in.as_secs())
+            }
+            WatchdogEvent::Recovered { attempts: 0 } => "Virtual controller is working again".to_string(),
+            WatchdogEvent::Recovered { attempts } => format!("Virtual controller plugged back in after {} attempt(s)", attempts),
+        }
+    }
+
+    pub fn log(&self) {
+        match self {
+            WatchdogEvent::Recovered { .. } => log::info!("{}", self.message()),
+            _ => log::warn!("{}", self.message()),
+        }
+    }
+
+    pub fn health(&self) -> PadHealth {
+        match self {
+            WatchdogEvent::Failed(error) | WatchdogEvent::RetryFailed { error, .. } => PadHealth::Failed(error.clone()),
+            WatchdogEvent::Recovered { .. } => PadHealth::Working,
+        }
+    }
+}
+
+// Plugs a pad the bus lost back in, waiting twice as long after every failed attempt. Sleep and
+// wake or a driver update otherwise leave it dead until Reconnect is pressed.
+pub struct PadWatchdog {
+    attempts: u32,
+    // Set while the pad is faulted
+    next_attempt: Option<Instant>,
+}
+
+impl PadWatchdog {
+    pub fn new() -> Self {
+        Self {
+            attempts: 0,
+            next_attempt: None,
+        }
+    }
+
+    // Called regularly, returns what changed
+    pub fn check(&mut self, controller: &mut VirtualController, now: Instant) -> Option<WatchdogEvent> {
+        let Some(error) = controller.fault().map(str::to_string) else {
+            // Also covers a manual reconnect that got there first
+            return self.next_attempt.take().map(|_| WatchdogEvent::Recovered { attempts: std::mem::take(&mut self.attempts) });
+        };
+        let Some(next_attempt) = self.next_attempt else {
+            self.attempts = 0;
+            self.next_attempt = Some(now + FIRST_RETRY);
+            return Some(WatchdogEvent::Failed(error));
+        };
+        if now < next_attempt {
+            return None;
+        }
+
+        self.attempts += 1;
+        match controller.replug() {
+            Ok(()) => {
+                self.next_attempt = None;
+                Some(WatchdogEvent::Recovered { attempts: std::mem::take(&mut self.attempts) })
+            }
+            Err(e) => {
+                let retry_in = (FIRST_RETRY * 2u32.saturating_pow(self.attempts)).min(MAX_RETRY);
+                self.next_attempt = Some(now + retry_in);
+                Some(WatchdogEvent::RetryFailed { error: e.to_string(), retry_in })
+            }
+        }
+    }
+
+    // None while the pad works
+    pub fn retry_in(&self, now: Instant) -> Option<Duration> {
+        self.next_attempt.map(|next_attempt| next_attempt.saturating_duration_since(now))
+    }
+}
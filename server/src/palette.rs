@@ -0,0 +1,90 @@
+use imgui::Ui;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+use crate::i18n::tr;
+
+// Colors for status text. Color is never the only signal, status text also leads with a marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Standard,
+    // Blue and orange instead of green and red, apart for every kind of color blindness
+    Colorblind,
+    // Bright colors that stand out against any theme's background
+    HighContrast,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Good,
+    Warning,
+    Bad,
+}
+
+// Windows pick the colors up at draw time, so the palette lives here instead of being passed around
+static ACTIVE: AtomicU8 = AtomicU8::new(0);
+
+impl Palette {
+    pub const ALL: [Palette; 3] = [Palette::Standard, Palette::Colorblind, Palette::HighContrast];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Standard => "Standard",
+            Palette::Colorblind => "Colorblind Friendly",
+            Palette::HighContrast => "High Contrast",
+        }
+    }
+
+    pub fn color(self, status: Status) -> [f32; 4] {
+        match (self, status) {
+            (Palette::Standard, Status::Good) => [0.0, 1.0, 0.0, 1.0],
+            (Palette::Standard, Status::Warning) => [1.0, 1.0, 0.0, 1.0],
+            (Palette::Standard, Status::Bad) => [1.0, 0.3, 0.3, 1.0],
+            (Palette::Colorblind, Status::Good) => [0.35, 0.7, 0.9, 1.0],
+            (Palette::Colorblind, Status::Warning) => [0.95, 0.9, 0.25, 1.0],
+            (Palette::Colorblind, Status::Bad) => [0.9, 0.5, 0.0, 1.0],
+            (Palette::HighContrast, Status::Good) => [0.0, 1.0, 1.0, 1.0],
+            (Palette::HighContrast, Status::Warning) => [1.0, 1.0, 1.0, 1.0],
+            (Palette::HighContrast, Status::Bad) => [1.0, 0.2, 1.0, 1.0],
+        }
+    }
+}
+
+impl Status {
+    // Readable without telling the colors apart, the default font has no symbols beyond ASCII
+    pub fn marker(self) -> &'static str {
+        match self {
+            Status::Good => "[ok]",
+            Status::Warning => "[!]",
+            Status::Bad => "[x]",
+        }
+    }
+}
+
+pub fn set_palette(palette: Palette) {
+    ACTIVE.store(palette as u8, Ordering::Relaxed);
+}
+
+pub fn palette() -> Palette {
+    Palette::ALL[ACTIVE.load(Ordering::Relaxed) as usize % Palette::ALL.len()]
+}
+
+// In the selected palette
+pub fn color(status: Status) -> [f32; 4] {
+    palette().color(status)
+}
+
+pub fn status_text(ui: &Ui, status: Status, text: impl AsRef<str>) {
+    ui.text_colored(color(status), format!("{} {}", status.marker(), text.as_ref()));
+}
+
+// Picks the palette in a settings window, returns true when it changed
+pub fn render_combo(ui: &Ui, palette: &mut Palette) -> bool {
+    let names: Vec<&str> = Palette::ALL.iter().map(|p| tr(p.name())).collect();
+    let mut index = Palette::ALL.iter().position(|p| p == palette).unwrap_or(0);
+    if ui.combo_simple_string(tr("Status Colors"), &mut index, &names) {
+        *palette = Palette::ALL[index];
+        return true;
+    }
+    false
+}
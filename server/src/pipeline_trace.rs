@@ -0,0 +1,300 @@
+use anyhow::{Context, Result};
+use imgui::*;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::i18n;
+use crate::palette::{self, Status};
+
+// A few minutes of input at the default sampling, nothing more is kept after that
+const MAX_SAMPLES: usize = 50_000;
+const DECK_PID: u32 = 1;
+const NETWORK_PID: u32 = 2;
+const PC_PID: u32 = 3;
+// Outside a trace every this many messages are sampled for the latency budget, which averages
+// the last BUDGET_SAMPLES of them
+const BUDGET_SAMPLE_EVERY: u64 = 10;
+const BUDGET_SAMPLES: usize = 200;
+pub const BUDGET_STAGES: [&str; 4] = ["Deck (capture to send)", "Network", "Parse", "Virtual Controller"];
+const BUDGET_COLORS: [[f32; 4]; 4] = [
+    [0.35, 0.6, 1.0, 1.0],
+    [1.0, 0.65, 0.2, 1.0],
+    [0.6, 0.85, 0.3, 1.0],
+    [0.85, 0.4, 0.85, 1.0],
+];
+
+// Timings of one sampled message, all in microseconds on the server's clock
+#[derive(Debug, Clone)]
+pub struct MessageTrace {
+    pub message_id: u64,
+    pub controller_id: u32,
+    pub events: usize,
+    // None until the client's clock is synced, sent_us also for clients that don't stamp sends
+    pub captured_us: Option<u64>,
+    pub sent_us: Option<u64>,
+    pub received_us: u64,
+    pub parsed_us: u64,
+    // None while forwarding to the virtual controller is off
+    pub applied_us: Option<(u64, u64)>,
+}
+
+struct TraceState {
+    recording: bool,
+    sample_every: u32,
+    seen: u64,
+    samples: Vec<MessageTrace>,
+    recent: VecDeque<MessageTrace>,
+}
+
+// Average time sampled messages spent in each of BUDGET_STAGES, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyBudget {
+    pub stages_ms: [f64; 4],
+    pub samples: usize,
+}
+
+impl LatencyBudget {
+    pub fn total_ms(&self) -> f64 {
+        self.stages_ms.iter().sum()
+    }
+}
+
+// Shared by all connections, which record every sample_every-th message while a trace runs
+pub struct PipelineTrace {
+    state: Mutex<TraceState>,
+}
+
+impl PipelineTrace {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(TraceState {
+                recording: false,
+                sample_every: 1,
+                seen: 0,
+                samples: Vec::new(),
+                recent: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn start(&self, sample_every: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.recording = true;
+        state.sample_every = sample_every.max(1);
+        state.seen = 0;
+        state.samples.clear();
+    }
+
+    // Hands over everything sampled since start
+    pub fn stop(&self) -> Vec<MessageTrace> {
+        let mut state = self.state.lock().unwrap();
+        state.recording = false;
+        std::mem::take(&mut state.samples)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.state.lock().unwrap().recording
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.state.lock().unwrap().samples.len()
+    }
+
+    // Called once per received message, true when this one should be recorded. A running trace
+    // sets the rate, the latency budget takes what it samples.
+    pub fn should_sample(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let every = if state.recording { state.sample_every as u64 } else { BUDGET_SAMPLE_EVERY };
+        let sampled = state.seen.is_multiple_of(every);
+        state.seen += 1;
+        sampled
+    }
+
+    pub fn record(&self, trace: MessageTrace) {
+        let mut state = self.state.lock().unwrap();
+        if state.recent.len() >= BUDGET_SAMPLES {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(trace.clone());
+        if state.recording && state.samples.len() < MAX_SAMPLES {
+            state.samples.push(trace);
+        }
+    }
+
+    // Only messages that made it all the way count, which takes a synced clock and forwarding on.
+    // None until there are any.
+    pub fn latency_budget(&self) -> Option<LatencyBudget> {
+        let state = self.state.lock().unwrap();
+        let mut totals_us = [0u64; 4];
+        let mut samples = 0;
+        for sample in &state.recent {
+            let (Some(captured), Some((_, applied))) = (sample.captured_us, sample.applied_us) else {
+                continue;
+            };
+            // An estimated clock can put the send a little before the capture or after the arrival
+            let sent = sample.sent_us.unwrap_or(captured).clamp(captured, sample.received_us.max(captured));
+            let stages = [
+                sent - captured,
+                sample.received_us.saturating_sub(sent),
+                sample.parsed_us.saturating_sub(sample.received_us),
+                applied.saturating_sub(sample.parsed_us),
+            ];
+            for (total, stage) in totals_us.iter_mut().zip(stages) {
+                *total += stage;
+            }
+            samples += 1;
+        }
+        (samples > 0).then(|| LatencyBudget {
+            stages_ms: totals_us.map(|total| total as f64 / samples as f64 / 1000.0),
+            samples,
+        })
+    }
+}
+
+// Trace Event Format, opens in chrome://tracing and ui.perfetto.dev. The Deck, the network and
+// this PC are separate processes on one timeline, with a flow arrow following each message.
+pub fn write_chrome_trace(samples: &[MessageTrace], path: &Path) -> Result<()> {
+    let mut events = vec![
+        process_name(DECK_PID, "Steam Deck"),
+        process_name(NETWORK_PID, "Network"),
+        process_name(PC_PID, "PC"),
+    ];
+    for sample in samples {
+        let args = json!({
+            "message_id": sample.message_id,
+            "controller_id": sample.controller_id,
+            "events": sample.events,
+        });
+        let left_deck = sample.sent_us.or(sample.captured_us);
+
+        if let Some(captured) = sample.captured_us {
+            let sent = sample.sent_us.unwrap_or(captured).max(captured);
+            events.push(span("capture to send", DECK_PID, captured, sent, &args));
+            events.push(flow("s", DECK_PID, captured, sample.message_id));
+        }
+        if let Some(left_deck) = left_deck {
+            events.push(span("network", NETWORK_PID, left_deck.min(sample.received_us), sample.received_us, &args));
+        }
+        events.push(span("parse", PC_PID, sample.received_us, sample.parsed_us, &args));
+        if sample.captured_us.is_some() {
+            events.push(flow("f", PC_PID, sample.received_us, sample.message_id));
+        }
+        if let Some((start, end)) = sample.applied_us {
+            events.push(span("virtual controller update", PC_PID, start, end, &args));
+        }
+    }
+
+    let file = std::io::BufWriter::new(std::fs::File::create(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?);
+    serde_json::to_writer(file, &json!({ "traceEvents": events, "displayTimeUnit": "ms" }))?;
+    Ok(())
+}
+
+fn process_name(pid: u32, name: &str) -> Value {
+    json!({ "name": "process_name", "ph": "M", "pid": pid, "tid": 1, "args": { "name": name } })
+}
+
+fn span(name: &str, pid: u32, start_us: u64, end_us: u64, args: &Value) -> Value {
+    json!({
+        "name": name,
+        "cat": "pipeline",
+        "ph": "X",
+        "ts": start_us,
+        "dur": end_us.saturating_sub(start_us),
+        "pid": pid,
+        "tid": 1,
+        "args": args,
+    })
+}
+
+// "s" starts an arrow inside the enclosing slice, "f" ends it at the next slice to begin
+fn flow(phase: &str, pid: u32, ts_us: u64, message_id: u64) -> Value {
+    json!({ "name": "message", "cat": "pipeline", "ph": phase, "bp": "e", "id": message_id, "ts": ts_us, "pid": pid, "tid": 1 })
+}
+
+// One bar split by stage, with the legend below it
+pub fn render_latency_budget(ui: &Ui, budget: &LatencyBudget) {
+    let width = ui.content_region_avail()[0].max(100.0);
+    let height = 18.0;
+    let origin = ui.cursor_screen_pos();
+    ui.dummy([width, height]);
+
+    let draw_list = ui.get_window_draw_list();
+    let total = budget.total_ms();
+    let mut x = origin[0];
+    for (stage_ms, color) in budget.stages_ms.iter().zip(BUDGET_COLORS) {
+        let stage_width = if total > 0.0 { (stage_ms / total) as f32 * width } else { 0.0 };
+        draw_list.add_rect([x, origin[1]], [x + stage_width, origin[1] + height], color).filled(true).build();
+        x += stage_width;
+    }
+    draw_list.add_rect(origin, [origin[0] + width, origin[1] + height], [0.5, 0.5, 0.5, 1.0]).build();
+
+    for ((name, stage_ms), color) in BUDGET_STAGES.iter().zip(budget.stages_ms).zip(BUDGET_COLORS) {
+        ui.text_colored(color, format!("{}: {:.2}ms", name, stage_ms));
+    }
+    ui.text(format!("Total: {:.2}ms over {} sampled messages", total, budget.samples));
+}
+
+pub struct TraceWindow {
+    sample_every: u32,
+    status: Option<String>,
+}
+
+impl TraceWindow {
+    pub fn new() -> Self {
+        Self {
+            sample_every: 10,
+            status: None,
+        }
+    }
+
+    // Same as pressing Start Trace or Stop and Save, returns what happened
+    pub fn toggle(&mut self, trace: &PipelineTrace) -> String {
+        if trace.is_recording() {
+            let status = save_trace(&trace.stop());
+            self.status = Some(status.clone());
+            status
+        } else {
+            trace.start(self.sample_every);
+            self.status = None;
+            format!("Pipeline trace started, sampling every {} messages", self.sample_every)
+        }
+    }
+
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool, trace: &PipelineTrace) {
+        ui.window(i18n::window_title("Pipeline Trace"))
+            .size([420.0, 200.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                ui.text_wrapped("Records when sampled messages were captured and sent on the Deck, received here and applied to the virtual controller. Open the saved file in chrome://tracing or ui.perfetto.dev.");
+                ui.separator();
+
+                if trace.is_recording() {
+                    palette::status_text(ui, Status::Bad, format!("Recording, {} messages sampled", trace.sample_count()));
+                    if ui.button("Stop and Save") {
+                        self.toggle(trace);
+                    }
+                } else {
+                    ui.slider("Sample every Nth message", 1, 100, &mut self.sample_every);
+                    if ui.button("Start Trace") {
+                        self.toggle(trace);
+                    }
+                }
+                if let Some(status) = &self.status {
+                    ui.text_wrapped(status);
+                }
+            });
+    }
+}
+
+fn save_trace(samples: &[MessageTrace]) -> String {
+    let path = PathBuf::from(format!("pipeline_trace_{}.json", chrono::Local::now().format("%Y%m%d_%H%M%S")));
+    match write_chrome_trace(samples, &path) {
+        Ok(()) => format!("Saved {} messages to {}", samples.len(), path.display()),
+        Err(e) => {
+            log::error!("Failed to save pipeline trace: {:#}", e);
+            format!("Failed to save: {:#}", e)
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use crate::input_id::{AxisId, ButtonId};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControllerInputData {
+    // Assigned by the client when sending, lets client and server logs be matched up
+    #[serde(default)]
+    pub message_id: u64,
+    pub timestamp: u64,
+    // When the message actually went out, after any queueing or simulated delay. 0 from older clients.
+    #[serde(default)]
+    pub sent_at: u64,
+    pub controller_id: u32,
+    pub button_events: Vec<ButtonEvent>,
+    pub axis_events: Vec<AxisEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ButtonEvent {
+    pub button: ButtonId,
+    pub pressed: bool,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AxisEvent {
+    pub axis: AxisId,
+    pub value: f32,
+    pub timestamp: u64,
+}
+
+// Advertised to every client when it connects and again whenever the mapping profiles change
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProfileList {
+    pub profiles: Vec<String>,
+    pub active_profile: String,
+}
+
+// Sent by a client to switch the active mapping profile to one of the advertised ones
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileRequest {
+    pub select_profile: String,
+}
+
+// Sent every few seconds with the server's clock, the client echoes it back with its own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockProbe {
+    pub clock_probe: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockReply {
+    pub clock_probe: u64,
+    pub client_time: u64,
+}
+
+// A controller attached to this PC, streamed to every client while the local controller is set
+// to go to the Deck
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PcInput {
+    pub pc_input: ControllerInputData,
+}
+
+// The highest message ID applied so far, so the client can tell what's still in flight
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ack {
+    pub ack: u64,
+    // How long ago it was applied, so the client can leave the wait for the next ack out of its latency
+    pub held_us: u64,
+}
+
+// Whether the virtual pad is taking input, for clients to show next to their own connection state
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PadHealth {
+    #[default]
+    Working,
+    // The bus lost the pad and the server is plugging it back in, with the error it hit
+    Failed(String),
+}
+
+// Sent to every client when the virtual pad fails and again once it's back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PadHealthReport {
+    pub pad_health: PadHealth,
+}
+
+// What a client's input ends up on, so it can tell whether its input reaches games at all
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PadStatus {
+    // No virtual pad plugged in, the input only shows up in the server's window
+    #[default]
+    Unplugged,
+    // player is 1-based, None for pads without an XInput slot
+    Active { pad: String, player: Option<u32> },
+    // Forwarding to the virtual controller is switched off
+    Paused,
+    // Paired with no pad on purpose
+    Spectator,
+}
+
+// Sent when a client connects and whenever its pad is plugged in or out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PadStatusReport {
+    pub pad_status: PadStatus,
+}
+
+// Bits in Capabilities::sensors
+pub const SENSOR_GYRO: u32 = 1 << 0;
+pub const SENSOR_ACCELEROMETER: u32 = 1 << 1;
+pub const SENSOR_TOUCHPAD: u32 = 1 << 2;
+
+// What a client's controller can produce, one bit per ButtonId and AxisId discriminant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub buttons: u32,
+    pub axes: u32,
+    pub sensors: u32,
+}
+
+impl Capabilities {
+    pub fn has_button(&self, button: ButtonId) -> bool {
+        self.buttons & (1 << button as u32) != 0
+    }
+
+    pub fn has_axis(&self, axis: AxisId) -> bool {
+        self.axes & (1 << axis as u32) != 0
+    }
+
+    pub fn has_sensor(&self, sensor: u32) -> bool {
+        self.sensors & sensor != 0
+    }
+
+    // Everything either controller can produce
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            buttons: self.buttons | other.buttons,
+            axes: self.axes | other.axes,
+            sensors: self.sensors | other.sensors,
+        }
+    }
+}
+
+// Sent by a client for each of its controllers when it connects
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CapabilityReport {
+    pub controller_id: u32,
+    pub capabilities: Capabilities,
+    // USB IDs, missing from older clients and where the client's platform can't tell
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+}
+
+impl CapabilityReport {
+    pub fn usb_ids(&self) -> Option<(u16, u16)> {
+        self.vendor_id.zip(self.product_id)
+    }
+}
+
+// Charge of a client's wireless controller, sent whenever it changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatteryReport {
+    pub controller_id: u32,
+    pub battery_percent: u8,
+    pub charging: bool,
+}
+
+// At or below this the player is warned, a few minutes of play are usually left
+pub const LOW_BATTERY_PERCENT: u8 = 20;
+
+impl BatteryReport {
+    pub fn is_low(&self) -> bool {
+        !self.charging && self.battery_percent <= LOW_BATTERY_PERCENT
+    }
+}
+
+// The device a client forwards raw HID reports from, sent before the first report
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HidDevice {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub name: String,
+    pub report_descriptor: Vec<u8>,
+}
+
+// None when the client switched passthrough off again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HidDeviceAnnouncement {
+    // Required even though it's an Option, otherwise every other message would parse as this one
+    #[serde(deserialize_with = "Option::deserialize")]
+    pub hid_device: Option<HidDevice>,
+}
+
+// Longest raw report a client sends, the Deck's are 64 bytes
+pub const MAX_HID_REPORT_SIZE: usize = 64;
+
+// A full state sync is 25 events and well under 4 KiB, anything far beyond that is not a client
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024;
+pub const MAX_EVENTS_PER_MESSAGE: usize = 256;
+
+// Malformed messages a client may send per window before it gets disconnected
+const ERROR_BUDGET: u32 = 20;
+const ERROR_BUDGET_WINDOW: Duration = Duration::from_secs(60);
+
+// Unknown fields are ignored so newer clients can add to the format without breaking older servers
+pub fn parse_message(text: &str) -> Result<ControllerInputData> {
+    if text.len() > MAX_MESSAGE_SIZE {
+        bail!("message of {} bytes exceeds the {} byte limit", text.len(), MAX_MESSAGE_SIZE);
+    }
+
+    let data: ControllerInputData = serde_json::from_str(text)?;
+
+    let event_count = data.button_events.len() + data.axis_events.len();
+    if event_count > MAX_EVENTS_PER_MESSAGE {
+        bail!("message carries {} events, the limit is {}", event_count, MAX_EVENTS_PER_MESSAGE);
+    }
+    // The range check also catches NaN and infinities from numbers too large for an f32
+    if let Some(axis_event) = data.axis_events.iter().find(|e| !(-1.0..=1.0).contains(&e.value)) {
+        bail!("{} value {} is out of range", axis_event.axis, axis_event.value);
+    }
+
+    Ok(data)
+}
+
+// None for anything else, which then goes through parse_message
+pub fn parse_profile_request(text: &str) -> Option<String> {
+    serde_json::from_str::<ProfileRequest>(text).ok().map(|request| request.select_profile)
+}
+
+pub fn parse_clock_reply(text: &str) -> Option<ClockReply> {
+    serde_json::from_str(text).ok()
+}
+
+pub fn parse_capability_report(text: &str) -> Option<CapabilityReport> {
+    serde_json::from_str(text).ok()
+}
+
+pub fn parse_battery_report(text: &str) -> Option<BatteryReport> {
+    serde_json::from_str(text).ok()
+}
+
+pub fn parse_hid_device_announcement(text: &str) -> Option<HidDeviceAnnouncement> {
+    serde_json::from_str(text).ok()
+}
+
+// Binary frames are raw HID reports: the client's timestamp in milliseconds, little endian,
+// then the report as the client read it
+pub fn decode_hid_report(frame: &[u8]) -> Result<(u64, &[u8])> {
+    if frame.len() <= 8 || frame.len() > 8 + MAX_HID_REPORT_SIZE {
+        bail!("HID report frame of {} bytes, expected 9 to {}", frame.len(), 8 + MAX_HID_REPORT_SIZE);
+    }
+    let (timestamp, report) = frame.split_at(8);
+    Ok((u64::from_le_bytes(timestamp.try_into()?), report))
+}
+
+// Tolerates the odd bad message but not a client that keeps sending them
+pub struct ErrorBudget {
+    errors: u32,
+    window_start: Instant,
+}
+
+impl ErrorBudget {
+    pub fn new() -> Self {
+        Self {
+            errors: 0,
+            window_start: Instant::now(),
+        }
+    }
+
+    // Returns false once the budget for the current window is used up
+    pub fn spend(&mut self) -> bool {
+        if self.window_start.elapsed() >= ERROR_BUDGET_WINDOW {
+            self.errors = 0;
+            self.window_start = Instant::now();
+        }
+        self.errors += 1;
+        self.errors <= ERROR_BUDGET
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(buttons: usize, axis_value: f32) -> String {
+        let data = ControllerInputData {
+            message_id: 0,
+            timestamp: 0,
+            sent_at: 0,
+            controller_id: 0,
+            button_events: (0..buttons)
+                .map(|_| ButtonEvent { button: ButtonId::South, pressed: true, timestamp: 0 })
+                .collect(),
+            axis_events: vec![AxisEvent { axis: AxisId::LeftStickX, value: axis_value, timestamp: 0 }],
+        };
+        serde_json::to_string(&data).unwrap()
+    }
+
+    #[test]
+    fn messages_past_the_size_limit_are_rejected_before_parsing() {
+        let valid = message(1, 0.5);
+        assert!(parse_message(&valid).is_ok());
+
+        // Still valid JSON, only too long
+        let padded = format!("{}{}", valid, " ".repeat(MAX_MESSAGE_SIZE + 1 - valid.len()));
+        let error = parse_message(&padded).unwrap_err();
+        assert!(error.to_string().contains("byte limit"), "{}", error);
+        assert!(parse_message(&padded[..MAX_MESSAGE_SIZE]).is_ok());
+    }
+
+    #[test]
+    fn messages_past_the_event_limit_are_rejected() {
+        // The axis event counts too
+        assert!(parse_message(&message(MAX_EVENTS_PER_MESSAGE - 1, 0.0)).is_ok());
+        let error = parse_message(&message(MAX_EVENTS_PER_MESSAGE, 0.0)).unwrap_err();
+        assert!(error.to_string().contains("events"), "{}", error);
+    }
+
+    #[test]
+    fn axis_values_outside_the_range_are_rejected() {
+        assert!(parse_message(&message(0, -1.0)).is_ok());
+        assert!(parse_message(&message(0, 1.0)).is_ok());
+        assert!(parse_message(&message(0, 1.01)).is_err());
+        // Too large for an f32, it arrives as infinity
+        let overflow = r#"{"timestamp":0,"controller_id":0,"button_events":[],"axis_events":[{"axis":0,"value":1e39,"timestamp":0}]}"#;
+        assert!(parse_message(overflow).is_err());
+        // JSON has no NaN, serde_json writes it as null, which is no number at all
+        assert!(parse_message(&message(0, f32::NAN)).is_err());
+    }
+
+    #[test]
+    fn error_budget_runs_out_after_twenty_errors_in_a_window() {
+        let mut budget = ErrorBudget::new();
+        for _ in 0..ERROR_BUDGET {
+            assert!(budget.spend());
+        }
+        assert!(!budget.spend());
+        assert!(!budget.spend());
+    }
+
+    #[test]
+    fn error_budget_starts_over_with_each_window() {
+        let mut budget = ErrorBudget::new();
+        for _ in 0..ERROR_BUDGET {
+            budget.spend();
+        }
+        budget.window_start = Instant::now().checked_sub(ERROR_BUDGET_WINDOW).unwrap();
+        for _ in 0..ERROR_BUDGET {
+            assert!(budget.spend());
+        }
+        assert!(!budget.spend());
+    }
+}
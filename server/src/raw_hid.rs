@@ -0,0 +1,358 @@
+use anyhow::Result;
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::i18n;
+use crate::protocol::HidDevice;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HidPassthroughSettings {
+    // Recreate each forwarded device through /dev/uhid, Linux only
+    pub recreate_device: bool,
+}
+
+impl HidPassthroughSettings {
+    // Returns true when the setting changed
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool, passthrough: &HidPassthrough) -> bool {
+        let mut changed = false;
+        ui.window(i18n::window_title("HID Passthrough"))
+            .size([420.0, 360.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                ui.text_wrapped("Clients with Raw HID Passthrough on forward their controller's HID reports alongside the regular input. Steam Deck reports are decoded below.");
+                ui.separator();
+
+                let _disabled = ui.begin_disabled(!cfg!(target_os = "linux"));
+                changed |= ui.checkbox("Recreate devices on this PC", &mut self.recreate_device);
+                drop(_disabled);
+                if ui.is_item_hovered_with_flags(ItemHoveredFlags::ALLOW_WHEN_DISABLED) {
+                    ui.tooltip_text(if cfg!(target_os = "linux") {
+                        "Creates a HID device with the same descriptor through /dev/uhid and feeds it the reports, while forwarding is on. Games then see both it and the virtual Xbox pad."
+                    } else {
+                        "Needs /dev/uhid, only available on Linux"
+                    });
+                }
+                ui.separator();
+
+                let clients = passthrough.clients();
+                if clients.is_empty() {
+                    ui.text_disabled("No client is forwarding HID reports");
+                }
+                for (addr, client) in &clients {
+                    let label = format!("{} ({:04x}:{:04x}) from {}", client.device.name, client.device.vendor_id, client.device.product_id, addr.ip());
+                    if !ui.collapsing_header(&label, TreeNodeFlags::DEFAULT_OPEN) {
+                        continue;
+                    }
+                    let _id = ui.push_id(label.as_str());
+                    ui.text(format!("Reports: {}", client.reports));
+                    if let Some(status) = &client.device_status {
+                        ui.text_wrapped(status);
+                    }
+                    match &client.latest {
+                        Some(report) => render_deck_report(ui, report),
+                        None if client.reports > 0 => ui.text_disabled("Not a Steam Deck report, forwarded as is"),
+                        None => {}
+                    }
+                }
+            });
+        changed
+    }
+}
+
+fn render_deck_report(ui: &Ui, report: &DeckReport) {
+    for (name, pad) in [("Left Pad", &report.left_pad), ("Right Pad", &report.right_pad)] {
+        if pad.touched {
+            ui.text(format!("{}: {:6} {:6}", name, pad.x, pad.y));
+        } else {
+            ui.text_disabled(format!("{}: not touched", name));
+        }
+        ui.same_line_with_pos(200.0);
+        ProgressBar::new(pad.pressure as f32 / i16::MAX as f32)
+            .size([-1.0, 0.0])
+            .overlay_text(format!("Pressure {}", pad.pressure))
+            .build(ui);
+    }
+    ui.text(format!("Triggers: {} / {}", report.left_trigger, report.right_trigger));
+    ui.text(format!("Gyro: {:6} {:6} {:6}", report.gyro[0], report.gyro[1], report.gyro[2]));
+    ui.text(format!("Accel: {:6} {:6} {:6}", report.accel[0], report.accel[1], report.accel[2]));
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Touchpad {
+    pub touched: bool,
+    pub x: i16,
+    pub y: i16,
+    pub pressure: u16,
+}
+
+// What the Deck's input report carries beyond what gilrs sees, offsets as in SDL's
+// SteamDeckStatePacket_t after the 4 byte header
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeckReport {
+    pub left_pad: Touchpad,
+    pub right_pad: Touchpad,
+    pub accel: [i16; 3],
+    pub gyro: [i16; 3],
+    pub left_trigger: u16,
+    pub right_trigger: u16,
+}
+
+impl DeckReport {
+    // None for anything but the Deck's controller state report, type 9 after report ID 1
+    pub fn parse(report: &[u8]) -> Option<Self> {
+        if report.len() < 60 || report[0] != 0x01 || report[2] != 0x09 {
+            return None;
+        }
+        let i16_at = |offset: usize| i16::from_le_bytes([report[offset], report[offset + 1]]);
+        let u16_at = |offset: usize| u16::from_le_bytes([report[offset], report[offset + 1]]);
+        Some(Self {
+            left_pad: Touchpad {
+                touched: report[10] & 0x08 != 0,
+                x: i16_at(16),
+                y: i16_at(18),
+                pressure: u16_at(56),
+            },
+            right_pad: Touchpad {
+                touched: report[10] & 0x10 != 0,
+                x: i16_at(20),
+                y: i16_at(22),
+                pressure: u16_at(58),
+            },
+            accel: [i16_at(24), i16_at(26), i16_at(28)],
+            gyro: [i16_at(30), i16_at(32), i16_at(34)],
+            left_trigger: u16_at(44),
+            right_trigger: u16_at(46),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HidClient {
+    pub device: HidDevice,
+    pub reports: u64,
+    // None while the reports aren't ones DeckReport understands
+    pub latest: Option<DeckReport>,
+    // Whether recreating the device worked, None while that's off
+    pub device_status: Option<String>,
+}
+
+// Shared by all connections, the UI thread reads what each client forwards from here
+pub struct HidPassthrough {
+    recreate_device: AtomicBool,
+    clients: Mutex<HashMap<SocketAddr, HidClient>>,
+}
+
+impl HidPassthrough {
+    pub fn new(recreate_device: bool) -> Self {
+        Self {
+            recreate_device: AtomicBool::new(recreate_device),
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Picked up by every connection with its next report
+    pub fn set_recreate_device(&self, recreate_device: bool) {
+        self.recreate_device.store(recreate_device, Ordering::SeqCst);
+    }
+
+    // Every client currently forwarding a device
+    pub fn clients(&self) -> Vec<(SocketAddr, HidClient)> {
+        let mut clients: Vec<_> = self.clients.lock().unwrap()
+            .iter()
+            .map(|(addr, client)| (*addr, client.clone()))
+            .collect();
+        clients.sort_by_key(|(addr, _)| *addr);
+        clients
+    }
+
+    fn update(&self, addr: SocketAddr, update: impl FnOnce(&mut HidClient)) {
+        if let Some(client) = self.clients.lock().unwrap().get_mut(&addr) {
+            update(client);
+        }
+    }
+}
+
+// One client's passthrough, owned by its connection task
+pub struct HidConnection {
+    addr: SocketAddr,
+    shared: Arc<HidPassthrough>,
+    device: Option<HidDevice>,
+    uhid: Option<UhidDevice>,
+    // Not retried on every report once it failed, only after the setting was toggled
+    uhid_failed: bool,
+}
+
+impl HidConnection {
+    pub fn new(addr: SocketAddr, shared: Arc<HidPassthrough>) -> Self {
+        Self {
+            addr,
+            shared,
+            device: None,
+            uhid: None,
+            uhid_failed: false,
+        }
+    }
+
+    pub fn announce(&mut self, device: Option<HidDevice>) {
+        self.uhid = None;
+        self.uhid_failed = false;
+        let mut clients = self.shared.clients.lock().unwrap();
+        match &device {
+            Some(device) => {
+                log::info!("Client {} forwards raw HID reports from {} ({:04x}:{:04x})", self.addr, device.name, device.vendor_id, device.product_id);
+                clients.insert(self.addr, HidClient {
+                    device: device.clone(),
+                    reports: 0,
+                    latest: None,
+                    device_status: None,
+                });
+            }
+            None => {
+                log::info!("Client {} stopped forwarding raw HID reports", self.addr);
+                clients.remove(&self.addr);
+            }
+        }
+        self.device = device;
+    }
+
+    // Recorded for the UI, and fed to the recreated device while forward is set
+    pub fn report(&mut self, report: &[u8], forward: bool) -> Result<()> {
+        let Some(device) = &self.device else {
+            anyhow::bail!("HID report before the client announced its device");
+        };
+
+        let recreate = self.shared.recreate_device.load(Ordering::SeqCst);
+        let mut device_status = None;
+        if !recreate {
+            self.uhid = None;
+            self.uhid_failed = false;
+        } else if self.uhid.is_none() && !self.uhid_failed {
+            match UhidDevice::create(device) {
+                Ok(uhid) => {
+                    log::info!("Recreated {} through uhid for client {}", device.name, self.addr);
+                    self.uhid = Some(uhid);
+                    device_status = Some("Recreated on this PC".to_string());
+                }
+                Err(e) => {
+                    log::error!("Failed to recreate {}: {:#}", device.name, e);
+                    self.uhid_failed = true;
+                    device_status = Some(format!("Failed to recreate: {:#}", e));
+                }
+            }
+        }
+        if let (Some(uhid), true) = (&mut self.uhid, forward) {
+            if let Err(e) = uhid.input(report) {
+                log::error!("Failed to write HID report to uhid: {}", e);
+                self.uhid = None;
+                self.uhid_failed = true;
+                device_status = Some(format!("Failed to forward: {}", e));
+            }
+        }
+
+        let latest = DeckReport::parse(report);
+        self.shared.update(self.addr, |client| {
+            client.reports += 1;
+            client.latest = latest;
+            if !recreate {
+                client.device_status = None;
+            } else if device_status.is_some() {
+                client.device_status = device_status;
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Drop for HidConnection {
+    fn drop(&mut self) {
+        if self.device.is_some() {
+            self.shared.clients.lock().unwrap().remove(&self.addr);
+        }
+    }
+}
+
+// A user-space HID device, destroyed by the kernel when the file is closed
+#[cfg(target_os = "linux")]
+struct UhidDevice {
+    file: std::fs::File,
+}
+
+// From linux/uhid.h, events are written packed and in native byte order
+#[cfg(target_os = "linux")]
+const UHID_CREATE2: u32 = 11;
+#[cfg(target_os = "linux")]
+const UHID_INPUT2: u32 = 12;
+#[cfg(target_os = "linux")]
+const UHID_DATA_MAX: usize = 4096;
+#[cfg(target_os = "linux")]
+const BUS_USB: u16 = 0x03;
+
+#[cfg(target_os = "linux")]
+impl UhidDevice {
+    // Requests the kernel makes of the device, such as feature reports, are left unanswered
+    fn create(device: &HidDevice) -> Result<Self> {
+        use anyhow::Context;
+        use std::io::Write;
+
+        if device.report_descriptor.len() > UHID_DATA_MAX {
+            anyhow::bail!("report descriptor of {} bytes is too large", device.report_descriptor.len());
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/uhid")
+            .context("Failed to open /dev/uhid")?;
+
+        let mut event = Vec::with_capacity(4 + 256 + 20 + UHID_DATA_MAX);
+        event.extend_from_slice(&UHID_CREATE2.to_ne_bytes());
+        // The name needs room for its terminating NUL
+        push_fixed(&mut event, &device.name.as_bytes()[..device.name.len().min(127)], 128);
+        push_fixed(&mut event, b"steamdeck-controls", 64);
+        push_fixed(&mut event, b"", 64);
+        event.extend_from_slice(&(device.report_descriptor.len() as u16).to_ne_bytes());
+        event.extend_from_slice(&BUS_USB.to_ne_bytes());
+        event.extend_from_slice(&(device.vendor_id as u32).to_ne_bytes());
+        event.extend_from_slice(&(device.product_id as u32).to_ne_bytes());
+        event.extend_from_slice(&0u32.to_ne_bytes());
+        event.extend_from_slice(&0u32.to_ne_bytes());
+        push_fixed(&mut event, &device.report_descriptor, UHID_DATA_MAX);
+        file.write_all(&event).context("Failed to create the uhid device")?;
+        Ok(Self { file })
+    }
+
+    fn input(&mut self, report: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut event = Vec::with_capacity(6 + report.len());
+        event.extend_from_slice(&UHID_INPUT2.to_ne_bytes());
+        event.extend_from_slice(&(report.len() as u16).to_ne_bytes());
+        event.extend_from_slice(report);
+        self.file.write_all(&event)
+    }
+}
+
+// Fills one of the fixed size arrays, zero padded
+#[cfg(target_os = "linux")]
+fn push_fixed(event: &mut Vec<u8>, bytes: &[u8], size: usize) {
+    let length = bytes.len().min(size);
+    event.extend_from_slice(&bytes[..length]);
+    event.resize(event.len() + size - length, 0);
+}
+
+#[cfg(not(target_os = "linux"))]
+struct UhidDevice;
+
+#[cfg(not(target_os = "linux"))]
+impl UhidDevice {
+    fn create(_device: &HidDevice) -> Result<Self> {
+        anyhow::bail!("recreating HID devices needs /dev/uhid, which is only available on Linux")
+    }
+
+    fn input(&mut self, _report: &[u8]) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,330 @@
+use imgui::*;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use crate::i18n;
+use crate::input_id::{AxisId, ButtonId};
+use crate::palette::{self, Status};
+use crate::protocol::{AxisEvent, ButtonEvent, ControllerInputData};
+
+// How often the script file is checked for changes
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+// Stops a script stuck in a loop from freezing the input path
+const MAX_OPERATIONS: u64 = 100_000;
+
+const SCRIPT_HELP: &str = "Rhai script run on every event from the Deck before the mapping profile. Both hooks are optional:\n\
+    fn on_button(button, pressed) - return false to drop the press, a button name like \"East\" to turn it into that button, or nothing to keep it\n\
+    fn on_axis(axis, value) - return a new value, false to drop it, or nothing to keep it\n\
+    Scripts can call is_pressed(button), axis(axis), press(button), release(button) and set_axis(axis, value). \
+    Names are the ones in the overlay's state.json, e.g. South, LeftBumper, LeftStickX.";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptSettings {
+    pub enabled: bool,
+    pub path: String,
+}
+
+impl ScriptSettings {
+    pub fn load(&self) -> Option<InputScript> {
+        (self.enabled && !self.path.is_empty()).then(|| InputScript::load(PathBuf::from(&self.path)))
+    }
+
+    // Returns true when the script should be (re)loaded
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool, error: Option<&str>) -> bool {
+        let mut changed = false;
+        ui.window(i18n::window_title("Scripting"))
+            .size([480.0, 300.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                ui.text_wrapped(SCRIPT_HELP);
+                ui.separator();
+
+                changed |= ui.checkbox("Run script", &mut self.enabled);
+                changed |= ui.input_text("Script file", &mut self.path)
+                    .hint("C:\\path\\to\\script.rhai")
+                    .enter_returns_true(true)
+                    .build();
+                if self.enabled && ui.button("Reload") {
+                    changed = true;
+                }
+
+                if !self.enabled || self.path.is_empty() {
+                    return;
+                }
+                match error {
+                    Some(error) => palette::status_text(ui, Status::Bad, error),
+                    None => palette::status_text(ui, Status::Good, "Running, saved changes are picked up automatically"),
+                }
+            });
+        changed
+    }
+}
+
+// What the script can see and queue while a hook runs
+#[derive(Default)]
+struct ScriptContext {
+    // Raw input from the Deck, before the script changed anything
+    buttons: HashMap<ButtonId, bool>,
+    axes: HashMap<AxisId, f32>,
+    queued_buttons: Vec<(ButtonId, bool)>,
+    queued_axes: Vec<(AxisId, f32)>,
+}
+
+// A user script that sees every event before the virtual controller and can drop, change or add to it.
+// Errors are kept for the UI instead of stopping input.
+pub struct InputScript {
+    path: PathBuf,
+    engine: Engine,
+    ast: Option<AST>,
+    has_on_button: bool,
+    has_on_axis: bool,
+    context: Arc<Mutex<ScriptContext>>,
+    // What each held button was pressed as, so its release matches even if the script decides differently
+    pressed_as: HashMap<ButtonId, Option<ButtonId>>,
+    error: Option<String>,
+}
+
+impl InputScript {
+    pub fn load(path: PathBuf) -> Self {
+        let context = Arc::new(Mutex::new(ScriptContext::default()));
+        let mut script = Self {
+            path,
+            engine: create_engine(&context),
+            ast: None,
+            has_on_button: false,
+            has_on_axis: false,
+            context,
+            pressed_as: HashMap::new(),
+            error: None,
+        };
+        script.compile();
+        script
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    fn compile(&mut self) {
+        let source = match std::fs::read_to_string(&self.path) {
+            Ok(source) => source,
+            Err(e) => return self.report(format!("Failed to read {}: {}", self.path.display(), e)),
+        };
+        let ast = match self.engine.compile(source) {
+            Ok(ast) => ast,
+            Err(e) => return self.report(format!("Script error: {}", e)),
+        };
+        // Top-level statements run once per load, the hooks run per event
+        if let Err(e) = self.engine.run_ast_with_scope(&mut Scope::new(), &ast) {
+            return self.report(format!("Script error: {}", e));
+        }
+        self.has_on_button = ast.iter_functions().any(|f| f.name == "on_button" && f.params.len() == 2);
+        self.has_on_axis = ast.iter_functions().any(|f| f.name == "on_axis" && f.params.len() == 2);
+        self.ast = Some(ast);
+        log::info!("Loaded script {}", self.path.display());
+    }
+
+    // Logged once, not for every event that hits the same problem
+    fn report(&mut self, error: String) {
+        if self.error.as_ref() != Some(&error) {
+            log::error!("{}", error);
+            self.error = Some(error);
+        }
+    }
+
+    pub fn process(&mut self, mut input: ControllerInputData) -> ControllerInputData {
+        if self.ast.is_none() {
+            return input;
+        }
+
+        let timestamp = input.timestamp;
+        let button_events = std::mem::take(&mut input.button_events);
+        let axis_events = std::mem::take(&mut input.axis_events);
+
+        for event in button_events {
+            self.context.lock().unwrap().buttons.insert(event.button, event.pressed);
+            let result = self.call_hook(self.has_on_button, "on_button", format!("{:?}", event.button), event.pressed.into());
+            let decided = match result {
+                Ok(result) => self.button_result(event.button, result),
+                Err(e) => {
+                    self.report(e);
+                    Some(event.button)
+                }
+            };
+            let output = if event.pressed {
+                self.pressed_as.insert(event.button, decided);
+                decided
+            } else {
+                self.pressed_as.remove(&event.button).unwrap_or(decided)
+            };
+            if let Some(button) = output {
+                input.button_events.push(ButtonEvent { button, pressed: event.pressed, timestamp: event.timestamp });
+            }
+            self.push_queued(&mut input, timestamp);
+        }
+
+        for event in axis_events {
+            self.context.lock().unwrap().axes.insert(event.axis, event.value);
+            let result = self.call_hook(self.has_on_axis, "on_axis", format!("{:?}", event.axis), (event.value as f64).into());
+            let output = match result {
+                Ok(result) => self.axis_result(event.value, result),
+                Err(e) => {
+                    self.report(e);
+                    Some(event.value)
+                }
+            };
+            if let Some(value) = output {
+                input.axis_events.push(AxisEvent { axis: event.axis, value, timestamp: event.timestamp });
+            }
+            self.push_queued(&mut input, timestamp);
+        }
+        input
+    }
+
+    fn call_hook(&self, exists: bool, name: &str, id: String, value: Dynamic) -> Result<Dynamic, String> {
+        let Some(ast) = self.ast.as_ref().filter(|_| exists) else {
+            return Ok(Dynamic::UNIT);
+        };
+        let options = CallFnOptions::new().eval_ast(false);
+        self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, name, (id, value))
+            .map_err(|e| format!("Script error in {}: {}", name, e))
+    }
+
+    fn button_result(&mut self, button: ButtonId, result: Dynamic) -> Option<ButtonId> {
+        if result.is_unit() {
+            return Some(button);
+        }
+        if let Ok(keep) = result.as_bool() {
+            return keep.then_some(button);
+        }
+        if let Ok(name) = result.clone().into_string() {
+            return match button_named(&name) {
+                Ok(remapped) => Some(remapped),
+                Err(e) => {
+                    self.report(format!("Script error in on_button: {}", e));
+                    Some(button)
+                }
+            };
+        }
+        self.report(format!("Script error in on_button: returned a {}, expected a bool or a button name", result.type_name()));
+        Some(button)
+    }
+
+    fn axis_result(&mut self, value: f32, result: Dynamic) -> Option<f32> {
+        if result.is_unit() {
+            return Some(value);
+        }
+        if let Ok(keep) = result.as_bool() {
+            return keep.then_some(value);
+        }
+        if let Some(number) = result.as_float().ok().or_else(|| result.as_int().ok().map(|int| int as f64)) {
+            return Some((number as f32).clamp(-1.0, 1.0));
+        }
+        self.report(format!("Script error in on_axis: returned a {}, expected a number or a bool", result.type_name()));
+        Some(value)
+    }
+
+    // Events the script asked for with press, release and set_axis go out right after the one that triggered them
+    fn push_queued(&self, input: &mut ControllerInputData, timestamp: u64) {
+        let mut context = self.context.lock().unwrap();
+        for (button, pressed) in context.queued_buttons.drain(..) {
+            input.button_events.push(ButtonEvent { button, pressed, timestamp });
+        }
+        for (axis, value) in context.queued_axes.drain(..) {
+            input.axis_events.push(AxisEvent { axis, value, timestamp });
+        }
+    }
+}
+
+// Looks for saved edits to the script from the UI thread, or the headless loop. Reading and
+// compiling the file happens there, the input path only gets the finished script swapped in.
+pub struct ScriptWatcher {
+    path: String,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl ScriptWatcher {
+    pub fn new() -> Self {
+        Self {
+            path: String::new(),
+            modified: None,
+            last_check: Instant::now(),
+        }
+    }
+
+    // The script loaded again when its file changed since the last look
+    pub fn poll(&mut self, settings: &ScriptSettings) -> Option<InputScript> {
+        if self.last_check.elapsed() < RELOAD_CHECK_INTERVAL {
+            return None;
+        }
+        self.last_check = Instant::now();
+        if !settings.enabled {
+            self.path.clear();
+            return None;
+        }
+
+        let modified = std::fs::metadata(&settings.path).and_then(|metadata| metadata.modified()).ok();
+        // Whoever changed the setting loaded the new file already, it's only watched from here on
+        if self.path != settings.path {
+            self.path = settings.path.clone();
+            self.modified = modified;
+            return None;
+        }
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        settings.load()
+    }
+}
+
+fn create_engine(context: &Arc<Mutex<ScriptContext>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| log::info!("Script: {}", text));
+    engine.on_debug(|text, _, _| log::debug!("Script: {}", text));
+
+    let state = context.clone();
+    engine.register_fn("is_pressed", move |name: &str| -> Result<bool, Box<EvalAltResult>> {
+        let button = button_named(name)?;
+        Ok(state.lock().unwrap().buttons.get(&button).copied().unwrap_or(false))
+    });
+    let state = context.clone();
+    engine.register_fn("axis", move |name: &str| -> Result<f64, Box<EvalAltResult>> {
+        let axis = axis_named(name)?;
+        Ok(state.lock().unwrap().axes.get(&axis).copied().unwrap_or(0.0) as f64)
+    });
+    let state = context.clone();
+    engine.register_fn("press", move |name: &str| -> Result<(), Box<EvalAltResult>> {
+        state.lock().unwrap().queued_buttons.push((button_named(name)?, true));
+        Ok(())
+    });
+    let state = context.clone();
+    engine.register_fn("release", move |name: &str| -> Result<(), Box<EvalAltResult>> {
+        state.lock().unwrap().queued_buttons.push((button_named(name)?, false));
+        Ok(())
+    });
+    let state = context.clone();
+    engine.register_fn("set_axis", move |name: &str, value: f64| -> Result<(), Box<EvalAltResult>> {
+        state.lock().unwrap().queued_axes.push((axis_named(name)?, (value as f32).clamp(-1.0, 1.0)));
+        Ok(())
+    });
+    engine
+}
+
+fn button_named(name: &str) -> Result<ButtonId, Box<EvalAltResult>> {
+    ButtonId::ALL.iter().copied()
+        .find(|button| format!("{:?}", button) == name)
+        .ok_or_else(|| format!("unknown button '{}'", name).into())
+}
+
+fn axis_named(name: &str) -> Result<AxisId, Box<EvalAltResult>> {
+    AxisId::ALL.iter().copied()
+        .find(|axis| format!("{:?}", axis) == name)
+        .ok_or_else(|| format!("unknown axis '{}'", name).into())
+}
@@ -0,0 +1,200 @@
+use chrono::Datelike;
+use imgui::*;
+use std::collections::BTreeMap;
+use crate::i18n::{self, tr};
+use crate::palette::{self, Status};
+use crate::session_report::{self, SessionSummary, STARTED_FORMAT};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GroupBy {
+    Profile,
+    Game,
+}
+
+// Sessions added up, latency is weighted by the messages it was measured over
+#[derive(Debug, Clone, Default)]
+struct Totals {
+    sessions: u32,
+    seconds: f64,
+    events: u64,
+    latency_sum_ms: f64,
+    latency_messages: u64,
+}
+
+impl Totals {
+    fn add(&mut self, summary: &SessionSummary) {
+        self.sessions += 1;
+        self.seconds += summary.duration_secs;
+        self.events += summary.events;
+        if let Some(avg) = summary.avg_latency_ms {
+            self.latency_sum_ms += avg * summary.messages as f64;
+            self.latency_messages += summary.messages;
+        }
+    }
+
+    fn avg_latency_ms(&self) -> Option<f64> {
+        (self.latency_messages > 0).then(|| self.latency_sum_ms / self.latency_messages as f64)
+    }
+}
+
+// Past sessions per profile or game and week, read from the summaries saved after each session
+pub struct SessionHistoryWindow {
+    pub show: bool,
+    summaries: Vec<SessionSummary>,
+    load_error: Option<String>,
+    loaded: bool,
+    group_by: GroupBy,
+    // The profile or game whose weeks are listed, None for all of them
+    selected: Option<String>,
+}
+
+impl SessionHistoryWindow {
+    pub fn new() -> Self {
+        Self {
+            show: false,
+            summaries: Vec::new(),
+            load_error: None,
+            loaded: false,
+            group_by: GroupBy::Profile,
+            selected: None,
+        }
+    }
+
+    // Read again the next time the window is drawn, e.g. after a session was saved
+    pub fn invalidate(&mut self) {
+        self.loaded = false;
+    }
+
+    fn reload(&mut self) {
+        self.loaded = true;
+        match session_report::load_summaries(&session_report::reports_dir()) {
+            Ok(summaries) => {
+                self.summaries = summaries;
+                self.load_error = None;
+            }
+            Err(e) => self.load_error = Some(format!("{:#}", e)),
+        }
+    }
+
+    fn key(&self, summary: &SessionSummary) -> String {
+        let key = match self.group_by {
+            GroupBy::Profile => summary.profile.clone(),
+            GroupBy::Game => summary.game.clone().unwrap_or_default(),
+        };
+        if key.is_empty() { tr("(none)").to_string() } else { key }
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        if !self.show {
+            return;
+        }
+        if !self.loaded {
+            self.reload();
+        }
+
+        let mut show = self.show;
+        ui.window(i18n::window_title("Session History"))
+            .size([560.0, 480.0], Condition::FirstUseEver)
+            .opened(&mut show)
+            .build(|| {
+                if ui.button(tr("Refresh")) {
+                    self.reload();
+                }
+                ui.same_line();
+                let mut by_game = self.group_by == GroupBy::Game;
+                if ui.checkbox(tr("Group by Game"), &mut by_game) {
+                    self.group_by = if by_game { GroupBy::Game } else { GroupBy::Profile };
+                    self.selected = None;
+                }
+                if let Some(error) = &self.load_error {
+                    palette::status_text(ui, Status::Bad, error);
+                }
+                if self.summaries.is_empty() {
+                    ui.text_disabled(tr("No sessions saved yet"));
+                    return;
+                }
+                ui.separator();
+
+                let mut groups: BTreeMap<String, Totals> = BTreeMap::new();
+                for summary in &self.summaries {
+                    groups.entry(self.key(summary)).or_default().add(summary);
+                }
+                let first_header = match self.group_by {
+                    GroupBy::Profile => tr("Profile"),
+                    GroupBy::Game => tr("Game"),
+                };
+                let mut clicked = None;
+                render_header(ui, "history_groups", first_header);
+                for (key, totals) in &groups {
+                    let selected = self.selected.as_ref() == Some(key);
+                    if ui.selectable_config(key).selected(selected).span_all_columns(false).build() {
+                        clicked = Some(if selected { None } else { Some(key.clone()) });
+                    }
+                    ui.next_column();
+                    render_totals(ui, totals);
+                }
+                ui.columns(1, "", false);
+                if let Some(selected) = clicked {
+                    self.selected = selected;
+                }
+
+                ui.separator();
+                match &self.selected {
+                    Some(key) => ui.text(format!("{} {}", tr("Weekly:"), key)),
+                    None => ui.text(tr("Weekly: all sessions")),
+                }
+                // BTreeMap keeps the ISO weeks in order
+                let mut weeks: BTreeMap<String, Totals> = BTreeMap::new();
+                for summary in &self.summaries {
+                    if self.selected.as_ref().is_some_and(|selected| *selected != self.key(summary)) {
+                        continue;
+                    }
+                    let Ok(started) = chrono::NaiveDateTime::parse_from_str(&summary.started, STARTED_FORMAT) else {
+                        continue;
+                    };
+                    let week = started.iso_week();
+                    weeks.entry(format!("{}-W{:02}", week.year(), week.week())).or_default().add(summary);
+                }
+
+                let latencies: Vec<f32> = weeks.values().filter_map(|totals| totals.avg_latency_ms()).map(|ms| ms as f32).collect();
+                if latencies.len() > 1 {
+                    ui.plot_lines(tr("Average Latency (ms)"), &latencies)
+                        .graph_size([0.0, 60.0])
+                        .scale_min(0.0)
+                        .build();
+                }
+                render_header(ui, "history_weeks", tr("Week"));
+                // Newest first, that's usually what's being compared
+                for (week, totals) in weeks.iter().rev() {
+                    ui.text(week);
+                    ui.next_column();
+                    render_totals(ui, totals);
+                }
+                ui.columns(1, "", false);
+            });
+        self.show = show;
+    }
+}
+
+fn render_header(ui: &Ui, id: &str, first: &str) {
+    ui.columns(5, id, true);
+    for header in [first, tr("Sessions"), tr("Hours"), tr("Events"), tr("Avg Latency")] {
+        ui.text(header);
+        ui.next_column();
+    }
+    ui.separator();
+}
+
+fn render_totals(ui: &Ui, totals: &Totals) {
+    ui.text(totals.sessions.to_string());
+    ui.next_column();
+    ui.text(format!("{:.1}", totals.seconds / 3600.0));
+    ui.next_column();
+    ui.text(totals.events.to_string());
+    ui.next_column();
+    match totals.avg_latency_ms() {
+        Some(ms) => ui.text(format!("{:.2}ms", ms)),
+        None => ui.text_disabled("-"),
+    }
+    ui.next_column();
+}
@@ -0,0 +1,263 @@
+use anyhow::{Context, Result};
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::i18n;
+use crate::websocket_server::InputDigest;
+
+// A client coming back within this continues the session as a reconnect
+const RECONNECT_GRACE: Duration = Duration::from_secs(10);
+const HISTORY_FILE: &str = "sessions.csv";
+pub const STARTED_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const CSV_HEADER: &str = "started,duration_secs,messages,events,avg_latency_ms,p95_latency_ms,p99_latency_ms,dropped_messages,coalesced_axis_updates,reconnects";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub started: String,
+    // The mapping profile active when the session started, empty in summaries saved before
+    // profiles were recorded
+    #[serde(default)]
+    pub profile: String,
+    // Named after the profile's launch command, None when it has none
+    #[serde(default)]
+    pub game: Option<String>,
+    pub duration_secs: f64,
+    pub messages: u64,
+    pub events: u64,
+    // None when the client never answered a clock probe, e.g. an older client
+    pub avg_latency_ms: Option<f64>,
+    pub p95_latency_ms: Option<u64>,
+    pub p99_latency_ms: Option<u64>,
+    pub dropped_messages: u64,
+    pub coalesced_axis_updates: u64,
+    pub reconnects: u32,
+}
+
+impl SessionSummary {
+    // Writes session_<start>.json into dir and adds a row to the sessions.csv kept there
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(format!("session_{}.json", self.started.replace([':', '-', ' '], "")));
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        let history = dir.join(HISTORY_FILE);
+        let new_file = !history.exists();
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&history)
+            .with_context(|| format!("Failed to open {}", history.display()))?;
+        if new_file {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        writeln!(file, "{},{:.1},{},{},{},{},{},{},{},{}",
+            self.started,
+            self.duration_secs,
+            self.messages,
+            self.events,
+            optional(self.avg_latency_ms.map(|ms| format!("{:.2}", ms))),
+            optional(self.p95_latency_ms.map(|ms| ms.to_string())),
+            optional(self.p99_latency_ms.map(|ms| ms.to_string())),
+            self.dropped_messages,
+            self.coalesced_axis_updates,
+            self.reconnects)?;
+        Ok(path)
+    }
+}
+
+struct Session {
+    started: chrono::DateTime<chrono::Local>,
+    profile: String,
+    game: Option<String>,
+    start: Instant,
+    messages: u64,
+    events: u64,
+    delays_ms: Vec<u64>,
+    dropped_messages: u64,
+    coalesced_axis_updates: u64,
+    reconnects: u32,
+}
+
+impl Session {
+    fn new(profile: &str, game: Option<String>) -> Self {
+        Self {
+            started: chrono::Local::now(),
+            profile: profile.to_string(),
+            game,
+            start: Instant::now(),
+            messages: 0,
+            events: 0,
+            delays_ms: Vec::new(),
+            dropped_messages: 0,
+            coalesced_axis_updates: 0,
+            reconnects: 0,
+        }
+    }
+
+    fn summarize(mut self, ended: Instant) -> SessionSummary {
+        self.delays_ms.sort_unstable();
+        // Nearest rank, so p99 of a short session is its slowest message rather than an interpolation
+        let percentile = |delays: &[u64], p: f64| {
+            let rank = ((p * delays.len() as f64).ceil() as usize).max(1);
+            delays.get(rank - 1).copied()
+        };
+        SessionSummary {
+            started: self.started.format(STARTED_FORMAT).to_string(),
+            profile: self.profile,
+            game: self.game,
+            duration_secs: ended.duration_since(self.start).as_secs_f64(),
+            messages: self.messages,
+            events: self.events,
+            avg_latency_ms: (!self.delays_ms.is_empty())
+                .then(|| self.delays_ms.iter().sum::<u64>() as f64 / self.delays_ms.len() as f64),
+            p95_latency_ms: percentile(&self.delays_ms, 0.95),
+            p99_latency_ms: percentile(&self.delays_ms, 0.99),
+            dropped_messages: self.dropped_messages,
+            coalesced_axis_updates: self.coalesced_axis_updates,
+            reconnects: self.reconnects,
+        }
+    }
+}
+
+// A session runs from the first client connecting until nobody has been connected for
+// RECONNECT_GRACE, so a dropped Wi-Fi link shows up as a reconnect instead of a new session
+pub struct SessionTracker {
+    session: Option<Session>,
+    clients: usize,
+    // Set while no client is connected
+    idle_since: Option<Instant>,
+}
+
+impl SessionTracker {
+    pub fn new() -> Self {
+        Self {
+            session: None,
+            clients: 0,
+            idle_since: None,
+        }
+    }
+
+    // The profile and game only count when this starts a new session
+    pub fn client_connected(&mut self, profile: &str, game: Option<String>) {
+        self.clients += 1;
+        let reconnected = self.idle_since.take().is_some();
+        match &mut self.session {
+            Some(session) if reconnected => session.reconnects += 1,
+            Some(_) => {}
+            None => self.session = Some(Session::new(profile, game)),
+        }
+    }
+
+    pub fn client_disconnected(&mut self) {
+        self.clients = self.clients.saturating_sub(1);
+        if self.clients == 0 {
+            self.idle_since = Some(Instant::now());
+        }
+    }
+
+    pub fn record(&mut self, digest: &InputDigest) {
+        let Some(session) = &mut self.session else {
+            return;
+        };
+        session.messages += digest.message_count as u64;
+        session.events += (digest.data.button_events.len() + digest.data.axis_events.len()) as u64
+            + digest.dropped_axis_events as u64;
+        session.delays_ms.extend(&digest.delays_ms);
+        session.dropped_messages += digest.missing_messages;
+        session.coalesced_axis_updates += digest.dropped_axis_events as u64;
+    }
+
+    // The summary of a session that just ended, once the grace period for a reconnect is over
+    pub fn update(&mut self) -> Option<SessionSummary> {
+        let idle_since = self.idle_since.filter(|since| since.elapsed() >= RECONNECT_GRACE)?;
+        self.idle_since = None;
+        self.session.take().map(|session| session.summarize(idle_since))
+    }
+}
+
+pub fn reports_dir() -> PathBuf {
+    crate::settings::config_dir().join("sessions")
+}
+
+// Every summary saved in dir, oldest first. Files that can't be read are skipped so one broken
+// summary doesn't hide the rest.
+pub fn load_summaries(dir: &Path) -> Result<Vec<SessionSummary>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut summaries = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let is_summary = path.file_name().and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("session_") && name.ends_with(".json"));
+        if !is_summary {
+            continue;
+        }
+        match std::fs::read_to_string(&path).map_err(anyhow::Error::from)
+            .and_then(|text| Ok(serde_json::from_str::<SessionSummary>(&text)?)) {
+            Ok(summary) => summaries.push(summary),
+            Err(e) => log::warn!("Skipping session summary {}: {:#}", path.display(), e),
+        }
+    }
+    summaries.sort_by(|a, b| a.started.cmp(&b.started));
+    Ok(summaries)
+}
+
+// Shows the summary of the last session until it's closed
+pub struct SessionReportWindow {
+    report: Option<(SessionSummary, Option<PathBuf>)>,
+}
+
+impl SessionReportWindow {
+    pub fn new() -> Self {
+        Self { report: None }
+    }
+
+    pub fn show(&mut self, summary: SessionSummary, saved_to: Option<PathBuf>) {
+        self.report = Some((summary, saved_to));
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        let Some((summary, saved_to)) = &self.report else {
+            return;
+        };
+        let mut opened = true;
+        let mut close = false;
+        ui.window(i18n::window_title("Session Summary"))
+            .size([360.0, 300.0], Condition::FirstUseEver)
+            .opened(&mut opened)
+            .build(|| {
+                ui.text(format!("Started: {}", summary.started));
+                ui.text(format!("Profile: {}", summary.profile));
+                if let Some(game) = &summary.game {
+                    ui.text(format!("Game: {}", game));
+                }
+                ui.text(format!("Duration: {:.0}s", summary.duration_secs));
+                ui.text(format!("Messages: {} ({} events)", summary.messages, summary.events));
+                ui.separator();
+                match (summary.avg_latency_ms, summary.p95_latency_ms, summary.p99_latency_ms) {
+                    (Some(avg), Some(p95), Some(p99)) => {
+                        ui.text(format!("Average Latency: {:.2}ms", avg));
+                        ui.text(format!("p95 Latency: {}ms", p95));
+                        ui.text(format!("p99 Latency: {}ms", p99));
+                    }
+                    _ => ui.text_disabled("No latency data, the client's clock was never synced"),
+                }
+                ui.separator();
+                ui.text(format!("Dropped Messages: {}", summary.dropped_messages));
+                ui.text(format!("Coalesced Axis Updates: {}", summary.coalesced_axis_updates));
+                ui.text(format!("Reconnects: {}", summary.reconnects));
+                ui.separator();
+                if let Some(path) = saved_to {
+                    ui.text_wrapped(format!("Saved to {}", path.display()));
+                }
+                if ui.button("Close") {
+                    close = true;
+                }
+            });
+        if !opened || close {
+            self.report = None;
+        }
+    }
+}
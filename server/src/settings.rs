@@ -0,0 +1,271 @@
+use anyhow::Result;
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::hotkeys::HotkeySettings;
+use crate::i18n::{self, tr, Language};
+use crate::local_controller::LocalControllerSettings;
+use crate::mapping::MappingSettings;
+use crate::overlay::OverlaySettings;
+use crate::palette::{self, Palette};
+use crate::raw_hid::HidPassthroughSettings;
+use crate::scripting::ScriptSettings;
+use crate::virtual_controller::PadType;
+
+const APP_DIR: &str = "steamdeck-controls-server";
+const SETTINGS_FILE: &str = "settings.toml";
+// Settings were stored as JSON before, read once and converted
+const LEGACY_SETTINGS_FILE: &str = "settings.json";
+const IMGUI_INI_FILE: &str = "imgui.ini";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Theme {
+    Dark,
+    Light,
+    Classic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiSettings {
+    pub theme: Theme,
+    // Status colors, picked apart from the theme
+    pub palette: Palette,
+    pub language: Language,
+    // Low battery warnings also go to Windows' notifications, which show over fullscreen games
+    pub system_notifications: bool,
+    pub font_scale: f32,
+    pub ui_scale: f32,
+    // Input is processed at update_rate_hz no matter how often the window is redrawn
+    pub update_rate_hz: u32,
+    pub max_fps: u32,
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::Dark,
+            palette: Palette::Standard,
+            language: Language::English,
+            system_notifications: true,
+            font_scale: 1.0,
+            ui_scale: 1.0,
+            update_rate_hz: 250,
+            max_fps: 60,
+        }
+    }
+}
+
+impl UiSettings {
+    // base_style is the style captured at startup, so scaling is never applied twice
+    pub fn apply(&self, imgui: &mut imgui::Context, base_style: &Style) {
+        let style = imgui.style_mut();
+        *style = *base_style;
+        match self.theme {
+            Theme::Dark => style.use_dark_colors(),
+            Theme::Light => style.use_light_colors(),
+            Theme::Classic => style.use_classic_colors(),
+        };
+        style.scale_all_sizes(self.ui_scale);
+        imgui.io_mut().font_global_scale = self.font_scale;
+        palette::set_palette(self.palette);
+        i18n::set_language(self.language);
+    }
+
+    pub fn update_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.update_rate_hz.max(1) as f64)
+    }
+
+    pub fn render_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.max_fps.max(1) as f64)
+    }
+
+    // Returns true when a value changed and the settings should be re-applied
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool) -> bool {
+        let mut changed = false;
+
+        ui.window(i18n::window_title("Settings"))
+            .size([350.0, 310.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                ui.text(tr("Appearance"));
+                ui.separator();
+
+                let themes = [Theme::Dark, Theme::Light, Theme::Classic];
+                let mut theme_index = themes.iter().position(|&t| t == self.theme).unwrap_or(0);
+                if ui.combo_simple_string(tr("Theme"), &mut theme_index, &[tr("Dark"), tr("Light"), tr("Classic")]) {
+                    self.theme = themes[theme_index];
+                    changed = true;
+                }
+                changed |= palette::render_combo(ui, &mut self.palette);
+                changed |= i18n::render_combo(ui, &mut self.language);
+                if cfg!(windows) {
+                    changed |= ui.checkbox(tr("Windows Notifications"), &mut self.system_notifications);
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text(tr("Low controller batteries are also announced by Windows, which shows over fullscreen games"));
+                    }
+                }
+
+                changed |= ui.slider(tr("Font Size"), 0.5, 3.0, &mut self.font_scale);
+                changed |= ui.slider(tr("UI Scale"), 0.5, 3.0, &mut self.ui_scale);
+
+                ui.spacing();
+                ui.text(tr("Performance"));
+                ui.separator();
+
+                changed |= ui.slider(tr("Update Rate (Hz)"), 30, 1000, &mut self.update_rate_hz);
+                changed |= ui.slider(tr("Max FPS"), 15, 240, &mut self.max_fps);
+
+                ui.separator();
+                if ui.button(tr("Reset to Defaults")) {
+                    *self = Self::default();
+                    changed = true;
+                }
+            });
+
+        changed
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+    pub bind_address: String,
+    pub port: i32,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0".to_string(),
+            port: 8080,
+        }
+    }
+}
+
+impl NetworkSettings {
+    pub fn listen_address(&self) -> String {
+        format!("{}:{}", self.bind_address, self.port)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VirtualControllerSettings {
+    // XInput player 1-4 to claim, 0 takes the first free slot
+    pub player_slot: u32,
+    // The same for player 2's pad in split mode
+    pub second_player_slot: u32,
+    // The pad each client is paired with by IP address, clients not in here share the Xbox 360 pad
+    pub client_pads: BTreeMap<String, PadType>,
+}
+
+impl VirtualControllerSettings {
+    // 0-based, the way XInput counts
+    pub fn player_slots(&self) -> [Option<u32>; 2] {
+        let slot = |player: u32| (1..=4).contains(&player).then(|| player - 1);
+        [slot(self.player_slot), slot(self.second_player_slot)]
+    }
+
+    pub fn pad_types(&self) -> HashMap<IpAddr, PadType> {
+        self.client_pads.iter()
+            .filter_map(|(address, &pad_type)| Some((address.parse().ok()?, pad_type)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub ui: UiSettings,
+    pub network: NetworkSettings,
+    pub virtual_controller: VirtualControllerSettings,
+    pub mapping: MappingSettings,
+    pub local_controller: LocalControllerSettings,
+    pub overlay: OverlaySettings,
+    pub scripting: ScriptSettings,
+    pub hid_passthrough: HidPassthroughSettings,
+    pub hotkeys: HotkeySettings,
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
+                log::warn!("Failed to parse {}: {} - using defaults", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::load_legacy(path).unwrap_or_default(),
+        }
+    }
+
+    fn load_legacy(path: &Path) -> Option<Self> {
+        let legacy_path = path.with_file_name(LEGACY_SETTINGS_FILE);
+        let contents = std::fs::read_to_string(&legacy_path).ok()?;
+        let settings: Self = match serde_json::from_str(&contents) {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::warn!("Failed to parse {}: {} - using defaults", legacy_path.display(), e);
+                return None;
+            }
+        };
+
+        match settings.save(path) {
+            Ok(_) => log::info!("Migrated {} to {}", legacy_path.display(), path.display()),
+            Err(e) => log::warn!("Failed to migrate {}: {}", legacy_path.display(), e),
+        }
+        Some(settings)
+    }
+
+    // Nothing saved yet, not even by an older version
+    pub fn is_first_run(path: &Path) -> bool {
+        !path.exists() && !path.with_file_name(LEGACY_SETTINGS_FILE).exists()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+pub fn default_settings_path() -> PathBuf {
+    config_dir().join(SETTINGS_FILE)
+}
+
+pub fn config_dir() -> PathBuf {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+
+    base.unwrap_or_else(|| PathBuf::from(".")).join(APP_DIR)
+}
+
+// Window layout lives next to the settings instead of the working directory, which
+// changes depending on how the app is launched (Steam shortcut, Proton, terminal)
+pub fn imgui_ini_path() -> Option<PathBuf> {
+    let dir = config_dir();
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        log::warn!("Failed to create {}: {} - window layout will not be saved", dir.display(), e);
+        return None;
+    }
+
+    let path = dir.join(IMGUI_INI_FILE);
+    let legacy_path = PathBuf::from(IMGUI_INI_FILE);
+    if !path.exists() && legacy_path.exists() {
+        if let Err(e) = std::fs::copy(&legacy_path, &path) {
+            log::warn!("Failed to migrate {}: {}", legacy_path.display(), e);
+        }
+    }
+
+    Some(path)
+}
@@ -0,0 +1,149 @@
+use anyhow::{bail, Context, Result};
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use crate::i18n;
+use crate::palette::{self, Status};
+use crate::settings::{config_dir, Settings};
+
+const BUNDLE_FORMAT: &str = "steamdeck-controls-settings";
+const BUNDLE_VERSION: u32 = 1;
+// Client and server settings have nothing in common, a bundle only imports into the app that wrote it
+const BUNDLE_APP: &str = "server";
+const EXPORTS_DIR: &str = "exports";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleHeader {
+    format: String,
+    version: u32,
+    app: String,
+    exported_at: String,
+}
+
+// The whole configuration in one file, so a setup can be copied to another machine
+#[derive(Debug, Serialize, Deserialize)]
+struct SettingsBundle {
+    bundle: BundleHeader,
+    settings: Settings,
+}
+
+pub fn export_bundle(settings: &Settings, path: &Path) -> Result<()> {
+    let bundle = SettingsBundle {
+        bundle: BundleHeader {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            app: BUNDLE_APP.to_string(),
+            exported_at: chrono::Local::now().to_rfc3339(),
+        },
+        settings: settings.clone(),
+    };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, toml::to_string_pretty(&bundle)?)?;
+    Ok(())
+}
+
+pub fn import_bundle(path: &Path) -> Result<Settings> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let bundle: SettingsBundle = toml::from_str(&contents)
+        .with_context(|| format!("{} is not a settings bundle", path.display()))?;
+
+    if bundle.bundle.format != BUNDLE_FORMAT {
+        bail!("{} is not a settings bundle", path.display());
+    }
+    if bundle.bundle.app != BUNDLE_APP {
+        bail!("{} holds {} settings, not {} settings", path.display(), bundle.bundle.app, BUNDLE_APP);
+    }
+    if bundle.bundle.version > BUNDLE_VERSION {
+        bail!("{} was exported by a newer version (bundle version {})", path.display(), bundle.bundle.version);
+    }
+
+    Ok(bundle.settings)
+}
+
+fn default_bundle_path() -> PathBuf {
+    config_dir()
+        .join(EXPORTS_DIR)
+        .join(format!("{}-settings-{}.toml", BUNDLE_APP, chrono::Local::now().format("%Y%m%d_%H%M%S")))
+}
+
+pub struct BundleWindow {
+    pub open: bool,
+    path: String,
+    // Result of the last export or import, shown under the buttons
+    status: Option<(bool, String)>,
+}
+
+impl BundleWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            path: String::new(),
+            status: None,
+        }
+    }
+
+    // Returns the imported settings, the caller applies and saves them
+    pub fn render(&mut self, ui: &Ui, settings: &Settings) -> Option<Settings> {
+        if !self.open {
+            return None;
+        }
+
+        let mut imported = None;
+        let mut open = self.open;
+        ui.window(i18n::window_title("Import / Export Settings"))
+            .size([450.0, 180.0], Condition::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                ui.text_wrapped("Export writes every setting to one file. Copy it to another PC and import it there.");
+                ui.separator();
+
+                ui.input_text("File", &mut self.path)
+                    .hint("Leave empty to export to the config directory")
+                    .build();
+
+                if ui.button("Export") {
+                    let path = match self.path.trim() {
+                        "" => default_bundle_path(),
+                        path => PathBuf::from(path),
+                    };
+                    self.status = Some(match export_bundle(settings, &path) {
+                        Ok(_) => {
+                            log::info!("Exported settings to {}", path.display());
+                            self.path = path.display().to_string();
+                            (true, format!("Exported to {}", path.display()))
+                        }
+                        Err(e) => {
+                            log::error!("Failed to export settings to {}: {}", path.display(), e);
+                            (false, format!("Export failed: {}", e))
+                        }
+                    });
+                }
+                ui.same_line();
+                if ui.button("Import") {
+                    let path = PathBuf::from(self.path.trim());
+                    self.status = Some(match import_bundle(&path) {
+                        Ok(settings) => {
+                            log::info!("Imported settings from {}", path.display());
+                            imported = Some(settings);
+                            (true, format!("Imported {}", path.display()))
+                        }
+                        Err(e) => {
+                            log::error!("Failed to import settings: {:#}", e);
+                            (false, format!("Import failed: {:#}", e))
+                        }
+                    });
+                }
+
+                if let Some((ok, ref status)) = self.status {
+                    palette::status_text(ui, if ok { Status::Good } else { Status::Bad }, status);
+                }
+            });
+        self.open = open;
+
+        imported
+    }
+}
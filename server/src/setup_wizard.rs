@@ -0,0 +1,219 @@
+use imgui::*;
+use std::net::IpAddr;
+use crate::i18n;
+use crate::palette::{self, Status};
+use crate::settings::NetworkSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SetupStep {
+    Welcome,
+    Network,
+    VirtualController,
+    InputTest,
+}
+
+// Shown on first launch, walks through what the hard-coded defaults used to assume
+pub struct SetupWizard {
+    open: bool,
+    step: SetupStep,
+    bind_address: String,
+    port: i32,
+    vigem_status: Option<Result<(), String>>,
+    input_received: bool,
+    network_update: Option<NetworkSettings>,
+    should_check_vigem: bool,
+    should_finish: bool,
+}
+
+impl SetupWizard {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            step: SetupStep::Welcome,
+            bind_address: String::new(),
+            port: 0,
+            vigem_status: None,
+            input_received: false,
+            network_update: None,
+            should_check_vigem: false,
+            should_finish: false,
+        }
+    }
+
+    pub fn open(&mut self, network: &NetworkSettings) {
+        *self = Self::new();
+        self.open = true;
+        self.bind_address = network.bind_address.clone();
+        self.port = network.port;
+    }
+
+    pub fn set_vigem_status(&mut self, status: Result<(), String>) {
+        self.vigem_status = Some(status);
+    }
+
+    pub fn input_received(&mut self) {
+        if self.step == SetupStep::InputTest {
+            self.input_received = true;
+        }
+    }
+
+    pub fn network_update(&mut self) -> Option<NetworkSettings> {
+        self.network_update.take()
+    }
+
+    pub fn should_check_vigem(&mut self) -> bool {
+        if self.should_check_vigem {
+            self.should_check_vigem = false;
+            return true;
+        }
+        false
+    }
+
+    // Also set when setup is skipped, either way the defaults get written and it won't show again
+    pub fn should_finish(&mut self) -> bool {
+        if self.should_finish {
+            self.should_finish = false;
+            return true;
+        }
+        false
+    }
+
+    fn network_error(&self) -> Option<&'static str> {
+        if self.bind_address.trim().parse::<IpAddr>().is_err() {
+            Some("Enter an IP address, e.g. 0.0.0.0")
+        } else if !(1..=65535).contains(&self.port) {
+            Some("The port must be between 1 and 65535")
+        } else {
+            None
+        }
+    }
+
+    fn finish(&mut self) {
+        self.open = false;
+        self.should_finish = true;
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        ui.window(i18n::window_title("Setup"))
+            .size([480.0, 300.0], Condition::FirstUseEver)
+            .position_pivot([0.5, 0.5])
+            .position([ui.io().display_size[0] * 0.5, ui.io().display_size[1] * 0.5], Condition::FirstUseEver)
+            .opened(&mut open)
+            .build(|| match self.step {
+                SetupStep::Welcome => self.render_welcome(ui),
+                SetupStep::Network => self.render_network(ui),
+                SetupStep::VirtualController => self.render_virtual_controller(ui),
+                SetupStep::InputTest => self.render_input_test(ui),
+            });
+
+        // Closing the window counts as skipping
+        if !open && self.open {
+            self.finish();
+        }
+    }
+
+    fn render_welcome(&mut self, ui: &Ui) {
+        ui.text("Welcome to Steam Deck Controls");
+        ui.separator();
+        ui.text_wrapped("This is the server. It runs on the PC you play on and turns input from the Steam Deck into a virtual Xbox 360 controller. The Steam Deck runs the client app and connects to this PC.");
+        ui.spacing();
+        ui.text_wrapped("The next steps pick where the server listens, check the virtual controller driver and test input from the Deck.");
+        ui.spacing();
+
+        if ui.button("Next") {
+            self.step = SetupStep::Network;
+        }
+        ui.same_line();
+        if ui.button("Skip Setup") {
+            self.finish();
+        }
+    }
+
+    fn render_network(&mut self, ui: &Ui) {
+        ui.text("Step 1 of 3: Network");
+        ui.separator();
+        ui.text_wrapped("0.0.0.0 accepts connections on every network interface. Enter this PC's LAN address instead to only accept connections from that network.");
+        ui.spacing();
+
+        ui.input_text("Bind Address", &mut self.bind_address).build();
+        ui.input_int("Port", &mut self.port).build();
+        ui.text_wrapped(format!("On the Deck, enter this PC's IP address and port {}.", self.port));
+
+        let error = self.network_error();
+        if let Some(error) = error {
+            palette::status_text(ui, Status::Bad, error);
+        }
+        ui.spacing();
+
+        if ui.button("Back") {
+            self.step = SetupStep::Welcome;
+        }
+        ui.same_line();
+        if ui.button("Next") && error.is_none() {
+            self.network_update = Some(NetworkSettings {
+                bind_address: self.bind_address.trim().to_string(),
+                port: self.port,
+            });
+            self.should_check_vigem = true;
+            self.step = SetupStep::VirtualController;
+        }
+    }
+
+    fn render_virtual_controller(&mut self, ui: &Ui) {
+        ui.text("Step 2 of 3: Virtual Controller");
+        ui.separator();
+        ui.text_wrapped("Games see the Deck as an Xbox 360 controller created through the ViGEmBus driver.");
+        ui.spacing();
+
+        match &self.vigem_status {
+            None => ui.text("Checking..."),
+            Some(Ok(())) => palette::status_text(ui, Status::Good, "Virtual controller plugged in"),
+            Some(Err(e)) => {
+                palette::status_text(ui, Status::Bad, e);
+                ui.text_wrapped("Install ViGEmBus from https://github.com/nefarius/ViGEmBus and restart the PC. You can carry on without it, input will show up here but won't reach any game.");
+            }
+        }
+        if ui.button("Check Again") {
+            self.vigem_status = None;
+            self.should_check_vigem = true;
+        }
+        ui.spacing();
+
+        if ui.button("Back") {
+            self.step = SetupStep::Network;
+        }
+        ui.same_line();
+        if ui.button("Next") {
+            self.input_received = false;
+            self.step = SetupStep::InputTest;
+        }
+    }
+
+    fn render_input_test(&mut self, ui: &Ui) {
+        ui.text("Step 3 of 3: Input Test");
+        ui.separator();
+        ui.text_wrapped("Start the client on the Deck, connect to this PC and press any button.");
+        ui.spacing();
+
+        if self.input_received {
+            palette::status_text(ui, Status::Good, "Input received from the Deck - all set!");
+        } else {
+            palette::status_text(ui, Status::Warning, "Waiting for input...");
+        }
+        ui.spacing();
+
+        if ui.button("Back") {
+            self.step = SetupStep::VirtualController;
+        }
+        ui.same_line();
+        let label = if self.input_received { "Finish" } else { "Finish Without Testing" };
+        if ui.button(label) {
+            self.finish();
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::time::Instant;
+use crate::input_id::AxisId;
+use crate::protocol::AxisEvent;
+
+// EMA time constant at full strength
+const MAX_EMA_TIME_CONSTANT: f32 = 0.1;
+// One-Euro cutoff for a still stick, from no smoothing at strength 0 to heavy at strength 1
+const ONE_EURO_MAX_CUTOFF_HZ: f32 = 10.0;
+const ONE_EURO_MIN_CUTOFF_HZ: f32 = 0.5;
+// How much faster movement raises the cutoff, this is what keeps One-Euro from lagging on flicks
+const ONE_EURO_BETA: f32 = 0.5;
+const ONE_EURO_DERIVATIVE_CUTOFF_HZ: f32 = 1.0;
+// Close enough to the raw value that the filter stops sending updates
+const SETTLED: f32 = 0.001;
+
+const SMOOTHED_AXES: [AxisId; 6] = [
+    AxisId::LeftStickX, AxisId::LeftStickY,
+    AxisId::RightStickX, AxisId::RightStickY,
+    AxisId::LeftTrigger, AxisId::RightTrigger,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SmoothingFilter {
+    Off,
+    // Exponential moving average, steady but lags the same on every movement
+    Ema,
+    // Smooths hard while the stick is still and backs off while it moves fast
+    OneEuro,
+}
+
+impl SmoothingFilter {
+    pub const ALL: [SmoothingFilter; 3] = [SmoothingFilter::Off, SmoothingFilter::Ema, SmoothingFilter::OneEuro];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SmoothingFilter::Off => "Off",
+            SmoothingFilter::Ema => "EMA",
+            SmoothingFilter::OneEuro => "One-Euro",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AxisSmoothing {
+    pub axis: AxisId,
+    // 0 leaves the axis alone, 1 is the heaviest smoothing
+    pub strength: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmoothingSettings {
+    pub filter: SmoothingFilter,
+    pub axes: Vec<AxisSmoothing>,
+}
+
+impl Default for SmoothingSettings {
+    fn default() -> Self {
+        Self {
+            filter: SmoothingFilter::Off,
+            axes: SMOOTHED_AXES.iter().map(|&axis| AxisSmoothing { axis, strength: 0.3 }).collect(),
+        }
+    }
+}
+
+impl SmoothingSettings {
+    fn strength(&self, axis: AxisId) -> f32 {
+        if self.filter == SmoothingFilter::Off {
+            return 0.0;
+        }
+        self.axes.iter().find(|smoothing| smoothing.axis == axis).map_or(0.0, |smoothing| smoothing.strength)
+    }
+
+    // Returns true when a setting changed
+    pub fn render(&mut self, ui: &Ui) -> bool {
+        let mut changed = false;
+        let mut index = SmoothingFilter::ALL.iter().position(|filter| *filter == self.filter).unwrap_or(0);
+        let names: Vec<&str> = SmoothingFilter::ALL.iter().map(|filter| filter.name()).collect();
+        ui.set_next_item_width(150.0);
+        if ui.combo_simple_string("Filter", &mut index, &names) {
+            self.filter = SmoothingFilter::ALL[index];
+            changed = true;
+        }
+        if self.filter == SmoothingFilter::Off {
+            return changed;
+        }
+
+        for smoothing in &mut self.axes {
+            ui.set_next_item_width(150.0);
+            changed |= ui.slider_config(smoothing.axis.name(), 0.0, 1.0)
+                .display_format("%.2f")
+                .build(&mut smoothing.strength);
+        }
+        changed
+    }
+}
+
+struct AxisFilter {
+    // Latest raw value from the Deck
+    target: f32,
+    value: f32,
+    derivative: f32,
+    last_step: Instant,
+}
+
+impl AxisFilter {
+    fn step(&mut self, filter: SmoothingFilter, strength: f32, now: Instant) {
+        let dt = now.duration_since(self.last_step).as_secs_f32().max(0.001);
+        self.last_step = now;
+
+        match filter {
+            SmoothingFilter::Off => self.value = self.target,
+            SmoothingFilter::Ema => {
+                let time_constant = (strength * MAX_EMA_TIME_CONSTANT).max(0.001);
+                self.value += (self.target - self.value) * (1.0 - (-dt / time_constant).exp());
+            }
+            SmoothingFilter::OneEuro => {
+                let derivative = (self.target - self.value) / dt;
+                self.derivative += (derivative - self.derivative) * one_euro_alpha(ONE_EURO_DERIVATIVE_CUTOFF_HZ, dt);
+                let min_cutoff = ONE_EURO_MAX_CUTOFF_HZ + (ONE_EURO_MIN_CUTOFF_HZ - ONE_EURO_MAX_CUTOFF_HZ) * strength;
+                let cutoff = min_cutoff + ONE_EURO_BETA * self.derivative.abs();
+                self.value += (self.target - self.value) * one_euro_alpha(cutoff, dt);
+            }
+        }
+
+        if (self.target - self.value).abs() < SETTLED {
+            self.value = self.target;
+        }
+    }
+}
+
+fn one_euro_alpha(cutoff_hz: f32, dt: f32) -> f32 {
+    let time_constant = 1.0 / (2.0 * PI * cutoff_hz);
+    1.0 / (1.0 + time_constant / dt)
+}
+
+// Per-axis filter state. The Deck only sends an axis when it changes, so a filter still catching
+// up is stepped on the server's timer through tick() until it settles.
+pub struct Smoother {
+    filters: HashMap<AxisId, AxisFilter>,
+}
+
+impl Smoother {
+    pub fn new() -> Self {
+        Self {
+            filters: HashMap::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.filters.clear();
+    }
+
+    pub fn filter(&mut self, settings: &SmoothingSettings, axis: AxisId, raw: f32, now: Instant) -> f32 {
+        let strength = settings.strength(axis);
+        if strength <= 0.0 {
+            self.filters.remove(&axis);
+            return raw;
+        }
+
+        let filter = self.filters.entry(axis).or_insert(AxisFilter {
+            target: raw,
+            value: raw,
+            derivative: 0.0,
+            last_step: now,
+        });
+        filter.target = raw;
+        // A stick or trigger that was let go goes straight back to rest, easing it back would only
+        // read as lag
+        if raw == 0.0 {
+            filter.value = 0.0;
+            filter.derivative = 0.0;
+            filter.last_step = now;
+            return 0.0;
+        }
+        filter.step(settings.filter, strength, now);
+        filter.value
+    }
+
+    // Updated values for axes that haven't caught up with the raw value yet
+    pub fn tick(&mut self, settings: &SmoothingSettings, now: Instant, timestamp: u64) -> Vec<AxisEvent> {
+        let mut axis_events = Vec::new();
+        self.filters.retain(|&axis, filter| {
+            if filter.value == filter.target {
+                return true;
+            }
+            let strength = settings.strength(axis);
+            filter.step(settings.filter, strength, now);
+            axis_events.push(AxisEvent { axis, value: filter.value, timestamp });
+            // Switched off while catching up, the raw value was just sent
+            strength > 0.0
+        });
+        axis_events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn smoothed_axes_catch_up_with_the_stick_and_snap_back_when_released() {
+        let ms = Duration::from_millis;
+        for filter in [SmoothingFilter::Ema, SmoothingFilter::OneEuro] {
+            let settings = SmoothingSettings { filter, ..Default::default() };
+            let mut smoother = Smoother::new();
+            let start = Instant::now();
+
+            assert_eq!(smoother.filter(&settings, AxisId::LeftStickX, 0.0, start), 0.0);
+            let mut value = smoother.filter(&settings, AxisId::LeftStickX, 0.8, start + ms(8));
+            assert!(value > 0.0 && value < 0.8, "{:?} didn't smooth, got {}", filter, value);
+
+            // Nothing more comes from the Deck while the stick is held still, the server's timer
+            // carries it the rest of the way
+            let mut step = 2;
+            loop {
+                let events = smoother.tick(&settings, start + ms(8 * step), step);
+                let Some(event) = events.first() else { break };
+                assert!(event.value >= value && event.value <= 0.8, "{:?} went from {} to {}", filter, value, event.value);
+                value = event.value;
+                step += 1;
+                assert!(step < 100, "{:?} never settled", filter);
+            }
+            assert_eq!(value, 0.8);
+
+            assert_eq!(smoother.filter(&settings, AxisId::LeftStickX, 0.0, start + ms(8 * step)), 0.0);
+            assert!(smoother.tick(&settings, start + ms(8 * step + 8), step).is_empty());
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::{bail, Context, Result};
+use imgui::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use crate::i18n;
+use crate::input_id::ButtonId;
+use crate::mapping::{ButtonRemap, MappingProfile};
+use crate::palette::{self, Status};
+
+// Where each Deck button sits in a Steam controller config, as a source and the input inside it
+const DECK_INPUTS: [(&str, &str, ButtonId); 16] = [
+    ("button_diamond", "button_a", ButtonId::South),
+    ("button_diamond", "button_b", ButtonId::East),
+    ("button_diamond", "button_x", ButtonId::West),
+    ("button_diamond", "button_y", ButtonId::North),
+    ("switch", "left_bumper", ButtonId::LeftBumper),
+    ("switch", "right_bumper", ButtonId::RightBumper),
+    ("switch", "button_menu", ButtonId::Start),
+    ("switch", "button_escape", ButtonId::Select),
+    ("left_trigger", "click", ButtonId::LeftTrigger),
+    ("right_trigger", "click", ButtonId::RightTrigger),
+    ("joystick", "click", ButtonId::LeftStick),
+    ("right_joystick", "click", ButtonId::RightStick),
+    ("dpad", "dpad_north", ButtonId::DPadUp),
+    ("dpad", "dpad_south", ButtonId::DPadDown),
+    ("dpad", "dpad_west", ButtonId::DPadLeft),
+    ("dpad", "dpad_east", ButtonId::DPadRight),
+];
+
+// The gamepad button behind Steam's "xinput_button <name>" bindings
+fn xinput_button(name: &str) -> Option<ButtonId> {
+    Some(match name.to_ascii_uppercase().as_str() {
+        "A" => ButtonId::South,
+        "B" => ButtonId::East,
+        "X" => ButtonId::West,
+        "Y" => ButtonId::North,
+        "SHOULDER_LEFT" => ButtonId::LeftBumper,
+        "SHOULDER_RIGHT" => ButtonId::RightBumper,
+        "TRIGGER_LEFT" => ButtonId::LeftTrigger,
+        "TRIGGER_RIGHT" => ButtonId::RightTrigger,
+        "JOYSTICK_LEFT" => ButtonId::LeftStick,
+        "JOYSTICK_RIGHT" => ButtonId::RightStick,
+        "START" => ButtonId::Start,
+        "SELECT" => ButtonId::Select,
+        "GUIDE" => ButtonId::Guide,
+        "DPAD_UP" => ButtonId::DPadUp,
+        "DPAD_DOWN" => ButtonId::DPadDown,
+        "DPAD_LEFT" => ButtonId::DPadLeft,
+        "DPAD_RIGHT" => ButtonId::DPadRight,
+        _ => return None,
+    })
+}
+
+enum Vdf {
+    Value(String),
+    // A list rather than a map, Steam repeats keys such as "group"
+    Block(Vec<(String, Vdf)>),
+}
+
+impl Vdf {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Vdf::Value(text) => Some(text),
+            Vdf::Block(_) => None,
+        }
+    }
+
+    // Every entry under key, Steam itself doesn't care about the case of keys
+    fn all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a Vdf> + 'a {
+        self.entries().iter().filter(move |(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, node)| node)
+    }
+
+    fn get(&self, key: &str) -> Option<&Vdf> {
+        self.entries().iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, node)| node)
+    }
+
+    fn entries(&self) -> &[(String, Vdf)] {
+        match self {
+            Vdf::Block(entries) => entries,
+            Vdf::Value(_) => &[],
+        }
+    }
+}
+
+// Reads the text format Steam writes its configs in: quoted or bare keys, each followed by a
+// value or a braced block. Comments and platform conditionals like [$WIN32] are skipped.
+fn parse_vdf(text: &str) -> Result<(String, Vdf)> {
+    let mut tokens = tokenize(text)?.into_iter();
+    let key = match tokens.next() {
+        Some(Token::Text(key)) => key,
+        _ => bail!("Expected a key at the start of the file"),
+    };
+    match tokens.next() {
+        Some(Token::Open) => Ok((key, parse_block(&mut tokens)?)),
+        _ => bail!("Expected a block after \"{}\"", key),
+    }
+}
+
+enum Token {
+    Text(String),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '{' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            '}' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '/' => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '[' => {
+                while chars.next().is_some_and(|c| c != ']') {}
+            }
+            '"' => {
+                chars.next();
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => token.push('\n'),
+                            Some('t') => token.push('\t'),
+                            Some(c) => token.push(c),
+                            None => bail!("Unterminated string"),
+                        },
+                        Some(c) => token.push(c),
+                        None => bail!("Unterminated string"),
+                    }
+                }
+                tokens.push(Token::Text(token));
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !matches!(c, '{' | '}' | '"')) {
+                    token.push(c);
+                }
+                tokens.push(Token::Text(token));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_block(tokens: &mut impl Iterator<Item = Token>) -> Result<Vdf> {
+    let mut entries = Vec::new();
+    loop {
+        let key = match tokens.next() {
+            Some(Token::Text(key)) => key,
+            Some(Token::Close) => return Ok(Vdf::Block(entries)),
+            Some(Token::Open) => bail!("Expected a key, found {{"),
+            None => bail!("Missing }} at the end of the file"),
+        };
+        let node = match tokens.next() {
+            Some(Token::Text(value)) => Vdf::Value(value),
+            Some(Token::Open) => parse_block(tokens)?,
+            _ => bail!("Expected a value or block after \"{}\"", key),
+        };
+        entries.push((key, node));
+    }
+}
+
+
+// A profile remapping whatever a Deck controller config sends as a different gamepad button.
+// Only the first action set's gamepad bindings carry over, keyboard, mouse and game action
+// bindings have nothing to map to on the virtual controller.
+pub fn import_profile(path: &Path) -> Result<MappingProfile> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let (key, root) = parse_vdf(&text).with_context(|| format!("{} is not a VDF file", path.display()))?;
+    if !key.eq_ignore_ascii_case("controller_mappings") {
+        bail!("{} is not a Steam controller config", path.display());
+    }
+
+    let groups: HashMap<&str, &Vdf> = root.all("group")
+        .filter_map(|group| Some((group.get("id")?.as_str()?, group)))
+        .collect();
+    // Mode shifts and inactive groups don't count
+    let mut sources = HashMap::new();
+    if let Some(bindings) = root.get("preset").and_then(|preset| preset.get("group_source_bindings")) {
+        for (id, binding) in bindings.entries() {
+            let words: Vec<&str> = binding.as_str().unwrap_or_default().split_whitespace().collect();
+            if let ([source, "active"], Some(group)) = (words.as_slice(), groups.get(id.as_str())) {
+                sources.insert(source.to_string(), *group);
+            }
+        }
+    }
+
+    let mut remaps = Vec::new();
+    for (source, input, from) in DECK_INPUTS {
+        let bindings = sources.get(source)
+            .and_then(|group| group.get("inputs"))
+            .and_then(|inputs| inputs.get(input))
+            .and_then(|input| input.get("activators"))
+            .and_then(|activators| activators.get("Full_Press"))
+            .and_then(|press| press.get("bindings"));
+        // "xinput_button <name>, <title>", the title part is optional
+        let to = bindings.into_iter()
+            .flat_map(|bindings| bindings.all("binding"))
+            .filter_map(Vdf::as_str)
+            .filter_map(|binding| binding.split(',').next()?.trim().strip_prefix("xinput_button "))
+            .find_map(|name| xinput_button(name.trim()));
+        if let Some(to) = to.filter(|&to| to != from) {
+            remaps.push(ButtonRemap { from, to });
+        }
+    }
+
+    let name = root.get("title").and_then(Vdf::as_str).map(str::trim).filter(|title| !title.is_empty())
+        .or_else(|| path.file_stem().and_then(|stem| stem.to_str()))
+        .unwrap_or("Steam Config")
+        .to_string();
+    Ok(MappingProfile {
+        name,
+        remaps,
+        ..Default::default()
+    })
+}
+
+pub struct SteamImportWindow {
+    pub open: bool,
+    path: String,
+    // Result of the last import, shown under the button
+    status: Option<(bool, String)>,
+}
+
+impl SteamImportWindow {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            path: String::new(),
+            status: None,
+        }
+    }
+
+    // Returns the imported profile, the caller adds and activates it
+    pub fn render(&mut self, ui: &Ui) -> Option<MappingProfile> {
+        if !self.open {
+            return None;
+        }
+
+        let mut imported = None;
+        let mut open = self.open;
+        ui.window(i18n::window_title("Import Steam Config"))
+            .size([450.0, 160.0], Condition::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                ui.text_wrapped("Creates a mapping profile from a Steam controller config for the Deck. Buttons the config binds to another gamepad button are remapped the same way.");
+                ui.separator();
+
+                ui.input_text("File", &mut self.path)
+                    .hint("Path to a .vdf file")
+                    .build();
+                if ui.button("Import") {
+                    let path = PathBuf::from(self.path.trim());
+                    self.status = Some(match import_profile(&path) {
+                        Ok(profile) => {
+                            log::info!("Imported {} remaps from {}", profile.remaps.len(), path.display());
+                            let status = format!("Imported '{}' with {} remaps", profile.name, profile.remaps.len());
+                            imported = Some(profile);
+                            (true, status)
+                        }
+                        Err(e) => {
+                            log::error!("Failed to import Steam controller config: {:#}", e);
+                            (false, format!("Import failed: {:#}", e))
+                        }
+                    });
+                }
+
+                if let Some((ok, ref status)) = self.status {
+                    palette::status_text(ui, if ok { Status::Good } else { Status::Bad }, status);
+                }
+            });
+        self.open = open;
+
+        imported
+    }
+}
@@ -0,0 +1,97 @@
+use imgui::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crate::palette::{self, Status};
+
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+const FADE_DURATION: Duration = Duration::from_millis(500);
+const MAX_TOASTS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToastKind {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+struct Toast {
+    id: u64,
+    kind: ToastKind,
+    message: String,
+    created: Instant,
+}
+
+pub struct Toasts {
+    toasts: VecDeque<Toast>,
+    next_id: u64,
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        Self {
+            toasts: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn push(&mut self, kind: ToastKind, message: impl Into<String>) {
+        self.toasts.push_back(Toast {
+            id: self.next_id,
+            kind,
+            message: message.into(),
+            created: Instant::now(),
+        });
+        self.next_id += 1;
+
+        while self.toasts.len() > MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+    }
+
+    // Stacks the active toasts upwards from the bottom-right corner of the main window
+    pub fn render(&mut self, ui: &Ui) {
+        self.toasts.retain(|toast| toast.created.elapsed() < TOAST_DURATION);
+
+        let [width, height] = ui.io().display_size;
+        let mut y = height - 10.0;
+
+        for toast in self.toasts.iter().rev() {
+            let remaining = TOAST_DURATION.saturating_sub(toast.created.elapsed());
+            let alpha = (remaining.as_secs_f32() / FADE_DURATION.as_secs_f32()).min(1.0);
+
+            let status = match toast.kind {
+                ToastKind::Info => None,
+                ToastKind::Success => Some(Status::Good),
+                ToastKind::Warning => Some(Status::Warning),
+                ToastKind::Error => Some(Status::Bad),
+            };
+            let (color, text) = match status {
+                Some(status) => {
+                    let [r, g, b, _] = palette::color(status);
+                    ([r, g, b, alpha], format!("{} {}", status.marker(), toast.message))
+                }
+                None => ([0.8, 0.8, 0.8, alpha], toast.message.clone()),
+            };
+
+            let mut toast_height = 0.0;
+            ui.window(format!("##toast_{}", toast.id))
+                .position([width - 10.0, y], Condition::Always)
+                .position_pivot([1.0, 1.0])
+                .bg_alpha(0.8 * alpha)
+                .flags(WindowFlags::NO_DECORATION
+                    | WindowFlags::ALWAYS_AUTO_RESIZE
+                    | WindowFlags::NO_INPUTS
+                    | WindowFlags::NO_NAV
+                    | WindowFlags::NO_FOCUS_ON_APPEARING
+                    | WindowFlags::NO_SAVED_SETTINGS)
+                .build(|| {
+                    ui.text_colored(color, &text);
+                    toast_height = ui.window_size()[1];
+                });
+
+            y -= toast_height + 5.0;
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use imgui::*;
+use std::collections::HashMap;
+use std::time::Instant;
+use crate::i18n;
+use crate::{AxisId, ButtonId, ControllerInputData};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortOrder {
+    NameAscending,
+    NameDescending,
+    ValueAscending,
+    ValueDescending,
+}
+
+pub struct UsageStats {
+    button_presses: HashMap<ButtonId, u64>,
+    button_states: HashMap<ButtonId, bool>,
+    axis_travel: HashMap<AxisId, f64>,
+    axis_values: HashMap<AxisId, f32>,
+    session_start: Instant,
+    sort_orders: HashMap<String, SortOrder>,
+}
+
+impl UsageStats {
+    pub fn new() -> Self {
+        Self {
+            button_presses: HashMap::new(),
+            button_states: HashMap::new(),
+            axis_travel: HashMap::new(),
+            axis_values: HashMap::new(),
+            session_start: Instant::now(),
+            sort_orders: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, data: &ControllerInputData) {
+        for button_event in &data.button_events {
+            // Sync packets repeat the current state, so only count released -> pressed transitions
+            let was_pressed = self.button_states.insert(button_event.button, button_event.pressed).unwrap_or(false);
+            let count = self.button_presses.entry(button_event.button).or_insert(0);
+            if button_event.pressed && !was_pressed {
+                *count += 1;
+            }
+        }
+
+        for axis_event in &data.axis_events {
+            let previous = self.axis_values.insert(axis_event.axis, axis_event.value).unwrap_or(0.0);
+            *self.axis_travel.entry(axis_event.axis).or_insert(0.0) += (axis_event.value - previous).abs() as f64;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        let sort_orders = std::mem::take(&mut self.sort_orders);
+        *self = Self::new();
+        self.sort_orders = sort_orders;
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        ui.window(i18n::window_title("Usage Statistics"))
+            .size([400.0, 500.0], Condition::FirstUseEver)
+            .build(|| {
+                ui.text(&format!("Session: {}s", self.session_start.elapsed().as_secs()));
+                ui.same_line();
+                if ui.button("Reset Statistics") {
+                    self.reset();
+                }
+                ui.separator();
+
+                let buttons: Vec<(String, f64)> = self.button_presses.iter()
+                    .map(|(button, &count)| (button.to_string(), count as f64))
+                    .collect();
+                self.render_heatmap_table(ui, "button_usage", "Button", "Presses", buttons, 0);
+
+                ui.separator();
+
+                let axes: Vec<(String, f64)> = self.axis_travel.iter()
+                    .map(|(axis, &travel)| (axis.to_string(), travel))
+                    .collect();
+                self.render_heatmap_table(ui, "axis_usage", "Axis", "Travel", axes, 2);
+            });
+    }
+
+    // Two-column table sorted by clicking a header, values tinted from blue (unused) to red (most used)
+    fn render_heatmap_table(&mut self, ui: &Ui, id: &str, name_header: &str, value_header: &str, mut rows: Vec<(String, f64)>, precision: usize) {
+        let sort = self.sort_orders.entry(id.to_string()).or_insert(SortOrder::ValueDescending);
+
+        ui.columns(2, id, true);
+        let name_label = match sort {
+            SortOrder::NameAscending => format!("{} ^", name_header),
+            SortOrder::NameDescending => format!("{} v", name_header),
+            _ => name_header.to_string(),
+        };
+        if ui.selectable(&format!("{}##{}_name", name_label, id)) {
+            *sort = if *sort == SortOrder::NameAscending { SortOrder::NameDescending } else { SortOrder::NameAscending };
+        }
+        ui.next_column();
+        let value_label = match sort {
+            SortOrder::ValueAscending => format!("{} ^", value_header),
+            SortOrder::ValueDescending => format!("{} v", value_header),
+            _ => value_header.to_string(),
+        };
+        if ui.selectable(&format!("{}##{}_value", value_label, id)) {
+            *sort = if *sort == SortOrder::ValueDescending { SortOrder::ValueAscending } else { SortOrder::ValueDescending };
+        }
+        ui.next_column();
+        ui.separator();
+
+        rows.sort_by(|a, b| match sort {
+            SortOrder::NameAscending => a.0.cmp(&b.0),
+            SortOrder::NameDescending => b.0.cmp(&a.0),
+            SortOrder::ValueAscending => a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal),
+            SortOrder::ValueDescending => b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal),
+        });
+
+        let max_value = rows.iter().map(|(_, value)| *value).fold(0.0, f64::max);
+
+        for (name, value) in &rows {
+            ui.text(name);
+            ui.next_column();
+            let heat = if max_value > 0.0 { (*value / max_value) as f32 } else { 0.0 };
+            ui.text_colored([0.3 + 0.7 * heat, 0.4, 1.0 - 0.7 * heat, 1.0], &format!("{:.*}", precision, value));
+            ui.next_column();
+        }
+
+        ui.columns(1, "", false);
+    }
+}
@@ -1,66 +1,355 @@
 use anyhow::Result;
-use vigem_client::{Client, Xbox360Wired};
+use serde::{Deserialize, Serialize};
+use vigem_client::{Client, DS4Report, DualShock4Wired, Xbox360Wired};
 use std::collections::HashMap;
-use crate::ControllerInputData;
+use std::time::Instant;
+use crate::{AxisId, ButtonId, ControllerInputData};
+use crate::protocol::{AxisEvent, ButtonEvent};
+use crate::input_filter::{FilterChain, PadState};
+use crate::mapping::{MappingEngine, MappingProfile, SplitSettings};
+use crate::scripting::InputScript;
 
-pub struct VirtualController {
+// What a client's input turns into on the PC, picked per client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PadType {
+    // The pad every client shares unless paired otherwise
+    #[default]
+    Xbox360,
+    // A pad of the client's own, for games that prefer PlayStation prompts
+    DualShock4,
+    // Shows up in the UI but reaches no pad
+    Spectator,
+}
+
+impl PadType {
+    pub const ALL: [PadType; 3] = [PadType::Xbox360, PadType::DualShock4, PadType::Spectator];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PadType::Xbox360 => "Xbox 360",
+            PadType::DualShock4 => "DualShock 4",
+            PadType::Spectator => "None (Spectator)",
+        }
+    }
+}
+
+// Creates the pad for a client paired with its own, NullBackend ones where the bus isn't wanted
+pub type PadFactory = fn(PadType) -> Result<VirtualController>;
+
+// Whatever the virtual pad is plugged into: the ViGEm bus on the host, nothing in tests
+pub trait PadBackend: Send {
+    // The preferred slot is 0-based and only a wish, Windows hands out the lowest free one
+    fn plug_in(&mut self, preferred_slot: Option<u32>) -> Result<()>;
+    fn unplug(&mut self) -> Result<()>;
+    // The XInput slot the pad ended up in
+    fn slot(&self) -> Option<u32>;
+    fn update(&mut self, gamepad: &vigem_client::XGamepad) -> Result<()>;
+    // Another pad on the same bus, for splitting the Deck between two players
+    fn create_another(&self) -> Result<Box<dyn PadBackend>>;
+    // Plugs in again after the bus lost the pad, e.g. when its driver restarted or the PC woke
+    // from sleep
+    fn replug(&mut self, preferred_slot: Option<u32>) -> Result<()> {
+        let _ = self.unplug();
+        self.plug_in(preferred_slot)
+    }
+}
+
+enum Target {
+    Xbox360(Xbox360Wired<Client>),
+    DualShock4(DualShock4Wired<Client>),
+}
+
+pub struct ViGEmBackend {
     client: Client,
-    target: Option<Xbox360Wired<Client>>,
-    gamepad_state: vigem_client::XGamepad,
-    button_states: HashMap<String, bool>,
-    axis_states: HashMap<String, f32>,
+    pad_type: PadType,
+    target: Option<Target>,
+    slot: Option<u32>,
 }
 
-impl VirtualController {
-    pub fn new() -> Result<Self> {
-        let client = Client::connect()?;
-        
+impl ViGEmBackend {
+    pub fn connect(pad_type: PadType) -> Result<Self> {
         Ok(Self {
-            client,
+            client: Client::connect()?,
+            pad_type,
             target: None,
-            gamepad_state: vigem_client::XGamepad::default(),
-            button_states: HashMap::new(),
-            axis_states: HashMap::new(),
+            slot: None,
         })
     }
 
-    pub fn create_controller(&mut self) -> Result<()> {
-        // Create a new target and get its ID
+    fn plug_in_target(&self) -> Result<Xbox360Wired<Client>> {
         let mut target = Xbox360Wired::new(self.client.try_clone()?, vigem_client::TargetId::XBOX360_WIRED);
-        
-        // Connect the target
         target.plugin()?;
-        
-        self.target = Some(target);
+        target.wait_ready()?;
+        Ok(target)
+    }
+}
+
+impl PadBackend for ViGEmBackend {
+    fn plug_in(&mut self, preferred_slot: Option<u32>) -> Result<()> {
+        // XInput slots are an Xbox thing, a DualShock 4 just gets plugged in
+        if self.pad_type == PadType::DualShock4 {
+            let mut target = DualShock4Wired::new(self.client.try_clone()?, vigem_client::TargetId::DUALSHOCK4_WIRED);
+            target.plugin()?;
+            target.wait_ready()?;
+            self.target = Some(Target::DualShock4(target));
+            return Ok(());
+        }
+
+        let mut target = self.plug_in_target()?;
+
+        // ViGEm can't pick a slot. Placeholder pads fill the free slots below the wanted one
+        // until the real pad lands on it, then go away again. A slot held by another controller
+        // can't be had, the pad then keeps whatever it got.
+        let mut placeholders = Vec::new();
+        let mut result = Ok(());
+        if let Some(preferred_slot) = preferred_slot {
+            while placeholders.len() < 4 && target.get_user_index().is_ok_and(|slot| slot < preferred_slot) {
+                match self.plug_in_target() {
+                    Ok(next) => placeholders.push(std::mem::replace(&mut target, next)),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+        }
+        for mut placeholder in placeholders {
+            let _ = placeholder.unplug();
+        }
+        if let Err(e) = result {
+            let _ = target.unplug();
+            return Err(e);
+        }
+
+        self.slot = target.get_user_index().ok();
+        self.target = Some(Target::Xbox360(target));
+        Ok(())
+    }
+
+    fn unplug(&mut self) -> Result<()> {
+        self.slot = None;
+        match self.target.take() {
+            Some(Target::Xbox360(mut target)) => target.unplug()?,
+            Some(Target::DualShock4(mut target)) => target.unplug()?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn slot(&self) -> Option<u32> {
+        self.slot
+    }
+
+    fn update(&mut self, gamepad: &vigem_client::XGamepad) -> Result<()> {
+        match &mut self.target {
+            Some(Target::Xbox360(target)) => target.update(gamepad)?,
+            Some(Target::DualShock4(target)) => target.update(&ds4_report(gamepad))?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn create_another(&self) -> Result<Box<dyn PadBackend>> {
+        Ok(Box::new(Self {
+            client: self.client.try_clone()?,
+            pad_type: self.pad_type,
+            target: None,
+            slot: None,
+        }))
+    }
+
+    // The old connection to the bus is as dead as the pad after a driver restart
+    fn replug(&mut self, preferred_slot: Option<u32>) -> Result<()> {
+        let _ = self.unplug();
+        self.client = Client::connect()?;
+        self.plug_in(preferred_slot)
+    }
+}
+
+// Accepts every report and drives nothing, so the input path can run without the bus driver
+pub struct NullBackend;
+
+impl PadBackend for NullBackend {
+    fn plug_in(&mut self, _preferred_slot: Option<u32>) -> Result<()> {
+        Ok(())
+    }
+
+    fn unplug(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn slot(&self) -> Option<u32> {
+        None
+    }
+
+    fn update(&mut self, _gamepad: &vigem_client::XGamepad) -> Result<()> {
+        Ok(())
+    }
+
+    fn create_another(&self) -> Result<Box<dyn PadBackend>> {
+        Ok(Box::new(NullBackend))
+    }
+}
+
+pub struct VirtualController {
+    backend: Box<dyn PadBackend>,
+    plugged_in: bool,
+    // Runs ahead of the mapping profile, so profiles see what the script made of the input
+    script: Option<InputScript>,
+    mapping: MappingEngine,
+    // Deadzones and the profile's filters, between the mapping and the pads
+    filters: FilterChain,
+    // A controller attached to the PC, merged with the Deck's input on every update
+    local_state: Option<vigem_client::XGamepad>,
+    split: SplitSettings,
+    // Player 2's pad while the profile splits the Deck, plugged in on demand
+    second_pad: Option<Box<dyn PadBackend>>,
+    // 0-based XInput slots to aim for when plugging in, None takes the first free one
+    player_slots: [Option<u32>; 2],
+    // What the mapping profile made of the Deck's input, and that after the filters. The pads are
+    // rebuilt from the filtered state on every update.
+    mapped: PadState,
+    output: PadState,
+    // Set by release_all, all input is ignored until unblock
+    blocked: bool,
+    // Why the bus stopped taking updates. Nothing is sent to the pads until they're plugged in
+    // again, the state keeps following the input meanwhile.
+    fault: Option<String>,
+}
+
+impl VirtualController {
+    pub fn new(pad_type: PadType) -> Result<Self> {
+        Ok(Self::with_backend(Box::new(ViGEmBackend::connect(pad_type)?)))
+    }
+
+    pub fn with_backend(backend: Box<dyn PadBackend>) -> Self {
+        Self {
+            backend,
+            plugged_in: false,
+            script: None,
+            mapping: MappingEngine::new(),
+            filters: FilterChain::new(),
+            local_state: None,
+            split: SplitSettings::default(),
+            second_pad: None,
+            player_slots: [None, None],
+            mapped: PadState::default(),
+            output: PadState::default(),
+            blocked: false,
+            fault: None,
+        }
+    }
+
+    pub fn create_controller(&mut self) -> Result<()> {
+        self.backend.plug_in(self.player_slots[0])?;
+        self.plugged_in = true;
+        self.fault = None;
         
         log::info!("Virtual Xbox 360 controller created successfully");
+        self.update_second_pad();
         Ok(())
     }
 
     pub fn disconnect_controller(&mut self) -> Result<()> {
-        if let Some(mut target) = self.target.take() {
-            target.unplug()?;
+        if self.plugged_in {
+            self.backend.unplug()?;
+            self.plugged_in = false;
             log::info!("Virtual Xbox 360 controller disconnected");
         }
+        self.update_second_pad();
         Ok(())
     }
 
-    pub fn process_controller_input(&mut self, input: ControllerInputData) -> Result<()> {
-        if self.target.is_none() {
-            return Ok(());
-        }
+    // For the watchdog once the pads stopped taking updates, they come back with the current state
+    pub fn replug(&mut self) -> Result<()> {
+        // Player 2's pad is recreated through the new connection
+        self.second_pad = None;
+        self.backend.replug(self.player_slots[0])?;
+        self.plugged_in = true;
+        self.fault = None;
+        log::info!("Virtual controller plugged back in");
+        self.update_second_pad();
+        self.update_virtual_controller()
+    }
 
-        // Process button events
-        for button_event in input.button_events {
-            self.button_states.insert(button_event.button.clone(), button_event.pressed);
-            self.update_button_state(&button_event.button, button_event.pressed);
+    pub fn fault(&self) -> Option<&str> {
+        self.fault.as_deref()
+    }
+
+    pub fn set_profile(&mut self, profile: MappingProfile) {
+        self.split = profile.split.clone();
+        self.filters = FilterChain::from_profile(&profile);
+        self.mapping.set_profile(profile);
+        self.update_second_pad();
+    }
+
+    pub fn profile(&self) -> &MappingProfile {
+        self.mapping.profile()
+    }
+
+    pub fn set_script(&mut self, script: Option<InputScript>) -> Result<()> {
+        self.script = script;
+        // Whatever the old script was holding down is let go
+        self.reset_state()
+    }
+
+    pub fn script_error(&self) -> Option<String> {
+        self.script.as_ref().and_then(|script| script.error().map(str::to_string))
+    }
+
+    // Plugs player 2's pad in or out to match the profile. Failing to is logged, player 1 carries on.
+    fn update_second_pad(&mut self) {
+        let wanted = self.plugged_in && self.split.enabled;
+        if wanted && self.second_pad.is_none() {
+            let second_pad = self.backend.create_another().and_then(|mut backend| {
+                backend.plug_in(self.player_slots[1])?;
+                Ok(backend)
+            });
+            match second_pad {
+                Ok(backend) => {
+                    self.second_pad = Some(backend);
+                    log::info!("Second virtual Xbox 360 controller created for split mode");
+                }
+                Err(e) => log::error!("Failed to create the second virtual controller: {}", e),
+            }
+        } else if !wanted {
+            if let Some(mut backend) = self.second_pad.take() {
+                if let Err(e) = backend.unplug() {
+                    log::error!("Failed to unplug the second virtual controller: {}", e);
+                }
+                log::info!("Second virtual Xbox 360 controller disconnected");
+            }
         }
+    }
 
-        // Process axis events
-        for axis_event in input.axis_events {
-            self.axis_states.insert(axis_event.axis.clone(), axis_event.value);
-            self.update_axis_state(&axis_event.axis, axis_event.value);
+    // Takes effect the next time the pads are plugged in
+    pub fn set_player_slots(&mut self, player_slots: [Option<u32>; 2]) {
+        self.player_slots = player_slots;
+    }
+
+    // The XInput slots the pads actually got, for player 1 and player 2
+    pub fn assigned_slots(&self) -> [Option<u32>; 2] {
+        [self.backend.slot(), self.second_pad.as_ref().and_then(|second_pad| second_pad.slot())]
+    }
+
+    pub fn is_split(&self) -> bool {
+        self.second_pad.is_some()
+    }
+
+    pub fn raw_sticks(&self) -> [[f32; 2]; 2] {
+        self.mapping.raw_sticks()
+    }
+
+    pub fn process_controller_input(&mut self, input: ControllerInputData) -> Result<()> {
+        if !self.plugged_in || self.blocked {
+            return Ok(());
         }
+        let input = match &mut self.script {
+            Some(script) => script.process(input),
+            None => input,
+        };
+        let input = self.mapping.map(input);
+        self.apply_events(input.button_events, input.axis_events);
 
         // Update the virtual controller
         self.update_virtual_controller()?;
@@ -68,90 +357,133 @@ impl VirtualController {
         Ok(())
     }
 
-    fn update_button_state(&mut self, button: &str, pressed: bool) {
-        use vigem_client::XButtons;
-
-        let button_flag = match button {
-            "A (South)" => XButtons::A,
-            "B (East)" => XButtons::B,
-            "X (West)" => XButtons::X,
-            "Y (North)" => XButtons::Y,
-            "LB" => XButtons::LB,
-            "RB" => XButtons::RB,
-            "Select" => XButtons::BACK,
-            "Start" => XButtons::START,
-            "Guide" => XButtons::GUIDE,
-            "LSB" => XButtons::LTHUMB,
-            "RSB" => XButtons::RTHUMB,
-            "D-Pad Up" => XButtons::UP,
-            "D-Pad Down" => XButtons::DOWN,
-            "D-Pad Left" => XButtons::LEFT,
-            "D-Pad Right" => XButtons::RIGHT,
-            // Handle RT/LT as digital buttons too
-            "RT [ID: 7] - Fire" | "LT [ID: 6] - Aim" => {
-                // For RT/LT, set the trigger to 100% when pressed, 0% when released
-
-                if button.contains("RT") {
-                    self.gamepad_state.right_trigger = if pressed { 255 } else { 0 };
-                    log::info!("RT digital button: {} -> trigger value: {}", pressed, self.gamepad_state.right_trigger);
-                } else if button.contains("LT") {
-                    self.gamepad_state.left_trigger = if pressed { 255 } else { 0 };
-                    log::info!("LT digital button: {} -> trigger value: {}", pressed, self.gamepad_state.left_trigger);
-                }
-                return; // Don't process as normal button
-            }
-            _ => return,
-        };
-        if pressed {
-            self.gamepad_state.buttons.raw |= button_flag;
-        } else {
-            self.gamepad_state.buttons.raw &= !button_flag;
+    fn apply_events(&mut self, button_events: Vec<ButtonEvent>, axis_events: Vec<AxisEvent>) {
+        for button_event in button_events {
+            self.mapped.buttons.insert(button_event.button, button_event.pressed);
+        }
+        for axis_event in axis_events {
+            self.mapped.axes.insert(axis_event.axis, axis_event.value);
         }
     }
 
-    fn update_axis_state(&mut self, axis: &str, value: f32) {
-        match axis {
-            "Left Stick X" => {
-                self.gamepad_state.thumb_lx = (value * 32767.0) as i16;
-            }
-            "Left Stick Y" => {
-                // Don't invert Y axis - use raw value
-                self.gamepad_state.thumb_ly = (value * 32767.0) as i16;
-            }
-            "Right Stick X" => {
-                self.gamepad_state.thumb_rx = (value * 32767.0) as i16;
-            }
-            "Right Stick Y" => {
-                // Don't invert Y axis - use raw value
-                self.gamepad_state.thumb_ry = (value * 32767.0) as i16;
+    // What each pad reports for the filtered state, with what the split gives player 2 on the second pad
+    fn build_pads(&self) -> [vigem_client::XGamepad; 2] {
+        let split = self.second_pad.is_some();
+        let mut pads = [vigem_client::XGamepad::default(); 2];
+        for (&axis, &value) in &self.output.axes {
+            match self.split.player_two_axis(axis).filter(|_| split) {
+                Some(axis) => apply_axis(&mut pads[1], axis, value),
+                None => apply_axis(&mut pads[0], axis, value),
             }
-            "LT Axis" => {
-                self.gamepad_state.left_trigger = (value * 255.0) as u8;
-            }
-            "RT Axis" => {
-                self.gamepad_state.right_trigger = (value * 255.0) as u8;
+        }
+        // After the axes, so a digital trigger press isn't undone by the analog value
+        for (&button, _) in self.output.buttons.iter().filter(|(_, pressed)| **pressed) {
+            match self.split.player_two_button(button).filter(|_| split) {
+                Some(button) => apply_button(&mut pads[1], button, true),
+                None => apply_button(&mut pads[0], button, true),
             }
-            _ => {}
         }
+        pads
+    }
+
+    // Input from a controller attached to the PC. It skips the mapping profile, that's for the Deck.
+    pub fn process_local_input(&mut self, input: ControllerInputData) -> Result<()> {
+        if self.blocked {
+            return Ok(());
+        }
+        let local_state = self.local_state.get_or_insert_with(vigem_client::XGamepad::default);
+        for button_event in input.button_events {
+            apply_button(local_state, button_event.button, button_event.pressed);
+        }
+        for axis_event in input.axis_events {
+            apply_axis(local_state, axis_event.axis, axis_event.value);
+        }
+        self.update_virtual_controller()
+    }
+
+    pub fn clear_local_input(&mut self) -> Result<()> {
+        if self.local_state.take().is_some() {
+            self.update_virtual_controller()?;
+        }
+        Ok(())
+    }
+
+    // Sends the repeats of held repeat buttons that are due and keeps time-based filters like
+    // turbo going
+    pub fn tick(&mut self) -> Result<()> {
+        if !self.plugged_in || self.blocked {
+            return Ok(());
+        }
+        let (button_events, axis_events) = self.mapping.tick(Instant::now());
+        if button_events.is_empty() && axis_events.is_empty() && !self.filters.needs_tick() {
+            return Ok(());
+        }
+        self.apply_events(button_events, axis_events);
+        self.update_virtual_controller()
+    }
+
+    // Releases every button and centers every axis on the virtual pad
+    pub fn reset_state(&mut self) -> Result<()> {
+        self.mapping.reset();
+        self.mapped = PadState::default();
+        self.update_virtual_controller()
+    }
+
+    // For a stuck input wrecking a game: lets go of everything, the PC's own controller included,
+    // and ignores input from anywhere until unblock
+    pub fn release_all(&mut self) -> Result<()> {
+        self.blocked = true;
+        self.local_state = None;
+        self.reset_state()
+    }
+
+    // Starts from a clean state, nothing that arrived while blocked is replayed
+    pub fn unblock(&mut self) {
+        self.blocked = false;
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        self.blocked
     }
 
     fn update_virtual_controller(&mut self) -> Result<()> {
-        if let Some(target) = &mut self.target {
-            target.update(&self.gamepad_state)?;
+        self.output = self.filters.process(self.mapped.clone(), Instant::now());
+        let [first_state, second_state] = self.build_pads();
+        if self.fault.is_some() {
+            return Ok(());
+        }
+        if self.plugged_in {
+            let state = match &self.local_state {
+                Some(local_state) => merge_gamepads(&first_state, local_state),
+                None => first_state,
+            };
+            let result = self.backend.update(&state);
+            self.check_fault(result)?;
+        }
+        if let Some(second_pad) = &mut self.second_pad {
+            let result = second_pad.update(&second_state);
+            self.check_fault(result)?;
         }
         Ok(())
     }
 
-    pub fn get_button_states(&self) -> &HashMap<String, bool> {
-        &self.button_states
+    fn check_fault(&mut self, result: Result<()>) -> Result<()> {
+        if let Err(e) = &result {
+            self.fault = Some(e.to_string());
+        }
+        result
     }
 
-    pub fn get_axis_states(&self) -> &HashMap<String, f32> {
-        &self.axis_states
+    pub fn get_button_states(&self) -> &HashMap<ButtonId, bool> {
+        &self.output.buttons
+    }
+
+    pub fn get_axis_states(&self) -> &HashMap<AxisId, f32> {
+        &self.output.axes
     }
 
     pub fn is_connected(&self) -> bool {
-        self.target.is_some()
+        self.plugged_in
     }
 }
 
@@ -159,8 +491,8 @@ impl std::fmt::Debug for VirtualController {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("VirtualController")
             .field("is_connected", &self.is_connected())
-            .field("button_states", &self.button_states)
-            .field("axis_states", &self.axis_states)
+            .field("button_states", &self.output.buttons)
+            .field("axis_states", &self.output.axes)
             .finish()
     }
 }
@@ -170,3 +502,145 @@ impl Drop for VirtualController {
         let _ = self.disconnect_controller();
     }
 }
+
+// Buttons held on either side stay held, each trigger and stick follows whichever side pushes it
+// further, so the other player can step in without the Deck letting go first
+pub fn merge_gamepads(a: &vigem_client::XGamepad, b: &vigem_client::XGamepad) -> vigem_client::XGamepad {
+    let stick = |ax: i16, ay: i16, bx: i16, by: i16| {
+        let magnitude = |x: i16, y: i16| (x as i32).pow(2) + (y as i32).pow(2);
+        if magnitude(ax, ay) >= magnitude(bx, by) { (ax, ay) } else { (bx, by) }
+    };
+    let (thumb_lx, thumb_ly) = stick(a.thumb_lx, a.thumb_ly, b.thumb_lx, b.thumb_ly);
+    let (thumb_rx, thumb_ry) = stick(a.thumb_rx, a.thumb_ry, b.thumb_rx, b.thumb_ry);
+    let mut merged = *a;
+    merged.buttons.raw = a.buttons.raw | b.buttons.raw;
+    merged.left_trigger = a.left_trigger.max(b.left_trigger);
+    merged.right_trigger = a.right_trigger.max(b.right_trigger);
+    merged.thumb_lx = thumb_lx;
+    merged.thumb_ly = thumb_ly;
+    merged.thumb_rx = thumb_rx;
+    merged.thumb_ry = thumb_ry;
+    merged
+}
+
+// The same state as a DualShock 4 report: face buttons by position, the D-Pad as a hat,
+// sticks centered on 0x80 with Y counting downwards
+pub fn ds4_report(gamepad: &vigem_client::XGamepad) -> DS4Report {
+    use vigem_client::XButtons;
+
+    let held = |flag: u16| gamepad.buttons.raw & flag != 0;
+    let x = held(XButtons::RIGHT) as i8 - held(XButtons::LEFT) as i8;
+    let y = held(XButtons::UP) as i8 - held(XButtons::DOWN) as i8;
+    let hat = match (x, y) {
+        (0, 1) => 0,
+        (1, 1) => 1,
+        (1, 0) => 2,
+        (1, -1) => 3,
+        (0, -1) => 4,
+        (-1, -1) => 5,
+        (-1, 0) => 6,
+        (-1, 1) => 7,
+        _ => 8,
+    };
+    let bits = [
+        (XButtons::X, 1 << 4),
+        (XButtons::A, 1 << 5),
+        (XButtons::B, 1 << 6),
+        (XButtons::Y, 1 << 7),
+        (XButtons::LB, 1 << 8),
+        (XButtons::RB, 1 << 9),
+        (XButtons::BACK, 1 << 12),
+        (XButtons::START, 1 << 13),
+        (XButtons::LTHUMB, 1 << 14),
+        (XButtons::RTHUMB, 1 << 15),
+    ];
+    let mut buttons = bits.iter()
+        .filter(|(flag, _)| held(*flag))
+        .fold(hat, |buttons, (_, bit)| buttons | bit);
+    // The DualShock 4 has digital trigger bits next to the analog values
+    if gamepad.left_trigger > 0 {
+        buttons |= 1 << 10;
+    }
+    if gamepad.right_trigger > 0 {
+        buttons |= 1 << 11;
+    }
+
+    let stick = |value: i16| ((value as i32 + 32768) >> 8) as u8;
+    DS4Report {
+        thumb_lx: stick(gamepad.thumb_lx),
+        thumb_ly: 255 - stick(gamepad.thumb_ly),
+        thumb_rx: stick(gamepad.thumb_rx),
+        thumb_ry: 255 - stick(gamepad.thumb_ry),
+        buttons,
+        // The PS button
+        special: held(XButtons::GUIDE) as u8,
+        trigger_l: gamepad.left_trigger,
+        trigger_r: gamepad.right_trigger,
+    }
+}
+
+// Free functions so the mapping can be benchmarked without a ViGEm bus connection
+pub fn apply_button(gamepad: &mut vigem_client::XGamepad, button: ButtonId, pressed: bool) {
+    use vigem_client::XButtons;
+
+    let button_flag = match button {
+        ButtonId::South => XButtons::A,
+        ButtonId::East => XButtons::B,
+        ButtonId::West => XButtons::X,
+        ButtonId::North => XButtons::Y,
+        ButtonId::LeftBumper => XButtons::LB,
+        ButtonId::RightBumper => XButtons::RB,
+        ButtonId::Select => XButtons::BACK,
+        ButtonId::Start => XButtons::START,
+        ButtonId::Guide => XButtons::GUIDE,
+        ButtonId::LeftStick => XButtons::LTHUMB,
+        ButtonId::RightStick => XButtons::RTHUMB,
+        ButtonId::DPadUp => XButtons::UP,
+        ButtonId::DPadDown => XButtons::DOWN,
+        ButtonId::DPadLeft => XButtons::LEFT,
+        ButtonId::DPadRight => XButtons::RIGHT,
+        // Digital triggers set the trigger to 100% when pressed, 0% when released
+        ButtonId::RightTrigger => {
+            gamepad.right_trigger = if pressed { 255 } else { 0 };
+            log::trace!("RT digital button: {} -> trigger value: {}", pressed, gamepad.right_trigger);
+            return;
+        }
+        ButtonId::LeftTrigger => {
+            gamepad.left_trigger = if pressed { 255 } else { 0 };
+            log::trace!("LT digital button: {} -> trigger value: {}", pressed, gamepad.left_trigger);
+            return;
+        }
+    };
+    if pressed {
+        gamepad.buttons.raw |= button_flag;
+    } else {
+        gamepad.buttons.raw &= !button_flag;
+    }
+}
+
+pub fn apply_axis(gamepad: &mut vigem_client::XGamepad, axis: AxisId, value: f32) {
+    match axis {
+        AxisId::LeftStickX => {
+            gamepad.thumb_lx = (value * 32767.0) as i16;
+        }
+        AxisId::LeftStickY => {
+            // Don't invert Y axis - use raw value
+            gamepad.thumb_ly = (value * 32767.0) as i16;
+        }
+        AxisId::RightStickX => {
+            gamepad.thumb_rx = (value * 32767.0) as i16;
+        }
+        AxisId::RightStickY => {
+            // Don't invert Y axis - use raw value
+            gamepad.thumb_ry = (value * 32767.0) as i16;
+        }
+        AxisId::LeftTrigger => {
+            gamepad.left_trigger = (value * 255.0) as u8;
+        }
+        AxisId::RightTrigger => {
+            gamepad.right_trigger = (value * 255.0) as u8;
+        }
+        // The D-Pad already arrives as button events
+        AxisId::DPadX | AxisId::DPadY => {}
+    }
+}
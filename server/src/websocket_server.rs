@@ -0,0 +1,566 @@
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_tungstenite::{accept_async_with_config, tungstenite::Message};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::WebSocketStream;
+use tracing::Instrument;
+use serde::Serialize;
+use crate::clock::{self, ClockEstimate, ClockSync};
+use crate::raw_hid::{HidConnection, HidPassthrough};
+use crate::pipeline_trace::{MessageTrace, PipelineTrace};
+use crate::protocol::{self, Ack, BatteryReport, CapabilityReport, ClockProbe, ControllerInputData, ErrorBudget, PadHealth, PadHealthReport, PadStatus, PadStatusReport, PcInput, ProfileList, MAX_MESSAGE_SIZE};
+use crate::pad_watchdog::PadWatchdog;
+use crate::virtual_controller::{PadFactory, PadType, VirtualController};
+
+// The UI only needs to look at input this often, everything in between is coalesced
+const UI_UPDATE_INTERVAL: Duration = Duration::from_millis(16);
+// Often enough to follow the drift between the two clocks over a long session
+const CLOCK_PROBE_INTERVAL: Duration = Duration::from_secs(5);
+// Only sent when something new was applied, so an idle client hears nothing
+const ACK_INTERVAL: Duration = Duration::from_millis(250);
+
+// Input already parsed and applied by the network task, condensed for display
+#[derive(Debug, Clone)]
+pub struct InputDigest {
+    // Button transitions in arrival order, but only the latest value of each axis
+    pub data: ControllerInputData,
+    // The client's ID of the message each event came in, in the same order as data's events
+    pub button_message_ids: Vec<u64>,
+    pub axis_message_ids: Vec<u64>,
+    pub message_count: u32,
+    // On our clock, 0 until the client's clock has been synced
+    pub delay_ms: u64,
+    // One per merged message that arrived after the clocks were synced
+    pub delays_ms: Vec<u64>,
+    pub clock: Option<ClockEstimate>,
+    // Gaps in the client's message IDs, a message overtaken by a newer one counts as missing too
+    pub missing_messages: u64,
+    // Axis values superseded by a newer one before the UI got to see them
+    pub dropped_axis_events: u32,
+}
+
+impl InputDigest {
+    pub fn new(controller_id: u32) -> Self {
+        Self {
+            data: ControllerInputData {
+                message_id: 0,
+                timestamp: 0,
+                sent_at: 0,
+                controller_id,
+                button_events: Vec::new(),
+                axis_events: Vec::new(),
+            },
+            button_message_ids: Vec::new(),
+            axis_message_ids: Vec::new(),
+            message_count: 0,
+            delay_ms: 0,
+            delays_ms: Vec::new(),
+            clock: None,
+            missing_messages: 0,
+            dropped_axis_events: 0,
+        }
+    }
+
+    pub fn merge(&mut self, data: &ControllerInputData, delay_ms: Option<u64>, clock: Option<ClockEstimate>) {
+        self.data.message_id = data.message_id;
+        self.data.timestamp = data.timestamp;
+        self.data.button_events.extend(data.button_events.iter().cloned());
+        self.button_message_ids.extend(std::iter::repeat_n(data.message_id, data.button_events.len()));
+        for axis_event in &data.axis_events {
+            match self.data.axis_events.iter().position(|e| e.axis == axis_event.axis) {
+                Some(index) => {
+                    self.data.axis_events[index] = axis_event.clone();
+                    self.axis_message_ids[index] = data.message_id;
+                    self.dropped_axis_events += 1;
+                }
+                None => {
+                    self.data.axis_events.push(axis_event.clone());
+                    self.axis_message_ids.push(data.message_id);
+                }
+            }
+        }
+        self.message_count += 1;
+        self.delay_ms = delay_ms.unwrap_or(0);
+        self.delays_ms.extend(delay_ms);
+        self.clock = clock;
+    }
+}
+
+// Everything the network tasks report to the UI thread
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+    // On the client's first message rather than the handshake, with a disconnect to match
+    ClientConnected(std::net::SocketAddr),
+    ClientDisconnected(std::net::SocketAddr),
+    Input(InputDigest),
+    // A client asked for a mapping profile by name, applied and saved by the UI thread
+    ProfileRequested(String),
+    // What one of a client's controllers can produce, dropped again when the client disconnects
+    CapabilitiesReported(std::net::SocketAddr, CapabilityReport),
+    BatteryReported(std::net::SocketAddr, BatteryReport),
+}
+
+// State the network tasks share with the UI thread
+#[derive(Clone)]
+pub struct SharedController {
+    pub virtual_controller: Arc<Mutex<VirtualController>>,
+    pub forwarding_enabled: Arc<AtomicBool>,
+    // Published by the UI thread, every connection passes changes on to its client
+    pub profiles: Arc<watch::Sender<ProfileList>>,
+    pub trace: Arc<PipelineTrace>,
+    pub hid_passthrough: Arc<HidPassthrough>,
+    // The pad each client is paired with, published by the UI thread. Unpaired clients share
+    // virtual_controller.
+    pub pad_types: Arc<watch::Sender<HashMap<IpAddr, PadType>>>,
+    // Whether the shared pad takes input, kept by the UI thread's watchdog
+    pub pad_health: Arc<watch::Sender<PadHealth>>,
+    pub create_pad: PadFactory,
+    // The PC's own controller while it streams to the Deck, every connection passes it on
+    pub pc_input: Arc<broadcast::Sender<ControllerInputData>>,
+}
+
+// Where one client's input goes
+enum ClientPad {
+    Shared,
+    // Created when the client connects and unplugged again when it leaves. It follows the shared
+    // pad's mapping profile and blocking, the script, split and local controller stay with that one.
+    Own(Box<VirtualController>),
+    Spectator,
+}
+
+impl ClientPad {
+    fn new(pad_type: PadType, addr: std::net::SocketAddr, shared_controller: &SharedController) -> Self {
+        match pad_type {
+            PadType::Xbox360 => ClientPad::Shared,
+            PadType::Spectator => ClientPad::Spectator,
+            PadType::DualShock4 => {
+                let pad = (shared_controller.create_pad)(pad_type).and_then(|mut pad| {
+                    pad.set_profile(shared_controller.virtual_controller.lock().unwrap().profile().clone());
+                    pad.create_controller()?;
+                    Ok(pad)
+                });
+                match pad {
+                    Ok(pad) => {
+                        log::info!("Plugged in a {} for {}", pad_type.name(), addr);
+                        ClientPad::Own(Box::new(pad))
+                    }
+                    Err(e) => {
+                        log::error!("Failed to create a {} for {}, its input isn't forwarded: {}", pad_type.name(), addr, e);
+                        ClientPad::Spectator
+                    }
+                }
+            }
+        }
+    }
+
+    // What the client's input ends up on right now, pad_type is what it's paired with
+    fn status(&self, pad_type: PadType, shared_controller: &SharedController) -> PadStatus {
+        let active = |pad: &VirtualController, pad_type: PadType| {
+            if pad.is_connected() {
+                PadStatus::Active {
+                    pad: pad_type.name().to_string(),
+                    player: pad.assigned_slots()[0].map(|slot| slot + 1),
+                }
+            } else {
+                PadStatus::Unplugged
+            }
+        };
+        match self {
+            ClientPad::Spectator if pad_type == PadType::Spectator => PadStatus::Spectator,
+            _ if !shared_controller.forwarding_enabled.load(Ordering::SeqCst) => PadStatus::Paused,
+            ClientPad::Shared => active(&shared_controller.virtual_controller.lock().unwrap(), PadType::Xbox360),
+            ClientPad::Own(pad) => active(pad, pad_type),
+            // Its own pad couldn't be created
+            ClientPad::Spectator => PadStatus::Unplugged,
+        }
+    }
+
+    fn process(&mut self, input: ControllerInputData, shared: &Mutex<VirtualController>) -> Result<()> {
+        match self {
+            ClientPad::Shared => shared.lock().unwrap().process_controller_input(input),
+            ClientPad::Own(pad) => {
+                follow_shared(pad, &shared.lock().unwrap());
+                pad.process_controller_input(input)
+            }
+            ClientPad::Spectator => Ok(()),
+        }
+    }
+
+    // The shared pad is ticked whatever the client is paired with, it may be fed from elsewhere
+    fn tick(&mut self, shared: &Mutex<VirtualController>) -> Result<()> {
+        let mut shared = shared.lock().unwrap();
+        shared.tick()?;
+        if let ClientPad::Own(pad) = self {
+            follow_shared(pad, &shared);
+            pad.tick()?;
+        }
+        Ok(())
+    }
+
+    // Lets go of whatever the client was holding, for when forwarding stops
+    fn reset(&mut self) -> Result<()> {
+        match self {
+            ClientPad::Own(pad) => pad.reset_state(),
+            // The UI resets the shared pad itself
+            ClientPad::Shared | ClientPad::Spectator => Ok(()),
+        }
+    }
+}
+
+// Profile edits and Release Everything in the UI only reach the shared pad
+fn follow_shared(pad: &mut VirtualController, shared: &VirtualController) {
+    if pad.profile() != shared.profile() {
+        pad.set_profile(shared.profile().clone());
+    }
+    if shared.is_blocked() && !pad.is_blocked() {
+        if let Err(e) = pad.release_all() {
+            log::error!("Failed to release a client's pad: {}", e);
+        }
+    } else if !shared.is_blocked() && pad.is_blocked() {
+        pad.unblock();
+    }
+}
+
+// Binds synchronously so the UI thread can move the server without blocking on the runtime,
+// and a bad address is reported right away instead of failing in the background
+pub fn listen(address: &str) -> Result<TcpListener> {
+    let listener = std::net::TcpListener::bind(address)
+        .with_context(|| format!("Failed to listen on {}", address))?;
+    listener.set_nonblocking(true)?;
+    log::info!("WebSocket server listening on {}", address);
+    Ok(TcpListener::from_std(listener)?)
+}
+
+pub async fn serve(listener: TcpListener, event_sender: mpsc::Sender<ServerEvent>, shared_controller: SharedController) -> Result<()> {
+    while let Ok((stream, addr)) = listener.accept().await {
+        log::info!("New connection from {}", addr);
+        
+        let sender = event_sender.clone();
+        let shared_controller = shared_controller.clone();
+        // Everything logged for the connection carries the client's address
+        let span = tracing::info_span!("connection", client = %addr);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, addr, sender, shared_controller).await {
+                log::error!("Error handling connection: {}", e);
+            }
+        }.instrument(span));
+    }
+    
+    Ok(())
+}
+
+async fn handle_connection(
+    stream: tokio::net::TcpStream,
+    addr: std::net::SocketAddr,
+    event_sender: mpsc::Sender<ServerEvent>,
+    shared_controller: SharedController,
+) -> Result<()> {
+    // Oversized frames are refused while reading instead of being buffered in full
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_SIZE),
+        max_frame_size: Some(MAX_MESSAGE_SIZE),
+        ..Default::default()
+    };
+    let ws_stream = accept_async_with_config(stream, Some(config)).await?;
+    let (mut tx, mut rx) = ws_stream.split();
+    
+    log::info!("WebSocket connection established");
+
+    let mut announced = false;
+    let result = receive_messages(&mut tx, &mut rx, addr, &event_sender, &shared_controller, &mut announced).await;
+    if announced {
+        let _ = event_sender.send(ServerEvent::ClientDisconnected(addr)).await;
+    }
+    result
+}
+
+// Parses and applies input here, the UI only gets a digest every UI_UPDATE_INTERVAL. The same
+// timer sends the repeats of held repeat buttons.
+async fn receive_messages(
+    tx: &mut futures_util::stream::SplitSink<WebSocketStream<tokio::net::TcpStream>, Message>,
+    rx: &mut futures_util::stream::SplitStream<WebSocketStream<tokio::net::TcpStream>>,
+    addr: std::net::SocketAddr,
+    event_sender: &mpsc::Sender<ServerEvent>,
+    shared_controller: &SharedController,
+    announced: &mut bool,
+) -> Result<()> {
+    let mut pending: HashMap<u32, InputDigest> = HashMap::new();
+    let mut flush_interval = tokio::time::interval(UI_UPDATE_INTERVAL);
+    flush_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut error_budget = ErrorBudget::new();
+    let mut profiles = shared_controller.profiles.subscribe();
+    let profile_list = profiles.borrow_and_update().clone();
+    send_json(tx, &profile_list).await?;
+    // The first tick is immediate, so the clocks are synced before most of the input arrives
+    let mut clock_probe_interval = tokio::time::interval(CLOCK_PROBE_INTERVAL);
+    let mut clock_sync = ClockSync::new();
+    let mut last_message_id = 0;
+    let mut ack_interval = tokio::time::interval(ACK_INTERVAL);
+    // Highest message ID applied and when, and the last one acknowledged
+    let mut applied: Option<(u64, u64)> = None;
+    let mut acked = 0;
+    let mut hid = HidConnection::new(addr, shared_controller.hid_passthrough.clone());
+    let mut pad_types = shared_controller.pad_types.subscribe();
+    let mut pad_type = pad_types.borrow_and_update().get(&addr.ip()).copied().unwrap_or_default();
+    let mut pad = ClientPad::new(pad_type, addr, shared_controller);
+    let mut forwarding = shared_controller.forwarding_enabled.load(Ordering::SeqCst);
+    let mut pc_input = shared_controller.pc_input.subscribe();
+    let mut pad_health = shared_controller.pad_health.subscribe();
+    // Only news to a client that connects while the pad is down
+    let health = pad_health.borrow_and_update().clone();
+    if health != PadHealth::Working {
+        send_json(tx, &PadHealthReport { pad_health: health }).await?;
+    }
+    // Looks after the client's own pad, the shared one is the UI thread's
+    let mut own_pad_watchdog = PadWatchdog::new();
+    // Checked on every flush, the pads are plugged in and out from the UI thread as well
+    let mut pad_status = pad.status(pad_type, shared_controller);
+    send_json(tx, &PadStatusReport { pad_status: pad_status.clone() }).await?;
+
+    loop {
+        tokio::select! {
+            msg = rx.next() => {
+                let Some(msg) = msg else {
+                    break;
+                };
+                let msg = msg?;
+                // Connection tests and server scans close again without a word, only a client
+                // that talks back counts as connected, e.g. for the game launcher and sessions
+                if !*announced && matches!(msg, Message::Text(_) | Message::Binary(_)) {
+                    *announced = true;
+                    let _ = event_sender.send(ServerEvent::ClientConnected(addr)).await;
+                }
+                match msg {
+                    Message::Text(text) => {
+                        if let Some(name) = protocol::parse_profile_request(&text) {
+                            log::info!("Client asked for mapping profile '{}'", name);
+                            let _ = event_sender.send(ServerEvent::ProfileRequested(name)).await;
+                            continue;
+                        }
+                        if let Some(reply) = protocol::parse_clock_reply(&text) {
+                            clock_sync.add_sample(reply.clock_probe, reply.client_time, clock::now_ms());
+                            continue;
+                        }
+                        if let Some(announcement) = protocol::parse_hid_device_announcement(&text) {
+                            hid.announce(announcement.hid_device);
+                            continue;
+                        }
+                        if let Some(report) = protocol::parse_capability_report(&text) {
+                            log::info!("Client controller {} reported its capabilities", report.controller_id);
+                            let _ = event_sender.send(ServerEvent::CapabilitiesReported(addr, report)).await;
+                            continue;
+                        }
+                        if let Some(report) = protocol::parse_battery_report(&text) {
+                            let _ = event_sender.send(ServerEvent::BatteryReported(addr, report)).await;
+                            continue;
+                        }
+                        let received_us = clock::now_us();
+                        // message_id matches the one in the client's serialize/send spans
+                        let message_span = tracing::debug_span!(
+                            "receive",
+                            message_id = tracing::field::Empty,
+                            delay_ms = tracing::field::Empty,
+                        ).entered();
+                        let parsed = tracing::debug_span!("parse", bytes = text.len())
+                            .in_scope(|| protocol::parse_message(&text));
+                        
+                        let controller_data = match parsed {
+                            Ok(controller_data) => controller_data,
+                            Err(e) => {
+                                log::warn!("Rejected malformed message: {}", e);
+                                if !error_budget.spend() {
+                                    anyhow::bail!("too many malformed messages, disconnecting");
+                                }
+                                continue;
+                            }
+                        };
+                        
+                        let parsed_us = clock::now_us();
+                        let delay = clock_sync.delay_ms(controller_data.timestamp, clock::now_ms());
+                        message_span.record("message_id", controller_data.message_id);
+                        message_span.record("delay_ms", delay.unwrap_or(0));
+
+                        // IDs count up per client, clients that don't number their messages send 0
+                        let message_id = controller_data.message_id;
+                        let missing = if last_message_id > 0 && message_id > last_message_id {
+                            message_id - last_message_id - 1
+                        } else {
+                            0
+                        };
+                        last_message_id = last_message_id.max(message_id);
+                        
+                        for button_event in &controller_data.button_events {
+                            tracing::debug!(button = %button_event.button, pressed = button_event.pressed, "button");
+                        }
+                        
+                        for axis_event in &controller_data.axis_events {
+                            tracing::debug!(axis = %axis_event.axis, value = axis_event.value, "axis");
+                        }
+                        
+                        let digest = pending.entry(controller_data.controller_id)
+                            .or_insert_with(|| InputDigest::new(controller_data.controller_id));
+                        digest.merge(&controller_data, delay, clock_sync.estimate());
+                        digest.missing_messages += missing;
+
+                        let mut trace = shared_controller.trace.should_sample().then(|| {
+                            let clock = clock_sync.estimate();
+                            MessageTrace {
+                                message_id,
+                                controller_id: controller_data.controller_id,
+                                events: controller_data.button_events.len() + controller_data.axis_events.len(),
+                                captured_us: clock.map(|clock| clock.to_server_us(controller_data.timestamp)),
+                                sent_us: clock.filter(|_| controller_data.sent_at > 0)
+                                    .map(|clock| clock.to_server_us(controller_data.sent_at)),
+                                received_us,
+                                parsed_us,
+                                applied_us: None,
+                            }
+                        });
+                        
+                        if shared_controller.forwarding_enabled.load(Ordering::SeqCst) {
+                            let _vigem_span = tracing::debug_span!("vigem_update").entered();
+                            let applied_start = clock::now_us();
+                            if let Err(e) = pad.process(controller_data, &shared_controller.virtual_controller) {
+                                tracing::error!("Failed to process controller input: {}", e);
+                            }
+                            if let Some(trace) = &mut trace {
+                                trace.applied_us = Some((applied_start, clock::now_us()));
+                            }
+                        }
+                        if let Some(trace) = trace {
+                            shared_controller.trace.record(trace);
+                        }
+                        if applied.is_none_or(|(id, _)| message_id > id) {
+                            applied = Some((message_id, clock::now_us()));
+                        }
+                    }
+                    Message::Close(_) => {
+                        log::info!("WebSocket connection closed");
+                        break;
+                    }
+                    // Raw HID reports, everything else is JSON text
+                    Message::Binary(frame) => {
+                        let forward = shared_controller.forwarding_enabled.load(Ordering::SeqCst);
+                        let result = protocol::decode_hid_report(&frame)
+                            .and_then(|(_, report)| hid.report(report, forward));
+                        if let Err(e) = result {
+                            log::warn!("Rejected binary message: {}", e);
+                            if !error_budget.spend() {
+                                anyhow::bail!("too many malformed messages, disconnecting");
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(()) = profiles.changed() => {
+                let profile_list = profiles.borrow_and_update().clone();
+                send_json(tx, &profile_list).await?;
+            }
+            Ok(()) = pad_types.changed() => {
+                let paired = pad_types.borrow_and_update().get(&addr.ip()).copied().unwrap_or_default();
+                if paired != pad_type {
+                    // Whatever the client held on the shared pad would otherwise stay held
+                    if matches!(pad, ClientPad::Shared) {
+                        if let Err(e) = shared_controller.virtual_controller.lock().unwrap().reset_state() {
+                            log::error!("Failed to reset virtual controller: {}", e);
+                        }
+                    }
+                    pad_type = paired;
+                    pad = ClientPad::new(pad_type, addr, shared_controller);
+                    own_pad_watchdog = PadWatchdog::new();
+                }
+            }
+            Ok(()) = pad_health.changed() => {
+                let health = pad_health.borrow_and_update().clone();
+                send_json(tx, &PadHealthReport { pad_health: health }).await?;
+            }
+            input = pc_input.recv() => match input {
+                Ok(input) => send_json(tx, &PcInput { pc_input: input }).await?,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Client {} fell behind, skipped {} messages of PC controller input", addr, skipped);
+                }
+                // The sender lives as long as the server
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            _ = clock_probe_interval.tick() => {
+                send_json(tx, &ClockProbe { clock_probe: clock::now_ms() }).await?;
+            }
+            _ = ack_interval.tick() => {
+                // Clients that don't number their messages have nothing to match an ack with
+                if let Some((message_id, applied_us)) = applied.filter(|&(id, _)| id > acked) {
+                    send_json(tx, &Ack { ack: message_id, held_us: clock::now_us().saturating_sub(applied_us) }).await?;
+                    acked = message_id;
+                }
+            }
+            _ = flush_interval.tick() => {
+                let forwarding_enabled = shared_controller.forwarding_enabled.load(Ordering::SeqCst);
+                if forwarding_enabled {
+                    if let Err(e) = pad.tick(&shared_controller.virtual_controller) {
+                        tracing::error!("Failed to send repeated input: {}", e);
+                    }
+                } else if forwarding {
+                    if let Err(e) = pad.reset() {
+                        log::error!("Failed to reset a client's pad: {}", e);
+                    }
+                }
+                forwarding = forwarding_enabled;
+                if let ClientPad::Own(own) = &mut pad {
+                    if let Some(event) = own_pad_watchdog.check(own, std::time::Instant::now()) {
+                        event.log();
+                        send_json(tx, &PadHealthReport { pad_health: event.health() }).await?;
+                    }
+                }
+                let status = pad.status(pad_type, shared_controller);
+                if status != pad_status {
+                    send_json(tx, &PadStatusReport { pad_status: status.clone() }).await?;
+                    pad_status = status;
+                }
+                if !flush_digests(&mut pending, event_sender) {
+                    log::error!("Failed to send controller data to UI: channel closed");
+                    return Ok(());
+                }
+            }
+        }
+    }
+    
+    // Don't lose whatever arrived since the last flush, waiting is fine now the socket is gone
+    for (_, digest) in pending.drain() {
+        let _ = event_sender.send(ServerEvent::Input(digest)).await;
+    }
+    
+    Ok(())
+}
+
+async fn send_json(
+    tx: &mut futures_util::stream::SplitSink<WebSocketStream<tokio::net::TcpStream>, Message>,
+    message: &impl Serialize,
+) -> Result<()> {
+    tx.send(Message::Text(serde_json::to_string(message)?)).await?;
+    Ok(())
+}
+
+// Never blocks the socket on a busy UI: a digest that doesn't fit stays pending and keeps
+// merging, so axis updates get coalesced while button transitions are all kept.
+// Returns false once the UI side is gone.
+fn flush_digests(pending: &mut HashMap<u32, InputDigest>, event_sender: &mpsc::Sender<ServerEvent>) -> bool {
+    let mut retained = HashMap::new();
+    for (controller_id, digest) in pending.drain() {
+        match event_sender.try_send(ServerEvent::Input(digest)) {
+            Ok(_) => {}
+            Err(mpsc::error::TrySendError::Full(ServerEvent::Input(digest))) => {
+                retained.insert(controller_id, digest);
+            }
+            Err(_) => return false,
+        }
+    }
+    *pending = retained;
+    true
+}
@@ -0,0 +1,845 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+// The server is a binary crate, so the modules under test are pulled in by path. The client
+// core is pulled in the same way rather than as a dependency, since the server already builds
+// core's input ids as its own.
+#[allow(dead_code)]
+#[path = "../../core/src/input_id.rs"]
+mod input_id;
+#[allow(dead_code)]
+#[path = "../src/clock.rs"]
+mod clock;
+#[allow(dead_code)]
+#[path = "../src/raw_hid.rs"]
+mod raw_hid;
+#[allow(dead_code)]
+#[path = "../src/pipeline_trace.rs"]
+mod pipeline_trace;
+#[allow(dead_code)]
+#[path = "../src/protocol.rs"]
+mod protocol;
+#[allow(dead_code)]
+#[path = "../src/double_input.rs"]
+mod double_input;
+#[allow(dead_code)]
+#[path = "../src/deadzone.rs"]
+mod deadzone;
+#[allow(dead_code)]
+#[path = "../src/smoothing.rs"]
+mod smoothing;
+#[allow(dead_code)]
+#[path = "../src/input_filter.rs"]
+mod input_filter;
+#[allow(dead_code)]
+#[path = "../src/launcher.rs"]
+mod launcher;
+#[allow(dead_code)]
+#[path = "../src/mapping.rs"]
+mod mapping;
+#[allow(dead_code)]
+#[path = "../src/i18n.rs"]
+mod i18n;
+#[allow(dead_code)]
+#[path = "../src/pad_watchdog.rs"]
+mod pad_watchdog;
+#[allow(dead_code)]
+#[path = "../src/palette.rs"]
+mod palette;
+#[allow(dead_code)]
+#[path = "../src/scripting.rs"]
+mod scripting;
+#[allow(dead_code)]
+#[path = "../src/virtual_controller.rs"]
+mod virtual_controller;
+#[allow(dead_code)]
+#[path = "../src/websocket_server.rs"]
+mod websocket_server;
+#[allow(dead_code)]
+#[path = "../../core/src/capabilities.rs"]
+mod capabilities;
+#[allow(dead_code)]
+#[path = "../../core/src/delivery.rs"]
+mod delivery;
+#[allow(dead_code)]
+#[path = "../../core/src/haptics.rs"]
+mod haptics;
+#[allow(dead_code)]
+#[path = "../../core/src/hid_passthrough.rs"]
+mod hid_passthrough;
+#[allow(dead_code)]
+#[path = "../../core/src/input_source.rs"]
+mod input_source;
+#[allow(dead_code)]
+#[path = "../../core/src/network.rs"]
+mod network;
+#[allow(dead_code)]
+#[path = "../../core/src/network_sim.rs"]
+mod network_sim;
+
+use input_id::{AxisId, ButtonId};
+use network::{NetworkStatus, NetworkStreamer};
+use protocol::ControllerInputData;
+use virtual_controller::{NullBackend, PadBackend, PadType, VirtualController};
+use websocket_server::{InputDigest, ServerEvent, SharedController};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct TestServer {
+    port: u16,
+    events: mpsc::Receiver<ServerEvent>,
+    virtual_controller: Arc<Mutex<VirtualController>>,
+    forwarding_enabled: Arc<AtomicBool>,
+    profiles: Arc<tokio::sync::watch::Sender<protocol::ProfileList>>,
+    hid_passthrough: Arc<raw_hid::HidPassthrough>,
+    pad_types: Arc<tokio::sync::watch::Sender<HashMap<std::net::IpAddr, PadType>>>,
+    pc_input: Arc<tokio::sync::broadcast::Sender<ControllerInputData>>,
+    pad_health: Arc<tokio::sync::watch::Sender<protocol::PadHealth>>,
+}
+
+async fn start_server(forwarding_enabled: bool) -> TestServer {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let mut virtual_controller = VirtualController::with_backend(Box::new(NullBackend));
+    virtual_controller.create_controller().unwrap();
+    let virtual_controller = Arc::new(Mutex::new(virtual_controller));
+    let shared_controller = SharedController {
+        virtual_controller: virtual_controller.clone(),
+        forwarding_enabled: Arc::new(AtomicBool::new(forwarding_enabled)),
+        profiles: Arc::new(tokio::sync::watch::channel(protocol::ProfileList::default()).0),
+        trace: Arc::new(pipeline_trace::PipelineTrace::new()),
+        hid_passthrough: Arc::new(raw_hid::HidPassthrough::new(false)),
+        pad_types: Arc::new(tokio::sync::watch::channel(HashMap::new()).0),
+        pad_health: Arc::new(tokio::sync::watch::channel(protocol::PadHealth::Working).0),
+        create_pad: |_| {
+            let mut pad = VirtualController::with_backend(Box::new(NullBackend));
+            pad.create_controller()?;
+            Ok(pad)
+        },
+        pc_input: Arc::new(tokio::sync::broadcast::channel(16).0),
+    };
+    let profiles = shared_controller.profiles.clone();
+    let hid_passthrough = shared_controller.hid_passthrough.clone();
+    let pad_types = shared_controller.pad_types.clone();
+    let pc_input = shared_controller.pc_input.clone();
+    let pad_health = shared_controller.pad_health.clone();
+    let forwarding_enabled = shared_controller.forwarding_enabled.clone();
+
+    let (event_sender, events) = mpsc::channel(100);
+    tokio::spawn(websocket_server::serve(listener, event_sender, shared_controller));
+
+    TestServer { port, events, virtual_controller, forwarding_enabled, profiles, hid_passthrough, pad_types, pc_input, pad_health }
+}
+
+async fn connect_client(port: u16) -> NetworkStreamer {
+    let mut streamer = NetworkStreamer::new();
+    streamer.connect("127.0.0.1", port as i32);
+
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match streamer.poll_status() {
+                Some(NetworkStatus::Connected(_)) => break,
+                Some(NetworkStatus::ConnectionFailed(_, error)) => panic!("connection failed: {}", error),
+                _ => tokio::time::sleep(Duration::from_millis(5)).await,
+            }
+        }
+    })
+    .await
+    .expect("client did not connect");
+
+    streamer
+}
+
+async fn next_input(events: &mut mpsc::Receiver<ServerEvent>) -> InputDigest {
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match events.recv().await {
+                Some(ServerEvent::Input(digest)) => return digest,
+                Some(_) => continue,
+                None => panic!("server stopped"),
+            }
+        }
+    })
+    .await
+    .expect("no input reached the server")
+}
+
+fn client_message(buttons: &[(ButtonId, bool)], axes: &[(AxisId, f32)]) -> network::ControllerInputData {
+    let timestamp = network::get_current_timestamp();
+    network::ControllerInputData {
+        message_id: 0,
+        timestamp,
+        sent_at: 0,
+        controller_id: 0,
+        button_events: buttons.iter()
+            .map(|&(button, pressed)| network::ButtonEvent { button, pressed, timestamp })
+            .collect(),
+        axis_events: axes.iter()
+            .map(|&(axis, value)| network::AxisEvent { axis, value, timestamp })
+            .collect(),
+    }
+}
+
+#[tokio::test]
+async fn client_input_reaches_the_virtual_controller() {
+    let mut server = start_server(true).await;
+    let streamer = connect_client(server.port).await;
+
+    streamer.send_controller_data(client_message(&[(ButtonId::South, true)], &[(AxisId::LeftStickX, 0.5)])).unwrap();
+    let digest = next_input(&mut server.events).await;
+
+    assert_eq!(digest.message_count, 1);
+    assert_eq!(digest.data.message_id, 1);
+    assert_eq!(digest.dropped_axis_events, 0);
+    // Same machine, same clock: anything near a second means the bookkeeping is off
+    assert!(digest.delay_ms < 1000, "delay_ms = {}", digest.delay_ms);
+
+    let virtual_controller = server.virtual_controller.lock().unwrap();
+    assert_eq!(virtual_controller.get_button_states().get(&ButtonId::South), Some(&true));
+    assert_eq!(virtual_controller.get_axis_states().get(&AxisId::LeftStickX), Some(&0.5));
+}
+
+#[tokio::test]
+async fn input_is_only_displayed_while_forwarding_is_off() {
+    let mut server = start_server(false).await;
+    let streamer = connect_client(server.port).await;
+
+    streamer.send_controller_data(client_message(&[(ButtonId::East, true)], &[])).unwrap();
+    let digest = next_input(&mut server.events).await;
+
+    assert_eq!(digest.data.button_events.len(), 1);
+    assert!(server.virtual_controller.lock().unwrap().get_button_states().is_empty());
+}
+
+#[tokio::test]
+async fn released_input_stays_blocked_until_resumed() {
+    let mut server = start_server(true).await;
+    let streamer = connect_client(server.port).await;
+
+    streamer.send_controller_data(client_message(&[(ButtonId::South, true)], &[])).unwrap();
+    next_input(&mut server.events).await;
+    server.virtual_controller.lock().unwrap().release_all().unwrap();
+    assert_ne!(server.virtual_controller.lock().unwrap().get_button_states().get(&ButtonId::South), Some(&true));
+
+    streamer.send_controller_data(client_message(&[(ButtonId::East, true)], &[])).unwrap();
+    next_input(&mut server.events).await;
+    assert_ne!(server.virtual_controller.lock().unwrap().get_button_states().get(&ButtonId::East), Some(&true));
+
+    server.virtual_controller.lock().unwrap().unblock();
+    streamer.send_controller_data(client_message(&[(ButtonId::North, true)], &[])).unwrap();
+    next_input(&mut server.events).await;
+    assert_eq!(server.virtual_controller.lock().unwrap().get_button_states().get(&ButtonId::North), Some(&true));
+}
+
+#[tokio::test]
+async fn a_release_behind_a_full_send_queue_still_arrives() {
+    let mut server = start_server(true).await;
+    let streamer = connect_client(server.port).await;
+
+    // Nothing awaits in between, so the network task can't drain the queue meanwhile
+    let (_, capacity) = streamer.send_queue_depth();
+    for _ in 0..capacity {
+        streamer.send_controller_data(client_message(&[(ButtonId::South, true)], &[])).unwrap();
+    }
+    let release = streamer.send_controller_data(client_message(&[(ButtonId::South, false)], &[])).unwrap();
+    assert_eq!(release, None);
+
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            next_input(&mut server.events).await;
+            if server.virtual_controller.lock().unwrap().get_button_states().get(&ButtonId::South) == Some(&false) {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("the release never arrived");
+}
+
+#[tokio::test]
+async fn clients_paired_with_another_pad_stay_off_the_shared_one() {
+    let mut server = start_server(true).await;
+    let localhost: std::net::IpAddr = "127.0.0.1".parse().unwrap();
+    server.pad_types.send_replace(HashMap::from([(localhost, PadType::Spectator)]));
+    let streamer = connect_client(server.port).await;
+
+    streamer.send_controller_data(client_message(&[(ButtonId::South, true)], &[])).unwrap();
+    let digest = next_input(&mut server.events).await;
+    // Still shown, just not forwarded
+    assert_eq!(digest.data.button_events.len(), 1);
+    assert!(server.virtual_controller.lock().unwrap().get_button_states().is_empty());
+
+    // The connection picks up a new pairing on its own time
+    server.pad_types.send_replace(HashMap::from([(localhost, PadType::DualShock4)]));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    streamer.send_controller_data(client_message(&[(ButtonId::East, true)], &[])).unwrap();
+    next_input(&mut server.events).await;
+    assert!(server.virtual_controller.lock().unwrap().get_button_states().is_empty());
+
+    server.pad_types.send_replace(HashMap::from([(localhost, PadType::Xbox360)]));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    streamer.send_controller_data(client_message(&[(ButtonId::North, true)], &[])).unwrap();
+    next_input(&mut server.events).await;
+    assert_eq!(server.virtual_controller.lock().unwrap().get_button_states().get(&ButtonId::North), Some(&true));
+}
+
+#[tokio::test]
+async fn connection_tests_do_not_count_as_clients() {
+    let mut server = start_server(true).await;
+    network::probe_server("127.0.0.1", server.port as i32).await.expect("probe failed");
+    // The game launcher and session tracking only hear of clients through these
+    while let Ok(event) = server.events.try_recv() {
+        assert!(
+            !matches!(event, ServerEvent::ClientConnected(_) | ServerEvent::ClientDisconnected(_)),
+            "the probe was reported as a client: {:?}", event,
+        );
+    }
+
+    // A real client is, as soon as it says something
+    let _streamer = connect_client(server.port).await;
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match server.events.recv().await {
+                Some(ServerEvent::ClientConnected(_)) => break,
+                Some(_) => continue,
+                None => panic!("server stopped"),
+            }
+        }
+    })
+    .await
+    .expect("the client was never reported");
+}
+
+#[tokio::test]
+async fn disconnecting_gives_up_on_a_connection_attempt() {
+    // Takes the connection but never answers the WebSocket handshake
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (_stream, _) = listener.accept().await.unwrap();
+        std::future::pending::<()>().await;
+    });
+
+    let mut streamer = NetworkStreamer::new();
+    streamer.connect("127.0.0.1", port as i32);
+    streamer.disconnect();
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match streamer.next_status().await {
+                Some(NetworkStatus::Connecting(_)) => continue,
+                Some(NetworkStatus::Disconnected) => break,
+                other => panic!("expected a disconnect, got {:?}", other),
+            }
+        }
+    })
+    .await
+    .expect("the disconnect waited for the connection attempt");
+}
+
+#[test]
+fn dualshock4_reports_follow_the_xbox_layout() {
+    use vigem_client::{XButtons, XGamepad};
+
+    let mut gamepad = XGamepad::default();
+    assert_eq!(virtual_controller::ds4_report(&gamepad).buttons, 8);
+    assert_eq!(virtual_controller::ds4_report(&gamepad).thumb_lx, 0x80);
+
+    gamepad.buttons.raw = XButtons::A | XButtons::UP | XButtons::RIGHT | XButtons::GUIDE;
+    gamepad.thumb_ly = i16::MAX;
+    gamepad.right_trigger = 200;
+    let report = virtual_controller::ds4_report(&gamepad);
+    // Cross, the D-Pad pointing up and right, and the right trigger's digital bit
+    assert_eq!(report.buttons, 1 << 5 | 1 | 1 << 11);
+    assert_eq!(report.special, 1);
+    assert_eq!(report.thumb_ly, 0);
+    assert_eq!(report.trigger_r, 200);
+}
+
+#[test]
+fn latency_budget_splits_sampled_messages_by_stage() {
+    let trace = pipeline_trace::PipelineTrace::new();
+    assert!(trace.latency_budget().is_none());
+    let message = |message_id, captured_us, applied_us| pipeline_trace::MessageTrace {
+        message_id,
+        controller_id: 0,
+        events: 1,
+        captured_us,
+        sent_us: captured_us.map(|captured| captured + 1_000),
+        received_us: 10_000,
+        parsed_us: 10_500,
+        applied_us,
+    };
+    trace.record(message(0, Some(4_000), Some((10_600, 11_500))));
+    trace.record(message(1, Some(6_000), Some((10_600, 12_500))));
+    // Unsynced and not forwarded ones can't be split up
+    trace.record(message(2, None, Some((10_600, 11_000))));
+    trace.record(message(3, Some(4_000), None));
+
+    let budget = trace.latency_budget().unwrap();
+    assert_eq!(budget.samples, 2);
+    assert_eq!(budget.stages_ms, [1.0, 4.0, 0.5, 1.5]);
+    assert_eq!(budget.total_ms(), 7.0);
+}
+
+#[tokio::test]
+async fn events_keep_the_id_of_the_message_that_carried_them() {
+    let mut server = start_server(true).await;
+    let streamer = connect_client(server.port).await;
+
+    let first = streamer.send_controller_data(client_message(&[(ButtonId::South, true)], &[])).unwrap();
+    let second = streamer.send_controller_data(client_message(&[(ButtonId::South, false)], &[(AxisId::LeftStickX, 0.5)])).unwrap();
+    let (first, second) = (first.expect("first message not sent"), second.expect("second message not sent"));
+    assert_ne!(first, second);
+
+    // Both may arrive in one digest or two
+    let mut button_ids = Vec::new();
+    let mut axis_ids = Vec::new();
+    while button_ids.len() < 2 {
+        let digest = next_input(&mut server.events).await;
+        button_ids.extend(digest.button_message_ids);
+        axis_ids.extend(digest.axis_message_ids);
+    }
+    assert_eq!(button_ids, [first, second]);
+    assert_eq!(axis_ids, [second]);
+}
+
+#[tokio::test]
+async fn applied_messages_are_acknowledged() {
+    let mut server = start_server(true).await;
+    let mut streamer = connect_client(server.port).await;
+
+    let message_id = streamer.send_controller_data(client_message(&[(ButtonId::West, true)], &[])).unwrap();
+    next_input(&mut server.events).await;
+
+    let stats = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match streamer.poll_status() {
+                Some(NetworkStatus::Delivery(stats)) => return stats,
+                _ => tokio::time::sleep(Duration::from_millis(5)).await,
+            }
+        }
+    })
+    .await
+    .expect("no acknowledgement from the server");
+    assert_eq!(stats.in_flight, 0);
+    assert_eq!((stats.acked, Some(stats.acked_id)), (1, message_id));
+    assert!(stats.ack_latency < Duration::from_secs(1), "ack_latency = {:?}", stats.ack_latency);
+}
+
+#[tokio::test]
+async fn malformed_messages_are_skipped() {
+    use futures_util::SinkExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let mut server = start_server(true).await;
+    let url = format!("ws://127.0.0.1:{}/controller", server.port);
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    let out_of_range = r#"{"timestamp":0,"controller_id":0,"button_events":[],"axis_events":[{"axis":0,"value":2.0,"timestamp":0}]}"#;
+    let valid = ControllerInputData {
+        message_id: 7,
+        timestamp: 0,
+        sent_at: 0,
+        controller_id: 0,
+        button_events: Vec::new(),
+        axis_events: vec![protocol::AxisEvent { axis: AxisId::RightStickY, value: -1.0, timestamp: 0 }],
+    };
+    ws_stream.send(Message::Text("not json".to_string())).await.unwrap();
+    ws_stream.send(Message::Text(out_of_range.to_string())).await.unwrap();
+    ws_stream.send(Message::Text(serde_json::to_string(&valid).unwrap())).await.unwrap();
+
+    let digest = next_input(&mut server.events).await;
+    assert_eq!(digest.message_count, 1);
+    assert_eq!(digest.data.message_id, 7);
+
+    let virtual_controller = server.virtual_controller.lock().unwrap();
+    assert_eq!(virtual_controller.get_axis_states().get(&AxisId::LeftStickX), None);
+    assert_eq!(virtual_controller.get_axis_states().get(&AxisId::RightStickY), Some(&-1.0));
+}
+
+#[tokio::test]
+async fn clients_that_keep_sending_garbage_are_disconnected() {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    let server = start_server(true).await;
+    let url = format!("ws://127.0.0.1:{}/controller", server.port);
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    // One past the budget
+    for _ in 0..21 {
+        ws_stream.send(Message::Text("not json".to_string())).await.unwrap();
+    }
+    tokio::time::timeout(TIMEOUT, async {
+        // Whatever the server sent before giving up, then the end of the connection
+        while let Some(Ok(message)) = ws_stream.next().await {
+            if message.is_close() {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("the server kept the connection open");
+}
+
+#[tokio::test]
+async fn client_picks_an_advertised_profile() {
+    let mut server = start_server(true).await;
+    server.profiles.send_replace(protocol::ProfileList {
+        profiles: vec!["Racing".to_string(), "Shooter".to_string()],
+        active_profile: "Racing".to_string(),
+    });
+    let mut streamer = connect_client(server.port).await;
+
+    let advertised = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match streamer.next_status().await {
+                Some(NetworkStatus::Profiles(profiles)) => return profiles,
+                Some(_) => continue,
+                None => panic!("client stopped"),
+            }
+        }
+    })
+    .await
+    .expect("server did not advertise its profiles");
+    assert_eq!(advertised.profiles, ["Racing", "Shooter"]);
+    assert_eq!(advertised.active_profile, "Racing");
+
+    streamer.select_profile("Shooter");
+    let requested = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match server.events.recv().await {
+                Some(ServerEvent::ProfileRequested(name)) => return name,
+                Some(_) => continue,
+                None => panic!("server stopped"),
+            }
+        }
+    })
+    .await
+    .expect("profile request did not reach the server");
+    assert_eq!(requested, "Shooter");
+}
+
+#[tokio::test]
+async fn pc_controller_input_is_streamed_to_the_client() {
+    let server = start_server(true).await;
+    let mut streamer = connect_client(server.port).await;
+
+    // The connection subscribes once it has sent the profiles, anything earlier isn't for it
+    tokio::time::timeout(TIMEOUT, async {
+        while server.pc_input.receiver_count() == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("connection did not subscribe to PC controller input");
+
+    let timestamp = clock::now_ms();
+    server.pc_input.send(ControllerInputData {
+        message_id: 0,
+        timestamp,
+        sent_at: 0,
+        controller_id: 0,
+        button_events: vec![protocol::ButtonEvent { button: ButtonId::South, pressed: true, timestamp }],
+        axis_events: vec![protocol::AxisEvent { axis: AxisId::LeftStickX, value: -0.5, timestamp }],
+    }).unwrap();
+
+    let received = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match streamer.next_status().await {
+                Some(NetworkStatus::PcInput(data)) => return data,
+                Some(_) => continue,
+                None => panic!("client stopped"),
+            }
+        }
+    })
+    .await
+    .expect("PC controller input did not reach the client");
+    assert_eq!(received.button_events.len(), 1);
+    assert_eq!(received.button_events[0].button, ButtonId::South);
+    assert!(received.button_events[0].pressed);
+    assert_eq!(received.axis_events[0].axis, AxisId::LeftStickX);
+    assert_eq!(received.axis_events[0].value, -0.5);
+}
+
+#[tokio::test]
+async fn client_reports_controller_capabilities() {
+    let mut server = start_server(true).await;
+    let streamer = connect_client(server.port).await;
+
+    let mut reported = capabilities::Capabilities::default();
+    reported.add_button(ButtonId::South);
+    reported.add_axis(AxisId::LeftTrigger);
+    reported.sensors = capabilities::SENSOR_GYRO;
+    streamer.report_capabilities(3, reported);
+
+    let (_, report) = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match server.events.recv().await {
+                Some(ServerEvent::CapabilitiesReported(addr, report)) => return (addr, report),
+                Some(_) => continue,
+                None => panic!("server stopped"),
+            }
+        }
+    })
+    .await
+    .expect("capability report did not reach the server");
+    assert_eq!(report.controller_id, 3);
+    assert!(report.capabilities.has_button(ButtonId::South));
+    assert!(!report.capabilities.has_button(ButtonId::East));
+    assert!(report.capabilities.has_axis(AxisId::LeftTrigger));
+    assert!(report.capabilities.has_sensor(protocol::SENSOR_GYRO));
+    assert!(!report.capabilities.has_sensor(protocol::SENSOR_TOUCHPAD));
+    assert_eq!(report.usb_ids(), None);
+}
+
+#[tokio::test]
+async fn steam_streaming_the_deck_counts_as_double_input() {
+    let mut server = start_server(true).await;
+    let streamer = connect_client(server.port).await;
+
+    streamer.report_controller(&input_source::ControllerInfo {
+        controller_id: 0,
+        name: "Steam Deck".to_string(),
+        uuid: [0; 16],
+        vendor_id: Some(0x28DE),
+        product_id: Some(0x1205),
+        capabilities: capabilities::Capabilities::default(),
+        deck_model: Some(input_source::DeckModel::Oled),
+    });
+
+    let device = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match server.events.recv().await {
+                Some(ServerEvent::CapabilitiesReported(addr, report)) => return (addr, report),
+                Some(_) => continue,
+                None => panic!("server stopped"),
+            }
+        }
+    })
+    .await
+    .expect("controller report did not reach the server");
+    assert_eq!(device.1.usb_ids(), Some((0x28DE, 0x1205)));
+
+    // The server's own ViGEm pad must never count
+    let pads = [
+        double_input::LocalPad { name: "Xbox 360 Controller".to_string(), usb_ids: Some((0x045E, 0x028E)) },
+        double_input::LocalPad { name: "Steam Virtual Gamepad".to_string(), usb_ids: Some((0x28DE, 0x11FF)) },
+    ];
+    let found = double_input::detect(&pads, &[device]);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].local_name, "Steam Virtual Gamepad");
+    assert_eq!(found[0].client, device.0);
+}
+
+#[tokio::test]
+async fn battery_changes_reach_the_server() {
+    let mut server = start_server(true).await;
+    let streamer = connect_client(server.port).await;
+
+    // The repeated reading is dropped by the client, so the next report is the low one
+    streamer.report_battery(1, input_source::Battery { percent: 60, charging: false });
+    streamer.report_battery(1, input_source::Battery { percent: 60, charging: false });
+    streamer.report_battery(1, input_source::Battery { percent: 15, charging: false });
+
+    let mut reports = Vec::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while reports.len() < 2 {
+            match server.events.recv().await {
+                Some(ServerEvent::BatteryReported(_, report)) => reports.push(report),
+                Some(_) => continue,
+                None => panic!("server stopped"),
+            }
+        }
+    })
+    .await
+    .expect("battery reports did not reach the server");
+    assert_eq!(reports[0].battery_percent, 60);
+    assert!(!reports[0].is_low());
+    assert_eq!(reports[1].battery_percent, 15);
+    assert!(reports[1].is_low());
+}
+
+#[tokio::test]
+async fn raw_hid_reports_reach_the_server() {
+    let server = start_server(true).await;
+    let streamer = connect_client(server.port).await;
+
+    streamer.set_hid_device(Some(hid_passthrough::HidDevice {
+        vendor_id: 0x28de,
+        product_id: 0x1205,
+        name: "Steam Deck".to_string(),
+        report_descriptor: vec![0x06, 0xff, 0xff, 0x09, 0x01],
+    }));
+    // A Deck controller state report with the left pad touched and pressed
+    let mut report = [0u8; 64];
+    report[0] = 0x01;
+    report[2] = 0x09;
+    report[3] = 0x40;
+    report[10] = 0x08;
+    report[16..18].copy_from_slice(&(-1200i16).to_le_bytes());
+    report[56..58].copy_from_slice(&9000u16.to_le_bytes());
+
+    let client = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            streamer.send_hid_report(network::get_current_timestamp(), &report).unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            if let Some((_, client)) = server.hid_passthrough.clients().into_iter().find(|(_, client)| client.reports > 0) {
+                return client;
+            }
+        }
+    })
+    .await
+    .expect("HID reports did not reach the server");
+    assert_eq!(client.device.name, "Steam Deck");
+    let latest = client.latest.expect("report was not decoded");
+    assert!(latest.left_pad.touched);
+    assert!(!latest.right_pad.touched);
+    assert_eq!(latest.left_pad.x, -1200);
+    assert_eq!(latest.left_pad.pressure, 9000);
+}
+
+// Fails everything while broken is set, like a pad the bus lost across sleep and wake
+struct FlakyBackend {
+    broken: Arc<AtomicBool>,
+}
+
+impl PadBackend for FlakyBackend {
+    fn plug_in(&mut self, _preferred_slot: Option<u32>) -> anyhow::Result<()> {
+        if self.broken.load(std::sync::atomic::Ordering::SeqCst) {
+            anyhow::bail!("bus gone");
+        }
+        Ok(())
+    }
+
+    fn unplug(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn slot(&self) -> Option<u32> {
+        None
+    }
+
+    fn update(&mut self, _gamepad: &vigem_client::XGamepad) -> anyhow::Result<()> {
+        self.plug_in(None)
+    }
+
+    fn create_another(&self) -> anyhow::Result<Box<dyn PadBackend>> {
+        Ok(Box::new(NullBackend))
+    }
+}
+
+async fn next_pad_health(streamer: &mut NetworkStreamer) -> network::PadHealth {
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match streamer.poll_status() {
+                Some(NetworkStatus::PadHealth(health)) => return health,
+                _ => tokio::time::sleep(Duration::from_millis(5)).await,
+            }
+        }
+    })
+    .await
+    .expect("pad health did not reach the client")
+}
+
+#[tokio::test]
+async fn a_lost_pad_is_plugged_back_in_and_clients_are_told() {
+    let mut server = start_server(true).await;
+    let broken = Arc::new(AtomicBool::new(false));
+    let mut flaky = VirtualController::with_backend(Box::new(FlakyBackend { broken: broken.clone() }));
+    flaky.create_controller().unwrap();
+    *server.virtual_controller.lock().unwrap() = flaky;
+    let mut streamer = connect_client(server.port).await;
+
+    broken.store(true, std::sync::atomic::Ordering::SeqCst);
+    streamer.send_controller_data(client_message(&[(ButtonId::South, true)], &[])).unwrap();
+    next_input(&mut server.events).await;
+    assert!(server.virtual_controller.lock().unwrap().fault().is_some());
+
+    let mut watchdog = pad_watchdog::PadWatchdog::new();
+    let start = std::time::Instant::now();
+    let check = |watchdog: &mut pad_watchdog::PadWatchdog, after_secs: u64| {
+        watchdog.check(&mut server.virtual_controller.lock().unwrap(), start + Duration::from_secs(after_secs))
+    };
+    let failed = check(&mut watchdog, 0).expect("the fault was not noticed");
+    assert!(matches!(failed, pad_watchdog::WatchdogEvent::Failed(_)));
+    server.pad_health.send_replace(failed.health());
+    assert!(matches!(next_pad_health(&mut streamer).await, network::PadHealth::Failed(_)));
+
+    // Still gone on the first retry, so the next one waits twice as long
+    assert_eq!(check(&mut watchdog, 0), None);
+    assert!(matches!(check(&mut watchdog, 1), Some(pad_watchdog::WatchdogEvent::RetryFailed { retry_in, .. }) if retry_in == Duration::from_secs(2)));
+    assert_eq!(check(&mut watchdog, 2), None);
+
+    broken.store(false, std::sync::atomic::Ordering::SeqCst);
+    let recovered = check(&mut watchdog, 3).expect("the pad was not plugged back in");
+    assert_eq!(recovered, pad_watchdog::WatchdogEvent::Recovered { attempts: 2 });
+    {
+        let virtual_controller = server.virtual_controller.lock().unwrap();
+        assert!(virtual_controller.fault().is_none());
+        // What was held while the pad was gone is on it again
+        assert_eq!(virtual_controller.get_button_states().get(&ButtonId::South), Some(&true));
+    }
+    server.pad_health.send_replace(recovered.health());
+    assert_eq!(next_pad_health(&mut streamer).await, network::PadHealth::Working);
+}
+
+// Lands in the second XInput slot, as if another controller had the first
+struct SecondSlotBackend;
+
+impl PadBackend for SecondSlotBackend {
+    fn plug_in(&mut self, _preferred_slot: Option<u32>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn unplug(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn slot(&self) -> Option<u32> {
+        Some(1)
+    }
+
+    fn update(&mut self, _gamepad: &vigem_client::XGamepad) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn create_another(&self) -> anyhow::Result<Box<dyn PadBackend>> {
+        Ok(Box::new(NullBackend))
+    }
+}
+
+async fn next_pad_status(streamer: &mut NetworkStreamer) -> network::PadStatus {
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match streamer.poll_status() {
+                Some(NetworkStatus::PadStatus(status)) => return status,
+                _ => tokio::time::sleep(Duration::from_millis(5)).await,
+            }
+        }
+    })
+    .await
+    .expect("pad status did not reach the client")
+}
+
+#[tokio::test]
+async fn clients_are_told_what_their_input_ends_up_on() {
+    let server = start_server(true).await;
+    let mut second_slot = VirtualController::with_backend(Box::new(SecondSlotBackend));
+    second_slot.create_controller().unwrap();
+    *server.virtual_controller.lock().unwrap() = second_slot;
+    let mut streamer = connect_client(server.port).await;
+    let active = network::PadStatus::Active { pad: "Xbox 360".to_string(), player: Some(2) };
+    assert_eq!(next_pad_status(&mut streamer).await, active);
+
+    server.virtual_controller.lock().unwrap().disconnect_controller().unwrap();
+    assert_eq!(next_pad_status(&mut streamer).await, network::PadStatus::Unplugged);
+
+    server.forwarding_enabled.store(false, std::sync::atomic::Ordering::SeqCst);
+    assert_eq!(next_pad_status(&mut streamer).await, network::PadStatus::Paused);
+}
@@ -0,0 +1,93 @@
+use clap::{value_parser, Arg, ArgAction, Command};
+use std::path::PathBuf;
+use crate::settings::SavedServer;
+
+const DEFAULT_PORT: i32 = 8080;
+
+// How the terminal output is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    // One JSON object per line, see json_log
+    Json,
+}
+
+// The server and poll rate flags only apply to this run, they are never written to the settings
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub server: Option<String>,
+    pub port: Option<i32>,
+    pub connect: bool,
+    pub headless: bool,
+    pub poll_rate_hz: Option<u32>,
+    pub config: Option<PathBuf>,
+    pub log_format: LogFormat,
+}
+
+impl Args {
+    pub fn parse() -> Self {
+        let matches = command().get_matches();
+        Self {
+            server: matches.get_one::<String>("server").cloned(),
+            port: matches.get_one::<i32>("port").copied(),
+            connect: matches.get_flag("connect"),
+            headless: matches.get_flag("headless"),
+            poll_rate_hz: matches.get_one::<u32>("poll-rate").copied(),
+            config: matches.get_one::<PathBuf>("config").cloned(),
+            log_format: match matches.get_one::<String>("log-format").map(String::as_str) {
+                Some("json") => LogFormat::Json,
+                _ => LogFormat::Text,
+            },
+        }
+    }
+
+    // --server wins, otherwise the first saved server, with --port replacing either one's port
+    pub fn target(&self, saved_servers: &[SavedServer]) -> Option<(String, i32)> {
+        let (ip, saved_port) = match &self.server {
+            Some(server) => (server.clone(), DEFAULT_PORT),
+            None => saved_servers.first().map(|server| (server.ip.clone(), server.port))?,
+        };
+        Some((ip, self.port.unwrap_or(saved_port)))
+    }
+}
+
+fn command() -> Command {
+    Command::new("steamdeck")
+        .about("Streams Steam Deck controller input to the server on your PC")
+        .version(env!("CARGO_PKG_VERSION"))
+        .arg(Arg::new("server")
+            .long("server")
+            .short('s')
+            .value_name("ADDRESS")
+            .help("Server to use instead of the first saved one"))
+        .arg(Arg::new("port")
+            .long("port")
+            .short('p')
+            .value_name("PORT")
+            .value_parser(value_parser!(i32).range(1..=65535))
+            .help("Server port [default: the saved server's port, or 8080]"))
+        .arg(Arg::new("connect")
+            .long("connect")
+            .action(ArgAction::SetTrue)
+            .help("Connect to the server on startup"))
+        .arg(Arg::new("headless")
+            .long("headless")
+            .action(ArgAction::SetTrue)
+            .help("Run without a window and stream to the server until Ctrl+C, implies --connect"))
+        .arg(Arg::new("poll-rate")
+            .long("poll-rate")
+            .value_name("HZ")
+            .value_parser(value_parser!(u32).range(1..=1000))
+            .help("How often controllers are polled [default: the Update Rate setting]"))
+        .arg(Arg::new("config")
+            .long("config")
+            .value_name("FILE")
+            .value_parser(value_parser!(PathBuf))
+            .help("Settings file to load and save instead of settings.toml in the config directory"))
+        .arg(Arg::new("log-format")
+            .long("log-format")
+            .value_name("FORMAT")
+            .value_parser(["text", "json"])
+            .default_value("text")
+            .help("Terminal log format, json writes one object per line with the event's fields"))
+}
@@ -0,0 +1,232 @@
+use imgui::*;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use crate::i18n;
+use crate::network::{discover_servers, probe_server, DiscoveryEvent};
+use crate::palette::{self, Status};
+use crate::settings::SavedServer;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WizardStep {
+    Discover,
+    Test,
+    Save,
+}
+
+pub struct ConnectionWizard {
+    open: bool,
+    step: WizardStep,
+    port: String,
+    manual_ip: String,
+    discovered: Vec<String>,
+    selected: Option<usize>,
+    discovery_receiver: Option<mpsc::UnboundedReceiver<DiscoveryEvent>>,
+    test_receiver: Option<oneshot::Receiver<Result<Duration, String>>>,
+    test_result: Option<Result<Duration, String>>,
+    server_name: String,
+}
+
+impl ConnectionWizard {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            step: WizardStep::Discover,
+            port: "8080".to_string(),
+            manual_ip: String::new(),
+            discovered: Vec::new(),
+            selected: None,
+            discovery_receiver: None,
+            test_receiver: None,
+            test_result: None,
+            server_name: String::new(),
+        }
+    }
+
+    pub fn open(&mut self) {
+        *self = Self::new();
+        self.open = true;
+        self.start_discovery();
+    }
+
+    fn start_discovery(&mut self) {
+        let Ok(port) = self.port.parse::<i32>() else {
+            return;
+        };
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.discovered.clear();
+        self.selected = None;
+        self.discovery_receiver = Some(receiver);
+        tokio::spawn(discover_servers(port, sender));
+    }
+
+    fn start_test(&mut self) {
+        let (ip, port) = self.target();
+        let (sender, receiver) = oneshot::channel();
+        self.test_result = None;
+        self.test_receiver = Some(receiver);
+        tokio::spawn(async move {
+            let _ = sender.send(probe_server(&ip, port).await.map_err(|e| e.to_string()));
+        });
+    }
+
+    fn target(&self) -> (String, i32) {
+        let ip = match self.selected {
+            Some(index) => self.discovered[index].clone(),
+            None => self.manual_ip.trim().to_string(),
+        };
+        (ip, self.port.parse().unwrap_or(8080))
+    }
+
+    fn poll(&mut self) {
+        if let Some(ref mut receiver) = self.discovery_receiver {
+            while let Ok(event) = receiver.try_recv() {
+                match event {
+                    DiscoveryEvent::Found(ip) => self.discovered.push(ip),
+                    DiscoveryEvent::Finished => {
+                        self.discovery_receiver = None;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(ref mut receiver) = self.test_receiver {
+            match receiver.try_recv() {
+                Ok(result) => {
+                    self.test_result = Some(result);
+                    self.test_receiver = None;
+                }
+                Err(oneshot::error::TryRecvError::Closed) => self.test_receiver = None,
+                Err(oneshot::error::TryRecvError::Empty) => {}
+            }
+        }
+    }
+
+    // Returns the server to save and connect to once the user finishes the wizard
+    pub fn render(&mut self, ui: &Ui) -> Option<SavedServer> {
+        if !self.open {
+            return None;
+        }
+
+        self.poll();
+
+        let mut finished = None;
+        let mut open = self.open;
+
+        ui.window(i18n::window_title("Connection Wizard"))
+            .size([420.0, 360.0], Condition::FirstUseEver)
+            .opened(&mut open)
+            .build(|| match self.step {
+                WizardStep::Discover => {
+                    ui.text("Step 1 of 3: Find your PC");
+                    ui.separator();
+                    ui.text_wrapped("Start server.exe on your PC, then pick it from the list below.");
+
+                    ui.set_next_item_width(100.0);
+                    ui.input_text("Port", &mut self.port).build();
+
+                    let scanning = self.discovery_receiver.is_some();
+                    {
+                        let _disabled = ui.begin_disabled(scanning);
+                        if ui.button("Scan Network") {
+                            self.start_discovery();
+                        }
+                    }
+                    if scanning {
+                        ui.same_line();
+                        ui.text("Scanning...");
+                    }
+
+                    ui.child_window("discovered_servers")
+                        .size([0.0, 120.0])
+                        .border(true)
+                        .build(|| {
+                            if self.discovered.is_empty() && !scanning {
+                                ui.text_disabled("No servers found");
+                            }
+                            for (index, ip) in self.discovered.iter().enumerate() {
+                                if ui.selectable_config(ip).selected(self.selected == Some(index)).build() {
+                                    self.selected = Some(index);
+                                }
+                            }
+                        });
+
+                    ui.text("Not listed? Enter the address shown on the server:");
+                    if ui.input_text("Server IP", &mut self.manual_ip).build() {
+                        self.selected = None;
+                    }
+
+                    ui.separator();
+                    let has_target = self.selected.is_some() || !self.manual_ip.trim().is_empty();
+                    let _disabled = ui.begin_disabled(!has_target || self.port.parse::<i32>().is_err());
+                    if ui.button("Next") {
+                        self.step = WizardStep::Test;
+                        self.start_test();
+                    }
+                }
+                WizardStep::Test => {
+                    let (ip, port) = self.target();
+                    ui.text("Step 2 of 3: Test the connection");
+                    ui.separator();
+                    ui.text(&format!("Server: {}:{}", ip, port));
+
+                    match &self.test_result {
+                        None => ui.text("Testing..."),
+                        Some(Ok(round_trip)) => palette::status_text(
+                            ui,
+                            Status::Good,
+                            format!("Reachable - round trip {} ms", round_trip.as_millis()),
+                        ),
+                        Some(Err(e)) => {
+                            palette::status_text(ui, Status::Bad, "Server not reachable");
+                            ui.text_wrapped(e);
+                            ui.text_wrapped("Check that the server is running and that both devices are on the same network.");
+                        }
+                    }
+
+                    ui.separator();
+                    if ui.button("Back") {
+                        self.step = WizardStep::Discover;
+                    }
+                    ui.same_line();
+                    {
+                        let _disabled = ui.begin_disabled(self.test_receiver.is_some());
+                        if ui.button("Retry") {
+                            self.start_test();
+                        }
+                    }
+                    ui.same_line();
+                    let _disabled = ui.begin_disabled(!matches!(self.test_result, Some(Ok(_))));
+                    if ui.button("Next") {
+                        self.server_name = ip;
+                        self.step = WizardStep::Save;
+                    }
+                }
+                WizardStep::Save => {
+                    let (ip, port) = self.target();
+                    ui.text("Step 3 of 3: Save");
+                    ui.separator();
+                    ui.text(&format!("Server: {}:{}", ip, port));
+                    ui.input_text("Name", &mut self.server_name).build();
+
+                    ui.separator();
+                    if ui.button("Back") {
+                        self.step = WizardStep::Test;
+                    }
+                    ui.same_line();
+                    if ui.button("Save & Connect") {
+                        let name = if self.server_name.trim().is_empty() {
+                            ip.clone()
+                        } else {
+                            self.server_name.trim().to_string()
+                        };
+                        finished = Some(SavedServer { name, ip, port });
+                    }
+                }
+            });
+
+        self.open = open && finished.is_none();
+        finished
+    }
+}
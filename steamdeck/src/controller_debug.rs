@@ -1,13 +1,45 @@
-use gilrs::{GamepadId, EventType, Button, Axis};
+use gilrs::{Button, Axis};
 use imgui::*;
-use std::collections::HashMap;
-use std::time::Instant;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use crate::capabilities::{Capabilities, SENSOR_ACCELEROMETER, SENSOR_GYRO, SENSOR_TOUCHPAD};
+use crate::debug_json::DebugJsonViewer;
+use crate::i18n::{self, tr};
+use crate::delivery::DeliveryStats;
+use crate::input_filter::DEFAULT_AXIS_EPSILON;
+use crate::input_source::{ControllerInfo, DeckModel, InputEvent};
+use crate::link_quality::{LinkMonitor, LinkQuality};
+use crate::network::{axis_to_id, button_to_id, ControllerInputData, PadHealth, PadStatus, ProfileList, SEND_RATES};
+use crate::network_sim::NetworkConditions;
+use crate::palette::{self, Status};
+use crate::settings::{SavedServer, Transport, TriggerSettings};
 use crate::steam_input::SteamInputManager;
+use crate::steam_vdf;
+
+const ALL_BUTTONS: [Button; 17] = [
+    Button::South, Button::East, Button::North, Button::West,
+    Button::LeftTrigger, Button::RightTrigger, Button::LeftTrigger2, Button::RightTrigger2,
+    Button::Select, Button::Start, Button::Mode,
+    Button::LeftThumb, Button::RightThumb,
+    Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+];
+
+const ALL_AXES: [Axis; 8] = [
+    Axis::LeftStickX, Axis::LeftStickY, Axis::RightStickX, Axis::RightStickY,
+    Axis::LeftZ, Axis::RightZ, Axis::DPadX, Axis::DPadY,
+];
 
 #[derive(Debug, Clone)]
 pub struct ControllerState {
-    pub id: GamepadId,
+    pub id: u32,
     pub name: String,
+    pub uuid: String,
+    // vvvv:pppp, empty when the device didn't report its USB IDs
+    pub usb_id: String,
+    pub deck_model: Option<DeckModel>,
+    // None until the input source has probed the device, everything is shown until then
+    pub capabilities: Option<Capabilities>,
     pub buttons: HashMap<Button, bool>,
     pub axes: HashMap<Axis, f32>,
     pub last_activity: Instant,
@@ -15,10 +47,14 @@ pub struct ControllerState {
 }
 
 impl ControllerState {
-    pub fn new(id: GamepadId, name: String) -> Self {
+    pub fn new(id: u32, name: String) -> Self {
         Self {
             id,
             name,
+            uuid: String::new(),
+            usb_id: String::new(),
+            deck_model: None,
+            capabilities: None,
             buttons: HashMap::new(),
             axes: HashMap::new(),
             last_activity: Instant::now(),
@@ -26,6 +62,20 @@ impl ControllerState {
         }
     }
 
+    pub fn has_button(&self, button: Button) -> bool {
+        match (self.capabilities, button_to_id(button)) {
+            (Some(capabilities), Some(id)) => capabilities.has_button(id),
+            _ => true,
+        }
+    }
+
+    pub fn has_axis(&self, axis: Axis) -> bool {
+        match (self.capabilities, axis_to_id(axis)) {
+            (Some(capabilities), Some(id)) => capabilities.has_axis(id),
+            _ => true,
+        }
+    }
+
     pub fn update_button(&mut self, button: Button, pressed: bool) {
         self.buttons.insert(button, pressed);
         self.last_activity = Instant::now();
@@ -38,15 +88,21 @@ impl ControllerState {
 }
 
 pub struct ControllerDebugUI {
-    controllers: HashMap<GamepadId, ControllerState>,
+    controllers: HashMap<u32, ControllerState>,
     show_raw_input: bool,
     show_steam_input: bool,
     show_controller_mapping: bool,
     show_input_history: bool,
     show_debug_json: bool,
-    input_history: Vec<String>,
+    debug_json: DebugJsonViewer,
+    // Result of the last Steam config export, shown under its button
+    steam_export_status: Option<(bool, String)>,
+    steam_import_path: String,
+    should_import_steam_config: bool,
+    input_history: VecDeque<(Instant, String)>,
     max_history_size: usize,
-    steam_input_data: Option<SteamInputData>,
+    // Entries older than this are dropped, 0 keeps them regardless of age
+    max_history_age_secs: u64,
     // Network-related fields
     connection_status: String,
     network_enabled: bool,
@@ -55,14 +111,56 @@ pub struct ControllerDebugUI {
     should_connect: bool,
     should_disconnect: bool,
     sync_enabled: bool,
-}
-
-#[derive(Debug, Clone)]
-pub struct SteamInputData {
-    pub digital_actions: HashMap<String, bool>,
-    pub analog_actions: HashMap<String, (f32, f32)>,
-    pub controller_count: usize,
-    pub connected_controllers: Vec<String>,
+    saved_servers: Vec<SavedServer>,
+    selected_server: usize,
+    // Reordered or removed in the window, handed back through servers_update
+    servers_changed: bool,
+    failover: bool,
+    failover_changed: bool,
+    // Index into saved_servers of the server in use
+    active_server: Option<usize>,
+    // Waiting to fail over, Disconnect stops it
+    failover_pending: bool,
+    // From the server's last acknowledgement, None until one arrives on this connection
+    delivery: Option<DeliveryStats>,
+    // Fed from the acknowledgements, for the quality score
+    link: LinkMonitor,
+    should_open_wizard: bool,
+    send_rate_hz: u32,
+    send_rate_changed: bool,
+    axis_epsilon: f32,
+    axis_epsilon_changed: bool,
+    hid_passthrough: bool,
+    hid_passthrough_changed: bool,
+    pc_controller: bool,
+    pc_controller_changed: bool,
+    local_pad: bool,
+    local_pad_changed: bool,
+    pause_when_unfocused: bool,
+    pause_when_unfocused_changed: bool,
+    // Forwarding is held back because the window lost focus
+    paused: bool,
+    // Decides when analog trigger buttons show as pressed
+    triggers: TriggerSettings,
+    transport: Transport,
+    transport_changed: bool,
+    recording: bool,
+    should_toggle_recording: bool,
+    replay_path: String,
+    // Some while a replay is running
+    replay_progress: Option<f32>,
+    should_toggle_replay: bool,
+    network_conditions: NetworkConditions,
+    network_conditions_changed: bool,
+    // Advertised by the server, empty until it sent its list
+    server_profiles: ProfileList,
+    // Of the server's virtual pad, as last reported
+    pad_health: PadHealth,
+    // What this Deck's input ends up on, None until the server says
+    pad_status: Option<PadStatus>,
+    // Requested on every connect, empty leaves the server's active profile alone
+    profile: String,
+    profile_changed: bool,
 }
 
 impl ControllerDebugUI {
@@ -74,9 +172,13 @@ impl ControllerDebugUI {
             show_controller_mapping: true,
             show_input_history: true,
             show_debug_json: true,
-            input_history: Vec::new(),
+            debug_json: DebugJsonViewer::new(),
+            steam_export_status: None,
+            steam_import_path: String::new(),
+            should_import_steam_config: false,
+            input_history: VecDeque::new(),
             max_history_size: 100,
-            steam_input_data: None,
+            max_history_age_secs: 0,
             connection_status: "Disconnected".to_string(),
             network_enabled: false,
             server_ip: "192.168.1.185".to_string(),
@@ -84,35 +186,73 @@ impl ControllerDebugUI {
             should_connect: false,
             should_disconnect: false,
             sync_enabled: false,
+            saved_servers: Vec::new(),
+            selected_server: 0,
+            servers_changed: false,
+            failover: true,
+            failover_changed: false,
+            active_server: None,
+            failover_pending: false,
+            delivery: None,
+            link: LinkMonitor::new(),
+            should_open_wizard: false,
+            send_rate_hz: 0,
+            send_rate_changed: false,
+            axis_epsilon: DEFAULT_AXIS_EPSILON,
+            axis_epsilon_changed: false,
+            hid_passthrough: false,
+            hid_passthrough_changed: false,
+            pc_controller: false,
+            pc_controller_changed: false,
+            local_pad: false,
+            local_pad_changed: false,
+            pause_when_unfocused: false,
+            pause_when_unfocused_changed: false,
+            paused: false,
+            triggers: TriggerSettings::default(),
+            transport: Transport::Network,
+            transport_changed: false,
+            recording: false,
+            should_toggle_recording: false,
+            replay_path: String::new(),
+            replay_progress: None,
+            should_toggle_replay: false,
+            network_conditions: NetworkConditions::default(),
+            network_conditions_changed: false,
+            server_profiles: ProfileList::default(),
+            pad_health: PadHealth::Working,
+            pad_status: None,
+            profile: String::new(),
+            profile_changed: false,
         }
     }
 
-    pub fn handle_gilrs_event(&mut self, id: GamepadId, event: EventType, _time: f64) {
+    pub fn handle_input_event(&mut self, id: u32, event: InputEvent, _time: f64) {
         match event {
-            EventType::Connected => {
+            InputEvent::Connected => {
                 let name = format!("Controller {}", id);
                 self.controllers.insert(id, ControllerState::new(id, name.clone()));
                 self.add_to_history(format!("Controller {} connected: {}", id, name));
             }
-            EventType::Disconnected => {
+            InputEvent::Disconnected => {
                 if let Some(controller) = self.controllers.get_mut(&id) {
                     controller.connected = false;
                     self.add_to_history(format!("Controller {} disconnected", id));
                 }
             }
-            EventType::ButtonPressed(button, _) => {
+            InputEvent::ButtonPressed(button) => {
                 if let Some(controller) = self.controllers.get_mut(&id) {
                     controller.update_button(button, true);
                     self.add_to_history(format!("Controller {} - Button {:?} pressed", id, button));
                 }
             }
-            EventType::ButtonReleased(button, _) => {
+            InputEvent::ButtonReleased(button) => {
                 if let Some(controller) = self.controllers.get_mut(&id) {
                     controller.update_button(button, false);
                     self.add_to_history(format!("Controller {} - Button {:?} released", id, button));
                 }
             }
-            EventType::AxisChanged(axis, value, _) => {
+            InputEvent::AxisChanged(axis, value) => {
                 if let Some(controller) = self.controllers.get_mut(&id) {
                     controller.update_axis(axis, value);
                     
@@ -124,67 +264,87 @@ impl ControllerDebugUI {
                     }
                 }
             }
-            EventType::ButtonChanged(button, value, _) => {
+            InputEvent::ButtonChanged(button, value) => {
                 if let Some(controller) = self.controllers.get_mut(&id) {
-                    controller.update_button(button, value > 0.5);
+                    controller.update_button(button, self.triggers.button_pressed(button, value));
                     self.add_to_history(format!("Controller {} - Button {:?} changed: {:.3}", id, button, value));
                 }
             }
-            _ => {}
         }
     }
 
-    pub fn update_steam_input(&mut self, steam_input: &SteamInputManager) {
-        self.steam_input_data = Some(SteamInputData {
-            digital_actions: steam_input.get_digital_actions(),
-            analog_actions: steam_input.get_analog_actions(),
-            controller_count: steam_input.get_controller_count(),
-            connected_controllers: steam_input.get_connected_controllers(),
-        });
+    // Fills in the details the input source knows about a pad, registering it if we missed its Connected event
+    pub fn set_controller_info(&mut self, info: ControllerInfo) {
+        let controller = self.controllers.entry(info.controller_id)
+            .or_insert_with(|| ControllerState::new(info.controller_id, info.name.clone()));
+        controller.name = info.name;
+        controller.uuid = format_uuid(&info.uuid);
+        controller.usb_id = match (info.vendor_id, info.product_id) {
+            (Some(vendor_id), Some(product_id)) => format!("{:04x}:{:04x}", vendor_id, product_id),
+            _ => String::new(),
+        };
+        controller.deck_model = info.deck_model;
+        controller.capabilities = Some(info.capabilities);
+    }
+
+    // Ties the events above to the message that carried them, the server's Controller Events
+    // window lists each event under the same number
+    pub fn add_sent_message(&mut self, message_id: u64, data: &ControllerInputData) {
+        let events: Vec<String> = data.button_events.iter()
+            .map(|event| format!("{} {}", event.button, if event.pressed { "Pressed" } else { "Released" }))
+            .chain(data.axis_events.iter().map(|event| format!("{} {:.3}", event.axis, event.value)))
+            .collect();
+        self.add_to_history(format!("Controller {} - Sent #{}: {}", data.controller_id, message_id, events.join(", ")));
     }
 
     fn add_to_history(&mut self, message: String) {
-        self.input_history.push(format!("[{}] {}", 
+        self.input_history.push_back((Instant::now(), format!("[{}] {}", 
             chrono::Utc::now().format("%H:%M:%S%.3f"), 
-            message));
+            message)));
         
-        if self.input_history.len() > self.max_history_size {
-            self.input_history.remove(0);
+        self.prune_history();
+    }
+
+    fn prune_history(&mut self) {
+        while self.input_history.len() > self.max_history_size {
+            self.input_history.pop_front();
+        }
+
+        if self.max_history_age_secs > 0 {
+            let max_age = Duration::from_secs(self.max_history_age_secs);
+            while self.input_history.front().is_some_and(|(time, _)| time.elapsed() > max_age) {
+                self.input_history.pop_front();
+            }
         }
     }
 
     pub fn render(&mut self, ui: &Ui, steam_input: &SteamInputManager) {
         // Main menu bar
         ui.main_menu_bar(|| {
-            ui.menu("View", || {
-                ui.checkbox("Raw Input", &mut self.show_raw_input);
-                ui.checkbox("Steam Input", &mut self.show_steam_input);
-                ui.checkbox("Controller Mapping", &mut self.show_controller_mapping);
-                ui.checkbox("Input History", &mut self.show_input_history);
-                ui.checkbox("Debug JSON", &mut self.show_debug_json);
+            ui.menu(tr("View"), || {
+                ui.checkbox(tr("Raw Input"), &mut self.show_raw_input);
+                ui.checkbox(tr("Steam Input"), &mut self.show_steam_input);
+                ui.checkbox(tr("Controller Mapping"), &mut self.show_controller_mapping);
+                ui.checkbox(tr("Input History"), &mut self.show_input_history);
+                ui.checkbox(tr("Debug JSON"), &mut self.show_debug_json);
             });
-            ui.menu("Debug", || {
-                if ui.menu_item("Test Trigger Detection") {
+            ui.menu(tr("Debug"), || {
+                if ui.menu_item(tr("Test Trigger Detection")) {
                     self.add_to_history("Testing trigger detection - press triggers now".to_string());
                 }
             });
         });
 
         // Controller overview
-        ui.window("Controller Overview")
+        ui.window(i18n::window_title("Controller Overview"))
             .size([400.0, 300.0], Condition::FirstUseEver)
             .build(|| {
-                ui.text(&format!("Connected Controllers: {}", self.controllers.len()));
+                ui.text(format!("{} {}", tr("Connected Controllers:"), self.controllers.len()));
                 ui.separator();
                 
                 for (id, controller) in &self.controllers {
-                    let color = if controller.connected {
-                        [0.0, 1.0, 0.0, 1.0] // Green for connected
-                    } else {
-                        [1.0, 0.0, 0.0, 1.0] // Red for disconnected
-                    };
-                    
-                    ui.text_colored(color, &format!("Controller {}: {}", id, controller.name));
+                    let status = if controller.connected { Status::Good } else { Status::Bad };
+                    palette::status_text(ui, status, format!("Controller {}: {}", id, controller.name));
                     ui.text(&format!("  Last Activity: {:.2}s ago", 
                         controller.last_activity.elapsed().as_secs_f32()));
                     ui.text(&format!("  Buttons: {} pressed", 
@@ -194,65 +354,25 @@ impl ControllerDebugUI {
                 }
             });
 
-        // Raw input display
+        // Raw input display, one tab per controller
         if self.show_raw_input {
-            ui.window("Raw Controller Input")
+            ui.window(i18n::window_title("Raw Controller Input"))
                 .size([500.0, 400.0], Condition::FirstUseEver)
                 .build(|| {
-                    for (id, controller) in &self.controllers {
-                        if ui.collapsing_header(&format!("Controller {} - {}", id, controller.name), TreeNodeFlags::empty()) {
-                            ui.text("Buttons:");
-                            ui.indent();
-                            for (button, &pressed) in &controller.buttons {
-                                let color = if pressed {
-                                    [0.0, 1.0, 0.0, 1.0]
-                                } else {
-                                    [0.7, 0.7, 0.7, 1.0]
-                                };
-                                ui.text_colored(color, &format!("{:?}: {}", button, pressed));
-                            }
-                            ui.unindent();
-                            
-                            ui.text("Axes:");
-                            ui.indent();
-                            
-                            // Show triggers separately for better visibility
-                            ui.text("Triggers:");
-                            ui.indent();
-                            if let Some(&lt_value) = controller.axes.get(&Axis::LeftZ) {
-                                let color = if lt_value > 0.01 {
-                                    [1.0, 0.5, 0.0, 1.0] // Orange for active triggers
-                                } else {
-                                    [0.7, 0.7, 0.7, 1.0]
-                                };
-                                ui.text_colored(color, &format!("Left Trigger (LeftZ): {:.3}", lt_value));
-                            }
-                            if let Some(&rt_value) = controller.axes.get(&Axis::RightZ) {
-                                let color = if rt_value > 0.01 {
-                                    [1.0, 0.5, 0.0, 1.0] // Orange for active triggers
-                                } else {
-                                    [0.7, 0.7, 0.7, 1.0]
-                                };
-                                ui.text_colored(color, &format!("Right Trigger (RightZ): {:.3}", rt_value));
-                            }
-                            ui.unindent();
-                            
-                            ui.text("Other Axes:");
-                            ui.indent();
-                            for (axis, &value) in &controller.axes {
-                                // Skip triggers as they're shown above
-                                if matches!(axis, Axis::LeftZ | Axis::RightZ) {
-                                    continue;
-                                }
-                                
-                                let color = if value.abs() > 0.1 {
-                                    [1.0, 1.0, 0.0, 1.0]
-                                } else {
-                                    [0.7, 0.7, 0.7, 1.0]
-                                };
-                                ui.text_colored(color, &format!("{:?}: {:.3}", axis, value));
+                    if self.controllers.is_empty() {
+                        ui.text("No controllers detected");
+                        return;
+                    }
+                    
+                    let mut ids: Vec<&u32> = self.controllers.keys().collect();
+                    ids.sort();
+                    
+                    if let Some(_tab_bar) = ui.tab_bar("controller_tabs") {
+                        for id in ids {
+                            let controller = &self.controllers[id];
+                            if let Some(_tab) = ui.tab_item(&format!("{} ({})##controller_{}", controller.name, id, id)) {
+                                Self::render_controller_tab(ui, controller);
                             }
-                            ui.unindent();
                         }
                     }
                 });
@@ -260,23 +380,24 @@ impl ControllerDebugUI {
 
         // Steam Input display
         if self.show_steam_input {
-            ui.window("Steam Input")
+            ui.window(i18n::window_title("Steam Input"))
                 .size([500.0, 400.0], Condition::FirstUseEver)
                 .build(|| {
-                    if let Some(ref steam_data) = self.steam_input_data {
-                        ui.text(&format!("Steam Controllers: {}", steam_data.controller_count));
+                    // Borrowed straight from the manager, nothing is copied per frame
+                    if steam_input.is_initialized() {
+                        ui.text(&format!("Steam Controllers: {}", steam_input.get_controller_count()));
                         ui.separator();
                         
                         if ui.collapsing_header("Connected Controllers", TreeNodeFlags::empty()) {
-                            for controller in &steam_data.connected_controllers {
+                            for controller in steam_input.get_connected_controllers() {
                                 ui.text(&format!("• {}", controller));
                             }
                         }
                         
                         if ui.collapsing_header("Digital Actions", TreeNodeFlags::empty()) {
-                            for (action, &active) in &steam_data.digital_actions {
+                            for (action, &active) in steam_input.get_digital_actions() {
                                 let color = if active {
-                                    [0.0, 1.0, 0.0, 1.0]
+                                    palette::color(Status::Good)
                                 } else {
                                     [0.7, 0.7, 0.7, 1.0]
                                 };
@@ -285,16 +406,38 @@ impl ControllerDebugUI {
                         }
                         
                         if ui.collapsing_header("Analog Actions", TreeNodeFlags::empty()) {
-                            for (action, &(x, y)) in &steam_data.analog_actions {
+                            for (action, &(x, y)) in steam_input.get_analog_actions() {
                                 let magnitude = (x * x + y * y).sqrt();
                                 let color = if magnitude > 0.1 {
-                                    [1.0, 1.0, 0.0, 1.0]
+                                    palette::color(Status::Warning)
                                 } else {
                                     [0.7, 0.7, 0.7, 1.0]
                                 };
                                 ui.text_colored(color, &format!("{}: ({:.3}, {:.3})", action, x, y));
                             }
                         }
+
+                        ui.separator();
+                        if ui.button("Export Steam Config") {
+                            self.steam_export_status = Some(export_steam_config(steam_input));
+                        }
+                        if ui.is_item_hovered() {
+                            ui.tooltip_text("Writes these mappings as a Steam In-Game Actions file and a Deck controller config");
+                        }
+                        if let Some((ok, status)) = &self.steam_export_status {
+                            palette::status_text(ui, if *ok { Status::Good } else { Status::Bad }, status);
+                        }
+
+                        ui.input_text("##steam_config", &mut self.steam_import_path)
+                            .hint("Path to a Steam controller config (.vdf)")
+                            .build();
+                        ui.same_line();
+                        if ui.button("Import Steam Config") && !self.steam_import_path.trim().is_empty() {
+                            self.should_import_steam_config = true;
+                        }
+                        if ui.is_item_hovered() {
+                            ui.tooltip_text("Replaces these mappings with the game actions the config binds");
+                        }
                     } else {
                         ui.text("Steam Input not available");
                         ui.text("Make sure Steam is running and the game is launched through Steam");
@@ -304,7 +447,7 @@ impl ControllerDebugUI {
 
         // Controller mapping display
         if self.show_controller_mapping {
-            ui.window("Controller Mapping")
+            ui.window(i18n::window_title("Controller Mapping"))
                 .size([400.0, 300.0], Condition::FirstUseEver)
                 .build(|| {
                     ui.text("Button Mapping:");
@@ -336,7 +479,8 @@ impl ControllerDebugUI {
 
         // Input history
         if self.show_input_history {
-            ui.window("Input History")
+            self.prune_history();
+            ui.window(i18n::window_title("Input History"))
                 .size([600.0, 300.0], Condition::FirstUseEver)
                 .build(|| {
                     if ui.button("Clear History") {
@@ -345,12 +489,18 @@ impl ControllerDebugUI {
                     ui.same_line();
                     ui.text(&format!("({}/{} entries)", self.input_history.len(), self.max_history_size));
                     
+                    ui.set_next_item_width(150.0);
+                    ui.slider("Max entries", 10, 10000, &mut self.max_history_size);
+                    ui.same_line();
+                    ui.set_next_item_width(150.0);
+                    ui.slider("Max age (s, 0 = unlimited)", 0, 3600, &mut self.max_history_age_secs);
+                    
                     ui.separator();
                     
                     ui.child_window("history_scroll")
                         .size([0.0, 0.0])
                         .build(|| {
-                            for entry in &self.input_history {
+                            for (_, entry) in &self.input_history {
                                 ui.text(entry);
                             }
                         });
@@ -358,69 +508,343 @@ impl ControllerDebugUI {
         }
 
         // Network settings
-        ui.window("Network Settings")
+        ui.window(i18n::window_title("Network Settings"))
             .size([400.0, 300.0], Condition::FirstUseEver)
             .build(|| {
-                ui.text(&format!("Connection Status: {}", self.connection_status));
+                ui.text(format!("{} {}", tr("Connection Status:"), self.connection_status));
+                let names: Vec<&str> = Transport::ALL.iter().map(|transport| transport.name()).collect();
+                let mut transport_index = Transport::ALL.iter().position(|&transport| transport == self.transport).unwrap_or(0);
+                if ui.combo_simple_string(tr("Transport"), &mut transport_index, &names) {
+                    self.transport = Transport::ALL[transport_index];
+                    self.transport_changed = true;
+                }
+                match self.transport {
+                    Transport::UsbGadget => ui.text_wrapped("Input goes straight to the PC over the USB-C cable, the server isn't used. Needs root and USB Dual-Role Device set to DRD in the BIOS."),
+                    Transport::Bluetooth => ui.text_wrapped("Pair the PC with the Deck like any Bluetooth controller, the server isn't used. Needs bluetoothd started with --noplugin=input."),
+                    Transport::Network => {}
+                }
                 ui.separator();
                 
-                ui.input_text("Server IP", &mut self.server_ip).build();
-                ui.input_text("Server Port", &mut self.server_port).build();
+                if !self.saved_servers.is_empty() {
+                    let names: Vec<String> = self.saved_servers.iter()
+                        .map(|server| format!("{} ({}:{})", server.name, server.ip, server.port))
+                        .collect();
+                    if ui.combo_simple_string(tr("Server"), &mut self.selected_server, &names) {
+                        let server = &self.saved_servers[self.selected_server];
+                        self.server_ip = server.ip.clone();
+                        self.server_port = server.port.to_string();
+                    }
+                }
+                
+                if ui.checkbox(tr("Fail Over to the Next Server"), &mut self.failover) {
+                    self.failover_changed = true;
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(tr("When the server can't be reached, tries the saved servers in order until one answers"));
+                }
+                if !self.saved_servers.is_empty() && ui.collapsing_header(tr("Server Order"), TreeNodeFlags::empty()) {
+                    self.render_server_order(ui);
+                }
+                
+                if ui.button(tr("Connection Wizard...")) {
+                    self.should_open_wizard = true;
+                }
+                
+                if ui.collapsing_header(tr("Manual Address"), TreeNodeFlags::empty()) {
+                    ui.input_text(tr("Server IP"), &mut self.server_ip).build();
+                    ui.input_text(tr("Server Port"), &mut self.server_port).build();
+                }
                 
                 ui.separator();
                 
-                ui.checkbox("Enable Sync (Send all data every 200ms)", &mut self.sync_enabled);
+                let rates: Vec<u32> = std::iter::once(0).chain(SEND_RATES).collect();
+                let labels: Vec<String> = rates.iter()
+                    .map(|&rate| if rate == 0 { tr("On Change").to_string() } else { format!("Fixed {} Hz", rate) })
+                    .collect();
+                let mut rate_index = rates.iter().position(|&rate| rate == self.send_rate_hz).unwrap_or(0);
+                if ui.combo_simple_string(tr("Transmission"), &mut rate_index, &labels) {
+                    self.send_rate_hz = rates[rate_index];
+                    self.send_rate_changed = true;
+                }
+                
+                ui.slider_config(tr("Axis Deadband"), 0.0, 0.1)
+                    .display_format("%.3f")
+                    .build(&mut self.axis_epsilon);
+                // Applied once the slider is released so dragging doesn't rewrite the settings file
+                if ui.is_item_deactivated_after_edit() {
+                    self.axis_epsilon_changed = true;
+                }
+                
+                if ui.checkbox(tr("Raw HID Passthrough (Advanced)"), &mut self.hid_passthrough) {
+                    self.hid_passthrough_changed = true;
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Also forwards the controller's raw HID reports, for servers that recreate the device or read touchpad pressure and motion");
+                }
+                
+                if ui.checkbox(tr("Play the PC's Controller"), &mut self.pc_controller) {
+                    self.pc_controller_changed = true;
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("A controller the server streams to the Deck shows up here as a gamepad, for when the Deck runs the game");
+                }
+
+                if ui.checkbox(tr("Virtual Pad on This Deck"), &mut self.local_pad) {
+                    self.local_pad_changed = true;
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Also plays the input through a virtual Xbox 360 pad here, for games running on the Deck. Works without a PC and keeps going while this window is in the background.");
+                }
+
+                if ui.checkbox(tr("Pause When Unfocused"), &mut self.pause_when_unfocused) {
+                    self.pause_when_unfocused_changed = true;
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Releases everything on the PC and stops forwarding while this window is in the background, such as while the Steam overlay is open");
+                }
+                
+                ui.checkbox(tr("Enable Sync (Send all data every 200ms)"), &mut self.sync_enabled);
                 if self.sync_enabled {
-                    ui.text_colored([0.0, 1.0, 0.0, 1.0], "✓ Syncs all controller data every 200ms to reset positions");
+                    palette::status_text(ui, Status::Good, tr("Syncs all controller data every 200ms to reset positions"));
+                }
+                
+                if ui.collapsing_header(tr("Record / Replay"), TreeNodeFlags::empty()) {
+                    let record_label = if self.recording { "Stop Recording" } else { "Record" };
+                    if ui.button(record_label) {
+                        self.should_toggle_recording = true;
+                    }
+                    if self.recording {
+                        ui.same_line();
+                        ui.text_colored(palette::color(Status::Bad), "REC");
+                    }
+                    
+                    ui.input_text("Replay File", &mut self.replay_path).build();
+                    let replay_label = if self.replay_progress.is_some() { "Stop Replay" } else { "Replay" };
+                    if ui.button(replay_label) {
+                        self.should_toggle_replay = true;
+                    }
+                    if let Some(progress) = self.replay_progress {
+                        ui.same_line();
+                        ui.text(&format!("{:.0}%", progress * 100.0));
+                    }
+                }
+                
+                if ui.collapsing_header(tr("Network Simulation"), TreeNodeFlags::empty()) {
+                    palette::status_text(ui, Status::Warning, "Testing only: delays and drops outgoing input");
+                    let conditions = &mut self.network_conditions;
+                    ui.slider("Latency (ms)", 0, 500, &mut conditions.latency_ms);
+                    let mut edited = ui.is_item_deactivated_after_edit();
+                    ui.slider("Jitter (ms)", 0, 200, &mut conditions.jitter_ms);
+                    edited |= ui.is_item_deactivated_after_edit();
+                    ui.slider_config("Loss (%)", 0.0, 50.0)
+                        .display_format("%.1f")
+                        .build(&mut conditions.loss_percent);
+                    edited |= ui.is_item_deactivated_after_edit();
+                    ui.slider_config("Reorder (%)", 0.0, 50.0)
+                        .display_format("%.1f")
+                        .build(&mut conditions.reorder_percent);
+                    edited |= ui.is_item_deactivated_after_edit();
+                    if ui.button("Reset Simulation") {
+                        *conditions = NetworkConditions::default();
+                        edited = true;
+                    }
+                    if edited {
+                        self.network_conditions_changed = true;
+                    }
                 }
                 
                 ui.separator();
                 
-                let can_connect = !self.network_enabled && !self.should_connect;
-                let can_disconnect = self.network_enabled && !self.should_disconnect;
+                let can_connect = !self.network_enabled && !self.should_connect && self.transport == Transport::Network;
+                let can_disconnect = (self.network_enabled || self.failover_pending) && !self.should_disconnect;
                 
-                if ui.button("Connect") && can_connect {
+                if ui.button(tr("Connect")) && can_connect {
                     self.should_connect = true;
                     self.connection_status = "Connecting...".to_string();
                 }
                 ui.same_line();
-                if ui.button("Disconnect") && can_disconnect {
+                if ui.button(tr("Disconnect")) && can_disconnect {
                     self.should_disconnect = true;
                     self.connection_status = "Disconnecting...".to_string();
                 }
                 
                 ui.separator();
-                ui.text("Network Status:");
-                let status_color = if self.network_enabled {
-                    [0.0, 1.0, 0.0, 1.0] // Green for connected
+                ui.text(tr("Network Status:"));
+                if self.network_enabled {
+                    palette::status_text(ui, Status::Good, tr("Connected"));
                 } else {
-                    [1.0, 0.0, 0.0, 1.0] // Red for disconnected
-                };
-                ui.text_colored(status_color, if self.network_enabled { "Connected" } else { "Disconnected" });
+                    palette::status_text(ui, Status::Bad, tr("Disconnected"));
+                }
+                if self.paused {
+                    palette::status_text(ui, Status::Warning, tr("Paused, the window isn't focused"));
+                }
                 
                 if self.network_enabled {
-                    ui.text(&format!("Connected to: {}:{}", self.server_ip, self.server_port));
+                    ui.text(format!("{} {}:{}", tr("Connected to:"), self.server_ip, self.server_port));
+                    if let Some(server) = self.active_server.and_then(|index| self.saved_servers.get(index)) {
+                        ui.text(format!("{} {}", tr("Active server:"), server.name));
+                    }
+                    match &self.delivery {
+                        Some(delivery) => {
+                            ui.text(format!("In flight: {}  Ack latency: {:.1} ms", delivery.in_flight, delivery.ack_latency.as_secs_f64() * 1000.0));
+                            if let Some(retransmissions) = delivery.retransmissions {
+                                ui.text(format!("TCP retransmissions: {}", retransmissions));
+                            }
+                        }
+                        None => ui.text_disabled(tr("No acknowledgements from the server yet")),
+                    }
+                    ui.text(tr("Connection Quality:"));
+                    match self.link.quality(Instant::now()) {
+                        Some(quality) => render_link_quality(ui, &quality),
+                        None => ui.text_disabled(tr("Not enough traffic to rate the connection yet")),
+                    }
+                    if let Some(pad_status) = &self.pad_status {
+                        let (status, text) = pad_status_text(pad_status);
+                        palette::status_text(ui, status, text);
+                    }
+                    if let PadHealth::Failed(error) = &self.pad_health {
+                        palette::status_text(ui, Status::Bad, tr("The PC's virtual controller isn't responding, the server is plugging it back in"));
+                        ui.text_disabled(error);
+                    }
+                    self.render_profile_picker(ui);
                 }
             });
 
-        // Debug JSON display
         if self.show_debug_json {
-            ui.window("Debug JSON")
-                .size([600.0, 500.0], Condition::FirstUseEver)
-                .build(|| {
-                    ui.text("Complete controller state as JSON:");
-                    ui.separator();
-                    
-                    // Get the JSON debug data from Steam Input Manager
-                    let debug_json = steam_input.get_debug_json();
-                    
-                    // Use a scrollable text area for the JSON
-                    ui.child_window("json_scroll")
-                        .size([0.0, 0.0])
-                        .build(|| {
-                            ui.text_wrapped(&debug_json);
-                        });
-                });
+            self.debug_json.render(ui, &mut self.show_debug_json, steam_input);
+        }
+    }
+
+    // The saved servers in the order failover tries them, the one in use marked
+    fn render_server_order(&mut self, ui: &Ui) {
+        let mut remove = None;
+        let mut move_up = None;
+        for (index, server) in self.saved_servers.iter().enumerate() {
+            let _id = ui.push_id_usize(index);
+            let label = format!("{}. {} ({}:{})", index + 1, server.name, server.ip, server.port);
+            if self.active_server == Some(index) {
+                ui.text_colored(palette::color(Status::Good), format!("{}  [active]", label));
+            } else {
+                ui.text(label);
+            }
+            ui.same_line();
+            if index > 0 && ui.small_button("Up") {
+                move_up = Some(index);
+            }
+            ui.same_line();
+            if ui.small_button("Remove") {
+                remove = Some(index);
+            }
+        }
+        if let Some(index) = move_up {
+            self.saved_servers.swap(index - 1, index);
+            self.servers_changed = true;
+        }
+        if let Some(index) = remove {
+            self.saved_servers.remove(index);
+            self.servers_changed = true;
+        }
+        if self.servers_changed {
+            // The marker comes back with the next set_active_server
+            self.active_server = None;
+            self.selected_server = self.selected_server.min(self.saved_servers.len().saturating_sub(1));
+        }
+    }
+
+    fn render_profile_picker(&mut self, ui: &Ui) {
+        if self.server_profiles.profiles.is_empty() {
+            return;
+        }
+        let mut labels = vec!["Server's Choice".to_string()];
+        labels.extend(self.server_profiles.profiles.iter().cloned());
+        let mut index = self.server_profiles.profiles.iter()
+            .position(|name| *name == self.profile)
+            .map_or(0, |index| index + 1);
+        if ui.combo_simple_string("Server Profile", &mut index, &labels) {
+            self.profile = if index == 0 { String::new() } else { labels[index].clone() };
+            self.profile_changed = true;
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Mapping profile the server uses for this Deck, asked for again on every connect");
+        }
+        ui.text_disabled(&format!("Active on server: {}", self.server_profiles.active_profile));
+    }
+
+    fn render_controller_tab(ui: &Ui, controller: &ControllerState) {
+        let status = if controller.connected { Status::Good } else { Status::Bad };
+        
+        ui.text(&format!("Name: {}", controller.name));
+        ui.text(&format!("ID: {}", controller.id));
+        ui.text(&format!("UUID: {}", if controller.uuid.is_empty() { "Unknown" } else { &controller.uuid }));
+        ui.text(&format!("USB ID: {}", if controller.usb_id.is_empty() { "Unknown" } else { &controller.usb_id }));
+        if let Some(deck_model) = controller.deck_model {
+            ui.text(format!("{} {}", tr("Model:"), deck_model.name()));
+        }
+        ui.text("Status:");
+        ui.same_line();
+        palette::status_text(ui, status, tr(if controller.connected { "Connected" } else { "Disconnected" }));
+        ui.text(&format!("Last Activity: {:.2}s ago", controller.last_activity.elapsed().as_secs_f32()));
+        
+        ui.separator();
+        ui.text("Buttons:");
+        ui.columns(2, "button_columns", false);
+        for button in ALL_BUTTONS {
+            if !controller.has_button(button) {
+                ui.text_disabled(format!("{:?}: Not available", button));
+                ui.next_column();
+                continue;
+            }
+            let pressed = controller.buttons.get(&button).copied().unwrap_or(false);
+            let color = if pressed {
+                palette::color(Status::Good)
+            } else {
+                [0.7, 0.7, 0.7, 1.0]
+            };
+            ui.text_colored(color, &format!("{:?}: {}", button, if pressed { "Pressed" } else { "Released" }));
+            ui.next_column();
+        }
+        ui.columns(1, "", false);
+        
+        ui.separator();
+        ui.text("Axes:");
+        for axis in ALL_AXES {
+            if !controller.has_axis(axis) {
+                ui.text_disabled(format!("{:?}: Not available", axis));
+                continue;
+            }
+            let value = controller.axes.get(&axis).copied().unwrap_or(0.0);
+            let trigger = matches!(axis, Axis::LeftZ | Axis::RightZ);
+            let active = if trigger {
+                value > 0.01
+            } else {
+                value.abs() > 0.1
+            };
+            let color = if active {
+                palette::color(Status::Warning)
+            } else {
+                [0.7, 0.7, 0.7, 1.0]
+            };
+            ui.text_colored(color, &format!("{:?}: {:.3}", axis, value));
+            ui.same_line_with_pos(180.0);
+            // Map -1..1 to 0..1 so centered sticks sit in the middle of the bar, triggers already
+            // go from 0 to 1 and start out empty
+            ProgressBar::new(if trigger { value } else { (value + 1.0) / 2.0 })
+                .size([-1.0, 0.0])
+                .overlay_text("")
+                .build(ui);
+        }
+
+        if let Some(capabilities) = controller.capabilities {
+            ui.separator();
+            ui.text("Sensors:");
+            for (sensor, name) in [(SENSOR_GYRO, "Gyro"), (SENSOR_ACCELEROMETER, "Accelerometer"), (SENSOR_TOUCHPAD, "Touchpad")] {
+                ui.same_line();
+                if capabilities.has_sensor(sensor) {
+                    ui.text(name);
+                } else {
+                    ui.text_disabled(name);
+                }
+            }
         }
     }
 
@@ -431,6 +855,13 @@ impl ControllerDebugUI {
 
     pub fn set_network_enabled(&mut self, enabled: bool) {
         self.network_enabled = enabled;
+        if !enabled {
+            self.server_profiles = ProfileList::default();
+            self.pad_health = PadHealth::Working;
+            self.pad_status = None;
+            self.delivery = None;
+            self.link.clear();
+        }
     }
 
     pub fn should_connect_network(&mut self) -> Option<(String, i32)> {
@@ -454,4 +885,289 @@ impl ControllerDebugUI {
     pub fn is_sync_enabled(&self) -> bool {
         self.sync_enabled
     }
+
+    pub fn set_saved_servers(&mut self, servers: Vec<SavedServer>) {
+        self.saved_servers = servers;
+        if self.selected_server >= self.saved_servers.len() {
+            self.selected_server = 0;
+        }
+    }
+
+    pub fn servers_update(&mut self) -> Option<Vec<SavedServer>> {
+        if self.servers_changed {
+            self.servers_changed = false;
+            return Some(self.saved_servers.clone());
+        }
+        None
+    }
+
+    pub fn set_delivery_stats(&mut self, stats: DeliveryStats) {
+        let now = Instant::now();
+        self.link.round_trip(now, stats.ack_latency.as_secs_f64() * 1000.0);
+        // Retransmitted segments stand in for lost messages, TCP never loses them for good
+        let retransmitted = match (self.delivery.and_then(|delivery| delivery.retransmissions), stats.retransmissions) {
+            (Some(before), Some(after)) => after.saturating_sub(before),
+            _ => 0,
+        };
+        self.link.messages(now, stats.acked as u64, retransmitted as u64);
+        self.delivery = Some(stats);
+    }
+
+    pub fn set_failover(&mut self, failover: bool) {
+        self.failover = failover;
+    }
+
+    pub fn failover_update(&mut self) -> Option<bool> {
+        if self.failover_changed {
+            self.failover_changed = false;
+            return Some(self.failover);
+        }
+        None
+    }
+
+    // pending while waiting to try the next server
+    pub fn set_active_server(&mut self, index: Option<usize>, pending: bool) {
+        self.active_server = index;
+        self.failover_pending = pending;
+    }
+
+    pub fn set_server_address(&mut self, ip: String, port: i32) {
+        if let Some(index) = self.saved_servers.iter().position(|s| s.ip == ip && s.port == port) {
+            self.selected_server = index;
+        }
+        self.server_ip = ip;
+        self.server_port = port.to_string();
+    }
+
+    pub fn request_connect(&mut self) {
+        if !self.network_enabled && !self.should_connect && self.transport == Transport::Network {
+            self.should_connect = true;
+            self.connection_status = "Connecting...".to_string();
+        }
+    }
+
+    pub fn set_send_rate(&mut self, send_rate_hz: u32) {
+        self.send_rate_hz = send_rate_hz;
+    }
+
+    pub fn send_rate_update(&mut self) -> Option<u32> {
+        if self.send_rate_changed {
+            self.send_rate_changed = false;
+            return Some(self.send_rate_hz);
+        }
+        None
+    }
+
+    pub fn set_axis_epsilon(&mut self, axis_epsilon: f32) {
+        self.axis_epsilon = axis_epsilon;
+    }
+
+    pub fn axis_epsilon_update(&mut self) -> Option<f32> {
+        if self.axis_epsilon_changed {
+            self.axis_epsilon_changed = false;
+            return Some(self.axis_epsilon);
+        }
+        None
+    }
+
+    pub fn set_transport(&mut self, transport: Transport) {
+        self.transport = transport;
+    }
+
+    pub fn transport_update(&mut self) -> Option<Transport> {
+        if self.transport_changed {
+            self.transport_changed = false;
+            return Some(self.transport);
+        }
+        None
+    }
+
+    pub fn set_hid_passthrough(&mut self, enabled: bool) {
+        self.hid_passthrough = enabled;
+    }
+
+    pub fn hid_passthrough_update(&mut self) -> Option<bool> {
+        if self.hid_passthrough_changed {
+            self.hid_passthrough_changed = false;
+            return Some(self.hid_passthrough);
+        }
+        None
+    }
+
+    pub fn set_pc_controller(&mut self, enabled: bool) {
+        self.pc_controller = enabled;
+    }
+
+    pub fn pc_controller_update(&mut self) -> Option<bool> {
+        if self.pc_controller_changed {
+            self.pc_controller_changed = false;
+            return Some(self.pc_controller);
+        }
+        None
+    }
+
+    pub fn set_local_pad(&mut self, enabled: bool) {
+        self.local_pad = enabled;
+    }
+
+    pub fn local_pad_update(&mut self) -> Option<bool> {
+        if self.local_pad_changed {
+            self.local_pad_changed = false;
+            return Some(self.local_pad);
+        }
+        None
+    }
+
+    pub fn set_pause_when_unfocused(&mut self, enabled: bool) {
+        self.pause_when_unfocused = enabled;
+    }
+
+    pub fn pause_when_unfocused_update(&mut self) -> Option<bool> {
+        if self.pause_when_unfocused_changed {
+            self.pause_when_unfocused_changed = false;
+            return Some(self.pause_when_unfocused);
+        }
+        None
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn set_triggers(&mut self, triggers: TriggerSettings) {
+        self.triggers = triggers;
+    }
+
+    pub fn network_conditions_update(&mut self) -> Option<NetworkConditions> {
+        if self.network_conditions_changed {
+            self.network_conditions_changed = false;
+            return Some(self.network_conditions);
+        }
+        None
+    }
+
+    // Returns the previous health, the server repeats a failure on every attempt to recover
+    pub fn set_pad_health(&mut self, health: PadHealth) -> PadHealth {
+        std::mem::replace(&mut self.pad_health, health)
+    }
+
+    pub fn set_pad_status(&mut self, status: PadStatus) {
+        self.pad_status = Some(status);
+    }
+
+    pub fn set_server_profiles(&mut self, profiles: ProfileList) {
+        self.server_profiles = profiles;
+    }
+
+    pub fn set_profile(&mut self, profile: String) {
+        self.profile = profile;
+    }
+
+    pub fn profile_update(&mut self) -> Option<String> {
+        if self.profile_changed {
+            self.profile_changed = false;
+            return Some(self.profile.clone());
+        }
+        None
+    }
+
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    pub fn should_toggle_recording(&mut self) -> bool {
+        if self.should_toggle_recording {
+            self.should_toggle_recording = false;
+            return true;
+        }
+        false
+    }
+
+    pub fn set_replay_path(&mut self, path: String) {
+        self.replay_path = path;
+    }
+
+    pub fn set_replay_progress(&mut self, progress: Option<f32>) {
+        self.replay_progress = progress;
+    }
+
+    pub fn should_toggle_replay(&mut self) -> bool {
+        if self.should_toggle_replay {
+            self.should_toggle_replay = false;
+            return true;
+        }
+        false
+    }
+
+    pub fn replay_path(&self) -> &str {
+        self.replay_path.trim()
+    }
+
+    pub fn steam_config_import(&mut self) -> Option<PathBuf> {
+        if self.should_import_steam_config {
+            self.should_import_steam_config = false;
+            return Some(PathBuf::from(self.steam_import_path.trim()));
+        }
+        None
+    }
+
+    pub fn should_open_connection_wizard(&mut self) -> bool {
+        if self.should_open_wizard {
+            self.should_open_wizard = false;
+            return true;
+        }
+        false
+    }
+}
+
+fn format_uuid(uuid: &[u8; 16]) -> String {
+    let hex: String = uuid.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+fn export_steam_config(steam_input: &SteamInputManager) -> (bool, String) {
+    let dir = steam_vdf::default_export_dir();
+    match steam_vdf::export_steam_config(&steam_input.get_button_mappings(), &steam_input.get_axis_mappings(), &dir) {
+        Ok(path) => {
+            log::info!("Exported Steam controller config to {}", path.display());
+            (true, format!("Exported to {}", dir.display()))
+        }
+        Err(e) => {
+            log::error!("Failed to export Steam controller config: {:#}", e);
+            (false, format!("Export failed: {:#}", e))
+        }
+    }
+}
+
+// The score with the numbers behind it and what would help, the other side shows the same
+fn render_link_quality(ui: &Ui, quality: &LinkQuality) {
+    let status = match quality.score {
+        65.. => Status::Good,
+        40.. => Status::Warning,
+        _ => Status::Bad,
+    };
+    palette::status_text(ui, status, format!("{} ({}/100)", tr(quality.label()), quality.score));
+    let sample = &quality.sample;
+    ui.text_disabled(format!(
+        "RTT {:.1} ms, jitter {:.1} ms, loss {:.1}%, {:.0} msg/s",
+        sample.rtt_ms, sample.jitter_ms, sample.loss * 100.0, sample.messages_per_sec
+    ));
+    for hint in &quality.hints {
+        ui.bullet();
+        ui.same_line();
+        ui.text_wrapped(tr(hint));
+    }
+}
+
+// Whether the Deck's input reaches games on the PC, for the Network window and toasts
+pub fn pad_status_text(pad_status: &PadStatus) -> (Status, String) {
+    match pad_status {
+        PadStatus::Active { pad, player: Some(player) } => {
+            (Status::Good, format!("{} {} ({})", tr("PC controller active as Player"), player, pad))
+        }
+        PadStatus::Active { pad, player: None } => (Status::Good, format!("{} ({})", tr("PC controller active"), pad)),
+        PadStatus::Unplugged => (Status::Warning, tr("No controller plugged in on the PC, input doesn't reach games").to_string()),
+        PadStatus::Paused => (Status::Warning, tr("Forwarding is off on the PC, input doesn't reach games").to_string()),
+        PadStatus::Spectator => (Status::Warning, tr("Paired as a spectator on the PC, input doesn't reach games").to_string()),
+    }
 }
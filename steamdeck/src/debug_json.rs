@@ -0,0 +1,120 @@
+use imgui::*;
+use serde_json::Value;
+use std::path::PathBuf;
+use crate::i18n;
+use crate::steam_input::SteamInputManager;
+
+// A snapshot of the Steam Input debug data, only taken again when Refresh is pressed so the
+// tree doesn't collapse or jump around while it's being read
+pub struct DebugJsonViewer {
+    snapshot: Option<Snapshot>,
+    status: Option<String>,
+}
+
+struct Snapshot {
+    value: Value,
+    // Pretty printed once, for copying and saving
+    text: String,
+    taken: chrono::DateTime<chrono::Local>,
+}
+
+impl DebugJsonViewer {
+    pub fn new() -> Self {
+        Self {
+            snapshot: None,
+            status: None,
+        }
+    }
+
+    pub fn refresh(&mut self, steam_input: &SteamInputManager) {
+        let value = steam_input.get_debug_value();
+        let text = serde_json::to_string_pretty(&value).unwrap_or_else(|_| "Failed to serialize debug data".to_string());
+        self.snapshot = Some(Snapshot {
+            value,
+            text,
+            taken: chrono::Local::now(),
+        });
+        self.status = None;
+    }
+
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool, steam_input: &SteamInputManager) {
+        if self.snapshot.is_none() {
+            self.refresh(steam_input);
+        }
+        ui.window(i18n::window_title("Debug JSON"))
+            .size([600.0, 500.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                if ui.button("Refresh") {
+                    self.refresh(steam_input);
+                }
+                let Some(snapshot) = &self.snapshot else {
+                    return;
+                };
+                ui.same_line();
+                if ui.button("Copy to Clipboard") {
+                    ui.set_clipboard_text(&snapshot.text);
+                    self.status = Some("Copied to the clipboard".to_string());
+                }
+                ui.same_line();
+                if ui.button("Save to File") {
+                    self.status = Some(save_snapshot(snapshot));
+                }
+                ui.text_disabled(format!("Taken at {}", snapshot.taken.format("%H:%M:%S")));
+                if let Some(status) = &self.status {
+                    ui.text_wrapped(status);
+                }
+                ui.separator();
+
+                ui.child_window("json_tree")
+                    .size([0.0, 0.0])
+                    .build(|| {
+                        render_children(ui, &snapshot.value);
+                    });
+            });
+    }
+}
+
+fn render_children(ui: &Ui, value: &Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                render_value(ui, key, child);
+            }
+        }
+        Value::Array(items) => {
+            for (index, child) in items.iter().enumerate() {
+                render_value(ui, &format!("[{}]", index), child);
+            }
+        }
+        leaf => ui.text(leaf.to_string()),
+    }
+}
+
+// Objects and arrays become tree nodes labelled with their size, everything else one line
+fn render_value(ui: &Ui, key: &str, value: &Value) {
+    let _id = ui.push_id(key);
+    let label = match value {
+        Value::Object(map) => format!("{} {{{}}}", key, map.len()),
+        Value::Array(items) => format!("{} [{}]", key, items.len()),
+        leaf => {
+            ui.bullet_text(format!("{}: {}", key, leaf));
+            return;
+        }
+    };
+    // The fixed id behind ### keeps the node open when a refresh changes the size in the label
+    if let Some(_node) = ui.tree_node(format!("{}###node", label)) {
+        render_children(ui, value);
+    }
+}
+
+fn save_snapshot(snapshot: &Snapshot) -> String {
+    let path = PathBuf::from(format!("steam_input_debug_{}.json", snapshot.taken.format("%Y%m%d_%H%M%S")));
+    match std::fs::write(&path, &snapshot.text) {
+        Ok(()) => format!("Saved to {}", path.display()),
+        Err(e) => {
+            log::error!("Failed to save debug JSON: {}", e);
+            format!("Failed to save: {}", e)
+        }
+    }
+}
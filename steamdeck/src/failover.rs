@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+// Between one server failing and trying the next
+const FAILOVER_DELAY: Duration = Duration::from_secs(1);
+// Once every server has failed, before starting over with the first
+const ROUND_DELAY: Duration = Duration::from_secs(3);
+
+// Walks the ordered server list while connections fail or drop, starting after the server
+// that was connected to and wrapping around, until one answers or it's stopped. An address
+// that isn't in the list fails over to the first entry. With a single server it just retries.
+pub struct ServerFailover {
+    servers: Vec<(String, i32)>,
+    // What's being connected to, None while idle
+    address: Option<(String, i32)>,
+    // Failed attempts since the last successful connection
+    failures: usize,
+    retry_at: Option<Instant>,
+}
+
+impl ServerFailover {
+    pub fn new() -> Self {
+        Self {
+            servers: Vec::new(),
+            address: None,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    pub fn set_servers(&mut self, servers: Vec<(String, i32)>) {
+        self.servers = servers;
+    }
+
+    // A connection the user asked for, failover follows the list from there
+    pub fn start(&mut self, ip: &str, port: i32) {
+        self.address = Some((ip.to_string(), port));
+        self.failures = 0;
+        self.retry_at = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.address = None;
+        self.retry_at = None;
+    }
+
+    pub fn connected(&mut self) {
+        self.failures = 0;
+    }
+
+    // Picks the server to try next and when, None while idle
+    pub fn failed(&mut self, now: Instant) -> Option<(String, i32)> {
+        self.address.as_ref()?;
+        self.failures += 1;
+        if !self.servers.is_empty() {
+            let next = self.active().map_or(0, |index| (index + 1) % self.servers.len());
+            self.address = Some(self.servers[next].clone());
+        }
+        let round = self.servers.len().max(1);
+        let delay = if self.failures.is_multiple_of(round) { ROUND_DELAY } else { FAILOVER_DELAY };
+        self.retry_at = Some(now + delay);
+        self.address.clone()
+    }
+
+    // The server to connect to once its turn has come
+    pub fn poll(&mut self, now: Instant) -> Option<(String, i32)> {
+        if self.retry_at.is_some_and(|at| now >= at) {
+            self.retry_at = None;
+            return self.address.clone();
+        }
+        None
+    }
+
+    // Index into the list of the server in use, None while idle or for an address that isn't saved
+    pub fn active(&self) -> Option<usize> {
+        let address = self.address.as_ref()?;
+        self.servers.iter().position(|server| server == address)
+    }
+
+    // Waiting to try the next server, there's nothing to connect or disconnect meanwhile
+    pub fn is_pending(&self) -> bool {
+        self.retry_at.is_some()
+    }
+}
@@ -0,0 +1,169 @@
+use imgui::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crate::i18n::{self, tr};
+use crate::palette::{self, Status};
+
+const HISTORY_LENGTH: usize = 120;
+
+// Occupancy of one queue in the input pipeline, sampled once per update
+struct QueueGauge {
+    name: &'static str,
+    depth: usize,
+    capacity: usize,
+    // A backlog that clears before the next redraw still shows up in the peak
+    history: VecDeque<usize>,
+}
+
+impl QueueGauge {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            depth: 0,
+            capacity: 0,
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+        }
+    }
+
+    fn sample(&mut self, depth: usize, capacity: usize) {
+        self.depth = depth;
+        self.capacity = capacity;
+        self.history.push_back(depth);
+        while self.history.len() > HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+    }
+
+    fn peak(&self) -> usize {
+        self.history.iter().copied().max().unwrap_or(0)
+    }
+
+    fn render(&self, ui: &Ui) {
+        let fraction = if self.capacity > 0 { self.depth as f32 / self.capacity as f32 } else { 0.0 };
+        let status = if fraction >= 0.9 {
+            Status::Bad
+        } else if fraction >= 0.5 {
+            Status::Warning
+        } else {
+            Status::Good
+        };
+        let _color = ui.push_style_color(StyleColor::PlotHistogram, palette::color(status));
+        ProgressBar::new(fraction)
+            .size([250.0, 0.0])
+            .overlay_text(format!("{} {}/{} ({} {})", status.marker(), self.depth, self.capacity, tr("peak"), self.peak()))
+            .build(ui);
+        ui.same_line();
+        ui.text(tr(self.name));
+    }
+}
+
+pub struct FrameStats {
+    frame_times: VecDeque<f32>,
+    update_times: VecDeque<f32>,
+    render_times: VecDeque<f32>,
+    last_frame: Instant,
+    events_processed: usize,
+    queues: Vec<QueueGauge>,
+    pub show_overlay: bool,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(HISTORY_LENGTH),
+            update_times: VecDeque::with_capacity(HISTORY_LENGTH),
+            render_times: VecDeque::with_capacity(HISTORY_LENGTH),
+            last_frame: Instant::now(),
+            events_processed: 0,
+            queues: Vec::new(),
+            show_overlay: false,
+        }
+    }
+
+    // Call once per redraw, before update, to record the time since the previous frame
+    pub fn begin_frame(&mut self) {
+        let now = Instant::now();
+        Self::push_sample(&mut self.frame_times, now.duration_since(self.last_frame));
+        self.last_frame = now;
+    }
+
+    pub fn record_update(&mut self, duration: Duration) {
+        Self::push_sample(&mut self.update_times, duration);
+    }
+
+    pub fn record_render(&mut self, duration: Duration) {
+        Self::push_sample(&mut self.render_times, duration);
+    }
+
+    pub fn set_events_processed(&mut self, count: usize) {
+        self.events_processed = count;
+    }
+
+    // Creates the gauge the first time a queue is reported
+    pub fn set_queue_depth(&mut self, name: &'static str, depth: usize, capacity: usize) {
+        let index = match self.queues.iter().position(|gauge| gauge.name == name) {
+            Some(index) => index,
+            None => {
+                self.queues.push(QueueGauge::new(name));
+                self.queues.len() - 1
+            }
+        };
+        self.queues[index].sample(depth, capacity);
+    }
+
+    fn push_sample(samples: &mut VecDeque<f32>, duration: Duration) {
+        samples.push_back(duration.as_secs_f32() * 1000.0);
+        while samples.len() > HISTORY_LENGTH {
+            samples.pop_front();
+        }
+    }
+
+    fn average(samples: &VecDeque<f32>) -> f32 {
+        if samples.is_empty() {
+            0.0
+        } else {
+            samples.iter().sum::<f32>() / samples.len() as f32
+        }
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        if !self.show_overlay {
+            return;
+        }
+
+        let frame_ms = Self::average(&self.frame_times);
+        let fps = if frame_ms > 0.0 { 1000.0 / frame_ms } else { 0.0 };
+
+        ui.window(i18n::window_title("Performance Overlay"))
+            .position([10.0, 30.0], Condition::FirstUseEver)
+            .bg_alpha(0.7)
+            .always_auto_resize(true)
+            .opened(&mut self.show_overlay)
+            .build(|| {
+                ui.text(&format!("FPS: {:.0} ({:.2} ms/frame)", fps, frame_ms));
+                ui.text(&format!("Update: {:.3} ms", Self::average(&self.update_times)));
+                ui.text(&format!("Render: {:.3} ms", Self::average(&self.render_times)));
+                ui.text(&format!("Input Events: {} per update", self.events_processed));
+
+                ui.plot_lines("Frame (ms)", self.frame_times.make_contiguous())
+                    .graph_size([250.0, 40.0])
+                    .scale_min(0.0)
+                    .build();
+                ui.plot_lines("Update (ms)", self.update_times.make_contiguous())
+                    .graph_size([250.0, 40.0])
+                    .scale_min(0.0)
+                    .build();
+
+                if !self.queues.is_empty() {
+                    ui.separator();
+                    ui.text(tr("Pipeline"));
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text(tr("Queues that stay full point at a backed-up pipeline rather than the network"));
+                    }
+                    for gauge in &self.queues {
+                        gauge.render(ui);
+                    }
+                }
+            });
+    }
+}
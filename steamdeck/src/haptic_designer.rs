@@ -0,0 +1,222 @@
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use crate::haptics::{HapticPattern, HapticPoint};
+use crate::i18n::{self, tr};
+
+// How finely the preview samples a pattern
+const PREVIEW_STEP_MS: u32 = 10;
+// Points are dragged up to this far into the pattern
+const MAX_POINT_MS: u32 = 5000;
+
+// What a pattern can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HapticEvent {
+    Connected,
+    Disconnected,
+    LowBattery,
+}
+
+impl HapticEvent {
+    pub const ALL: [HapticEvent; 3] = [HapticEvent::Connected, HapticEvent::Disconnected, HapticEvent::LowBattery];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HapticEvent::Connected => "Connected to the Server",
+            HapticEvent::Disconnected => "Disconnected",
+            HapticEvent::LowBattery => "Controller Battery Low",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HapticSettings {
+    pub patterns: Vec<HapticPattern>,
+    // Pattern names, empty plays nothing
+    pub on_connect: String,
+    pub on_disconnect: String,
+    pub on_low_battery: String,
+}
+
+impl Default for HapticSettings {
+    fn default() -> Self {
+        Self {
+            patterns: vec![
+                HapticPattern::new("Pulse", &[(0, 0.7, 0.7), (80, 0.7, 0.7), (100, 0.0, 0.0)]),
+                HapticPattern::new("Double Pulse", &[
+                    (0, 0.8, 0.8), (80, 0.8, 0.8), (100, 0.0, 0.0),
+                    (180, 0.0, 0.0), (200, 0.8, 0.8), (280, 0.8, 0.8), (300, 0.0, 0.0),
+                ]),
+                HapticPattern::new("Fade Out", &[(0, 1.0, 0.3), (600, 0.0, 0.0)]),
+            ],
+            on_connect: "Pulse".to_string(),
+            on_disconnect: "Double Pulse".to_string(),
+            on_low_battery: "Fade Out".to_string(),
+        }
+    }
+}
+
+impl HapticSettings {
+    pub fn pattern_for(&self, event: HapticEvent) -> Option<&HapticPattern> {
+        let name = self.binding(event);
+        self.patterns.iter().find(|pattern| !name.is_empty() && pattern.name == name)
+    }
+
+    fn binding(&self, event: HapticEvent) -> &str {
+        match event {
+            HapticEvent::Connected => &self.on_connect,
+            HapticEvent::Disconnected => &self.on_disconnect,
+            HapticEvent::LowBattery => &self.on_low_battery,
+        }
+    }
+
+    fn binding_mut(&mut self, event: HapticEvent) -> &mut String {
+        match event {
+            HapticEvent::Connected => &mut self.on_connect,
+            HapticEvent::Disconnected => &mut self.on_disconnect,
+            HapticEvent::LowBattery => &mut self.on_low_battery,
+        }
+    }
+}
+
+// Composes patterns and binds them to events, the settings themselves live in Settings
+pub struct HapticDesigner {
+    pub show: bool,
+    selected: usize,
+    // Asked for with the Play button, picked up by App::update
+    test: Option<HapticPattern>,
+}
+
+impl HapticDesigner {
+    pub fn new() -> Self {
+        Self {
+            show: false,
+            selected: 0,
+            test: None,
+        }
+    }
+
+    pub fn test_update(&mut self) -> Option<HapticPattern> {
+        self.test.take()
+    }
+
+    // Returns true when the settings changed
+    pub fn render(&mut self, ui: &Ui, settings: &mut HapticSettings) -> bool {
+        let mut changed = false;
+        if !self.show {
+            return changed;
+        }
+        ui.window(i18n::window_title("Haptic Patterns"))
+            .size([440.0, 520.0], Condition::FirstUseEver)
+            .opened(&mut self.show)
+            .build(|| {
+                ui.text(tr("Events"));
+                ui.separator();
+                let mut names = vec![tr("None").to_string()];
+                names.extend(settings.patterns.iter().map(|pattern| pattern.name.clone()));
+                for event in HapticEvent::ALL {
+                    let binding = settings.binding(event).to_string();
+                    let mut index = settings.patterns.iter().position(|pattern| pattern.name == binding).map_or(0, |index| index + 1);
+                    if ui.combo_simple_string(tr(event.name()), &mut index, &names) {
+                        let name = if index == 0 { String::new() } else { settings.patterns[index - 1].name.clone() };
+                        *settings.binding_mut(event) = name;
+                        changed = true;
+                    }
+                }
+
+                ui.spacing();
+                ui.text(tr("Patterns"));
+                ui.separator();
+                self.selected = self.selected.min(settings.patterns.len().saturating_sub(1));
+                let names: Vec<String> = settings.patterns.iter().map(|pattern| pattern.name.clone()).collect();
+                ui.combo_simple_string(tr("Pattern"), &mut self.selected, &names);
+                if ui.button(tr("New")) {
+                    settings.patterns.push(HapticPattern::new(&unique_name(settings, "Pattern"), &[(0, 0.5, 0.5), (200, 0.0, 0.0)]));
+                    self.selected = settings.patterns.len() - 1;
+                    changed = true;
+                }
+                if settings.patterns.is_empty() {
+                    return;
+                }
+                ui.same_line();
+                if ui.button(tr("Delete")) {
+                    let name = settings.patterns.remove(self.selected).name;
+                    for event in HapticEvent::ALL {
+                        if settings.binding(event) == name {
+                            settings.binding_mut(event).clear();
+                        }
+                    }
+                    changed = true;
+                    return;
+                }
+
+                let pattern = &mut settings.patterns[self.selected];
+                let old_name = pattern.name.clone();
+                if ui.input_text(tr("Name"), &mut pattern.name).build() {
+                    let new_name = pattern.name.clone();
+                    // Bindings follow the rename
+                    for event in HapticEvent::ALL {
+                        if settings.binding(event) == old_name {
+                            *settings.binding_mut(event) = new_name.clone();
+                        }
+                    }
+                    changed = true;
+                }
+                let pattern = &mut settings.patterns[self.selected];
+
+                ui.text_disabled(tr("Time, left motor, right motor"));
+                let mut remove = None;
+                for (index, point) in pattern.points.iter_mut().enumerate() {
+                    let _id = ui.push_id_usize(index);
+                    ui.set_next_item_width(90.0);
+                    changed |= Drag::new("##at").range(0, MAX_POINT_MS).speed(5.0).display_format("%d ms").build(ui, &mut point.at_ms);
+                    ui.same_line();
+                    ui.set_next_item_width(110.0);
+                    changed |= ui.slider_config("##left", 0.0, 1.0).display_format("L %.2f").build(&mut point.left);
+                    ui.same_line();
+                    ui.set_next_item_width(110.0);
+                    changed |= ui.slider_config("##right", 0.0, 1.0).display_format("R %.2f").build(&mut point.right);
+                    ui.same_line();
+                    if ui.small_button("X") {
+                        remove = Some(index);
+                    }
+                }
+                if let Some(index) = remove {
+                    pattern.points.remove(index);
+                    changed = true;
+                }
+                if ui.button(tr("Add Point")) {
+                    pattern.points.sort_by_key(|point| point.at_ms);
+                    let at_ms = pattern.duration_ms() + 100;
+                    pattern.points.push(HapticPoint { at_ms, left: 0.0, right: 0.0 });
+                    changed = true;
+                }
+
+                ui.separator();
+                let samples: Vec<(f32, f32)> = (0..=pattern.duration_ms() / PREVIEW_STEP_MS)
+                    .map(|step| pattern.at(step * PREVIEW_STEP_MS))
+                    .collect();
+                let left: Vec<f32> = samples.iter().map(|sample| sample.0).collect();
+                let right: Vec<f32> = samples.iter().map(|sample| sample.1).collect();
+                ui.plot_lines(tr("Left"), &left).scale_min(0.0).scale_max(1.0).graph_size([0.0, 50.0]).build();
+                ui.plot_lines(tr("Right"), &right).scale_min(0.0).scale_max(1.0).graph_size([0.0, 50.0]).build();
+                ui.text(format!("{} ms", pattern.duration_ms()));
+                if ui.button(tr("Play")) {
+                    self.test = Some(pattern.clone());
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(tr("Plays on every connected controller that supports rumble"));
+                }
+            });
+        changed
+    }
+}
+
+// The base name, or the base name with the first free number after it
+fn unique_name(settings: &HapticSettings, base: &str) -> String {
+    let taken = |name: &str| settings.patterns.iter().any(|pattern| pattern.name == name);
+    if !taken(base) {
+        return base.to_string();
+    }
+    (2..).map(|number| format!("{} {}", base, number)).find(|name| !taken(name)).unwrap()
+}
@@ -0,0 +1,220 @@
+use imgui::Ui;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// UI languages. The English strings double as the keys, text without a translation stays English.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+}
+
+// Like the palette, windows look the language up at draw time
+static ACTIVE: AtomicU8 = AtomicU8::new(0);
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::German];
+
+    // Written in the language itself, so it can be found without reading the current one
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::German => "Deutsch",
+        }
+    }
+}
+
+pub fn set_language(language: Language) {
+    ACTIVE.store(language as u8, Ordering::Relaxed);
+}
+
+pub fn language() -> Language {
+    Language::ALL[ACTIVE.load(Ordering::Relaxed) as usize % Language::ALL.len()]
+}
+
+pub fn tr(text: &'static str) -> &'static str {
+    match language() {
+        Language::English => text,
+        Language::German => german(text).unwrap_or(text),
+    }
+}
+
+// imgui keys windows by their title, the English one stays the ID so saved positions
+// survive switching languages
+pub fn window_title(title: &'static str) -> String {
+    format!("{}###{}", tr(title), title)
+}
+
+// Picks the language in a settings window, returns true when it changed
+pub fn render_combo(ui: &Ui, language: &mut Language) -> bool {
+    let names: Vec<&str> = Language::ALL.iter().map(|l| l.name()).collect();
+    let mut index = Language::ALL.iter().position(|l| l == language).unwrap_or(0);
+    if ui.combo_simple_string(tr("Language"), &mut index, &names) {
+        *language = Language::ALL[index];
+        return true;
+    }
+    false
+}
+
+fn german(text: &str) -> Option<&'static str> {
+    Some(match text {
+        // Menus
+        "Settings" => "Einstellungen",
+        "UI Settings" => "Oberfläche",
+        "Trigger Thresholds" => "Trigger-Schwellen",
+        "Radial Menu" => "Radialmenü",
+        "Haptic Patterns" => "Haptikmuster",
+        "Import / Export..." => "Import / Export...",
+        "Setup Wizard..." => "Einrichtungsassistent...",
+        "Controller Test..." => "Controller-Test...",
+        "Performance Overlay" => "Leistungsanzeige",
+        "View" => "Ansicht",
+        "Log" => "Protokoll",
+        "Debug" => "Debug",
+        "Raw Input" => "Roheingaben",
+        "Steam Input" => "Steam Input",
+        "Controller Mapping" => "Controller-Belegung",
+        "Input History" => "Eingabeverlauf",
+        "Debug JSON" => "Debug-JSON",
+        "Test Trigger Detection" => "Trigger-Erkennung testen",
+
+        // Windows
+        "Controller Overview" => "Controller-Übersicht",
+        "Raw Controller Input" => "Roheingaben des Controllers",
+        "Network Settings" => "Netzwerk",
+        "Connection Wizard" => "Verbindungsassistent",
+        "Import / Export Settings" => "Einstellungen importieren / exportieren",
+        "Setup" => "Einrichtung",
+        "Controller Test" => "Controller-Test",
+
+        // Settings
+        "Appearance" => "Darstellung",
+        "Theme" => "Design",
+        "Dark" => "Dunkel",
+        "Light" => "Hell",
+        "Classic" => "Klassisch",
+        "Status Colors" => "Statusfarben",
+        "Standard" => "Standard",
+        "Colorblind Friendly" => "Farbenblind-freundlich",
+        "High Contrast" => "Hoher Kontrast",
+        "Language" => "Sprache",
+        "Font Size" => "Schriftgröße",
+        "UI Scale" => "Skalierung",
+        "Performance" => "Leistung",
+        "Update Rate (Hz)" => "Abfragerate (Hz)",
+        "Max FPS" => "Max. FPS",
+        "Reset to Defaults" => "Zurücksetzen",
+
+        // Radial menu
+        "Touch the left trackpad to bring up the wheel, slide onto a slice and lift to press its button on the PC. Lifting near the center cancels." =>
+            "Das linke Trackpad berühren, um das Rad einzublenden, auf ein Segment gleiten und loslassen, um dessen Taste am PC zu drücken. Loslassen in der Mitte bricht ab.",
+        "Enabled" => "Aktiviert",
+        "No Steam Deck controller to read the trackpad from" => "Kein Steam-Deck-Controller, dessen Trackpad gelesen werden kann",
+        "Slices" => "Segmente",
+        "Slice" => "Segment",
+
+        // Haptic patterns
+        "Events" => "Ereignisse",
+        "None" => "Keines",
+        "Connected to the Server" => "Mit dem Server verbunden",
+        "Controller Battery Low" => "Controller-Akku schwach",
+        "Patterns" => "Muster",
+        "Pattern" => "Muster",
+        "New" => "Neu",
+        "Delete" => "Löschen",
+        "Name" => "Name",
+        "Time, left motor, right motor" => "Zeit, linker Motor, rechter Motor",
+        "Add Point" => "Punkt hinzufügen",
+        "Left" => "Links",
+        "Right" => "Rechts",
+        "Play" => "Abspielen",
+        "Plays on every connected controller that supports rumble" =>
+            "Spielt auf jedem verbundenen Controller mit Vibration ab",
+
+        // Performance overlay
+        "Pipeline" => "Verarbeitung",
+        "Queues that stay full point at a backed-up pipeline rather than the network" =>
+            "Dauerhaft volle Warteschlangen deuten auf eine überlastete Verarbeitung statt auf das Netzwerk hin",
+        "peak" => "Spitze",
+        "Send Queue" => "Sende-Warteschlange",
+        "HID Queue" => "HID-Warteschlange",
+
+        // Controller test
+        "Press or push:" => "Drücken oder bewegen:",
+        "Waiting for the server to apply it..." => "Warte, bis der Server die Eingabe übernimmt...",
+        "Doesn't Register, Skip" => "Reagiert nicht, überspringen",
+        "Passed:" => "Bestanden:",
+        "Passed" => "Bestanden",
+        "Failed" => "Fehlgeschlagen",
+        "Copy Report" => "Bericht kopieren",
+        "Copied to the clipboard" => "In die Zwischenablage kopiert",
+        "Restart" => "Neu starten",
+        "Input" => "Eingabe",
+        "Local" => "Lokal",
+        "Reaction" => "Reaktion",
+
+        // Network
+        "Connected Controllers:" => "Verbundene Controller:",
+        "Model:" => "Modell:",
+        "Connection Status:" => "Verbindungsstatus:",
+        "Transport" => "Übertragung",
+        "Server" => "Server",
+        "Fail Over to the Next Server" => "Auf den nächsten Server ausweichen",
+        "When the server can't be reached, tries the saved servers in order until one answers" =>
+            "Ist der Server nicht erreichbar, werden die gespeicherten Server der Reihe nach versucht, bis einer antwortet",
+        "Server Order" => "Server-Reihenfolge",
+        "Connection Wizard..." => "Verbindungsassistent...",
+        "Manual Address" => "Manuelle Adresse",
+        "Server IP" => "Server-IP",
+        "Server Port" => "Server-Port",
+        "Transmission" => "Senden",
+        "On Change" => "Bei Änderung",
+        "Axis Deadband" => "Achsen-Totband",
+        "Raw HID Passthrough (Advanced)" => "Rohe HID-Weiterleitung (Erweitert)",
+        "Play the PC's Controller" => "Controller des PCs spielen",
+        "Virtual Pad on This Deck" => "Virtueller Controller auf diesem Deck",
+        "Pause When Unfocused" => "Im Hintergrund pausieren",
+        "Enable Sync (Send all data every 200ms)" => "Abgleich (alle 200 ms alles senden)",
+        "Syncs all controller data every 200ms to reset positions" =>
+            "Gleicht alle 200 ms alle Controllerdaten ab, um Positionen zurückzusetzen",
+        "Record / Replay" => "Aufnahme / Wiedergabe",
+        "Network Simulation" => "Netzwerksimulation",
+        "Connect" => "Verbinden",
+        "Disconnect" => "Trennen",
+        "Network Status:" => "Netzwerkstatus:",
+        "Connected" => "Verbunden",
+        "Disconnected" => "Getrennt",
+        "Paused, the window isn't focused" => "Pausiert, das Fenster ist nicht im Vordergrund",
+        "Connected to:" => "Verbunden mit:",
+        "Active server:" => "Aktiver Server:",
+        "No acknowledgements from the server yet" => "Noch keine Bestätigungen vom Server",
+        "Connection Quality:" => "Verbindungsqualität:",
+        "Excellent" => "Ausgezeichnet",
+        "Good" => "Gut",
+        "Fair" => "Mittel",
+        "Poor" => "Schlecht",
+        "Not enough traffic to rate the connection yet" => "Noch zu wenig Verkehr, um die Verbindung zu bewerten",
+        "Switch the Deck and the PC to 5 GHz Wi-Fi, or move closer to the router" =>
+            "Deck und PC auf 5-GHz-WLAN umstellen oder näher an den Router gehen",
+        "Pause downloads and streams on the same network, they make delays uneven" =>
+            "Downloads und Streams im selben Netzwerk pausieren, sie machen die Verzögerung ungleichmäßig",
+        "Messages are getting lost, try a less crowded Wi-Fi channel or connect the PC by cable" =>
+            "Nachrichten gehen verloren, einen weniger belegten WLAN-Kanal versuchen oder den PC per Kabel anschließen",
+        "Reduce the send rate, 125 Hz is plenty for most games" =>
+            "Die Senderate senken, 125 Hz reichen für die meisten Spiele",
+        "Raise the Axis Deadband so tiny stick movements aren't sent" =>
+            "Das Achsen-Totband erhöhen, damit winzige Stickbewegungen nicht gesendet werden",
+        "The PC's virtual controller isn't responding, the server is plugging it back in" =>
+            "Der virtuelle Controller des PCs reagiert nicht, der Server schließt ihn neu an",
+        "PC controller active as Player" => "PC-Controller aktiv als Spieler",
+        "PC controller active" => "PC-Controller aktiv",
+        "No controller plugged in on the PC, input doesn't reach games" =>
+            "Am PC ist kein Controller angeschlossen, Eingaben erreichen keine Spiele",
+        "Forwarding is off on the PC, input doesn't reach games" =>
+            "Die Weiterleitung am PC ist aus, Eingaben erreichen keine Spiele",
+        "Paired as a spectator on the PC, input doesn't reach games" =>
+            "Am PC als Zuschauer gekoppelt, Eingaben erreichen keine Spiele",
+        _ => return None,
+    })
+}
@@ -0,0 +1,304 @@
+use gilrs::{Axis, Button};
+use imgui::*;
+use std::time::{Duration, Instant};
+use crate::i18n::{self, tr};
+use crate::input_source::InputEvent;
+use crate::network::{axis_to_id, button_to_id, ControllerInputData};
+use crate::palette::{self, Status};
+
+// How far a stick or trigger has to go to count
+const AXIS_THRESHOLD: f32 = 0.7;
+// The server acknowledges a few times a second, an input it hasn't taken by then counts as lost
+const SERVER_TIMEOUT: Duration = Duration::from_secs(2);
+
+// One prompt of the test. Either the button or the axis passes it, controllers report triggers
+// and D-pads as one or the other.
+#[derive(Debug, Clone, Copy)]
+struct TestInput {
+    name: &'static str,
+    button: Option<Button>,
+    // With the direction it has to be pushed in, 1 or -1
+    axis: Option<(Axis, f32)>,
+}
+
+const fn button(name: &'static str, button: Button) -> TestInput {
+    TestInput { name, button: Some(button), axis: None }
+}
+
+const fn either(name: &'static str, button: Button, axis: Axis, direction: f32) -> TestInput {
+    TestInput { name, button: Some(button), axis: Some((axis, direction)) }
+}
+
+const fn stick(name: &'static str, axis: Axis, direction: f32) -> TestInput {
+    TestInput { name, button: None, axis: Some((axis, direction)) }
+}
+
+const INPUTS: [TestInput; 25] = [
+    button("A", Button::South),
+    button("B", Button::East),
+    button("X", Button::West),
+    button("Y", Button::North),
+    button("L1", Button::LeftTrigger),
+    button("R1", Button::RightTrigger),
+    either("L2", Button::LeftTrigger2, Axis::LeftZ, 1.0),
+    either("R2", Button::RightTrigger2, Axis::RightZ, 1.0),
+    button("View", Button::Select),
+    button("Menu", Button::Start),
+    button("Steam / Guide", Button::Mode),
+    button("Left Stick Click", Button::LeftThumb),
+    button("Right Stick Click", Button::RightThumb),
+    either("D-Pad Up", Button::DPadUp, Axis::DPadY, 1.0),
+    either("D-Pad Down", Button::DPadDown, Axis::DPadY, -1.0),
+    either("D-Pad Left", Button::DPadLeft, Axis::DPadX, -1.0),
+    either("D-Pad Right", Button::DPadRight, Axis::DPadX, 1.0),
+    stick("Left Stick Up", Axis::LeftStickY, 1.0),
+    stick("Left Stick Down", Axis::LeftStickY, -1.0),
+    stick("Left Stick Left", Axis::LeftStickX, -1.0),
+    stick("Left Stick Right", Axis::LeftStickX, 1.0),
+    stick("Right Stick Up", Axis::RightStickY, 1.0),
+    stick("Right Stick Down", Axis::RightStickY, -1.0),
+    stick("Right Stick Left", Axis::RightStickX, -1.0),
+    stick("Right Stick Right", Axis::RightStickX, 1.0),
+];
+
+impl TestInput {
+    fn matches(&self, event: InputEvent) -> bool {
+        match event {
+            InputEvent::ButtonPressed(pressed) => self.button == Some(pressed),
+            InputEvent::ButtonChanged(changed, value) => self.button == Some(changed) && value >= AXIS_THRESHOLD,
+            InputEvent::AxisChanged(moved, value) => {
+                self.axis.is_some_and(|(axis, direction)| axis == moved && value * direction >= AXIS_THRESHOLD)
+            }
+            _ => false,
+        }
+    }
+
+    // Whether a message went out with this input in it, trigger buttons for one are only sent as axes
+    fn carried_by(&self, data: &ControllerInputData) -> bool {
+        let button_id = self.button.and_then(button_to_id);
+        let axis_id = self.axis.and_then(|(axis, _)| axis_to_id(axis));
+        data.button_events.iter().any(|event| Some(event.button) == button_id)
+            || data.axis_events.iter().any(|event| Some(event.axis) == axis_id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Check {
+    Pending,
+    Passed,
+    Failed,
+    // Not connected to a server when the input came in
+    Skipped,
+}
+
+impl Check {
+    fn render(self, ui: &Ui) {
+        match self {
+            Check::Pending => ui.text_disabled("..."),
+            Check::Passed => palette::status_text(ui, Status::Good, tr("Passed")),
+            Check::Failed => palette::status_text(ui, Status::Bad, tr("Failed")),
+            Check::Skipped => ui.text_disabled("-"),
+        }
+    }
+
+    fn report(self) -> &'static str {
+        match self {
+            Check::Pending => "...",
+            Check::Passed => "pass",
+            Check::Failed => "FAIL",
+            Check::Skipped => "-",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct InputResult {
+    local: Check,
+    server: Check,
+    // From the prompt to the input
+    reaction: Option<Duration>,
+}
+
+// Where the current input is on its way to the server, with when it was made
+#[derive(Debug, Clone, Copy)]
+enum Echo {
+    WaitingForSend(Instant),
+    WaitingForAck(u64, Instant),
+}
+
+// Prompts for every button and stick direction in turn and checks each one arrives, here and,
+// while connected, at the server. The server's acknowledgement of the message that carried it
+// counts as the echo.
+pub struct InputTest {
+    pub show: bool,
+    step: usize,
+    prompted: Instant,
+    echo: Option<Echo>,
+    results: Vec<InputResult>,
+    status: Option<String>,
+}
+
+impl InputTest {
+    pub fn new() -> Self {
+        Self {
+            show: false,
+            step: 0,
+            prompted: Instant::now(),
+            echo: None,
+            results: vec![InputResult { local: Check::Pending, server: Check::Pending, reaction: None }; INPUTS.len()],
+            status: None,
+        }
+    }
+
+    // Starts over from the first input
+    pub fn open(&mut self) {
+        *self = Self::new();
+        self.show = true;
+    }
+
+    fn is_running(&self) -> bool {
+        self.show && self.step < INPUTS.len()
+    }
+
+    // connected is whether this input is going to the server, paused doesn't count
+    pub fn handle_input_event(&mut self, event: InputEvent, connected: bool) {
+        if !self.is_running() || self.echo.is_some() || !INPUTS[self.step].matches(event) {
+            return;
+        }
+        let now = Instant::now();
+        let result = &mut self.results[self.step];
+        result.local = Check::Passed;
+        result.reaction = Some(now.duration_since(self.prompted));
+        if connected {
+            self.echo = Some(Echo::WaitingForSend(now));
+        } else {
+            result.server = Check::Skipped;
+            self.next();
+        }
+    }
+
+    pub fn message_sent(&mut self, message_id: u64, data: &ControllerInputData) {
+        if let Some(Echo::WaitingForSend(at)) = self.echo {
+            if INPUTS[self.step].carried_by(data) {
+                self.echo = Some(Echo::WaitingForAck(message_id, at));
+            }
+        }
+    }
+
+    // Everything up to acked_id has been applied on the PC
+    pub fn acked(&mut self, acked_id: u64) {
+        if let Some(Echo::WaitingForAck(message_id, _)) = self.echo {
+            if acked_id >= message_id {
+                self.results[self.step].server = Check::Passed;
+                self.next();
+            }
+        }
+    }
+
+    fn check_timeout(&mut self) {
+        let made = match self.echo {
+            Some(Echo::WaitingForSend(at)) | Some(Echo::WaitingForAck(_, at)) => at,
+            None => return,
+        };
+        if made.elapsed() > SERVER_TIMEOUT {
+            self.results[self.step].server = Check::Failed;
+            self.next();
+        }
+    }
+
+    // For an input that doesn't register at all
+    fn skip(&mut self) {
+        let result = &mut self.results[self.step];
+        result.local = Check::Failed;
+        result.server = Check::Skipped;
+        self.next();
+    }
+
+    fn next(&mut self) {
+        self.echo = None;
+        self.step += 1;
+        self.prompted = Instant::now();
+    }
+
+    // Plain text for pasting into a bug report
+    fn report(&self) -> String {
+        let mut report = format!("Controller test: {} of {} inputs passed\n", self.passed(), INPUTS.len());
+        for (input, result) in INPUTS.iter().zip(&self.results) {
+            let reaction = result.reaction.map_or(String::new(), |reaction| format!(" ({:.1}s)", reaction.as_secs_f32()));
+            report += &format!("{:<20} local {:<4}  server {:<4}{}\n", input.name, result.local.report(), result.server.report(), reaction);
+        }
+        report
+    }
+
+    // Passed here, and at the server unless that wasn't checked
+    fn passed(&self) -> usize {
+        self.results.iter()
+            .filter(|result| result.local == Check::Passed && result.server != Check::Failed)
+            .count()
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        if !self.show {
+            return;
+        }
+        self.check_timeout();
+
+        let mut show = self.show;
+        ui.window(i18n::window_title("Controller Test"))
+            .size([420.0, 520.0], Condition::FirstUseEver)
+            .opened(&mut show)
+            .build(|| {
+                if self.step < INPUTS.len() {
+                    ProgressBar::new(self.step as f32 / INPUTS.len() as f32)
+                        .overlay_text(format!("{} / {}", self.step + 1, INPUTS.len()))
+                        .build(ui);
+                    ui.text(tr("Press or push:"));
+                    ui.same_line();
+                    ui.text_colored(palette::color(Status::Good), INPUTS[self.step].name);
+                    if self.echo.is_some() {
+                        ui.text_disabled(tr("Waiting for the server to apply it..."));
+                    } else if ui.button(tr("Doesn't Register, Skip")) {
+                        self.skip();
+                    }
+                } else {
+                    let passed = self.passed();
+                    let status = if passed == INPUTS.len() { Status::Good } else { Status::Bad };
+                    palette::status_text(ui, status, format!("{} {} / {}", tr("Passed:"), passed, INPUTS.len()));
+                    if ui.button(tr("Copy Report")) {
+                        ui.set_clipboard_text(self.report());
+                        self.status = Some(tr("Copied to the clipboard").to_string());
+                    }
+                    ui.same_line();
+                }
+                if ui.button(tr("Restart")) {
+                    self.open();
+                }
+                if let Some(status) = &self.status {
+                    ui.text_disabled(status);
+                }
+                ui.separator();
+
+                ui.columns(4, "input_test_results", true);
+                for header in [tr("Input"), tr("Local"), tr("Server"), tr("Reaction")] {
+                    ui.text(header);
+                    ui.next_column();
+                }
+                ui.separator();
+                for (input, result) in INPUTS.iter().zip(&self.results).take(self.step + 1) {
+                    ui.text(input.name);
+                    ui.next_column();
+                    result.local.render(ui);
+                    ui.next_column();
+                    result.server.render(ui);
+                    ui.next_column();
+                    match result.reaction {
+                        Some(reaction) => ui.text(format!("{:.1}s", reaction.as_secs_f32())),
+                        None => ui.text_disabled("-"),
+                    }
+                    ui.next_column();
+                }
+                ui.columns(1, "", false);
+            });
+        self.show = show;
+    }
+}
@@ -1,7 +1,14 @@
 use anyhow::Result;
-use gilrs::{Gilrs, Event};
 use imgui_wgpu::{Renderer, RendererConfig};
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
 use wgpu::{Device, Queue, Surface, SurfaceConfiguration};
 use winit::{
     event::{Event as WinitEvent, WindowEvent},
@@ -9,13 +16,61 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
+mod cli;
+mod connection_wizard;
 mod controller_debug;
+mod debug_json;
+mod failover;
 mod steam_input;
-mod network;
+mod steam_vdf;
+mod frame_stats;
+mod haptic_designer;
+mod i18n;
+mod input_test;
+// The server's copy, both apps write the same JSON lines
+#[path = "../../server/src/json_log.rs"]
+mod json_log;
+// The server's copy, both apps show their log the same way
+#[path = "../../server/src/log_viewer.rs"]
+mod log_viewer;
+// The server's copy, so a status looks the same in both apps
+#[path = "../../server/src/palette.rs"]
+mod palette;
+mod radial_menu;
+mod recorder;
+mod settings;
+mod settings_bundle;
+mod setup_wizard;
+// The server's copy, notifications behave the same in both apps
+#[path = "../../server/src/toasts.rs"]
+mod toasts;
 
+// The input and networking side lives in the core library so other apps can stream without the UI
+use steamdeck_controls_core::{bluetooth_hid, capabilities, delivery, haptics, hid_passthrough, input_filter, input_id, input_source, link_quality, network, network_sim, uinput_pad, usb_gadget};
+
+use bluetooth_hid::BluetoothGamepad;
+use cli::{Args, LogFormat};
+use connection_wizard::ConnectionWizard;
 use controller_debug::ControllerDebugUI;
+use failover::ServerFailover;
 use steam_input::SteamInputManager;
-use network::{NetworkStreamer, ControllerInputData, ButtonEvent, AxisEvent, button_to_string, axis_to_string, get_current_timestamp};
+use frame_stats::FrameStats;
+use haptic_designer::{HapticDesigner, HapticEvent, HapticSettings};
+use i18n::tr;
+use input_test::InputTest;
+use hid_passthrough::HidReader;
+use input_filter::InputFilter;
+use input_source::{GilrsSource, InputEvent, InputSource, SourceEvent};
+use log_viewer::{LogEntry, LogViewer};
+use radial_menu::RadialMenu;
+use recorder::{InputRecorder, InputReplay};
+use settings::{Settings, Transport};
+use settings_bundle::BundleWindow;
+use setup_wizard::SetupWizard;
+use toasts::{ToastKind, Toasts};
+use uinput_pad::UinputPad;
+use usb_gadget::UsbGadget;
+use network::{NetworkStreamer, NetworkStatus, PadHealth, ControllerInputData, ButtonEvent, AxisEvent, button_to_id, axis_to_id, get_current_timestamp, push_network_event};
 
 pub struct App {
     surface: Surface,
@@ -27,68 +82,71 @@ pub struct App {
     platform: WinitPlatform,
     renderer: Renderer,
     controller_debug: ControllerDebugUI,
+    connection_wizard: ConnectionWizard,
+    setup_wizard: SetupWizard,
     steam_input: SteamInputManager,
-    gilrs: Gilrs,
+    input_source: Box<dyn InputSource>,
     last_cursor: Option<imgui::MouseCursor>,
     network_streamer: NetworkStreamer,
-    pending_connect: Option<(String, i32)>,
-    pending_disconnect: bool,
+    failover: ServerFailover,
+    // Some while raw HID passthrough is on
+    hid_reader: Option<HidReader>,
+    // Some while the server's PC controller is played on the Deck
+    pc_pad: Option<UinputPad>,
+    // Some while our own input is also played on the Deck
+    local_pad: Option<UinputPad>,
+    radial_menu: RadialMenu,
+    // Some while input goes over the USB cable instead of the network
+    usb_gadget: Option<UsbGadget>,
+    // Some while input goes to a paired PC as a Bluetooth gamepad
+    bluetooth_gamepad: Option<BluetoothGamepad>,
+    input_filter: InputFilter,
+    focused: bool,
+    // Nothing reaches the PC while the window is in the background, see set_focused
+    paused: bool,
+    recorder: InputRecorder,
+    replay: Option<InputReplay>,
     last_sync_time: std::time::Instant,
+    last_battery_poll: Instant,
+    // Controllers whose battery was low at the last poll, so the pattern plays once per drop
+    low_batteries: HashSet<u32>,
+    haptic_designer: HapticDesigner,
+    input_test: InputTest,
+    settings: Settings,
+    settings_path: PathBuf,
+    // --poll-rate, takes the place of the Update Rate setting without being saved
+    poll_rate_hz: Option<u32>,
+    base_style: imgui::Style,
+    show_settings: bool,
+    show_triggers: bool,
+    show_radial_menu: bool,
+    settings_dirty: bool,
+    bundle_window: BundleWindow,
+    toasts: Toasts,
+    frame_stats: FrameStats,
+    gpu_lost: Arc<AtomicBool>,
+    log_viewer: LogViewer,
 }
 
 impl App {
-    async fn new(window: &Window) -> Result<Self> {
+    async fn new(window: &Window, args: &Args, log_viewer: LogViewer) -> Result<Self> {
         let size = window.inner_size();
         
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(),
-        });
-
-        let surface = unsafe { instance.create_surface(window) }?;
-        
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            },
-        ).await.ok_or_else(|| anyhow::anyhow!("Failed to find suitable adapter"))?;
-
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: wgpu::Features::empty(),
-                limits: if cfg!(target_arch = "wasm32") {
-                    wgpu::Limits::downlevel_webgl2_defaults()
-                } else {
-                    wgpu::Limits::default()
-                },
-            },
-            None,
-        ).await?;
-
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps.formats.iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
-
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width.max(1),
-            height: size.height.max(1),
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-        };
-        surface.configure(&device, &config);
+        let gpu_lost = Arc::new(AtomicBool::new(false));
+        let (surface, device, queue, config) = create_gpu(window, gpu_lost.clone()).await?;
+        let surface_format = config.format;
 
         let mut imgui = imgui::Context::create();
+        imgui.set_ini_filename(settings::imgui_ini_path());
         let mut platform = WinitPlatform::init(&mut imgui);
         platform.attach_window(imgui.io_mut(), window, HiDpiMode::Default);
 
+        let settings_path = args.config.clone().unwrap_or_else(settings::default_settings_path);
+        let first_run = Settings::is_first_run(&settings_path);
+        let settings = Settings::load(&settings_path);
+        let base_style = *imgui.style();
+        settings.ui.apply(&mut imgui, &base_style);
+
         let renderer_config = RendererConfig {
             texture_format: surface_format,
             ..Default::default()
@@ -96,13 +154,55 @@ impl App {
         
         let renderer = Renderer::new(&mut imgui, &device, &queue, renderer_config);
 
-        let controller_debug = ControllerDebugUI::new();
-        let steam_input = SteamInputManager::new()?;
-        let gilrs = Gilrs::new().unwrap();
+        let mut controller_debug = ControllerDebugUI::new();
+        controller_debug.set_transport(settings.network.transport);
+        controller_debug.set_saved_servers(settings.network.servers.clone());
+        if let Some((ip, port)) = args.target(&settings.network.servers) {
+            controller_debug.set_server_address(ip, port);
+            if args.connect {
+                controller_debug.request_connect();
+            }
+        }
+        let mut steam_input = SteamInputManager::new()?;
+        steam_input.set_triggers(settings.triggers);
+        let input_source = GilrsSource::new()?;
+        for info in input_source.controllers() {
+            controller_debug.set_controller_info(info);
+        }
 
-        let network_streamer = NetworkStreamer::new();
+        let mut setup_wizard = SetupWizard::new();
+        if first_run {
+            setup_wizard.open();
+        }
+
+        let mut network_streamer = NetworkStreamer::new();
+        network_streamer.set_send_rate(settings.network.send_rate_hz);
+        network_streamer.select_profile(&settings.network.profile);
+        for info in input_source.controllers() {
+            network_streamer.report_controller(&info);
+        }
+        report_batteries(&input_source, &network_streamer);
+        controller_debug.set_send_rate(settings.network.send_rate_hz);
+        controller_debug.set_profile(settings.network.profile.clone());
+        controller_debug.set_axis_epsilon(settings.network.axis_epsilon);
+        controller_debug.set_failover(settings.network.failover);
+        let mut failover = ServerFailover::new();
+        failover.set_servers(settings.network.server_addresses());
+        let input_filter = InputFilter::new(settings.network.axis_epsilon);
+        let hid_reader = settings.network.hid_passthrough.then(|| open_hid_reader(&network_streamer)).flatten();
+        controller_debug.set_hid_passthrough(hid_reader.is_some());
+        let pc_pad = settings.network.pc_controller.then(open_pc_pad).flatten();
+        controller_debug.set_pc_controller(pc_pad.is_some());
+        let local_pad = settings.network.local_pad.then(open_local_pad).flatten();
+        controller_debug.set_local_pad(local_pad.is_some());
+        let mut radial_menu = RadialMenu::new();
+        if let Err(e) = radial_menu.set_enabled(settings.radial_menu.enabled) {
+            log::error!("Radial menu unavailable: {:#}", e);
+        }
+        controller_debug.set_pause_when_unfocused(settings.network.pause_when_unfocused);
+        controller_debug.set_triggers(settings.triggers);
 
-        Ok(Self {
+        let mut app = Self {
             surface,
             device,
             queue,
@@ -112,14 +212,213 @@ impl App {
             platform,
             renderer,
             controller_debug,
+            connection_wizard: ConnectionWizard::new(),
+            setup_wizard,
             steam_input,
-            gilrs,
+            input_source: Box::new(input_source),
             last_cursor: None,
             network_streamer,
-            pending_connect: None,
-            pending_disconnect: false,
+            failover,
+            hid_reader,
+            pc_pad,
+            local_pad,
+            radial_menu,
+            low_batteries: HashSet::new(),
+            haptic_designer: HapticDesigner::new(),
+            input_test: InputTest::new(),
+            usb_gadget: None,
+            bluetooth_gamepad: None,
+            input_filter,
+            focused: true,
+            paused: false,
+            recorder: InputRecorder::new(),
+            replay: None,
             last_sync_time: std::time::Instant::now(),
-        })
+            last_battery_poll: Instant::now(),
+            settings,
+            settings_path,
+            poll_rate_hz: args.poll_rate_hz,
+            base_style,
+            show_settings: false,
+            show_triggers: false,
+            show_radial_menu: false,
+            settings_dirty: false,
+            bundle_window: BundleWindow::new(),
+            toasts: Toasts::new(),
+            frame_stats: FrameStats::new(),
+            gpu_lost,
+            log_viewer,
+        };
+        if app.settings.network.transport != Transport::Network {
+            app.set_transport(app.settings.network.transport);
+        }
+        Ok(app)
+    }
+
+    // Only saved once the gadget or the Bluetooth gamepad could actually be set up
+    fn set_transport(&mut self, transport: Transport) {
+        // Whatever ran before stops first, a gadget or a profile can't be set up twice
+        if self.usb_gadget.take().is_some() | self.bluetooth_gamepad.take().is_some() {
+            self.controller_debug.set_connection_status("Disconnected".to_string());
+        }
+        match transport {
+            Transport::UsbGadget => match UsbGadget::start() {
+                Ok(usb_gadget) => {
+                    self.network_streamer.disconnect();
+                    self.usb_gadget = Some(usb_gadget);
+                    self.controller_debug.set_connection_status("USB gadget active".to_string());
+                    self.toasts.push(ToastKind::Success, "Sending input over the USB cable");
+                }
+                Err(e) => {
+                    self.toasts.push(ToastKind::Error, format!("USB gadget mode unavailable: {:#}", e));
+                    self.controller_debug.set_transport(Transport::Network);
+                    return;
+                }
+            },
+            Transport::Bluetooth => match BluetoothGamepad::start() {
+                Ok(bluetooth_gamepad) => {
+                    self.network_streamer.disconnect();
+                    self.bluetooth_gamepad = Some(bluetooth_gamepad);
+                    self.controller_debug.set_connection_status("Waiting for a Bluetooth host".to_string());
+                    self.toasts.push(ToastKind::Success, "Discoverable as a Bluetooth gamepad, pair it from the PC");
+                }
+                Err(e) => {
+                    self.toasts.push(ToastKind::Error, format!("Bluetooth gamepad mode unavailable: {:#}", e));
+                    self.controller_debug.set_transport(Transport::Network);
+                    return;
+                }
+            },
+            Transport::Network => {}
+        }
+        self.settings.network.transport = transport;
+        if let Err(e) = self.settings.save(&self.settings_path) {
+            log::error!("Failed to save settings: {}", e);
+        }
+    }
+
+    // Schedules the next server after a failed or lost connection, returns its address.
+    // None when failover is off or nothing was being connected to.
+    fn fail_over(&mut self) -> Option<String> {
+        if !self.settings.network.failover {
+            self.failover.stop();
+            return None;
+        }
+        let (server_ip, server_port) = self.failover.failed(Instant::now())?;
+        let address = format!("{}:{}", server_ip, server_port);
+        self.controller_debug.set_connection_status(format!("Failing over to {}...", address));
+        Some(address)
+    }
+
+    // Only saved once the device could actually be opened
+    fn set_hid_passthrough(&mut self, enabled: bool) {
+        if enabled {
+            match HidReader::open() {
+                Ok(hid_reader) => {
+                    self.network_streamer.set_hid_device(Some(hid_reader.device().clone()));
+                    self.toasts.push(ToastKind::Info, format!("Forwarding raw reports from {}", hid_reader.device().name));
+                    self.hid_reader = Some(hid_reader);
+                }
+                Err(e) => {
+                    self.toasts.push(ToastKind::Error, format!("Raw HID passthrough unavailable: {:#}", e));
+                    self.controller_debug.set_hid_passthrough(false);
+                    return;
+                }
+            }
+        } else {
+            self.hid_reader = None;
+            self.network_streamer.set_hid_device(None);
+        }
+        self.settings.network.hid_passthrough = enabled;
+        if let Err(e) = self.settings.save(&self.settings_path) {
+            log::error!("Failed to save settings: {}", e);
+        }
+    }
+
+    // Only saved once the pad could actually be created
+    fn set_pc_controller(&mut self, enabled: bool) {
+        if enabled {
+            match UinputPad::create(PC_PAD_NAME) {
+                Ok(pad) => {
+                    self.toasts.push(ToastKind::Info, "The PC's controller plays on this Deck while the server streams it");
+                    self.pc_pad = Some(pad);
+                }
+                Err(e) => {
+                    self.toasts.push(ToastKind::Error, format!("Can't play the PC's controller: {:#}", e));
+                    self.controller_debug.set_pc_controller(false);
+                    return;
+                }
+            }
+        } else {
+            self.pc_pad = None;
+        }
+        self.settings.network.pc_controller = enabled;
+        if let Err(e) = self.settings.save(&self.settings_path) {
+            log::error!("Failed to save settings: {}", e);
+        }
+    }
+
+    // Only saved once the pad could actually be created
+    fn set_local_pad(&mut self, enabled: bool) {
+        if enabled {
+            match UinputPad::create(LOCAL_PAD_NAME) {
+                Ok(pad) => {
+                    self.toasts.push(ToastKind::Info, "Input also plays on this Deck");
+                    self.local_pad = Some(pad);
+                }
+                Err(e) => {
+                    self.toasts.push(ToastKind::Error, format!("Virtual pad on this Deck unavailable: {:#}", e));
+                    self.controller_debug.set_local_pad(false);
+                    return;
+                }
+            }
+        } else {
+            self.local_pad = None;
+        }
+        self.settings.network.local_pad = enabled;
+        if let Err(e) = self.settings.save(&self.settings_path) {
+            log::error!("Failed to save settings: {}", e);
+        }
+    }
+
+    // Opening the Steam overlay in Game Mode takes focus from the window as well, so this also
+    // keeps the overlay's navigation from driving the game on the PC
+    fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.update_paused();
+    }
+
+    fn update_paused(&mut self) {
+        let paused = !self.focused && self.settings.network.pause_when_unfocused;
+        if paused == self.paused {
+            return;
+        }
+        self.paused = paused;
+        self.controller_debug.set_paused(paused);
+        if !paused {
+            log::info!("Window focused, forwarding input again");
+            return;
+        }
+
+        log::info!("Window lost focus, releasing everything and pausing input");
+        let result = if let Some(usb_gadget) = &mut self.usb_gadget {
+            usb_gadget.release_all()
+        } else if let Some(bluetooth_gamepad) = &mut self.bluetooth_gamepad {
+            bluetooth_gamepad.release_all()
+        } else {
+            self.network_streamer.release_all()
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to send the neutral state: {}", e);
+        }
+        // The zeroes went around the filter, so it no longer knows what the PC has
+        self.input_filter.reset();
+    }
+
+    fn update_interval(&self) -> Duration {
+        match self.poll_rate_hz {
+            Some(poll_rate_hz) => Duration::from_secs_f64(1.0 / poll_rate_hz as f64),
+            None => self.settings.ui.update_interval(),
+        }
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -131,6 +430,32 @@ impl App {
         }
     }
 
+    // The window moved to a display with a different scale, e.g. the Deck docking to an external screen
+    fn scale_factor_changed(&mut self, window: &Window, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.platform.attach_window(self.imgui.io_mut(), window, HiDpiMode::Default);
+        self.resize(new_size);
+    }
+
+    fn recreate_gpu(&mut self, window: &Window) {
+        log::warn!("Recreating GPU device and surface");
+        match pollster::block_on(create_gpu(window, self.gpu_lost.clone())) {
+            Ok((surface, device, queue, config)) => {
+                let renderer_config = RendererConfig {
+                    texture_format: config.format,
+                    ..Default::default()
+                };
+                self.renderer = Renderer::new(&mut self.imgui, &device, &queue, renderer_config);
+                self.surface = surface;
+                self.device = device;
+                self.queue = queue;
+                self.config = config;
+                self.size = window.inner_size();
+                self.gpu_lost.store(false, Ordering::SeqCst);
+            }
+            Err(e) => log::error!("Failed to recreate GPU device: {}", e),
+        }
+    }
+
     fn input(&mut self, event: &WindowEvent, window: &Window) -> bool {
         // Create a WindowEvent that owns the data
         let owned_event = match event {
@@ -168,68 +493,242 @@ impl App {
     }
 
     fn update(&mut self) {
-        // Handle pending network operations
-        if let Some((ip, port)) = self.pending_connect.take() {
-            let mut network_streamer = NetworkStreamer::new();
-            
-            // Use tokio::task::block_in_place to run async code in sync context
-            let connection_result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(network_streamer.connect(&ip, port))
-            });
-            
-            match connection_result {
-                Ok(_) => {
-                    self.network_streamer = network_streamer;
-                    self.controller_debug.set_connection_status("Connected".to_string());
-                    self.controller_debug.set_network_enabled(true);
-                    log::info!("Successfully connected to server");
+        // Check for UI-triggered network operations
+        if let Some((server_ip, server_port)) = self.controller_debug.should_connect_network() {
+            self.failover.start(&server_ip, server_port);
+            self.network_streamer.connect(&server_ip, server_port);
+        }
+        
+        if self.controller_debug.should_disconnect_network() {
+            self.failover.stop();
+            self.network_streamer.disconnect();
+        }
+
+        if let Some(failover) = self.controller_debug.failover_update() {
+            self.settings.network.failover = failover;
+            if let Err(e) = self.settings.save(&self.settings_path) {
+                log::error!("Failed to save settings: {}", e);
+            }
+        }
+
+        if let Some(servers) = self.controller_debug.servers_update() {
+            self.settings.network.servers = servers;
+            self.failover.set_servers(self.settings.network.server_addresses());
+            if let Err(e) = self.settings.save(&self.settings_path) {
+                log::error!("Failed to save settings: {}", e);
+            }
+        }
+
+        if let Some(send_rate_hz) = self.controller_debug.send_rate_update() {
+            self.network_streamer.set_send_rate(send_rate_hz);
+            self.settings.network.send_rate_hz = send_rate_hz;
+            if let Err(e) = self.settings.save(&self.settings_path) {
+                log::error!("Failed to save settings: {}", e);
+            }
+        }
+
+        if let Some(conditions) = self.controller_debug.network_conditions_update() {
+            self.network_streamer.set_conditions(conditions);
+        }
+
+        if let Some(profile) = self.controller_debug.profile_update() {
+            self.network_streamer.select_profile(&profile);
+            self.settings.network.profile = profile;
+            if let Err(e) = self.settings.save(&self.settings_path) {
+                log::error!("Failed to save settings: {}", e);
+            }
+        }
+
+        if let Some(axis_epsilon) = self.controller_debug.axis_epsilon_update() {
+            self.input_filter.axis_epsilon = axis_epsilon;
+            self.settings.network.axis_epsilon = axis_epsilon;
+            if let Err(e) = self.settings.save(&self.settings_path) {
+                log::error!("Failed to save settings: {}", e);
+            }
+        }
+
+        if let Some(transport) = self.controller_debug.transport_update() {
+            self.set_transport(transport);
+        }
+
+        if let Some(enabled) = self.controller_debug.hid_passthrough_update() {
+            self.set_hid_passthrough(enabled);
+        }
+
+        if let Some(enabled) = self.controller_debug.pc_controller_update() {
+            self.set_pc_controller(enabled);
+        }
+
+        if let Some(enabled) = self.controller_debug.local_pad_update() {
+            self.set_local_pad(enabled);
+        }
+
+        if let Some(pattern) = self.haptic_designer.test_update() {
+            let ids: Vec<u32> = self.input_source.controllers().iter().map(|info| info.controller_id).collect();
+            if ids.is_empty() {
+                self.toasts.push(ToastKind::Warning, "No controller to play the pattern on");
+            }
+            for id in ids {
+                if let Err(e) = self.input_source.play_haptics(id, &pattern) {
+                    self.toasts.push(ToastKind::Warning, format!("Controller {} can't play it: {:#}", id, e));
                 }
-                Err(e) => {
-                    self.controller_debug.set_connection_status("Connection Failed".to_string());
-                    self.controller_debug.set_network_enabled(false);
-                    log::error!("Failed to connect to server: {}", e);
+            }
+        }
+
+        if let Some(enabled) = self.controller_debug.pause_when_unfocused_update() {
+            self.settings.network.pause_when_unfocused = enabled;
+            self.update_paused();
+            if let Err(e) = self.settings.save(&self.settings_path) {
+                log::error!("Failed to save settings: {}", e);
+            }
+        }
+
+        if self.controller_debug.should_toggle_recording() {
+            if self.recorder.is_recording() {
+                match self.recorder.stop() {
+                    Ok(Some((path, event_count))) => {
+                        self.toasts.push(ToastKind::Success, format!("Recorded {} events to {}", event_count, path.display()));
+                        self.controller_debug.set_replay_path(path.display().to_string());
+                    }
+                    Ok(None) => {}
+                    Err(e) => self.toasts.push(ToastKind::Error, format!("Failed to save recording: {}", e)),
+                }
+            } else {
+                match self.recorder.start() {
+                    Ok(path) => self.toasts.push(ToastKind::Info, format!("Recording input to {}", path.display())),
+                    Err(e) => self.toasts.push(ToastKind::Error, format!("Failed to start recording: {}", e)),
                 }
             }
         }
 
-        if self.pending_disconnect {
-            self.pending_disconnect = false;
-            let _ = self.network_streamer.disconnect();
-            self.controller_debug.set_connection_status("Disconnected".to_string());
-            self.controller_debug.set_network_enabled(false);
+        if self.controller_debug.should_toggle_replay() {
+            if let Some(replay) = self.replay.take() {
+                self.toasts.push(ToastKind::Info, format!("Stopped replaying {}", replay.path().display()));
+            } else {
+                match InputReplay::load(std::path::Path::new(self.controller_debug.replay_path())) {
+                    Ok(replay) => {
+                        self.toasts.push(ToastKind::Info, format!("Replaying {} events from {}", replay.event_count(), replay.path().display()));
+                        self.replay = Some(replay);
+                    }
+                    Err(e) => self.toasts.push(ToastKind::Error, format!("Failed to load recording: {:#}", e)),
+                }
+            }
         }
 
-        // Check for UI-triggered network operations
-        if let Some((server_ip, server_port)) = self.controller_debug.should_connect_network() {
-            if !self.network_streamer.is_connected() && self.pending_connect.is_none() {
-                self.pending_connect = Some((server_ip, server_port));
+        // Reflect what the connection manager actually did
+        while let Some(status) = self.network_streamer.poll_status() {
+            match status {
+                NetworkStatus::Connecting(address) => {
+                    self.controller_debug.set_connection_status(format!("Connecting to {}...", address));
+                }
+                NetworkStatus::Connected(address) => {
+                    self.input_filter.reset();
+                    self.failover.connected();
+                    self.controller_debug.set_connection_status("Connected".to_string());
+                    self.controller_debug.set_network_enabled(true);
+                    self.toasts.push(ToastKind::Success, format!("Connected to {}", address));
+                    play_haptic_event(self.input_source.as_mut(), &self.settings.haptics, HapticEvent::Connected, None);
+                }
+                NetworkStatus::ConnectionFailed(address, error) => {
+                    self.controller_debug.set_connection_status("Connection Failed".to_string());
+                    self.controller_debug.set_network_enabled(false);
+                    // Failing over tries server after server, only switching is worth a toast
+                    match self.fail_over() {
+                        Some(next) if next != address => {
+                            log::error!("Failed to connect to {}: {}", address, error);
+                            self.toasts.push(ToastKind::Info, format!("{} unreachable, failing over to {}", address, next));
+                        }
+                        Some(_) => log::error!("Failed to connect to {}: {}", address, error),
+                        None => self.toasts.push(ToastKind::Error, format!("Failed to connect to {}: {}", address, error)),
+                    }
+                }
+                NetworkStatus::ConnectionLost(address) => {
+                    release_pc_pad(&mut self.pc_pad);
+                    play_haptic_event(self.input_source.as_mut(), &self.settings.haptics, HapticEvent::Disconnected, None);
+                    self.controller_debug.set_connection_status("Connection Lost".to_string());
+                    self.controller_debug.set_network_enabled(false);
+                    self.toasts.push(ToastKind::Error, format!("Lost connection to {}", address));
+                    self.fail_over();
+                }
+                NetworkStatus::Disconnected => {
+                    release_pc_pad(&mut self.pc_pad);
+                    play_haptic_event(self.input_source.as_mut(), &self.settings.haptics, HapticEvent::Disconnected, None);
+                    self.failover.stop();
+                    self.controller_debug.set_connection_status("Disconnected".to_string());
+                    self.controller_debug.set_network_enabled(false);
+                    self.toasts.push(ToastKind::Info, "Disconnected from server");
+                }
+                NetworkStatus::Profiles(profiles) => {
+                    self.controller_debug.set_server_profiles(profiles);
+                }
+                NetworkStatus::Delivery(stats) => {
+                    self.input_test.acked(stats.acked_id);
+                    self.controller_debug.set_delivery_stats(stats);
+                }
+                NetworkStatus::PcInput(data) => play_pc_input(&mut self.pc_pad, &data),
+                NetworkStatus::PadHealth(health) => {
+                    match (self.controller_debug.set_pad_health(health.clone()), health) {
+                        (PadHealth::Working, PadHealth::Failed(error)) => {
+                            log::warn!("The server's virtual controller stopped working: {}", error);
+                            self.toasts.push(ToastKind::Warning, "The PC's virtual controller stopped working, the server is plugging it back in");
+                        }
+                        (PadHealth::Failed(_), PadHealth::Working) => {
+                            log::info!("The server's virtual controller is working again");
+                            self.toasts.push(ToastKind::Success, "The PC's virtual controller is working again");
+                        }
+                        _ => {}
+                    }
+                }
+                NetworkStatus::PadStatus(pad_status) => {
+                    let (status, text) = controller_debug::pad_status_text(&pad_status);
+                    log::info!("{}", text);
+                    self.controller_debug.set_pad_status(pad_status);
+                    let kind = if status == palette::Status::Good { ToastKind::Success } else { ToastKind::Warning };
+                    self.toasts.push(kind, text);
+                }
             }
         }
-        
-        if self.controller_debug.should_disconnect_network() {
-            self.pending_disconnect = true;
+        if let Some((server_ip, server_port)) = self.failover.poll(Instant::now()) {
+            self.controller_debug.set_server_address(server_ip.clone(), server_port);
+            self.network_streamer.connect(&server_ip, server_port);
         }
+        self.controller_debug.set_active_server(self.failover.active(), self.failover.is_pending());
         
         // Poll controller events
         let mut network_data = ControllerInputData {
+            message_id: 0,
             timestamp: get_current_timestamp(),
+            sent_at: 0,
             controller_id: 0,
             button_events: Vec::new(),
             axis_events: Vec::new(),
         };
 
-        while let Some(Event { id, event, time }) = self.gilrs.next_event() {
+        // Covers polling through handing the message to the network task, which assigns its ID
+        let poll_span = tracing::debug_span!("poll", events = tracing::field::Empty).entered();
+        let mut events_processed = 0;
+        while let Some(SourceEvent { controller_id: id, event, time }) = self.input_source.next_event() {
+            events_processed += 1;
+            self.recorder.record(id, event, time);
+            self.setup_wizard.handle_input_event(event);
+            self.input_test.handle_input_event(event, self.network_streamer.is_connected() && !self.paused);
+            
             // Update controller debug UI
-            self.controller_debug.handle_gilrs_event(id, event, time.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64());
+            self.controller_debug.handle_input_event(id, event, time.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64());
             
             // Prepare network data
-            network_data.controller_id = usize::from(id) as u32;
+            network_data.controller_id = id;
             let timestamp = get_current_timestamp();
             
             // Update Steam Input with real controller data
             match event {
-                gilrs::EventType::Connected => {
+                InputEvent::Connected => {
                     log::info!("Controller {} connected", id);
+                    if let Some(info) = self.input_source.controller_info(id) {
+                        self.toasts.push(ToastKind::Info, format!("Controller connected: {}", info.name));
+                        self.network_streamer.report_controller(&info);
+                        self.controller_debug.set_controller_info(info);
+                    }
                     
                     // Auto-connect to server when controller connects
                     if !self.network_streamer.is_connected() {
@@ -239,107 +738,132 @@ impl App {
                         // We'll handle this in the render loop since we can't do async here
                     }
                 }
-                gilrs::EventType::Disconnected => {
+                InputEvent::Disconnected => {
                     log::info!("Controller {} disconnected", id);
                     self.steam_input.remove_controller(id);
+                    self.toasts.push(ToastKind::Warning, format!("Controller {} disconnected", id));
                 }
-                gilrs::EventType::ButtonPressed(button, _) => {
+                InputEvent::ButtonPressed(button) => {
                     self.steam_input.update_from_controller_input(id, Some((button, true)), None);
                     
-                    // Don't send trigger buttons as digital events - they're handled as analog axes
-                    if !matches!(button, gilrs::Button::LeftTrigger2 | gilrs::Button::RightTrigger2) {
-                        network_data.button_events.push(ButtonEvent {
-                            button: button_to_string(button),
-                            pressed: true,
-                            timestamp,
-                        });
-                    }
-                    
-                    log::info!("Button pressed: {:?}", button);
+                    tracing::debug!(?button, "button pressed");
                 }
-                gilrs::EventType::ButtonReleased(button, _) => {
+                InputEvent::ButtonReleased(button) => {
                     self.steam_input.update_from_controller_input(id, Some((button, false)), None);
                     
-                    // Don't send trigger buttons as digital events - they're handled as analog axes
-                    if !matches!(button, gilrs::Button::LeftTrigger2 | gilrs::Button::RightTrigger2) {
-                        network_data.button_events.push(ButtonEvent {
-                            button: button_to_string(button),
-                            pressed: false,
-                            timestamp,
-                        });
-                    }
-                    
-                    log::info!("Button released: {:?}", button);
+                    tracing::debug!(?button, "button released");
                 }
-                gilrs::EventType::AxisChanged(axis, value, _) => {
+                InputEvent::AxisChanged(axis, value) => {
                     self.steam_input.update_from_controller_input(id, None, Some((axis, value)));
                     
                     // Debug log for trigger axes
                     if matches!(axis, gilrs::Axis::LeftZ | gilrs::Axis::RightZ) {
-                        log::info!("Trigger axis detected: {:?} = {:.3}", axis, value);
-                    }
-                    
-                    // Send all trigger values (LeftZ/RightZ) and significant stick changes
-                    let should_send = match axis {
-                        gilrs::Axis::LeftZ | gilrs::Axis::RightZ => true,  // Always send trigger values
-                        _ => value.abs() > 0.1,  // Only send significant changes for other axes
-                    };
-                    
-                    if should_send {
-                        network_data.axis_events.push(AxisEvent {
-                            axis: axis_to_string(axis),
-                            value,
-                            timestamp,
-                        });
-                        
-                        // Debug log for network data
-                        if matches!(axis, gilrs::Axis::LeftZ | gilrs::Axis::RightZ) {
-                            log::info!("Sending trigger network data: {} = {:.3}", axis_to_string(axis), value);
-                        }
+                        tracing::debug!(?axis, value, "trigger axis");
                     }
                 }
-                gilrs::EventType::ButtonChanged(button, value, _) => {
+                InputEvent::ButtonChanged(button, value) => {
                     // Treat as digital input with threshold
-                    let pressed = value > 0.5;
+                    let pressed = self.settings.triggers.button_pressed(button, value);
                     self.steam_input.update_from_controller_input(id, Some((button, pressed)), None);
-                    
-                    // Don't send trigger buttons as digital events - they're handled as analog axes
-                    if !matches!(button, gilrs::Button::LeftTrigger2 | gilrs::Button::RightTrigger2) {
-                        network_data.button_events.push(ButtonEvent {
-                            button: button_to_string(button),
-                            pressed,
-                            timestamp,
-                        });
-                    }
                 }
-                _ => {}
             }
+
+            push_network_event(&mut network_data, event, timestamp);
         }
 
+        // Replayed events take the same route as live ones, through the filter and out to the server
+        if let Some(replay) = &mut self.replay {
+            for recorded in replay.due() {
+                network_data.controller_id = recorded.controller_id;
+                push_network_event(&mut network_data, recorded.event, get_current_timestamp());
+            }
+            if replay.is_finished() {
+                self.toasts.push(ToastKind::Success, format!("Finished replaying {}", replay.path().display()));
+                self.replay = None;
+            }
+        }
+        self.radial_menu.update(&self.settings.radial_menu.slices, &mut network_data);
+        self.controller_debug.set_recording(self.recorder.is_recording());
+        self.controller_debug.set_replay_progress(self.replay.as_ref().map(|replay| replay.progress()));
+
+        self.frame_stats.set_events_processed(events_processed);
+        poll_span.record("events", events_processed);
+
         // Send network data if we have events and are connected
-        if (!network_data.button_events.is_empty() || !network_data.axis_events.is_empty()) && self.network_streamer.is_connected() {
-            log::info!("Sending {} button events and {} axis events", 
-                network_data.button_events.len(), 
-                network_data.axis_events.len());
-                
+        self.input_filter.filter(&mut network_data);
+        play_local_input(&mut self.local_pad, &network_data);
+        if self.paused {
+            // Still recorded and shown above, the transports only get to flush
+            network_data.button_events.clear();
+            network_data.axis_events.clear();
+        }
+        if let Some(usb_gadget) = &mut self.usb_gadget {
+            // Also retries a report the host hasn't taken yet, so it runs without new events too
+            if let Err(e) = usb_gadget.send_controller_data(&network_data) {
+                tracing::error!("Failed to send USB gadget report: {}", e);
+            }
+        } else if let Some(bluetooth_gamepad) = &mut self.bluetooth_gamepad {
+            // Also where hosts get accepted, so it runs without new events too
+            if let Err(e) = bluetooth_gamepad.send_controller_data(&network_data) {
+                tracing::error!("Failed to send Bluetooth gamepad report: {}", e);
+            }
+            match bluetooth_gamepad.connection_update() {
+                Some(true) => self.controller_debug.set_connection_status("Bluetooth host connected".to_string()),
+                Some(false) => self.controller_debug.set_connection_status("Waiting for a Bluetooth host".to_string()),
+                None => {}
+            }
+        } else if (!network_data.button_events.is_empty() || !network_data.axis_events.is_empty()) && self.network_streamer.is_connected() {
             // Try to send the data
-            if let Err(e) = self.network_streamer.send_controller_data(network_data) {
-                log::error!("Failed to send network data: {}", e);
+            let sent = network_data.clone();
+            match self.network_streamer.send_controller_data(network_data) {
+                Ok(Some(message_id)) => {
+                    self.input_test.message_sent(message_id, &sent);
+                    self.controller_debug.add_sent_message(message_id, &sent);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!("Failed to send network data: {}", e),
+            }
+        }
+        drop(poll_span);
+
+        if let Some(hid_reader) = &self.hid_reader {
+            while let Some((timestamp, report)) = hid_reader.next_report() {
+                if self.paused {
+                    continue;
+                }
+                if let Err(e) = self.network_streamer.send_hid_report(timestamp, &report) {
+                    tracing::debug!("Failed to send HID report: {}", e);
+                }
             }
         }
 
+        let (depth, capacity) = self.network_streamer.send_queue_depth();
+        self.frame_stats.set_queue_depth("Send Queue", depth, capacity);
+        if self.hid_reader.is_some() {
+            let (depth, capacity) = self.network_streamer.hid_queue_depth();
+            self.frame_stats.set_queue_depth("HID Queue", depth, capacity);
+        }
+
+        if self.last_battery_poll.elapsed() >= BATTERY_POLL_INTERVAL {
+            self.last_battery_poll = Instant::now();
+            report_batteries(self.input_source.as_ref(), &self.network_streamer);
+            play_low_battery(self.input_source.as_mut(), &self.settings.haptics, &mut self.low_batteries);
+        }
+
         // Handle sync - send all controller data every 200ms if enabled
-        if self.controller_debug.is_sync_enabled() && self.network_streamer.is_connected() {
+        if self.controller_debug.is_sync_enabled() && self.network_streamer.is_connected() && !self.paused {
             let now = std::time::Instant::now();
             if now.duration_since(self.last_sync_time) >= std::time::Duration::from_millis(200) {
                 self.last_sync_time = now;
                 
                 // Send current state of all controllers
-                for controller in self.gilrs.gamepads() {
-                    let (id, gamepad) = controller;
+                for controller in self.input_source.controllers() {
+                    let id = controller.controller_id;
                     let mut sync_data = ControllerInputData {
+                        message_id: 0,
                         timestamp: get_current_timestamp(),
-                        controller_id: usize::from(id) as u32,
+                        sent_at: 0,
+                        controller_id: id,
                         button_events: Vec::new(),
                         axis_events: Vec::new(),
                     };
@@ -352,11 +876,13 @@ impl App {
                         gilrs::Button::LeftThumb, gilrs::Button::RightThumb,
                         gilrs::Button::DPadUp, gilrs::Button::DPadDown, gilrs::Button::DPadLeft, gilrs::Button::DPadRight,
                     ] {
-                        sync_data.button_events.push(ButtonEvent {
-                            button: button_to_string(button),
-                            pressed: gamepad.is_pressed(button),
-                            timestamp: get_current_timestamp(),
-                        });
+                        if let Some(button_id) = button_to_id(button) {
+                            sync_data.button_events.push(ButtonEvent {
+                                button: button_id,
+                                pressed: self.input_source.is_pressed(id, button),
+                                timestamp: get_current_timestamp(),
+                            });
+                        }
                     }
                     
                     // Add all axis states (including triggers as analog)
@@ -366,11 +892,13 @@ impl App {
                         gilrs::Axis::LeftZ, gilrs::Axis::RightZ,  // Triggers as analog
                         gilrs::Axis::DPadX, gilrs::Axis::DPadY,
                     ] {
-                        sync_data.axis_events.push(AxisEvent {
-                            axis: axis_to_string(axis),
-                            value: gamepad.value(axis),
-                            timestamp: get_current_timestamp(),
-                        });
+                        if let Some(axis_id) = axis_to_id(axis) {
+                            sync_data.axis_events.push(AxisEvent {
+                                axis: axis_id,
+                                value: self.input_source.value(id, axis),
+                                timestamp: get_current_timestamp(),
+                            });
+                        }
                     }
                     
                     // Send the sync data
@@ -383,9 +911,6 @@ impl App {
 
         // Update Steam Input (this now just maintains internal state)
         self.steam_input.update();
-        
-        // Update controller debug UI with Steam Input data
-        self.controller_debug.update_steam_input(&self.steam_input);
     }
 
     fn render(&mut self, window: &Window) -> Result<(), wgpu::SurfaceError> {
@@ -402,6 +927,149 @@ impl App {
         // Render controller debug UI
         self.controller_debug.render(&ui, &self.steam_input);
 
+        if let Some(path) = self.controller_debug.steam_config_import() {
+            let imported = steam_vdf::import_mappings(&path, &self.steam_input.get_button_mappings(), &self.steam_input.get_axis_mappings());
+            match imported {
+                Ok((buttons, axes)) => {
+                    self.toasts.push(ToastKind::Success, format!("Imported {} button and {} axis mappings", buttons.len(), axes.len()));
+                    self.steam_input.set_mappings(buttons, axes);
+                }
+                Err(e) => {
+                    log::error!("Failed to import Steam controller config: {:#}", e);
+                    self.toasts.push(ToastKind::Error, format!("Import failed: {:#}", e));
+                }
+            }
+        }
+        if self.controller_debug.should_open_connection_wizard() {
+            self.connection_wizard.open();
+        }
+        if let Some(server) = self.connection_wizard.render(&ui) {
+            self.setup_wizard.set_server(server.ip.clone(), server.port);
+            self.settings.network.add_server(server.clone());
+            self.controller_debug.set_saved_servers(self.settings.network.servers.clone());
+            self.failover.set_servers(self.settings.network.server_addresses());
+            self.controller_debug.set_server_address(server.ip, server.port);
+            self.controller_debug.request_connect();
+            if let Err(e) = self.settings.save(&self.settings_path) {
+                log::error!("Failed to save settings: {}", e);
+            }
+        }
+
+        if self.setup_wizard.is_open() {
+            self.setup_wizard.set_controllers(self.input_source.controllers().into_iter().map(|info| info.name).collect());
+        }
+        self.setup_wizard.render(&ui);
+        if self.setup_wizard.should_open_connection_wizard() {
+            self.connection_wizard.open();
+        }
+        if let Some(server) = self.setup_wizard.server_update() {
+            self.settings.network.add_server(server.clone());
+            self.controller_debug.set_saved_servers(self.settings.network.servers.clone());
+            self.failover.set_servers(self.settings.network.server_addresses());
+            self.controller_debug.set_server_address(server.ip, server.port);
+            self.controller_debug.request_connect();
+        }
+        if self.setup_wizard.should_finish() {
+            // Written even if nothing changed, so setup doesn't come back on the next start
+            self.settings_dirty = true;
+        }
+
+        ui.main_menu_bar(|| {
+            ui.menu(tr("Settings"), || {
+                if ui.menu_item(tr("UI Settings")) {
+                    self.show_settings = true;
+                }
+                if ui.menu_item(tr("Trigger Thresholds")) {
+                    self.show_triggers = true;
+                }
+                if ui.menu_item(tr("Haptic Patterns")) {
+                    self.haptic_designer.show = true;
+                }
+                if ui.menu_item(tr("Radial Menu")) {
+                    self.show_radial_menu = true;
+                }
+                if ui.menu_item(tr("Import / Export...")) {
+                    self.bundle_window.open = true;
+                }
+                if ui.menu_item(tr("Setup Wizard...")) {
+                    self.setup_wizard.open();
+                }
+                if ui.menu_item(tr("Controller Test...")) {
+                    self.input_test.open();
+                }
+                if ui.menu_item_config(tr("Performance Overlay")).selected(self.frame_stats.show_overlay).build() {
+                    self.frame_stats.show_overlay = !self.frame_stats.show_overlay;
+                }
+            });
+            ui.menu(tr("View"), || {
+                ui.checkbox(tr("Log"), &mut self.log_viewer.show);
+            });
+        });
+
+        if self.show_settings && self.settings.ui.render(&ui, &mut self.show_settings) {
+            self.settings_dirty = true;
+        }
+
+        if self.show_triggers {
+            // The furthest pulled trigger across all controllers, for the preview
+            let controllers = self.input_source.controllers();
+            let pulled = |axis| controllers.iter()
+                .map(|info| self.input_source.value(info.controller_id, axis))
+                .fold(0.0, f32::max);
+            let values = [pulled(gilrs::Axis::LeftZ), pulled(gilrs::Axis::RightZ)];
+            if self.settings.triggers.render(&ui, &mut self.show_triggers, values) {
+                self.steam_input.set_triggers(self.settings.triggers);
+                self.controller_debug.set_triggers(self.settings.triggers);
+                self.settings_dirty = true;
+            }
+        }
+
+        self.input_test.render(ui);
+
+        if self.haptic_designer.render(ui, &mut self.settings.haptics) {
+            self.settings_dirty = true;
+        }
+
+        if self.show_radial_menu {
+            let was_enabled = self.settings.radial_menu.enabled;
+            if self.settings.radial_menu.render(ui, &mut self.show_radial_menu, &self.radial_menu) {
+                if self.settings.radial_menu.enabled != was_enabled {
+                    if let Err(e) = self.radial_menu.set_enabled(self.settings.radial_menu.enabled) {
+                        self.toasts.push(ToastKind::Error, format!("Radial menu unavailable: {:#}", e));
+                    }
+                }
+                self.settings_dirty = true;
+            }
+        }
+        self.radial_menu.render(ui, &self.settings.radial_menu.slices);
+
+        if let Some(settings) = self.bundle_window.render(&ui, &self.settings) {
+            self.settings = settings;
+            self.controller_debug.set_saved_servers(self.settings.network.servers.clone());
+            self.failover.set_servers(self.settings.network.server_addresses());
+            self.controller_debug.set_failover(self.settings.network.failover);
+            self.controller_debug.set_send_rate(self.settings.network.send_rate_hz);
+            self.controller_debug.set_axis_epsilon(self.settings.network.axis_epsilon);
+            self.controller_debug.set_pause_when_unfocused(self.settings.network.pause_when_unfocused);
+            self.steam_input.set_triggers(self.settings.triggers);
+            self.controller_debug.set_triggers(self.settings.triggers);
+            self.network_streamer.set_send_rate(self.settings.network.send_rate_hz);
+            self.input_filter.axis_epsilon = self.settings.network.axis_epsilon;
+            if let Err(e) = self.radial_menu.set_enabled(self.settings.radial_menu.enabled) {
+                log::error!("Radial menu unavailable: {:#}", e);
+            }
+            self.toasts.push(ToastKind::Success, "Settings imported");
+            // Applied and saved below like any other change
+            self.settings_dirty = true;
+        }
+
+        self.log_viewer.render(&ui);
+        self.toasts.render(&ui);
+        self.frame_stats.render(&ui);
+
+        // Only persist once the user has let go of the slider being dragged
+        let save_settings = self.settings_dirty && !ui.is_any_item_active();
+
         // Handle cursor before rendering
         let cursor = ui.mouse_cursor();
         if self.last_cursor != cursor {
@@ -436,12 +1104,138 @@ impl App {
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
+        if self.settings_dirty {
+            self.settings.ui.apply(&mut self.imgui, &self.base_style);
+            if save_settings {
+                self.settings_dirty = false;
+                if let Err(e) = self.settings.save(&self.settings_path) {
+                    log::error!("Failed to save settings: {}", e);
+                }
+            }
+        }
+
         Ok(())
     }
 }
 
-async fn run() -> Result<()> {
-    env_logger::init();
+// What games on the Deck see the PC's controller as
+const PC_PAD_NAME: &str = "Steam Deck Controls PC Controller";
+const LOCAL_PAD_NAME: &str = "Steam Deck Controls Virtual Pad";
+
+// Charge changes slowly, and the network task only sends a reading that differs from the last
+const BATTERY_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+// Creates the surface and device, also used to start over after the device is lost
+async fn create_gpu(window: &Window, gpu_lost: Arc<AtomicBool>) -> Result<(Surface, Device, Queue, SurfaceConfiguration)> {
+    let size = window.inner_size();
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        dx12_shader_compiler: Default::default(),
+    });
+
+    let surface = unsafe { instance.create_surface(window) }?;
+    
+    let adapter = instance.request_adapter(
+        &wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: Some(&surface),
+            force_fallback_adapter: false,
+        },
+    ).await.ok_or_else(|| anyhow::anyhow!("Failed to find suitable adapter"))?;
+
+    let (device, queue) = adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: if cfg!(target_arch = "wasm32") {
+                wgpu::Limits::downlevel_webgl2_defaults()
+            } else {
+                wgpu::Limits::default()
+            },
+        },
+        None,
+    ).await?;
+
+    let surface_caps = surface.get_capabilities(&adapter);
+    let surface_format = surface_caps.formats.iter()
+        .copied()
+        .find(|f| f.is_srgb())
+        .unwrap_or(surface_caps.formats[0]);
+
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: surface_format,
+        width: size.width.max(1),
+        height: size.height.max(1),
+        // The frame limiter paces rendering, vsync would also hold back input processing
+        present_mode: wgpu::PresentMode::AutoNoVsync,
+        alpha_mode: surface_caps.alpha_modes[0],
+        view_formats: vec![],
+    };
+    surface.configure(&device, &config);
+
+    // wgpu panics on uncaptured errors by default. A lost device is flagged so the main loop can
+    // start over with a new one, anything else is only logged.
+    device.on_uncaptured_error(Box::new(move |e| {
+        log::error!("GPU error: {}", e);
+        if is_device_lost(&e) {
+            gpu_lost.store(true, Ordering::SeqCst);
+        }
+    }));
+
+    Ok((surface, device, queue, config))
+}
+
+// wgpu 0.16 reports a lost device as a validation error, the loss is one of its sources
+fn is_device_lost(error: &wgpu::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(error) = source {
+        if error.to_string() == "Parent device is lost" {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+// Code still using the log macros is forwarded into tracing, RUST_LOG takes the usual target=level list.
+// The terminal only gets errors by default, the Log window is there for everything else. JSON
+// output is asked for to be read, so it starts at info like the Log window.
+fn init_tracing(log_format: LogFormat) -> std::sync::mpsc::Receiver<LogEntry> {
+    let from_env = std::env::var("RUST_LOG").ok()
+        .and_then(|value| value.parse::<Targets>().ok());
+    let json = log_format == LogFormat::Json;
+    let log_window_filter = from_env.clone()
+        .unwrap_or_else(|| Targets::new()
+            .with_default(LevelFilter::INFO)
+            .with_target("wgpu_hal", LevelFilter::OFF)
+            .with_target("wgpu_core", LevelFilter::OFF)
+            .with_target("wgpu", LevelFilter::OFF));
+    let filter = match from_env {
+        Some(from_env) => from_env,
+        None if json => log_window_filter.clone(),
+        None => Targets::new().with_default(LevelFilter::ERROR),
+    };
+
+    let (log_layer, log_receiver) = log_viewer::channel_layer();
+    tracing_subscriber::registry()
+        // Closing spans report how long each stage took
+        .with((!json).then(|| tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE).with_filter(filter.clone())))
+        .with(json.then(|| json_log::JsonLayer.with_filter(filter)))
+        .with(log_layer.with_filter(log_window_filter))
+        .init();
+    log_receiver
+}
+
+async fn run(args: Args) -> Result<()> {
+    let log_receiver = init_tracing(args.log_format);
+
+    if args.headless {
+        // Nothing would drain it without a window
+        drop(log_receiver);
+        return run_headless(&args).await;
+    }
     
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
@@ -449,7 +1243,11 @@ async fn run() -> Result<()> {
         .with_inner_size(winit::dpi::LogicalSize::new(1200.0, 800.0))
         .build(&event_loop)?;
 
-    let mut app = App::new(&window).await?;
+    let mut app = App::new(&window, &args, LogViewer::new(log_receiver)).await?;
+
+    // Updates and redraws are scheduled independently so input isn't tied to the frame rate
+    let mut next_update = Instant::now();
+    let mut next_render = Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
         match event {
@@ -464,31 +1262,324 @@ async fn run() -> Result<()> {
                             app.resize(*physical_size);
                         }
                         WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            app.resize(**new_inner_size);
+                            app.scale_factor_changed(&window, **new_inner_size);
                         }
+                        WindowEvent::Focused(focused) => app.set_focused(*focused),
                         _ => {}
                     }
                 }
             }
             WinitEvent::RedrawRequested(window_id) if window_id == window.id() => {
-                app.update();
+                // Minimized, there is nothing to draw into and the surface can't be configured
+                let size = window.inner_size();
+                if size.width == 0 || size.height == 0 {
+                    return;
+                }
+                if app.gpu_lost.load(Ordering::SeqCst) {
+                    app.recreate_gpu(&window);
+                }
+                // Resize events can go missing while switching displays
+                if size != app.size {
+                    app.resize(size);
+                }
+
+                app.frame_stats.begin_frame();
+                let render_start = Instant::now();
                 match app.render(&window) {
                     Ok(_) => {}
-                    Err(wgpu::SurfaceError::Lost) => app.resize(app.size),
+                    // Only the surface is stale, the device itself is rebuilt once it reports being lost
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        log::warn!("Surface lost or outdated, reconfiguring");
+                        app.resize(size);
+                    }
+                    Err(wgpu::SurfaceError::Timeout) => log::warn!("Timed out acquiring surface texture, skipping frame"),
                     Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    Err(e) => eprintln!("{:?}", e),
                 }
+                app.frame_stats.record_render(render_start.elapsed());
             }
             WinitEvent::MainEventsCleared => {
-                window.request_redraw();
+                let now = Instant::now();
+                if now >= next_update {
+                    let update_start = Instant::now();
+                    app.update();
+                    app.frame_stats.record_update(update_start.elapsed());
+                    next_update = next_deadline(next_update, app.update_interval(), now);
+                }
+                if now >= next_render {
+                    window.request_redraw();
+                    next_render = next_deadline(next_render, app.settings.ui.render_interval(), now);
+                }
+                if *control_flow != ControlFlow::Exit {
+                    *control_flow = ControlFlow::WaitUntil(next_update.min(next_render));
+                }
             }
             _ => {}
         }
     });
 }
 
+// Passthrough stays off for this run when the device can't be opened, the setting is kept
+fn open_hid_reader(network_streamer: &NetworkStreamer) -> Option<HidReader> {
+    match HidReader::open() {
+        Ok(hid_reader) => {
+            network_streamer.set_hid_device(Some(hid_reader.device().clone()));
+            Some(hid_reader)
+        }
+        Err(e) => {
+            log::error!("Raw HID passthrough unavailable: {:#}", e);
+            None
+        }
+    }
+}
+
+// Playing the PC's controller stays off for this run when the pad can't be created, the setting is kept
+fn open_pc_pad() -> Option<UinputPad> {
+    UinputPad::create(PC_PAD_NAME)
+        .inspect_err(|e| log::error!("Can't play the PC's controller: {:#}", e))
+        .ok()
+}
+
+fn play_pc_input(pc_pad: &mut Option<UinputPad>, data: &ControllerInputData) {
+    if let Some(pad) = pc_pad {
+        if let Err(e) = pad.send_controller_data(data) {
+            tracing::error!("Failed to play the PC's controller: {}", e);
+        }
+    }
+}
+
+// Nothing the PC's controller held survives the connection
+fn release_pc_pad(pc_pad: &mut Option<UinputPad>) {
+    if let Some(pad) = pc_pad {
+        if let Err(e) = pad.release_all() {
+            log::error!("Failed to release the PC's controller: {}", e);
+        }
+    }
+}
+
+// Stays off for this run when the pad can't be created, the setting is kept
+fn open_local_pad() -> Option<UinputPad> {
+    UinputPad::create(LOCAL_PAD_NAME)
+        .inspect_err(|e| log::error!("Virtual pad on this Deck unavailable: {:#}", e))
+        .ok()
+}
+
+// Games on the Deck have the focus while they run, so this ignores the pause
+fn play_local_input(local_pad: &mut Option<UinputPad>, data: &ControllerInputData) {
+    if let Some(pad) = local_pad {
+        if let Err(e) = pad.send_controller_data(data) {
+            tracing::error!("Failed to play input on this Deck: {}", e);
+        }
+    }
+}
+
+// Lets the server warn before a wireless controller dies, wired ones have nothing to report
+fn report_batteries(input_source: &dyn InputSource, network_streamer: &NetworkStreamer) {
+    for info in input_source.controllers() {
+        if let Some(battery) = input_source.battery(info.controller_id) {
+            network_streamer.report_battery(info.controller_id, battery);
+        }
+    }
+}
+
+// None plays it on every controller, those without rumble are skipped
+fn play_haptic_event(input_source: &mut dyn InputSource, haptics: &HapticSettings, event: HapticEvent, controller_id: Option<u32>) {
+    let Some(pattern) = haptics.pattern_for(event) else {
+        return;
+    };
+    let ids: Vec<u32> = match controller_id {
+        Some(id) => vec![id],
+        None => input_source.controllers().iter().map(|info| info.controller_id).collect(),
+    };
+    for id in ids {
+        if let Err(e) = input_source.play_haptics(id, pattern) {
+            log::debug!("No haptics on controller {}: {:#}", id, e);
+        }
+    }
+}
+
+// Plays the low battery pattern when a controller's battery drops low, not again until it recovered
+fn play_low_battery(input_source: &mut dyn InputSource, haptics: &HapticSettings, low_batteries: &mut HashSet<u32>) {
+    for info in input_source.controllers() {
+        let id = info.controller_id;
+        if !input_source.battery(id).is_some_and(|battery| battery.is_low()) {
+            low_batteries.remove(&id);
+        } else if low_batteries.insert(id) {
+            play_haptic_event(input_source, haptics, HapticEvent::LowBattery, Some(id));
+        }
+    }
+}
+
+// Just the input path of App::update: poll, filter, send. Reconnects on its own since
+// there is nobody to press the button
+async fn run_headless(args: &Args) -> Result<()> {
+    let settings = Settings::load(&args.config.clone().unwrap_or_else(settings::default_settings_path));
+    // Over the USB cable or Bluetooth there's no server to connect to
+    let mut usb_gadget = None;
+    let mut bluetooth_gamepad = None;
+    let server = match settings.network.transport {
+        Transport::UsbGadget => {
+            usb_gadget = Some(UsbGadget::start()?);
+            None
+        }
+        Transport::Bluetooth => {
+            bluetooth_gamepad = Some(BluetoothGamepad::start()?);
+            None
+        }
+        Transport::Network => Some(args.target(&settings.network.servers)
+            .ok_or_else(|| anyhow::anyhow!("--headless needs --server or a saved server"))?),
+    };
+
+    let mut input_source = GilrsSource::new()?;
+    let mut input_filter = InputFilter::new(settings.network.axis_epsilon);
+    let mut network_streamer = NetworkStreamer::new();
+    network_streamer.set_send_rate(settings.network.send_rate_hz);
+    network_streamer.select_profile(&settings.network.profile);
+    for info in input_source.controllers() {
+        network_streamer.report_controller(&info);
+    }
+    let hid_reader = settings.network.hid_passthrough.then(|| open_hid_reader(&network_streamer)).flatten();
+    let mut pc_pad = settings.network.pc_controller.then(open_pc_pad).flatten();
+    let mut local_pad = settings.network.local_pad.then(open_local_pad).flatten();
+    // Without the saved list, or with --server or --port picking one, the same server is retried
+    let mut failover = ServerFailover::new();
+    if settings.network.failover && args.server.is_none() && args.port.is_none() {
+        failover.set_servers(settings.network.server_addresses());
+    }
+    if let Some((server_ip, server_port)) = &server {
+        failover.start(server_ip, *server_port);
+        network_streamer.connect(server_ip, *server_port);
+    }
+
+    let poll_interval = args.poll_rate_hz
+        .map(|poll_rate_hz| Duration::from_secs_f64(1.0 / poll_rate_hz as f64))
+        .unwrap_or_else(|| settings.ui.update_interval());
+    let mut poll = tokio::time::interval(poll_interval);
+    poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut last_battery_poll = Instant::now();
+    let mut low_batteries = HashSet::new();
+    report_batteries(&input_source, &network_streamer);
+    log::info!("Running headless, press Ctrl+C to stop");
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = poll.tick() => {}
+        }
+
+        while let Some(status) = network_streamer.poll_status() {
+            match status {
+                NetworkStatus::Connecting(address) => log::info!("Connecting to {}...", address),
+                NetworkStatus::Connected(address) => {
+                    input_filter.reset();
+                    failover.connected();
+                    log::info!("Connected to {}", address);
+                    play_haptic_event(&mut input_source, &settings.haptics, HapticEvent::Connected, None);
+                }
+                NetworkStatus::ConnectionFailed(address, error) => {
+                    log::error!("Failed to connect to {}: {}", address, error);
+                    if let Some((server_ip, server_port)) = failover.failed(Instant::now()) {
+                        log::info!("Trying {}:{} next", server_ip, server_port);
+                    }
+                }
+                NetworkStatus::ConnectionLost(address) => {
+                    release_pc_pad(&mut pc_pad);
+                    play_haptic_event(&mut input_source, &settings.haptics, HapticEvent::Disconnected, None);
+                    log::error!("Lost connection to {}", address);
+                    if let Some((server_ip, server_port)) = failover.failed(Instant::now()) {
+                        log::info!("Trying {}:{} next", server_ip, server_port);
+                    }
+                }
+                NetworkStatus::Disconnected => release_pc_pad(&mut pc_pad),
+                NetworkStatus::Profiles(profiles) => {
+                    log::info!("Server profile: {} (of {})", profiles.active_profile, profiles.profiles.join(", "));
+                }
+                NetworkStatus::Delivery(_) => {}
+                NetworkStatus::PcInput(data) => play_pc_input(&mut pc_pad, &data),
+                NetworkStatus::PadHealth(PadHealth::Failed(error)) => {
+                    log::warn!("The server's virtual controller isn't responding: {}", error);
+                }
+                NetworkStatus::PadHealth(PadHealth::Working) => log::info!("The server's virtual controller is working again"),
+                NetworkStatus::PadStatus(pad_status) => log::info!("{}", controller_debug::pad_status_text(&pad_status).1),
+            }
+        }
+        if let Some((server_ip, server_port)) = failover.poll(Instant::now()) {
+            network_streamer.connect(&server_ip, server_port);
+        }
+
+        let mut network_data = ControllerInputData {
+            message_id: 0,
+            timestamp: get_current_timestamp(),
+            sent_at: 0,
+            controller_id: 0,
+            button_events: Vec::new(),
+            axis_events: Vec::new(),
+        };
+        while let Some(SourceEvent { controller_id, event, .. }) = input_source.next_event() {
+            match event {
+                InputEvent::Connected => {
+                    log::info!("Controller {} connected", controller_id);
+                    if let Some(info) = input_source.controller_info(controller_id) {
+                        network_streamer.report_controller(&info);
+                    }
+                }
+                InputEvent::Disconnected => log::info!("Controller {} disconnected", controller_id),
+                _ => {}
+            }
+            network_data.controller_id = controller_id;
+            push_network_event(&mut network_data, event, get_current_timestamp());
+        }
+
+        input_filter.filter(&mut network_data);
+        play_local_input(&mut local_pad, &network_data);
+        if let Some(usb_gadget) = &mut usb_gadget {
+            if let Err(e) = usb_gadget.send_controller_data(&network_data) {
+                tracing::error!("Failed to send USB gadget report: {}", e);
+            }
+        } else if let Some(bluetooth_gamepad) = &mut bluetooth_gamepad {
+            if let Err(e) = bluetooth_gamepad.send_controller_data(&network_data) {
+                tracing::error!("Failed to send Bluetooth gamepad report: {}", e);
+            }
+            match bluetooth_gamepad.connection_update() {
+                Some(true) => log::info!("Bluetooth host connected"),
+                Some(false) => log::info!("Waiting for a Bluetooth host"),
+                None => {}
+            }
+        } else if (!network_data.button_events.is_empty() || !network_data.axis_events.is_empty()) && network_streamer.is_connected() {
+            if let Err(e) = network_streamer.send_controller_data(network_data) {
+                tracing::error!("Failed to send network data: {}", e);
+            }
+        }
+        if let Some(hid_reader) = &hid_reader {
+            while let Some((timestamp, report)) = hid_reader.next_report() {
+                if let Err(e) = network_streamer.send_hid_report(timestamp, &report) {
+                    tracing::debug!("Failed to send HID report: {}", e);
+                }
+            }
+        }
+        if last_battery_poll.elapsed() >= BATTERY_POLL_INTERVAL {
+            last_battery_poll = Instant::now();
+            report_batteries(&input_source, &network_streamer);
+            play_low_battery(&mut input_source, &settings.haptics, &mut low_batteries);
+        }
+    }
+
+    network_streamer.disconnect();
+    Ok(())
+}
+
+// Keeps a steady cadence, but skips ahead instead of bursting after a stall
+fn next_deadline(deadline: Instant, interval: Duration, now: Instant) -> Instant {
+    let next = deadline + interval;
+    if next < now {
+        now + interval
+    } else {
+        next
+    }
+}
+
 fn main() -> Result<()> {
     // Use Tokio runtime instead of pollster
+    let args = Args::parse();
     let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(run())
+    rt.block_on(run(args))
 }
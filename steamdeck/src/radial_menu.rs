@@ -0,0 +1,190 @@
+use anyhow::Result;
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+use crate::hid_passthrough::{self, HidReader};
+use crate::i18n::{self, tr};
+use crate::input_id::ButtonId;
+use crate::network::{get_current_timestamp, ButtonEvent, ControllerInputData};
+use crate::palette::{self, Status};
+
+// Long enough for games that poll the pad every frame to see the press
+const PRESS_DURATION: Duration = Duration::from_millis(60);
+// Lifting closer to the center than this, as a share of the pad's radius, cancels
+const CENTER_DEADZONE: f32 = 0.3;
+const WHEEL_RADIUS: f32 = 140.0;
+const MIN_SLICES: usize = 2;
+const MAX_SLICES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RadialMenuSettings {
+    pub enabled: bool,
+    // Clockwise, the first one is centered at the top
+    pub slices: Vec<ButtonId>,
+}
+
+impl Default for RadialMenuSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            slices: vec![ButtonId::DPadUp, ButtonId::DPadRight, ButtonId::DPadDown, ButtonId::DPadLeft],
+        }
+    }
+}
+
+impl RadialMenuSettings {
+    // Returns true when a setting changed
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool, menu: &RadialMenu) -> bool {
+        let mut changed = false;
+        ui.window(i18n::window_title("Radial Menu"))
+            .size([360.0, 340.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                ui.text_wrapped(tr("Touch the left trackpad to bring up the wheel, slide onto a slice and lift to press its button on the PC. Lifting near the center cancels."));
+                changed |= ui.checkbox(tr("Enabled"), &mut self.enabled);
+                if self.enabled && !menu.is_reading() {
+                    palette::status_text(ui, Status::Bad, tr("No Steam Deck controller to read the trackpad from"));
+                }
+                ui.separator();
+
+                let mut count = self.slices.len() as u32;
+                if ui.slider(tr("Slices"), MIN_SLICES as u32, MAX_SLICES as u32, &mut count) {
+                    self.slices.resize(count as usize, ButtonId::South);
+                    changed = true;
+                }
+                let names: Vec<&str> = ButtonId::ALL.iter().map(|button| button.name()).collect();
+                for (index, slice) in self.slices.iter_mut().enumerate() {
+                    let mut selected = ButtonId::ALL.iter().position(|button| button == slice).unwrap_or(0);
+                    if ui.combo_simple_string(format!("{} {}", tr("Slice"), index + 1), &mut selected, &names) {
+                        *slice = ButtonId::ALL[selected];
+                        changed = true;
+                    }
+                }
+
+                ui.separator();
+                if ui.button(tr("Reset to Defaults")) {
+                    *self = Self { enabled: self.enabled, ..Self::default() };
+                    changed = true;
+                }
+            });
+        changed
+    }
+}
+
+// Reads the left trackpad on its own, so it works with Raw HID Passthrough off
+pub struct RadialMenu {
+    reader: Option<HidReader>,
+    // Where the left pad is touched, -1 to 1 on both axes, None while it isn't
+    touch: Option<[f32; 2]>,
+    // The slice's button and when it was pressed, released once PRESS_DURATION has passed
+    pressed: Option<(ButtonId, Instant)>,
+}
+
+impl RadialMenu {
+    pub fn new() -> Self {
+        Self {
+            reader: None,
+            touch: None,
+            pressed: None,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.touch = None;
+        self.reader = None;
+        if enabled {
+            self.reader = Some(HidReader::open()?);
+        }
+        Ok(())
+    }
+
+    pub fn is_reading(&self) -> bool {
+        self.reader.is_some()
+    }
+
+    // Adds the press and release of a picked slice to data, which goes out like any other input
+    pub fn update(&mut self, slices: &[ButtonId], data: &mut ControllerInputData) {
+        if let Some((button, since)) = self.pressed {
+            if since.elapsed() >= PRESS_DURATION {
+                self.pressed = None;
+                push_button(data, button, false);
+            }
+        }
+
+        let Some(reader) = &self.reader else {
+            return;
+        };
+        let mut lifted_at = None;
+        while let Some((_, report)) = reader.next_report() {
+            let Some([left_pad, _]) = hid_passthrough::deck_touchpads(&report) else {
+                continue;
+            };
+            if left_pad.touched {
+                self.touch = Some([left_pad.x as f32 / i16::MAX as f32, left_pad.y as f32 / i16::MAX as f32]);
+            } else if let Some(touch) = self.touch.take() {
+                lifted_at = Some(touch);
+            }
+        }
+
+        // A slice still held is let go first, so picking it again counts as a new press
+        let picked = lifted_at.and_then(|touch| slice_at(touch, slices.len())).map(|index| slices[index]);
+        if let Some(button) = picked {
+            if let Some((held, _)) = self.pressed.take() {
+                push_button(data, held, false);
+            }
+            push_button(data, button, true);
+            self.pressed = Some((button, Instant::now()));
+        }
+    }
+
+    // Drawn over every window while the left pad is touched
+    pub fn render(&self, ui: &Ui, slices: &[ButtonId]) {
+        let Some(touch) = self.touch else {
+            return;
+        };
+        let display = ui.io().display_size;
+        let center = [display[0] / 2.0, display[1] / 2.0];
+        let selected = slice_at(touch, slices.len());
+        let draw_list = ui.get_foreground_draw_list();
+
+        draw_list.add_circle(center, WHEEL_RADIUS, [0.0, 0.0, 0.0, 0.75]).filled(true).num_segments(64).build();
+        draw_list.add_circle(center, WHEEL_RADIUS * CENTER_DEADZONE, [0.6, 0.6, 0.6, 1.0]).num_segments(32).build();
+        let width = TAU / slices.len() as f32;
+        for (index, button) in slices.iter().enumerate() {
+            // Screen Y grows downwards, the angle is measured clockwise from the top
+            let at = |angle: f32, distance: f32| [center[0] + angle.sin() * distance, center[1] - angle.cos() * distance];
+            let edge = index as f32 * width - width / 2.0;
+            draw_list.add_line(at(edge, WHEEL_RADIUS * CENTER_DEADZONE), at(edge, WHEEL_RADIUS), [0.6, 0.6, 0.6, 1.0]).build();
+
+            let label = button.name();
+            let size = ui.calc_text_size(label);
+            let position = at(index as f32 * width, WHEEL_RADIUS * 0.65);
+            let color = if selected == Some(index) { palette::color(Status::Good) } else { [1.0, 1.0, 1.0, 1.0] };
+            draw_list.add_text([position[0] - size[0] / 2.0, position[1] - size[1] / 2.0], color, label);
+        }
+
+        // Where the thumb is, in the wheel's coordinates
+        let thumb = [center[0] + touch[0] * WHEEL_RADIUS, center[1] - touch[1] * WHEEL_RADIUS];
+        draw_list.add_circle(thumb, 8.0, [0.0, 0.8, 1.0, 1.0]).filled(true).build();
+    }
+}
+
+// Which slice a touch points at, None close to the center
+fn slice_at(touch: [f32; 2], slices: usize) -> Option<usize> {
+    if slices == 0 || (touch[0] * touch[0] + touch[1] * touch[1]).sqrt() < CENTER_DEADZONE {
+        return None;
+    }
+    let width = TAU / slices as f32;
+    let angle = touch[0].atan2(touch[1]) + width / 2.0;
+    Some((angle.rem_euclid(TAU) / width) as usize % slices)
+}
+
+fn push_button(data: &mut ControllerInputData, button: ButtonId, pressed: bool) {
+    data.button_events.push(ButtonEvent {
+        button,
+        pressed,
+        timestamp: get_current_timestamp(),
+    });
+}
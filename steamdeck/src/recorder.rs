@@ -0,0 +1,186 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+use crate::input_source::InputEvent;
+use crate::settings::config_dir;
+
+// .sdcrec files are JSON lines: a header followed by one recorded event per line
+pub const RECORDING_EXTENSION: &str = "sdcrec";
+const RECORDING_FORMAT: &str = "sdcrec";
+// 2 since events are stored as InputEvent rather than gilrs' own events
+const RECORDING_VERSION: u32 = 2;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordingHeader {
+    format: String,
+    version: u32,
+    recorded_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    // Microseconds since the recording started, taken from the event time
+    pub offset_us: u64,
+    pub controller_id: u32,
+    pub event: InputEvent,
+}
+
+struct Recording {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: SystemTime,
+    event_count: usize,
+}
+
+pub struct InputRecorder {
+    recording: Option<Recording>,
+}
+
+impl InputRecorder {
+    pub fn new() -> Self {
+        Self { recording: None }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn start(&mut self) -> Result<PathBuf> {
+        let dir = recordings_dir();
+        std::fs::create_dir_all(&dir)?;
+        let now = chrono::Local::now();
+        let path = dir.join(format!("input_{}.{}", now.format("%Y%m%d_%H%M%S"), RECORDING_EXTENSION));
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let header = RecordingHeader {
+            format: RECORDING_FORMAT.to_string(),
+            version: RECORDING_VERSION,
+            recorded_at: now.to_rfc3339(),
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+
+        log::info!("Recording input to {}", path.display());
+        self.recording = Some(Recording {
+            path: path.clone(),
+            writer,
+            started: SystemTime::now(),
+            event_count: 0,
+        });
+        Ok(path)
+    }
+
+    pub fn record(&mut self, controller_id: u32, event: InputEvent, time: SystemTime) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+
+        let recorded = RecordedEvent {
+            offset_us: time.duration_since(recording.started).unwrap_or_default().as_micros() as u64,
+            controller_id,
+            event,
+        };
+        let result = serde_json::to_writer(&mut recording.writer, &recorded)
+            .map_err(std::io::Error::from)
+            .and_then(|_| recording.writer.write_all(b"\n"));
+
+        match result {
+            Ok(()) => recording.event_count += 1,
+            Err(e) => {
+                // Stop rather than leave a file with gaps in it
+                log::error!("Failed to write to {}: {} - recording stopped", recording.path.display(), e);
+                self.recording = None;
+            }
+        }
+    }
+
+    // Returns the file that was written and how many events it holds
+    pub fn stop(&mut self) -> Result<Option<(PathBuf, usize)>> {
+        let Some(mut recording) = self.recording.take() else {
+            return Ok(None);
+        };
+        recording.writer.flush()?;
+        log::info!("Recorded {} events to {}", recording.event_count, recording.path.display());
+        Ok(Some((recording.path, recording.event_count)))
+    }
+}
+
+// Feeds a recording back in with the same spacing the events were captured with
+pub struct InputReplay {
+    path: PathBuf,
+    events: Vec<RecordedEvent>,
+    next: usize,
+    started: Instant,
+}
+
+impl InputReplay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header: RecordingHeader = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).context("invalid recording header")?,
+            None => bail!("{} is empty", path.display()),
+        };
+        if header.format != RECORDING_FORMAT || header.version != RECORDING_VERSION {
+            bail!("unsupported recording format {} v{}", header.format, header.version);
+        }
+
+        let mut events = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // Line numbers are 1-based and the header is line 1
+            let event: RecordedEvent = serde_json::from_str(&line)
+                .with_context(|| format!("invalid event on line {}", index + 2))?;
+            events.push(event);
+        }
+
+        log::info!("Loaded {} recorded events from {} ({})", events.len(), path.display(), header.recorded_at);
+        Ok(Self {
+            path: path.to_path_buf(),
+            events,
+            next: 0,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn event_count(&self) -> usize {
+        self.events.len()
+    }
+
+    // Events whose offset has passed, so timing is only as fine as the update interval
+    pub fn due(&mut self) -> &[RecordedEvent] {
+        let elapsed_us = self.started.elapsed().as_micros() as u64;
+        let start = self.next;
+        while self.next < self.events.len() && self.events[self.next].offset_us <= elapsed_us {
+            self.next += 1;
+        }
+        &self.events[start..self.next]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.events.is_empty() {
+            1.0
+        } else {
+            self.next as f32 / self.events.len() as f32
+        }
+    }
+}
+
+pub fn recordings_dir() -> PathBuf {
+    config_dir().join("recordings")
+}
@@ -0,0 +1,369 @@
+use anyhow::Result;
+use gilrs::{Axis, Button};
+use imgui::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::input_filter::DEFAULT_AXIS_EPSILON;
+use crate::haptic_designer::HapticSettings;
+use crate::i18n::{self, tr, Language};
+use crate::palette::{self, Palette};
+use crate::radial_menu::RadialMenuSettings;
+
+const APP_DIR: &str = "steamdeck-controls";
+const SETTINGS_FILE: &str = "settings.toml";
+// Settings were stored as JSON before, read once and converted
+const LEGACY_SETTINGS_FILE: &str = "settings.json";
+const IMGUI_INI_FILE: &str = "imgui.ini";
+
+// How input gets to the PC
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Transport {
+    // WebSocket to the server
+    #[default]
+    Network,
+    // A wired HID gamepad over the USB-C cable, see UsbGadget
+    UsbGadget,
+    // A paired Bluetooth HID gamepad, see BluetoothGamepad
+    Bluetooth,
+}
+
+impl Transport {
+    pub const ALL: [Transport; 3] = [Transport::Network, Transport::UsbGadget, Transport::Bluetooth];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Transport::Network => "Network",
+            Transport::UsbGadget => "USB Cable (Gadget)",
+            Transport::Bluetooth => "Bluetooth Gamepad",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Theme {
+    Dark,
+    Light,
+    Classic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiSettings {
+    pub theme: Theme,
+    // Status colors, picked apart from the theme
+    pub palette: Palette,
+    pub language: Language,
+    pub font_scale: f32,
+    pub ui_scale: f32,
+    // Input is processed at update_rate_hz no matter how often the window is redrawn
+    pub update_rate_hz: u32,
+    pub max_fps: u32,
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::Dark,
+            palette: Palette::Standard,
+            language: Language::English,
+            font_scale: 1.0,
+            ui_scale: 1.0,
+            update_rate_hz: 250,
+            max_fps: 60,
+        }
+    }
+}
+
+impl UiSettings {
+    // base_style is the style captured at startup, so scaling is never applied twice
+    pub fn apply(&self, imgui: &mut imgui::Context, base_style: &Style) {
+        let style = imgui.style_mut();
+        *style = *base_style;
+        match self.theme {
+            Theme::Dark => style.use_dark_colors(),
+            Theme::Light => style.use_light_colors(),
+            Theme::Classic => style.use_classic_colors(),
+        };
+        style.scale_all_sizes(self.ui_scale);
+        imgui.io_mut().font_global_scale = self.font_scale;
+        palette::set_palette(self.palette);
+        i18n::set_language(self.language);
+    }
+
+    pub fn update_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.update_rate_hz.max(1) as f64)
+    }
+
+    pub fn render_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.max_fps.max(1) as f64)
+    }
+
+    // Returns true when a value changed and the settings should be re-applied
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool) -> bool {
+        let mut changed = false;
+
+        ui.window(i18n::window_title("Settings"))
+            .size([350.0, 310.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                ui.text(tr("Appearance"));
+                ui.separator();
+
+                let themes = [Theme::Dark, Theme::Light, Theme::Classic];
+                let mut theme_index = themes.iter().position(|&t| t == self.theme).unwrap_or(0);
+                if ui.combo_simple_string(tr("Theme"), &mut theme_index, &[tr("Dark"), tr("Light"), tr("Classic")]) {
+                    self.theme = themes[theme_index];
+                    changed = true;
+                }
+                changed |= palette::render_combo(ui, &mut self.palette);
+                changed |= i18n::render_combo(ui, &mut self.language);
+
+                changed |= ui.slider(tr("Font Size"), 0.5, 3.0, &mut self.font_scale);
+                changed |= ui.slider(tr("UI Scale"), 0.5, 3.0, &mut self.ui_scale);
+
+                ui.spacing();
+                ui.text(tr("Performance"));
+                ui.separator();
+
+                changed |= ui.slider(tr("Update Rate (Hz)"), 30, 1000, &mut self.update_rate_hz);
+                changed |= ui.slider(tr("Max FPS"), 15, 240, &mut self.max_fps);
+
+                ui.separator();
+                if ui.button(tr("Reset to Defaults")) {
+                    *self = Self::default();
+                    changed = true;
+                }
+            });
+
+        changed
+    }
+}
+
+// How far a trigger has to be pulled before it counts as pressed, for the Steam Input actions
+// and the debug view. The server always gets the analog value.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerThreshold {
+    // For pads that report the trigger as an axis
+    pub analog: f32,
+    // For pads that report it as an analog button
+    pub button: f32,
+}
+
+impl Default for TriggerThreshold {
+    fn default() -> Self {
+        Self {
+            analog: 0.1,
+            button: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerSettings {
+    pub left: TriggerThreshold,
+    pub right: TriggerThreshold,
+}
+
+impl TriggerSettings {
+    pub fn axis_pressed(&self, axis: Axis, value: f32) -> bool {
+        match axis {
+            Axis::LeftZ => value > self.left.analog,
+            Axis::RightZ => value > self.right.analog,
+            _ => value > TriggerThreshold::default().analog,
+        }
+    }
+
+    // Other analog buttons keep the default threshold
+    pub fn button_pressed(&self, button: Button, value: f32) -> bool {
+        match button {
+            Button::LeftTrigger2 => value > self.left.button,
+            Button::RightTrigger2 => value > self.right.button,
+            _ => value > TriggerThreshold::default().button,
+        }
+    }
+
+    // values is how far each trigger is pulled right now, shown against its thresholds
+    pub fn render(&mut self, ui: &Ui, opened: &mut bool, values: [f32; 2]) -> bool {
+        let mut changed = false;
+
+        ui.window(i18n::window_title("Trigger Thresholds"))
+            .size([380.0, 300.0], Condition::FirstUseEver)
+            .opened(opened)
+            .build(|| {
+                ui.text_wrapped("Low thresholds make hair triggers, high ones need a deep pull.");
+                for (name, threshold, value) in [("Left", &mut self.left, values[0]), ("Right", &mut self.right, values[1])] {
+                    let _id = ui.push_id(name);
+                    ui.separator();
+                    ui.text(format!("{} Trigger", name));
+                    changed |= ui.slider_config("Axis", 0.0, 1.0).display_format("%.2f").build(&mut threshold.analog);
+                    changed |= ui.slider_config("Analog Button", 0.0, 1.0).display_format("%.2f").build(&mut threshold.button);
+                    let pressed = value > threshold.analog;
+                    ProgressBar::new(value.clamp(0.0, 1.0))
+                        .size([-1.0, 0.0])
+                        .overlay_text(format!("{:.2} - {}", value, if pressed { "Pressed" } else { "Released" }))
+                        .build(ui);
+                }
+                ui.separator();
+                if ui.button(tr("Reset to Defaults")) {
+                    *self = Self::default();
+                    changed = true;
+                }
+            });
+
+        changed
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedServer {
+    pub name: String,
+    pub ip: String,
+    pub port: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+    // In failover order
+    pub servers: Vec<SavedServer>,
+    // Move on to the next server when the current one can't be reached
+    pub failover: bool,
+    // 0 sends on every input change, anything else streams the full state at that rate
+    pub send_rate_hz: u32,
+    pub axis_epsilon: f32,
+    // Mapping profile asked of the server on connect, empty uses whatever is active there
+    pub profile: String,
+    // Also forward the Deck's raw HID reports, see HidReader
+    pub hid_passthrough: bool,
+    // Play a controller the server streams back through a virtual pad on the Deck
+    pub pc_controller: bool,
+    // Also play our own input through a virtual pad on the Deck, for games running there
+    pub local_pad: bool,
+    // Stop forwarding while the window is in the background, see App::set_focused
+    pub pause_when_unfocused: bool,
+    pub transport: Transport,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            servers: Vec::new(),
+            failover: true,
+            send_rate_hz: 0,
+            axis_epsilon: DEFAULT_AXIS_EPSILON,
+            profile: String::new(),
+            hid_passthrough: false,
+            pc_controller: false,
+            local_pad: false,
+            pause_when_unfocused: false,
+            transport: Transport::Network,
+        }
+    }
+}
+
+impl NetworkSettings {
+    // Re-saving a known address just renames it instead of adding a duplicate entry
+    pub fn add_server(&mut self, server: SavedServer) {
+        if let Some(existing) = self.servers.iter_mut().find(|s| s.ip == server.ip && s.port == server.port) {
+            existing.name = server.name;
+        } else {
+            self.servers.push(server);
+        }
+    }
+
+    pub fn server_addresses(&self) -> Vec<(String, i32)> {
+        self.servers.iter().map(|server| (server.ip.clone(), server.port)).collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub ui: UiSettings,
+    pub network: NetworkSettings,
+    pub triggers: TriggerSettings,
+    pub radial_menu: RadialMenuSettings,
+    pub haptics: HapticSettings,
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
+                log::warn!("Failed to parse {}: {} - using defaults", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::load_legacy(path).unwrap_or_default(),
+        }
+    }
+
+    fn load_legacy(path: &Path) -> Option<Self> {
+        let legacy_path = path.with_file_name(LEGACY_SETTINGS_FILE);
+        let contents = std::fs::read_to_string(&legacy_path).ok()?;
+        let settings: Self = match serde_json::from_str(&contents) {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::warn!("Failed to parse {}: {} - using defaults", legacy_path.display(), e);
+                return None;
+            }
+        };
+
+        match settings.save(path) {
+            Ok(_) => log::info!("Migrated {} to {}", legacy_path.display(), path.display()),
+            Err(e) => log::warn!("Failed to migrate {}: {}", legacy_path.display(), e),
+        }
+        Some(settings)
+    }
+
+    // Nothing saved yet, not even by an older version
+    pub fn is_first_run(path: &Path) -> bool {
+        !path.exists() && !path.with_file_name(LEGACY_SETTINGS_FILE).exists()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+pub fn default_settings_path() -> PathBuf {
+    config_dir().join(SETTINGS_FILE)
+}
+
+pub fn config_dir() -> PathBuf {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+
+    base.unwrap_or_else(|| PathBuf::from(".")).join(APP_DIR)
+}
+
+// Window layout lives next to the settings instead of the working directory, which
+// changes depending on how the app is launched (Steam shortcut, Proton, terminal)
+pub fn imgui_ini_path() -> Option<PathBuf> {
+    let dir = config_dir();
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        log::warn!("Failed to create {}: {} - window layout will not be saved", dir.display(), e);
+        return None;
+    }
+
+    let path = dir.join(IMGUI_INI_FILE);
+    let legacy_path = PathBuf::from(IMGUI_INI_FILE);
+    if !path.exists() && legacy_path.exists() {
+        if let Err(e) = std::fs::copy(&legacy_path, &path) {
+            log::warn!("Failed to migrate {}: {}", legacy_path.display(), e);
+        }
+    }
+
+    Some(path)
+}
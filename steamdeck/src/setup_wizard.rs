@@ -0,0 +1,223 @@
+use imgui::*;
+use std::net::IpAddr;
+use crate::i18n;
+use crate::input_source::InputEvent;
+use crate::palette::{self, Status};
+use crate::settings::SavedServer;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SetupStep {
+    Welcome,
+    InputTest,
+    Server,
+}
+
+// Shown on first launch, walks through what the hard-coded defaults used to assume
+pub struct SetupWizard {
+    open: bool,
+    step: SetupStep,
+    controllers: Vec<String>,
+    last_input: Option<String>,
+    server_ip: String,
+    port: i32,
+    // Already saved and connected to by the connection wizard, under the name given there
+    saved_server: Option<(String, i32)>,
+    server_update: Option<SavedServer>,
+    should_open_connection_wizard: bool,
+    should_finish: bool,
+}
+
+impl SetupWizard {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            step: SetupStep::Welcome,
+            controllers: Vec::new(),
+            last_input: None,
+            server_ip: String::new(),
+            port: 8080,
+            saved_server: None,
+            server_update: None,
+            should_open_connection_wizard: false,
+            should_finish: false,
+        }
+    }
+
+    pub fn open(&mut self) {
+        *self = Self::new();
+        self.open = true;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_controllers(&mut self, controllers: Vec<String>) {
+        self.controllers = controllers;
+    }
+
+    pub fn handle_input_event(&mut self, event: InputEvent) {
+        if self.step != SetupStep::InputTest {
+            return;
+        }
+        match event {
+            InputEvent::ButtonPressed(button) => self.last_input = Some(format!("{:?} pressed", button)),
+            InputEvent::AxisChanged(axis, value) if value.abs() > 0.5 => {
+                self.last_input = Some(format!("{:?} moved", axis));
+            }
+            _ => {}
+        }
+    }
+
+    // Filled in from the connection wizard once it found and saved a server
+    pub fn set_server(&mut self, server_ip: String, port: i32) {
+        self.saved_server = Some((server_ip.clone(), port));
+        self.server_ip = server_ip;
+        self.port = port;
+    }
+
+    pub fn server_update(&mut self) -> Option<SavedServer> {
+        self.server_update.take()
+    }
+
+    pub fn should_open_connection_wizard(&mut self) -> bool {
+        if self.should_open_connection_wizard {
+            self.should_open_connection_wizard = false;
+            return true;
+        }
+        false
+    }
+
+    // Also set when setup is skipped, either way the defaults get written and it won't show again
+    pub fn should_finish(&mut self) -> bool {
+        if self.should_finish {
+            self.should_finish = false;
+            return true;
+        }
+        false
+    }
+
+    fn server_error(&self) -> Option<&'static str> {
+        if self.server_ip.trim().is_empty() {
+            None
+        } else if self.server_ip.trim().parse::<IpAddr>().is_err() {
+            Some("Enter an IP address, e.g. 192.168.1.20")
+        } else if !(1..=65535).contains(&self.port) {
+            Some("The port must be between 1 and 65535")
+        } else {
+            None
+        }
+    }
+
+    fn finish(&mut self) {
+        self.open = false;
+        self.should_finish = true;
+    }
+
+    pub fn render(&mut self, ui: &Ui) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        ui.window(i18n::window_title("Setup"))
+            .size([480.0, 300.0], Condition::FirstUseEver)
+            .position_pivot([0.5, 0.5])
+            .position([ui.io().display_size[0] * 0.5, ui.io().display_size[1] * 0.5], Condition::FirstUseEver)
+            .opened(&mut open)
+            .build(|| match self.step {
+                SetupStep::Welcome => self.render_welcome(ui),
+                SetupStep::InputTest => self.render_input_test(ui),
+                SetupStep::Server => self.render_server(ui),
+            });
+
+        // Closing the window counts as skipping
+        if !open && self.open {
+            self.finish();
+        }
+    }
+
+    fn render_welcome(&mut self, ui: &Ui) {
+        ui.text("Welcome to Steam Deck Controls");
+        ui.separator();
+        ui.text_wrapped("This is the client. It runs on the Steam Deck and sends its controls to the server app on your PC, where they show up as an Xbox 360 controller.");
+        ui.spacing();
+        ui.text_wrapped("The next steps check that the Deck's controls are picked up and find the PC to connect to.");
+        ui.spacing();
+
+        if ui.button("Next") {
+            self.last_input = None;
+            self.step = SetupStep::InputTest;
+        }
+        ui.same_line();
+        if ui.button("Skip Setup") {
+            self.finish();
+        }
+    }
+
+    fn render_input_test(&mut self, ui: &Ui) {
+        ui.text("Step 1 of 2: Input Test");
+        ui.separator();
+
+        if self.controllers.is_empty() {
+            palette::status_text(ui, Status::Bad, "No controllers found");
+            ui.text_wrapped("On the Deck, make sure the app runs through Steam so the built-in controls are available.");
+        } else {
+            for name in &self.controllers {
+                palette::status_text(ui, Status::Good, format!("Found: {}", name));
+            }
+        }
+        ui.spacing();
+
+        ui.text("Press any button or move a stick.");
+        match &self.last_input {
+            Some(input) => palette::status_text(ui, Status::Good, format!("Got it: {}", input)),
+            None => palette::status_text(ui, Status::Warning, "Waiting for input..."),
+        }
+        ui.spacing();
+
+        if ui.button("Back") {
+            self.step = SetupStep::Welcome;
+        }
+        ui.same_line();
+        if ui.button("Next") {
+            self.step = SetupStep::Server;
+        }
+    }
+
+    fn render_server(&mut self, ui: &Ui) {
+        ui.text("Step 2 of 2: Server");
+        ui.separator();
+        ui.text_wrapped("Start the server on your PC, then search for it or enter the address it shows.");
+        ui.spacing();
+
+        if ui.button("Search Network...") {
+            self.should_open_connection_wizard = true;
+        }
+        ui.input_text("Server IP", &mut self.server_ip).build();
+        ui.input_int("Port", &mut self.port).build();
+
+        let error = self.server_error();
+        if let Some(error) = error {
+            palette::status_text(ui, Status::Bad, error);
+        }
+        ui.spacing();
+
+        if ui.button("Back") {
+            self.step = SetupStep::InputTest;
+        }
+        ui.same_line();
+        let label = if self.server_ip.trim().is_empty() { "Finish Without Server" } else { "Finish and Connect" };
+        if ui.button(label) && error.is_none() {
+            let server_ip = self.server_ip.trim().to_string();
+            if !server_ip.is_empty() && self.saved_server != Some((server_ip.clone(), self.port)) {
+                self.server_update = Some(SavedServer {
+                    name: server_ip.clone(),
+                    ip: server_ip,
+                    port: self.port,
+                });
+            }
+            self.finish();
+        }
+    }
+}
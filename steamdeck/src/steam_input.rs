@@ -1,16 +1,19 @@
 use anyhow::Result;
 use std::collections::HashMap;
-use gilrs::{GamepadId, Button, Axis};
+use gilrs::{Button, Axis};
+use crate::input_source::DeckModel;
+use crate::settings::TriggerSettings;
 
 pub struct SteamInputManager {
     initialized: bool,
     digital_actions: HashMap<String, bool>,
     analog_actions: HashMap<String, (f32, f32)>,
-    controller_handles: Vec<GamepadId>,
+    controller_handles: Vec<u32>,
     action_sets: Vec<u64>,
     // Map gilrs buttons/axes to Steam Input actions
     button_mappings: HashMap<Button, String>,
     axis_mappings: HashMap<Axis, String>,
+    triggers: TriggerSettings,
 }
 
 impl SteamInputManager {
@@ -23,6 +26,7 @@ impl SteamInputManager {
             action_sets: Vec::new(),
             button_mappings: HashMap::new(),
             axis_mappings: HashMap::new(),
+            triggers: TriggerSettings::default(),
         };
 
         manager.initialize()?;
@@ -93,7 +97,7 @@ impl SteamInputManager {
     }
 
     // New method to update Steam Input based on real controller input
-    pub fn update_from_controller_input(&mut self, controller_id: GamepadId, button: Option<(Button, bool)>, axis: Option<(Axis, f32)>) {
+    pub fn update_from_controller_input(&mut self, controller_id: u32, button: Option<(Button, bool)>, axis: Option<(Axis, f32)>) {
         if !self.initialized {
             return;
         }
@@ -130,16 +134,20 @@ impl SteamInputManager {
                         self.analog_actions.insert(action_name.clone(), (value, 0.0));
                         
                         // Also update the digital action for LT button press
-                        let pressed = value > 0.1; // Threshold for digital press
-                        self.digital_actions.insert("LT [ID: 6] - Aim".to_string(), pressed);
+                        let pressed = self.triggers.axis_pressed(ax, value);
+                        if let Some(action) = self.button_mappings.get(&Button::LeftTrigger2) {
+                            self.digital_actions.insert(action.clone(), pressed);
+                        }
                     }
                     Axis::RightZ => {
                         // Right trigger (R2) - store as X component for "Right Trigger - Fire"
                         self.analog_actions.insert(action_name.clone(), (value, 0.0));
                         
                         // Also update the digital action for RT button press
-                        let pressed = value > 0.1; // Threshold for digital press
-                        self.digital_actions.insert("RT [ID: 7] - Fire".to_string(), pressed);
+                        let pressed = self.triggers.axis_pressed(ax, value);
+                        if let Some(action) = self.button_mappings.get(&Button::RightTrigger2) {
+                            self.digital_actions.insert(action.clone(), pressed);
+                        }
                     }
                     _ => {
                         // Other axes - treat as X component
@@ -151,7 +159,11 @@ impl SteamInputManager {
         }
     }
 
-    pub fn remove_controller(&mut self, controller_id: GamepadId) {
+    pub fn set_triggers(&mut self, triggers: TriggerSettings) {
+        self.triggers = triggers;
+    }
+
+    pub fn remove_controller(&mut self, controller_id: u32) {
         self.controller_handles.retain(|&id| id != controller_id);
         
         // Reset all actions if no controllers are connected
@@ -169,12 +181,16 @@ impl SteamInputManager {
         }
     }
 
-    pub fn get_digital_actions(&self) -> HashMap<String, bool> {
-        self.digital_actions.clone()
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
     }
 
-    pub fn get_analog_actions(&self) -> HashMap<String, (f32, f32)> {
-        self.analog_actions.clone()
+    pub fn get_digital_actions(&self) -> &HashMap<String, bool> {
+        &self.digital_actions
+    }
+
+    pub fn get_analog_actions(&self) -> &HashMap<String, (f32, f32)> {
+        &self.analog_actions
     }
 
     pub fn get_controller_count(&self) -> usize {
@@ -188,7 +204,9 @@ impl SteamInputManager {
         }
         
         // Add Steam Deck controller if we detect it
-        if self.is_steam_deck() {
+        if let Some(deck_model) = DeckModel::detect() {
+            controllers.push(format!("{} Built-in Controller", deck_model.name()));
+        } else if self.is_steam_deck() {
             controllers.push("Steam Deck Built-in Controller".to_string());
         }
         
@@ -196,20 +214,10 @@ impl SteamInputManager {
     }
 
     fn is_steam_deck(&self) -> bool {
-        // Check if we're running on Steam Deck
-        std::env::var("SteamDeck").is_ok() || 
-        std::env::var("STEAM_DECK").is_ok() ||
-        self.check_steam_deck_hardware()
-    }
-
-    fn check_steam_deck_hardware(&self) -> bool {
-        // Simple check for Steam Deck - look for specific hardware indicators
-        // This is a basic implementation, you could make it more sophisticated
-        if let Ok(hostname) = std::env::var("HOSTNAME") {
-            hostname.to_lowercase().contains("steamdeck")
-        } else {
-            false
-        }
+        // The firmware knows, Steam's variables are for where /sys can't be read, e.g. in a container
+        DeckModel::detect().is_some() ||
+        std::env::var("SteamDeck").is_ok() ||
+        std::env::var("STEAM_DECK").is_ok()
     }
 
     pub fn shutdown(&mut self) {
@@ -227,6 +235,15 @@ impl SteamInputManager {
         self.axis_mappings.clone()
     }
 
+    // Replaces every mapping, e.g. with ones imported from a Steam controller config
+    pub fn set_mappings(&mut self, button_mappings: HashMap<Button, String>, axis_mappings: HashMap<Axis, String>) {
+        self.digital_actions = button_mappings.values().map(|action| (action.clone(), false)).collect();
+        self.analog_actions = axis_mappings.values().map(|action| (action.clone(), (0.0, 0.0))).collect();
+        self.button_mappings = button_mappings;
+        self.axis_mappings = axis_mappings;
+        log::info!("Steam Input mappings replaced, {} buttons and {} axes mapped", self.button_mappings.len(), self.axis_mappings.len());
+    }
+
     pub fn get_action_for_button(&self, button: Button) -> Option<String> {
         self.button_mappings.get(&button).cloned()
     }
@@ -235,10 +252,10 @@ impl SteamInputManager {
         self.axis_mappings.get(&axis).cloned()
     }
 
-    pub fn get_debug_json(&self) -> String {
+    pub fn get_debug_value(&self) -> serde_json::Value {
         use serde_json::json;
         
-        let debug_data = json!({
+        json!({
             "initialized": self.initialized,
             "controller_count": self.controller_handles.len(),
             "connected_controllers": self.get_connected_controllers(),
@@ -261,9 +278,7 @@ impl SteamInputManager {
                 "DPadX": "ID 7 - D-pad horizontal",
                 "DPadY": "ID 8 - D-pad vertical"
             }
-        });
-        
-        serde_json::to_string_pretty(&debug_data).unwrap_or_else(|_| "Failed to serialize debug data".to_string())
+        })
     }
 }
 
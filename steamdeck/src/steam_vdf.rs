@@ -0,0 +1,481 @@
+use anyhow::{bail, Context, Result};
+use gilrs::{Axis, Button};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use crate::settings::config_dir;
+
+const ACTION_SET: &str = "gameplay";
+const ACTION_SET_TITLE: &str = "Gameplay";
+const EXPORTS_DIR: &str = "exports";
+
+// Steam's input sources in the order their groups are written, with the mode each one runs in
+const SOURCES: [(&str, &str); 7] = [
+    ("button_diamond", "four_buttons"),
+    ("switch", "switches"),
+    ("dpad", "dpad"),
+    ("joystick", "joystick_move"),
+    ("right_joystick", "joystick_move"),
+    ("left_trigger", "trigger"),
+    ("right_trigger", "trigger"),
+];
+
+// Where a gilrs button sits on the Deck, as a Steam source and the input inside it
+fn button_source(button: Button) -> Option<(&'static str, &'static str)> {
+    Some(match button {
+        Button::South => ("button_diamond", "button_a"),
+        Button::East => ("button_diamond", "button_b"),
+        Button::West => ("button_diamond", "button_x"),
+        Button::North => ("button_diamond", "button_y"),
+        // gilrs calls the bumpers triggers and the triggers Trigger2
+        Button::LeftTrigger => ("switch", "left_bumper"),
+        Button::RightTrigger => ("switch", "right_bumper"),
+        Button::Start => ("switch", "button_menu"),
+        Button::Select => ("switch", "button_escape"),
+        Button::LeftTrigger2 => ("left_trigger", "click"),
+        Button::RightTrigger2 => ("right_trigger", "click"),
+        Button::LeftThumb => ("joystick", "click"),
+        Button::RightThumb => ("right_joystick", "click"),
+        Button::DPadUp => ("dpad", "dpad_north"),
+        Button::DPadDown => ("dpad", "dpad_south"),
+        Button::DPadLeft => ("dpad", "dpad_west"),
+        Button::DPadRight => ("dpad", "dpad_east"),
+        _ => return None,
+    })
+}
+
+fn axis_source(axis: Axis) -> Option<&'static str> {
+    Some(match axis {
+        Axis::LeftStickX | Axis::LeftStickY => "joystick",
+        Axis::RightStickX | Axis::RightStickY => "right_joystick",
+        Axis::LeftZ => "left_trigger",
+        Axis::RightZ => "right_trigger",
+        _ => return None,
+    })
+}
+
+// The in-app names read "A (South) [ID: 0] - Jump", Steam gets the part after the dash as the
+// title and a lowercase version of it as the action name
+fn action_title(mapping: &str) -> &str {
+    mapping.rsplit_once(" - ").map_or(mapping, |(_, title)| title).trim()
+}
+
+fn action_name(title: &str) -> String {
+    title.to_lowercase().split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+// The actions and which Deck inputs trigger them, worked out once for both files
+struct SteamBindings {
+    // Action name to title
+    digital: BTreeMap<String, String>,
+    analog: BTreeMap<String, (String, &'static str)>,
+    // Per source, the input and the digital action it presses
+    inputs: HashMap<&'static str, BTreeMap<&'static str, String>>,
+    // Per source, the analog action it drives
+    analog_sources: HashMap<&'static str, String>,
+}
+
+impl SteamBindings {
+    fn new(button_mappings: &HashMap<Button, String>, axis_mappings: &HashMap<Axis, String>) -> Self {
+        let mut bindings = Self {
+            digital: BTreeMap::new(),
+            analog: BTreeMap::new(),
+            inputs: HashMap::new(),
+            analog_sources: HashMap::new(),
+        };
+        for (&button, mapping) in button_mappings {
+            let Some((source, input)) = button_source(button) else {
+                continue;
+            };
+            let title = action_title(mapping);
+            let name = action_name(title);
+            bindings.digital.insert(name.clone(), title.to_string());
+            bindings.inputs.entry(source).or_default().insert(input, name);
+        }
+        for (&axis, mapping) in axis_mappings {
+            let Some(source) = axis_source(axis) else {
+                continue;
+            };
+            let title = action_title(mapping);
+            let mut name = action_name(title);
+            // Steam wants unique names within a set, e.g. RT fires digitally and its axis too
+            if bindings.digital.contains_key(&name) {
+                name.push_str("_axis");
+            }
+            let mode = if source.ends_with("trigger") { "AnalogTrigger" } else { "StickPadGyro" };
+            bindings.analog.insert(name.clone(), (title.to_string(), mode));
+            bindings.analog_sources.insert(source, name);
+        }
+        bindings
+    }
+
+    fn localization(&self) -> Vdf {
+        let mut english = vec![value(&format!("Set_{}", ACTION_SET_TITLE), ACTION_SET_TITLE)];
+        for (name, title) in self.digital.iter().chain(self.analog.iter().map(|(name, (title, _))| (name, title))) {
+            english.push(value(&format!("Action_{}", name), title));
+        }
+        Vdf::Block(vec![("english".to_string(), Vdf::Block(english))])
+    }
+}
+
+enum Vdf {
+    Value(String),
+    // A list rather than a map, Steam repeats keys such as "group"
+    Block(Vec<(String, Vdf)>),
+}
+
+impl Vdf {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Vdf::Value(text) => Some(text),
+            Vdf::Block(_) => None,
+        }
+    }
+
+    // Every entry under key, Steam itself doesn't care about the case of keys
+    fn all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a Vdf> + 'a {
+        self.entries().iter().filter(move |(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, node)| node)
+    }
+
+    fn get(&self, key: &str) -> Option<&Vdf> {
+        self.entries().iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, node)| node)
+    }
+
+    fn entries(&self) -> &[(String, Vdf)] {
+        match self {
+            Vdf::Block(entries) => entries,
+            Vdf::Value(_) => &[],
+        }
+    }
+}
+
+// Reads the text format Steam writes its configs in: quoted or bare keys, each followed by a
+// value or a braced block. Comments and platform conditionals like [$WIN32] are skipped.
+fn parse_vdf(text: &str) -> Result<(String, Vdf)> {
+    let mut tokens = tokenize(text)?.into_iter();
+    let key = match tokens.next() {
+        Some(Token::Text(key)) => key,
+        _ => bail!("Expected a key at the start of the file"),
+    };
+    match tokens.next() {
+        Some(Token::Open) => Ok((key, parse_block(&mut tokens)?)),
+        _ => bail!("Expected a block after \"{}\"", key),
+    }
+}
+
+enum Token {
+    Text(String),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '{' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            '}' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '/' => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '[' => {
+                while chars.next().is_some_and(|c| c != ']') {}
+            }
+            '"' => {
+                chars.next();
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => token.push('\n'),
+                            Some('t') => token.push('\t'),
+                            Some(c) => token.push(c),
+                            None => bail!("Unterminated string"),
+                        },
+                        Some(c) => token.push(c),
+                        None => bail!("Unterminated string"),
+                    }
+                }
+                tokens.push(Token::Text(token));
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !matches!(c, '{' | '}' | '"')) {
+                    token.push(c);
+                }
+                tokens.push(Token::Text(token));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_block(tokens: &mut impl Iterator<Item = Token>) -> Result<Vdf> {
+    let mut entries = Vec::new();
+    loop {
+        let key = match tokens.next() {
+            Some(Token::Text(key)) => key,
+            Some(Token::Close) => return Ok(Vdf::Block(entries)),
+            Some(Token::Open) => bail!("Expected a key, found {{"),
+            None => bail!("Missing }} at the end of the file"),
+        };
+        let node = match tokens.next() {
+            Some(Token::Text(value)) => Vdf::Value(value),
+            Some(Token::Open) => parse_block(tokens)?,
+            _ => bail!("Expected a value or block after \"{}\"", key),
+        };
+        entries.push((key, node));
+    }
+}
+
+fn value(key: &str, value: &str) -> (String, Vdf) {
+    (key.to_string(), Vdf::Value(value.to_string()))
+}
+
+fn block(key: &str, entries: Vec<(String, Vdf)>) -> (String, Vdf) {
+    (key.to_string(), Vdf::Block(entries))
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn write_entry(out: &mut String, depth: usize, key: &str, node: &Vdf) {
+    let indent = "\t".repeat(depth);
+    match node {
+        Vdf::Value(text) => {
+            let _ = writeln!(out, "{}{}\t\t{}", indent, quote(key), quote(text));
+        }
+        Vdf::Block(entries) => {
+            let _ = writeln!(out, "{}{}\n{}{{", indent, quote(key), indent);
+            for (key, node) in entries {
+                write_entry(out, depth + 1, key, node);
+            }
+            let _ = writeln!(out, "{}}}", indent);
+        }
+    }
+}
+
+fn to_vdf(root: (String, Vdf)) -> String {
+    let mut out = String::new();
+    write_entry(&mut out, 0, &root.0, &root.1);
+    out
+}
+
+// The In-Game Actions file declaring every action the mappings use, Steam reads it from
+// controller_config/game_actions_<appid>.vdf in its install directory
+pub fn game_actions_vdf(button_mappings: &HashMap<Button, String>, axis_mappings: &HashMap<Axis, String>) -> String {
+    let bindings = SteamBindings::new(button_mappings, axis_mappings);
+
+    let mut stick_pad_gyro = Vec::new();
+    let mut analog_trigger = Vec::new();
+    for (name, (_, mode)) in &bindings.analog {
+        let title = format!("#Action_{}", name);
+        if *mode == "AnalogTrigger" {
+            analog_trigger.push(value(name, &title));
+        } else {
+            stick_pad_gyro.push(block(name, vec![value("title", &title), value("input_mode", "joystick_move")]));
+        }
+    }
+    let buttons = bindings.digital.keys().map(|name| value(name, &format!("#Action_{}", name))).collect();
+
+    to_vdf(block("In Game Actions", vec![
+        block("actions", vec![
+            block(ACTION_SET, vec![
+                value("title", &format!("#Set_{}", ACTION_SET_TITLE)),
+                block("StickPadGyro", stick_pad_gyro),
+                block("AnalogTrigger", analog_trigger),
+                block("Button", buttons),
+            ]),
+        ]),
+        ("localization".to_string(), bindings.localization()),
+    ]))
+}
+
+// A Deck controller configuration binding the same inputs to the same actions as this app does
+pub fn controller_config_vdf(button_mappings: &HashMap<Button, String>, axis_mappings: &HashMap<Axis, String>) -> String {
+    let bindings = SteamBindings::new(button_mappings, axis_mappings);
+
+    let mut entries = vec![
+        value("version", "3"),
+        value("revision", "1"),
+        value("title", "Steam Deck Controls"),
+        value("description", "Exported from Steam Deck Controls"),
+        value("creator", ""),
+        value("controller_type", "controller_neptune"),
+        block("actions", vec![
+            block(ACTION_SET, vec![
+                value("title", &format!("#Set_{}", ACTION_SET_TITLE)),
+                value("legacy_set", "0"),
+            ]),
+        ]),
+        ("localization".to_string(), bindings.localization()),
+    ];
+
+    let mut source_bindings = Vec::new();
+    for (source, mode) in SOURCES {
+        let inputs = bindings.inputs.get(source);
+        let analog = bindings.analog_sources.get(source);
+        if inputs.is_none() && analog.is_none() {
+            continue;
+        }
+        let id = source_bindings.len().to_string();
+        let mut group = vec![value("id", &id), value("mode", mode)];
+
+        let inputs = inputs.into_iter().flatten().map(|(input, action)| {
+            let title = &bindings.digital[action];
+            let binding = format!("game_action {} {}, {}, , ", ACTION_SET, action, title);
+            block(input, vec![
+                block("activators", vec![
+                    block("Full_Press", vec![
+                        block("bindings", vec![value("binding", &binding)]),
+                    ]),
+                ]),
+            ])
+        }).collect();
+        group.push(block("inputs", inputs));
+        if let Some(action) = analog {
+            group.push(block("gameactions", vec![value(ACTION_SET, action)]));
+        }
+
+        entries.push(block("group", group));
+        source_bindings.push(value(&id, &format!("{} active", source)));
+    }
+
+    entries.push(block("preset", vec![
+        value("id", "0"),
+        value("name", ACTION_SET),
+        block("group_source_bindings", source_bindings),
+    ]));
+
+    to_vdf(block("controller_mappings", entries))
+}
+
+pub fn default_export_dir() -> PathBuf {
+    config_dir().join(EXPORTS_DIR)
+}
+
+// Writes both files into dir and returns the controller config's path
+pub fn export_steam_config(button_mappings: &HashMap<Button, String>, axis_mappings: &HashMap<Axis, String>, dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let stamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+
+    let actions = dir.join(format!("steamdeck-game-actions-{}.vdf", stamp));
+    std::fs::write(&actions, game_actions_vdf(button_mappings, axis_mappings))
+        .with_context(|| format!("Failed to write {}", actions.display()))?;
+    let config = dir.join(format!("steamdeck-controller-config-{}.vdf", stamp));
+    std::fs::write(&config, controller_config_vdf(button_mappings, axis_mappings))
+        .with_context(|| format!("Failed to write {}", config.display()))?;
+    Ok(config)
+}
+
+// Every button the config can bind, in the order the mappings are listed
+const BUTTONS: [Button; 16] = [
+    Button::South, Button::East, Button::West, Button::North,
+    Button::LeftTrigger, Button::RightTrigger, Button::LeftTrigger2, Button::RightTrigger2,
+    Button::LeftThumb, Button::RightThumb, Button::Start, Button::Select,
+    Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+];
+const AXES: [Axis; 6] = [Axis::LeftStickX, Axis::LeftStickY, Axis::RightStickX, Axis::RightStickY, Axis::LeftZ, Axis::RightZ];
+
+// Reads the game actions a Deck controller config binds and turns them into button and axis
+// mappings. Each input keeps the label it has now, only the action after the dash changes, and
+// inputs the config leaves unbound lose their mapping so both sides agree.
+pub fn import_mappings(
+    path: &Path,
+    button_mappings: &HashMap<Button, String>,
+    axis_mappings: &HashMap<Axis, String>,
+) -> Result<(HashMap<Button, String>, HashMap<Axis, String>)> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let (key, root) = parse_vdf(&text).with_context(|| format!("{} is not a VDF file", path.display()))?;
+    if !key.eq_ignore_ascii_case("controller_mappings") {
+        bail!("{} is not a Steam controller config", path.display());
+    }
+
+    let english = root.get("localization").and_then(|localization| localization.get("english"));
+    let title_of = |action: &str, title: Option<&str>| -> String {
+        // Titles starting with # are localization keys
+        let key = match title.map(str::trim) {
+            Some(title) if !title.is_empty() && !title.starts_with('#') => return title.to_string(),
+            Some(title) if title.starts_with('#') => title[1..].to_string(),
+            _ => format!("Action_{}", action),
+        };
+        english.and_then(|english| english.get(&key)).and_then(Vdf::as_str).unwrap_or(action).to_string()
+    };
+
+    // The first preset is the first action set, mode shifts and inactive groups don't count
+    let groups: HashMap<&str, &Vdf> = root.all("group")
+        .filter_map(|group| Some((group.get("id")?.as_str()?, group)))
+        .collect();
+    let mut sources = HashMap::new();
+    if let Some(bindings) = root.get("preset").and_then(|preset| preset.get("group_source_bindings")) {
+        for (id, binding) in bindings.entries() {
+            let words: Vec<&str> = binding.as_str().unwrap_or_default().split_whitespace().collect();
+            if let ([source, "active"], Some(group)) = (words.as_slice(), groups.get(id.as_str())) {
+                sources.insert(source.to_string(), *group);
+            }
+        }
+    }
+
+    let label = |current: Option<&String>, fallback: String| {
+        current.and_then(|mapping| mapping.rsplit_once(" - ")).map_or(fallback, |(label, _)| label.to_string())
+    };
+
+    let mut buttons = HashMap::new();
+    for button in BUTTONS {
+        let Some((source, input)) = button_source(button) else {
+            continue;
+        };
+        let bindings = sources.get(source)
+            .and_then(|group| group.get("inputs"))
+            .and_then(|inputs| inputs.get(input))
+            .and_then(|input| input.get("activators"))
+            .and_then(|activators| activators.get("Full_Press"))
+            .and_then(|press| press.get("bindings"));
+        // "game_action <set> <action>, <title>, , "
+        let game_action = bindings.into_iter()
+            .flat_map(|bindings| bindings.all("binding"))
+            .filter_map(Vdf::as_str)
+            .find_map(|binding| binding.strip_prefix("game_action "));
+        let Some(game_action) = game_action else {
+            continue;
+        };
+        let mut parts = game_action.split(',');
+        let Some(action) = parts.next().and_then(|action| action.split_whitespace().nth(1)) else {
+            continue;
+        };
+        let title = title_of(action, parts.next());
+        buttons.insert(button, format!("{} - {}", label(button_mappings.get(&button), format!("{:?}", button)), title));
+    }
+
+    let mut axes = HashMap::new();
+    for axis in AXES {
+        let Some(source) = axis_source(axis) else {
+            continue;
+        };
+        let action = sources.get(source)
+            .and_then(|group| group.get("gameactions"))
+            .and_then(|actions| actions.entries().first())
+            .and_then(|(_, action)| action.as_str());
+        if let Some(action) = action {
+            let title = title_of(action, None);
+            axes.insert(axis, format!("{} - {}", label(axis_mappings.get(&axis), format!("{:?}", axis)), title));
+        }
+    }
+
+    if buttons.is_empty() && axes.is_empty() {
+        bail!("{} doesn't bind any game actions", path.display());
+    }
+    Ok((buttons, axes))
+}